@@ -1,28 +1,44 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::{
-    collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    cmp::Ordering,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
     io::Read,
     time::{Duration, SystemTime, UNIX_EPOCH},
     thread,
 };
 
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OpenFlags, TransactionBehavior};
+use r2d2_sqlite::SqliteConnectionManager;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use aes_gcm::{aead::{Aead, KeyInit}, Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
 use tauri::{AppHandle, Manager, Emitter};
-use tokio::{io::{AsyncBufReadExt, BufReader}, process::Command};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, process::Command};
 use std::process::Stdio;
 use uuid::Uuid;
 use text_splitter::{TextSplitter, ChunkConfig};
+use tokenizers::Tokenizer;
+use tiktoken_rs::CoreBPE;
+use tokio_util::sync::CancellationToken;
+use unicode_segmentation::UnicodeSegmentation;
 use csv::Reader;
+use calamine::Reader as _;
 use docx_rs::read_docx;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use scraper::{ElementRef, Html, Selector};
+use hnsw_rs::prelude::{DistCosine, Hnsw};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use sysinfo::{System, SystemExt, CpuExt, DiskExt, NetworkExt, ProcessorExt};
 use log::{info, warn, error, debug};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
 
 // ---------- System Monitoring Data Models ------------------------------------------
 
@@ -74,11 +90,29 @@ pub struct LogEntry {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EmbeddingModel {
     #[serde(rename = "huggingface")]
-    HuggingFace { model_name: String },
+    HuggingFace {
+        model_name: String,
+        #[serde(default)]
+        api_key: Option<String>, // HuggingFace access token, required for the hosted Inference API
+    },
     #[serde(rename = "openai")]
-    OpenAI { api_key: String, model: String },
+    OpenAI {
+        api_key: String,
+        model: String,
+        // Any OpenAI-compatible server (LM Studio, vLLM, llamafile, ...); api.openai.com when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base_url: Option<String>,
+    },
     #[serde(rename = "local")]
     Local { model_path: String },
+    #[serde(rename = "plugin")]
+    Plugin { provider: String, model: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderModel {
+    pub provider: String,
+    pub model: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,27 +125,349 @@ pub enum RAGMode {
     BaseWithRAG,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum AnswerStyle {
+    #[serde(rename = "concise")]
+    Concise,
+    #[default]
+    #[serde(rename = "detailed")]
+    Detailed,
+    #[serde(rename = "quotes_only")]
+    QuotesOnly,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum QaExtraction {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "faq_sections")]
+    FaqSections, // question lines already present in the document
+    #[serde(rename = "generated")]
+    Generated, // questions written by the generation provider for each chunk
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum AutoTagging {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "keywords")]
+    Keywords, // RAKE key phrases computed locally
+    #[serde(rename = "llm")]
+    Llm, // topics named by the generation provider; keywords when none is configured
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum EmbeddingStorage {
+    #[default]
+    #[serde(rename = "float32")]
+    Float32,
+    #[serde(rename = "int8")]
+    Int8, // one byte per dimension plus a per-vector scale, about 4x smaller
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum VectorBackend {
+    #[default]
+    #[serde(rename = "linear")]
+    Linear, // decode and score every stored BLOB
+    #[serde(rename = "sqlite_vec")]
+    SqliteVec, // nearest-neighbour candidates from a vec0 index, needs the sqlite-vec feature
+    #[serde(rename = "hnsw")]
+    Hnsw, // approximate candidates from an in-memory HNSW graph
+    #[serde(rename = "lancedb")]
+    LanceDb { uri: Option<String> }, // vectors mirrored to a LanceDB dataset, in the app data dir when unset
+    #[serde(rename = "qdrant")]
+    Qdrant {
+        url: String,
+        collection: String,
+        #[serde(default)]
+        api_key: Option<String>,
+    }, // vectors mirrored to a shared Qdrant collection, metadata stays local
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RerankerModel {
+    #[serde(rename = "local")]
+    Local { model_path: String }, // cross-encoder folder or HuggingFace repo id, run with candle
+    #[serde(rename = "cohere")]
+    Cohere { api_key: String, model: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerankerConfig {
+    pub model: RerankerModel,
+    #[serde(default = "default_rerank_candidates")]
+    pub candidates: usize, // passages retrieved for the reranker to choose top_k from
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum HybridFusion {
+    #[default]
+    #[serde(rename = "off")]
+    Off, // vector similarity only
+    #[serde(rename = "rrf")]
+    Rrf, // reciprocal rank fusion of the vector and BM25 rankings
+    #[serde(rename = "weighted")]
+    Weighted { vector_weight: f32 }, // blend of similarity and max-normalised BM25
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum ChunkUnit {
+    #[default]
+    #[serde(rename = "characters")]
+    Characters,
+    #[serde(rename = "tokens")]
+    Tokens,
+}
+
+// How a section is cut into chunks; chunk_size and chunk_overlap are measured in chunk_unit throughout
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum ChunkingStrategy {
+    #[default]
+    #[serde(rename = "character")]
+    Character, // recursive splitter, cutting at the largest boundary that fits
+    #[serde(rename = "sentence")]
+    Sentence, // whole sentences packed up to chunk_size, overlap counted in whole sentences
+    #[serde(rename = "semantic")]
+    Semantic {
+        // cut where the distance between neighbouring sentences exceeds this percentile of the section's distances
+        #[serde(default = "default_breakpoint_percentile")]
+        breakpoint_percentile: f32,
+    },
+}
+
+fn default_breakpoint_percentile() -> f32 {
+    90.0
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SimilarityMetric {
+    #[default]
+    #[serde(rename = "cosine")]
+    Cosine,
+    #[serde(rename = "dot")]
+    Dot,
+    #[serde(rename = "euclidean")]
+    Euclidean,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RAGConfig {
     pub embedding_model: EmbeddingModel,
     pub mode: RAGMode,
     pub chunk_size: usize,
     pub chunk_overlap: usize,
+    #[serde(default)]
+    pub parent_chunk_size: Option<usize>, // match on chunk_size pieces but prompt with parents this size; off unless larger
+    #[serde(default)]
+    pub chunk_unit: ChunkUnit, // unit for chunk_size and chunk_overlap
+    #[serde(default)]
+    pub chunking_strategy: ChunkingStrategy,
     pub top_k: usize,
     pub similarity_threshold: f32,
+    #[serde(default)]
+    pub similarity_metric: SimilarityMetric,
+    #[serde(default)]
+    pub mmap_embeddings: bool, // scan the memory-mapped sidecar store instead of BLOBs
+    #[serde(default)]
+    pub generation_provider: Option<ProviderModel>, // plugin used to write answers when llm_provider is unset
+    #[serde(default)]
+    pub llm_provider: Option<LLMProvider>, // built-in chat backend; mock template when neither is set
+    #[serde(default)]
+    pub temperature: Option<f32>, // overrides the answer style's default
+    #[serde(default)]
+    pub max_tokens: Option<u32>, // overrides the answer style's default
+    #[serde(default)]
+    pub context_window: Option<usize>, // model context size in tokens; guessed from the provider when unset
+    #[serde(default)]
+    pub top_p: Option<f32>, // nucleus sampling; provider default when unset
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>, // ignored by providers without one (Anthropic)
+    #[serde(default)]
+    pub stop: Vec<String>, // generation halts at any of these sequences
+    #[serde(default)]
+    pub regression_check_on_change: bool, // run the golden question set before applying a new config
+    #[serde(default)]
+    pub qa_extraction: QaExtraction, // index Q/A questions as extra retrieval keys at ingestion
+    #[serde(default)]
+    pub summarize_on_ingest: bool, // store a summary of each new or re-indexed document
+    #[serde(default)]
+    pub summary_retrieval: bool, // let document summaries that match the query join the retrieved passages
+    #[serde(default)]
+    pub auto_tagging: AutoTagging, // attach extracted keywords or topics as tags at ingestion
+    #[serde(default = "default_auto_tag_count")]
+    pub auto_tag_count: usize,
+    #[serde(default)]
+    pub entity_extraction: EntityExtraction, // index people, organizations and dates per chunk at ingestion
+    #[serde(default)]
+    pub multilingual_embedding_model: Option<EmbeddingModel>, // replaces an English-only model once non-English documents arrive
+    #[serde(default)]
+    pub translation: Translation, // bridge queries and documents in different languages; needs a generation provider
+    #[serde(default = "default_embedding_batch_size")]
+    pub embedding_batch_size: usize, // chunks sent per embedding request during ingestion
+    #[serde(default)]
+    pub personalization_profile: Option<String>, // boost documents this profile opens or cites; None turns it off
+    #[serde(default)]
+    pub embedding_storage: EmbeddingStorage, // format for newly written vectors; both formats are always readable
+    #[serde(default)]
+    pub vector_backend: VectorBackend, // how candidate chunks are found before scoring
+    #[serde(default)]
+    pub hybrid_fusion: HybridFusion, // merge FTS5 keyword matches into the vector ranking
+    #[serde(default)]
+    pub reranker: Option<RerankerConfig>, // second-stage scoring of retrieved passages
+    #[serde(default)]
+    pub diversity_lambda: Option<f32>, // MMR trade-off, 1.0 is pure relevance; None keeps the ranked top_k
+    #[serde(default)]
+    pub tags: Vec<String>, // only retrieve from documents carrying one of these tags; empty searches everything
+    #[serde(default)]
+    pub extraction: ExtractionOptions, // format-specific settings for built-in extractors
+    #[serde(default)]
+    pub chunk_filter: ChunkFilter, // only retrieve chunks whose metadata matches; default matches everything
+    #[serde(default)]
+    pub prompt_template: Option<String>, // id of a stored prompt template; None uses the built-in prompt
+    #[serde(default = "default_history_turns")]
+    pub history_turns: usize, // most recent messages of a conversation kept for follow-up questions
+    #[serde(default)]
+    pub query_rewrite: bool, // have the generation model fix, resolve and expand the query before it is embedded
+    #[serde(default)]
+    pub multi_query: usize, // extra phrasings of the query retrieved separately and fused; 0 turns it off
+    #[serde(default)]
+    pub grounding_check: bool, // compare each answer sentence with the retrieved passages after generation
+    #[serde(default = "default_grounding_threshold")]
+    pub grounding_threshold: f32, // similarity a sentence needs to some passage to count as supported
+    #[serde(default)]
+    pub chat_tools: Vec<String>, // tools the model may call in chat_with_documents; empty turns tool calling off
+}
+
+// Matches metadata extractors attach to chunks; email chunks carry "from" and "date"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkFilter {
+    #[serde(default)]
+    pub sender: Option<String>, // case-insensitive substring of the From header
+    #[serde(default)]
+    pub date_from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub date_to: Option<DateTime<Utc>>,
+}
+
+impl ChunkFilter {
+    // Same rules as chunk_filter_clause, for chunks that only exist in a shared vector store
+    fn matches(&self, metadata: &serde_json::Value) -> bool {
+        if let Some(sender) = self.sender.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+            let from = metadata.get("from").and_then(|v| v.as_str()).unwrap_or_default();
+            if !from.to_lowercase().contains(&sender.to_lowercase()) {
+                return false;
+            }
+        }
+        let date = metadata.get("date").and_then(|v| v.as_str());
+        if self.date_from.is_some_and(|from| date.map_or(true, |d| d < from.to_rfc3339().as_str())) {
+            return false;
+        }
+        if self.date_to.is_some_and(|to| date.map_or(true, |d| d > to.to_rfc3339().as_str())) {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionOptions {
+    #[serde(default)]
+    pub sheets: Vec<String>, // spreadsheet sheets to ingest, matched case-insensitively; empty means every sheet
+    #[serde(default)]
+    pub json_fields: JsonFieldMapping,
+    #[serde(default = "default_true")]
+    pub ocr: bool, // OCR images and PDFs without a text layer
+    #[serde(default)]
+    pub ocr_language: Option<String>, // tesseract language codes such as "eng+deu"; tesseract's default when unset
+    #[serde(default)]
+    pub pdf_tables: bool, // rebuild PDF tables as Markdown from glyph positions; slower than plain extraction
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        Self {
+            sheets: Vec::new(),
+            json_fields: JsonFieldMapping::default(),
+            ocr: true,
+            ocr_language: None,
+            pdf_tables: false,
+        }
+    }
+}
+
+// Dot paths into each JSON record, e.g. "fields.summary" or "comments.0.body"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JsonFieldMapping {
+    #[serde(default)]
+    pub content: Vec<String>, // joined into the record text; empty uses every other top-level field
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub metadata: Vec<String>, // copied onto the record's chunks
+}
+
+fn default_embedding_batch_size() -> usize {
+    32
+}
+
+fn default_auto_tag_count() -> usize {
+    5
 }
 
 impl Default for RAGConfig {
     fn default() -> Self {
         Self {
             embedding_model: EmbeddingModel::HuggingFace { 
-                model_name: "sentence-transformers/all-MiniLM-L6-v2".to_string() 
+                model_name: "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+                api_key: None,
             },
             mode: RAGMode::BaseWithRAG,
             chunk_size: 200,
             chunk_overlap: 50,
+            parent_chunk_size: None,
+            chunk_unit: ChunkUnit::Characters,
+            chunking_strategy: ChunkingStrategy::Character,
             top_k: 5,
             similarity_threshold: 0.3,
+            similarity_metric: SimilarityMetric::Cosine,
+            mmap_embeddings: false,
+            generation_provider: None,
+            llm_provider: None,
+            temperature: None,
+            max_tokens: None,
+            context_window: None,
+            top_p: None,
+            frequency_penalty: None,
+            stop: Vec::new(),
+            regression_check_on_change: false,
+            qa_extraction: QaExtraction::Off,
+            summarize_on_ingest: false,
+            summary_retrieval: false,
+            auto_tagging: AutoTagging::Off,
+            auto_tag_count: default_auto_tag_count(),
+            entity_extraction: EntityExtraction::Off,
+            multilingual_embedding_model: None,
+            translation: Translation::Off,
+            embedding_batch_size: default_embedding_batch_size(),
+            personalization_profile: None,
+            embedding_storage: EmbeddingStorage::Float32,
+            vector_backend: VectorBackend::Linear,
+            hybrid_fusion: HybridFusion::Off,
+            reranker: None,
+            diversity_lambda: None,
+            tags: Vec::new(),
+            extraction: ExtractionOptions::default(),
+            chunk_filter: ChunkFilter::default(),
+            prompt_template: None,
+            history_turns: default_history_turns(),
+            query_rewrite: false,
+            multi_query: 0,
+            grounding_check: false,
+            grounding_threshold: default_grounding_threshold(),
+            chat_tools: Vec::new(),
         }
     }
 }
@@ -131,6 +487,16 @@ pub struct RetrievalResult {
     pub document_title: String,
     pub similarity_score: f32,
     pub source_info: String,
+    #[serde(default)]
+    pub merged_chunk_ids: Vec<String>, // other chunks collapsed into this passage by deduplication
+    #[serde(default)]
+    pub merged_sources: Vec<PassageSource>, // other documents the collapsed near-duplicates came from
+    #[serde(default)]
+    pub vector_score: Option<f32>, // embedding similarity before hybrid fusion
+    #[serde(default)]
+    pub keyword_score: Option<f32>, // BM25 score when the chunk also matched the keyword index
+    #[serde(default)]
+    pub rerank_score: Option<f32>, // reranker relevance, also copied into similarity_score
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,9 +504,42 @@ pub struct RAGResponse {
     pub answer: String,
     pub retrieved_context: Vec<RetrievalResult>,
     pub mode_used: RAGMode,
+    #[serde(default)]
+    pub answer_style: AnswerStyle,
+    #[serde(default)]
+    pub structured: Option<serde_json::Value>, // parsed answer when a response_schema was given
+    #[serde(default)]
+    pub standalone_query: Option<String>, // condensed or rewritten query actually used for retrieval
+    #[serde(default)]
+    pub query_language: Option<String>, // ISO 639-3 code; None when the query is too short to tell
+    #[serde(default)]
+    pub translated_query: Option<String>, // query as translated into the knowledge base's language for retrieval
+    #[serde(default)]
+    pub citations: Vec<Citation>, // markers that appear in the answer, in marker order
+    #[serde(default)]
+    pub grounding_score: Option<f32>, // share of answer sentences supported by the context; None when not checked
+    #[serde(default)]
+    pub unsupported_sentences: Vec<String>,
     pub processing_time_ms: u64,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PassageSource {
+    pub document_title: String,
+    pub source_info: String,
+}
+
+// Links an inline [n] marker in the answer to the passage it cites
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub marker: usize,
+    pub chunk_id: String,
+    pub document_title: String,
+    pub source_info: String,
+    #[serde(default)]
+    pub merged_sources: Vec<PassageSource>, // the same passage also appears in these documents
+}
+
 // ---------- Original Data Models ---------------------------------------------------
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,6 +550,12 @@ pub struct Document {
     pub file_path: Option<String>,
     pub file_type: String,
     pub content_hash: String,
+    pub is_searchable: bool,
+    pub is_sensitive: bool,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -162,6 +567,10 @@ pub struct DocumentChunk {
     pub chunk_index: i32,
     pub content: String,
     pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub parent_chunk_id: Option<String>, // larger passage sent to the model in place of this chunk
     pub created_at: DateTime<Utc>,
 }
 
@@ -171,6 +580,34 @@ pub struct ChatMessage {
     pub content: String,
     pub role: String, // "user" or "assistant"
     pub document_references: Vec<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub reply_to: Option<String>, // user message an answer responds to; regenerated answers share it
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLogEntry {
+    pub id: String,
+    pub query: String,
+    pub source: String, // "rag_query" | "search" | "agentic"
+    #[serde(default)]
+    pub session_id: Option<String>,
+    pub filters: serde_json::Value,
+    pub result_count: usize,
+    pub top_score: Option<f32>,
+    pub latency_ms: u64,
+    pub followed_up: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub action: String,
+    pub target: Option<String>,
+    pub details: serde_json::Value,
     pub created_at: DateTime<Utc>,
 }
 
@@ -185,6 +622,39 @@ pub struct SearchResult {
 pub struct ChatResponse {
     pub message: ChatMessage,
     pub sources: Vec<SearchResult>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallTrace>, // tools the model called while answering, in call order
+}
+
+// What upload_document does when a document with identical content already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum DuplicatePolicy {
+    #[default]
+    #[serde(rename = "skip")]
+    Skip, // return the existing document untouched
+    #[serde(rename = "link")]
+    Link, // record the new path on the existing document instead of storing a copy
+    #[serde(rename = "version")]
+    Version, // store a new document that supersedes the existing one
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UploadStatus {
+    #[serde(rename = "created")]
+    Created,
+    #[serde(rename = "duplicate")]
+    Duplicate,
+    #[serde(rename = "linked")]
+    Linked,
+    #[serde(rename = "versioned")]
+    Versioned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadResult {
+    pub document: Document,
+    pub status: UploadStatus,
+    pub duplicate_of: Option<String>,
 }
 
 // ---------- Helper Functions -------------------------------------------------------
@@ -202,6 +672,23 @@ fn calculate_content_hash(content: &str) -> String {
 }
 
 fn chunk_text_with_config(text: &str, config: &RAGConfig) -> Vec<String> {
+    if config.chunk_unit == ChunkUnit::Tokens {
+        match load_tokenizer(&config.embedding_model) {
+            Ok(tokenizer) => {
+                return chunk_text_by_tokens(text, &tokenizer, config.chunk_size, config.chunk_overlap);
+            }
+            Err(e) => {
+                // Roughly four characters per token keeps chunk sizes in the same ballpark
+                warn!("Tokenizer unavailable ({}), falling back to character chunking", e);
+                return chunk_text(text, config.chunk_size * 4, config.chunk_overlap * 4);
+            }
+        }
+    }
+    if config.chunking_strategy != ChunkingStrategy::Character {
+        // Semantic grouping needs embeddings, so this synchronous path packs sentences for it instead
+        return pack_sentences(&char_sentences(text, config.chunk_size), config.chunk_size, config.chunk_overlap);
+    }
+
     let splitter = TextSplitter::new(ChunkConfig::new(config.chunk_size)
         .with_overlap(config.chunk_overlap)
         .with_trim(true));
@@ -209,6 +696,366 @@ fn chunk_text_with_config(text: &str, config: &RAGConfig) -> Vec<String> {
     splitter.chunks(text).map(|s| s.to_string()).collect()
 }
 
+// Splits text at section starts; text before the first section carries no metadata
+fn section_spans<'a>(text: &'a str, sections: &[TextSection]) -> Vec<(&'a str, serde_json::Value)> {
+    let mut boundaries: Vec<&TextSection> = sections
+        .iter()
+        .filter(|section| section.start < text.len() && text.is_char_boundary(section.start))
+        .collect();
+    boundaries.sort_by_key(|section| section.start);
+
+    let mut spans = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = 0;
+    let mut metadata = serde_json::Value::Null;
+    for section in boundaries {
+        spans.push((&text[start..section.start], std::mem::replace(&mut metadata, section.metadata.clone())));
+        start = section.start;
+    }
+    spans.push((&text[start..], metadata));
+    spans.retain(|(span, _)| !span.trim().is_empty());
+    spans
+}
+
+// Chunks each section on its own so no chunk straddles two chapters; every chunk carries its section's metadata
+// plus char_start/char_end, its character range in the extracted text. Markdown chunks are prefixed with their
+// heading breadcrumb so a chunk deep in a section still says what it is about.
+async fn chunk_document(
+    text: &str,
+    sections: &[TextSection],
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Vec<(String, serde_json::Value)> {
+    warm_tokenizer(config).await;
+    let mut chunks = Vec::new();
+    // Byte and character position of the last chunk located, so offsets are counted incrementally
+    let (mut byte_cursor, mut char_cursor) = (0, 0);
+    for (span, metadata) in section_spans(text, sections) {
+        let span_start = span.as_ptr() as usize - text.as_ptr() as usize;
+        let span_end = span_start + span.len();
+        let mut search_from = span_start;
+        let span_chunks = match &config.chunking_strategy {
+            ChunkingStrategy::Semantic { breakpoint_percentile } => {
+                match chunk_text_semantic(span, config, providers, *breakpoint_percentile).await {
+                    Ok(span_chunks) => span_chunks,
+                    Err(e) => {
+                        warn!("Semantic chunking failed ({}), packing sentences instead", e);
+                        chunk_text_with_config(span, config)
+                    }
+                }
+            }
+            _ => chunk_text_with_config(span, config),
+        };
+        for chunk in span_chunks {
+            let mut chunk_metadata = match metadata.clone() {
+                serde_json::Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            };
+            // Token chunks are decoded text and may not appear verbatim; those get no offsets
+            if let Some(start) = text[search_from..span_end].find(chunk.as_str()).map(|i| search_from + i) {
+                char_cursor += text[byte_cursor..start].chars().count();
+                byte_cursor = start;
+                chunk_metadata.insert("char_start".to_string(), serde_json::json!(char_cursor));
+                chunk_metadata.insert("char_end".to_string(), serde_json::json!(char_cursor + chunk.chars().count()));
+                search_from = start + chunk.chars().next().map_or(1, char::len_utf8);
+            }
+            let chunk = match chunk_metadata.get("heading_path").and_then(|h| h.as_str()) {
+                Some(path) => format!("{}\n\n{}", path, chunk),
+                None => chunk,
+            };
+            chunks.push((chunk, serde_json::Value::Object(chunk_metadata)));
+        }
+    }
+    chunks
+}
+
+// A larger passage stored for generation only; its small child chunks are what gets embedded and matched
+#[derive(Debug, Clone)]
+struct ParentChunk {
+    id: String,
+    content: String,
+    metadata: serde_json::Value,
+}
+
+// Chunks ready to embed, each with the id of the parent it expands to when small-to-big retrieval is on
+struct ChunkedDocument {
+    parents: Vec<ParentChunk>,
+    chunks: Vec<(String, serde_json::Value)>,
+    parent_ids: Vec<Option<String>>,
+}
+
+fn active_parent_chunk_size(config: &RAGConfig) -> Option<usize> {
+    config.parent_chunk_size.filter(|&size| size > config.chunk_size)
+}
+
+// Cuts the document into parent passages of parent_chunk_size, then splits each parent into chunk_size children.
+// Children inherit the parent's metadata, with character offsets narrowed to their own text where it can be found.
+async fn chunk_document_with_parents(
+    text: &str,
+    sections: &[TextSection],
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> ChunkedDocument {
+    let Some(parent_size) = active_parent_chunk_size(config) else {
+        let chunks = chunk_document(text, sections, config, providers).await;
+        let parent_ids = vec![None; chunks.len()];
+        return ChunkedDocument { parents: Vec::new(), chunks, parent_ids };
+    };
+
+    // Parents tile the text without overlap so each passage reaches the model at most once
+    let parent_config = RAGConfig { chunk_size: parent_size, chunk_overlap: 0, ..config.clone() };
+    let mut chunked = ChunkedDocument { parents: Vec::new(), chunks: Vec::new(), parent_ids: Vec::new() };
+    for (content, metadata) in chunk_document(text, sections, &parent_config, providers).await {
+        let parent = ParentChunk { id: Uuid::new_v4().to_string(), content, metadata };
+        let parent_start = parent.metadata.get("char_start").and_then(|v| v.as_u64());
+        // The heading breadcrumb prefixed to the parent is not part of the extracted text
+        let prefix_chars = parent.metadata.get("heading_path").and_then(|h| h.as_str()).map_or(0, |path| path.chars().count() + 2);
+        let mut search_from = 0;
+        for child in chunk_text_with_config(&parent.content, config) {
+            let mut child_metadata = match parent.metadata.clone() {
+                serde_json::Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            };
+            child_metadata.remove("char_start");
+            child_metadata.remove("char_end");
+            if let Some(at) = parent.content[search_from..].find(child.as_str()).map(|i| search_from + i) {
+                let offset = parent.content[..at].chars().count();
+                if let (Some(start), true) = (parent_start, offset >= prefix_chars) {
+                    let child_start = start as usize + offset - prefix_chars;
+                    child_metadata.insert("char_start".to_string(), serde_json::json!(child_start));
+                    child_metadata.insert("char_end".to_string(), serde_json::json!(child_start + child.chars().count()));
+                }
+                search_from = at + child.chars().next().map_or(1, char::len_utf8);
+            }
+            chunked.chunks.push((child, serde_json::Value::Object(child_metadata)));
+            chunked.parent_ids.push(Some(parent.id.clone()));
+        }
+        chunked.parents.push(parent);
+    }
+    chunked
+}
+
+fn insert_parent_chunks(conn: &Connection, document_id: &str, parents: &[ParentChunk]) -> rusqlite::Result<()> {
+    let now = Utc::now().to_rfc3339();
+    for (index, parent) in parents.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO parent_chunks (id, document_id, chunk_index, content, metadata, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                parent.id,
+                document_id,
+                index as i64,
+                parent.content,
+                (!parent.metadata.is_null()).then(|| parent.metadata.to_string()),
+                now,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Failed loads are cached too, so an offline model does not retry the download on every chunking call
+static TOKENIZER_CACHE: OnceLock<Mutex<HashMap<String, Result<Arc<Tokenizer>, (String, std::time::Instant)>>>> =
+    OnceLock::new();
+// How long a failed tokenizer load is remembered before the next attempt
+const TOKENIZER_RETRY_SECS: u64 = 600;
+
+// Loads (and caches) the tokenizer that matches the embedding model. A cache miss may download from the
+// HuggingFace Hub, so async code goes through load_tokenizer_async or warm_tokenizer instead
+fn load_tokenizer(model: &EmbeddingModel) -> Result<Arc<Tokenizer>> {
+    let cache_key = match model {
+        EmbeddingModel::HuggingFace { model_name, .. } => model_name.clone(),
+        EmbeddingModel::Local { model_path } => model_path.clone(),
+        EmbeddingModel::OpenAI { model, .. } | EmbeddingModel::Plugin { model, .. } => {
+            return Err(anyhow::anyhow!("no HuggingFace tokenizer for model {}", model));
+        }
+    };
+
+    let cache = TOKENIZER_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    match cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.get(&cache_key) {
+        Some(Ok(tokenizer)) => return Ok(tokenizer.clone()),
+        Some(Err((error, failed_at))) if failed_at.elapsed() < Duration::from_secs(TOKENIZER_RETRY_SECS) => {
+            return Err(anyhow::anyhow!(error.clone()));
+        }
+        _ => {}
+    }
+
+    let loaded = (|| -> Result<Arc<Tokenizer>> {
+        let tokenizer_path = match model {
+            EmbeddingModel::HuggingFace { model_name, api_key } => {
+                hf_hub::api::sync::ApiBuilder::new()
+                    .with_token(api_key.clone().filter(|t| !t.is_empty()))
+                    .build()?
+                    .model(model_name.clone())
+                    .get("tokenizer.json")?
+            }
+            _ => std::path::Path::new(&cache_key).join("tokenizer.json"),
+        };
+        Ok(Arc::new(Tokenizer::from_file(&tokenizer_path).map_err(|e| anyhow::anyhow!(e.to_string()))?))
+    })();
+    cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.insert(
+        cache_key,
+        loaded.as_ref().map(Arc::clone).map_err(|e| (e.to_string(), std::time::Instant::now())),
+    );
+    loaded
+}
+
+// The download and file parsing are blocking, so keep them off the async workers
+async fn load_tokenizer_async(model: &EmbeddingModel) -> Result<Arc<Tokenizer>> {
+    let model = model.clone();
+    tokio::task::spawn_blocking(move || load_tokenizer(&model)).await?
+}
+
+// Fills the tokenizer cache ahead of the synchronous chunkers, which then never download
+async fn warm_tokenizer(config: &RAGConfig) {
+    if config.chunk_unit == ChunkUnit::Tokens {
+        let _ = load_tokenizer_async(&config.embedding_model).await;
+    }
+}
+
+// Trimmed sentences with their size; sentences longer than a whole chunk are pre-split by `split_long`
+fn measured_sentences(
+    text: &str,
+    max_size: usize,
+    measure: impl Fn(&str) -> usize,
+    split_long: impl Fn(&str) -> Vec<String>,
+) -> Vec<(String, usize)> {
+    let mut sentences = Vec::new();
+    for sentence in text.split_sentence_bounds().map(str::trim).filter(|s| !s.is_empty()) {
+        let size = measure(sentence);
+        if size > max_size {
+            for piece in split_long(sentence) {
+                let size = measure(&piece);
+                sentences.push((piece, size));
+            }
+        } else {
+            sentences.push((sentence.to_string(), size));
+        }
+    }
+    sentences
+}
+
+fn token_sentences(text: &str, tokenizer: &Tokenizer, max_tokens: usize) -> Vec<(String, usize)> {
+    let long_splitter = TextSplitter::new(ChunkConfig::new(max_tokens)
+        .with_sizer(tokenizer.clone())
+        .with_trim(true));
+    measured_sentences(
+        text,
+        max_tokens,
+        |s| tokenizer.encode(s, false).map(|encoding| encoding.len()).unwrap_or_else(|_| s.split_whitespace().count()),
+        |s| long_splitter.chunks(s).map(str::to_string).collect(),
+    )
+}
+
+fn char_sentences(text: &str, max_chars: usize) -> Vec<(String, usize)> {
+    let long_splitter = TextSplitter::new(ChunkConfig::new(max_chars).with_trim(true));
+    measured_sentences(
+        text,
+        max_chars,
+        |s| s.chars().count(),
+        |s| long_splitter.chunks(s).map(str::to_string).collect(),
+    )
+}
+
+// Sentences in the config's chunk unit along with the size and overlap limits they should be packed to
+fn config_sentences(text: &str, config: &RAGConfig) -> (Vec<(String, usize)>, usize, usize) {
+    if config.chunk_unit == ChunkUnit::Tokens {
+        match load_tokenizer(&config.embedding_model) {
+            Ok(tokenizer) => {
+                return (token_sentences(text, &tokenizer, config.chunk_size), config.chunk_size, config.chunk_overlap);
+            }
+            Err(e) => {
+                warn!("Tokenizer unavailable ({}), measuring sentences in characters", e);
+                let (size, overlap) = (config.chunk_size * 4, config.chunk_overlap * 4);
+                return (char_sentences(text, size), size, overlap);
+            }
+        }
+    }
+    (char_sentences(text, config.chunk_size), config.chunk_size, config.chunk_overlap)
+}
+
+fn chunk_text_by_tokens(text: &str, tokenizer: &Tokenizer, max_tokens: usize, overlap_tokens: usize) -> Vec<String> {
+    pack_sentences(&token_sentences(text, tokenizer, max_tokens), max_tokens, overlap_tokens)
+}
+
+// Packs whole sentences up to max_size, starting each chunk with as many trailing sentences of the
+// previous one as fit the overlap budget
+fn pack_sentences(sentences: &[(String, usize)], max_size: usize, overlap_size: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < sentences.len() {
+        let mut end = start;
+        let mut total = 0;
+        while end < sentences.len() && (end == start || total + sentences[end].1 <= max_size) {
+            total += sentences[end].1;
+            end += 1;
+        }
+
+        chunks.push(
+            sentences[start..end]
+                .iter()
+                .map(|(sentence, _)| sentence.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        if end >= sentences.len() {
+            break;
+        }
+
+        // Step back over whole sentences that fit the overlap budget, always advancing
+        let mut next = end;
+        let mut overlap = 0;
+        while next > start + 1 && overlap + sentences[next - 1].1 <= overlap_size {
+            overlap += sentences[next - 1].1;
+            next -= 1;
+        }
+        start = next;
+    }
+
+    chunks
+}
+
+// Groups consecutive sentences and cuts where neighbouring sentences are least similar, or where the next
+// sentence would overflow chunk_size. Chunks do not overlap, since each should hold one thought.
+async fn chunk_text_semantic(
+    text: &str,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    breakpoint_percentile: f32,
+) -> Result<Vec<String>> {
+    let (sentences, max_size, _) = config_sentences(text, config);
+    if sentences.len() < 2 {
+        return Ok(sentences.into_iter().map(|(sentence, _)| sentence).collect());
+    }
+
+    // Sentence vectors are only used for grouping, so they skip the embedding cache
+    let texts: Vec<String> = sentences.iter().map(|(sentence, _)| sentence.clone()).collect();
+    let embeddings = providers.embed_uncached(&texts, config).await?;
+    let distances: Vec<f32> = embeddings.windows(2).map(|pair| 1.0 - cosine_similarity(&pair[0], &pair[1])).collect();
+    let mut sorted = distances.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let rank = ((sorted.len() - 1) as f32 * (breakpoint_percentile / 100.0).clamp(0.0, 1.0)).round() as usize;
+    let breakpoint = sorted[rank];
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut current_size = 0;
+    for (index, (sentence, size)) in sentences.iter().enumerate() {
+        let topic_shift = index > 0 && distances[index - 1] > breakpoint;
+        if !current.is_empty() && (topic_shift || current_size + size > max_size) {
+            chunks.push(current.join(" "));
+            current.clear();
+            current_size = 0;
+        }
+        current.push(sentence);
+        current_size += size;
+    }
+    if !current.is_empty() {
+        chunks.push(current.join(" "));
+    }
+    Ok(chunks)
+}
+
 fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
     let config = RAGConfig {
         chunk_size,
@@ -218,43 +1065,199 @@ fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
     chunk_text_with_config(text, &config)
 }
 
-async fn extract_text_from_file(file_path: &str) -> Result<String> {
-    let path = std::path::Path::new(file_path);
-    let extension = path.extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+// Typed per-file ingestion failures, so a folder import can report exactly which entries failed and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IngestError {
+    NotFound { path: String },
+    PermissionDenied { path: String },
+    Unsupported { path: String, extension: String },
+    InvalidEncoding { path: String },
+    ExtractionFailed { path: String, reason: String },
+    Io { path: String, reason: String },
+}
+
+impl std::fmt::Display for IngestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::NotFound { path } => write!(f, "File not found: {}", path),
+            IngestError::PermissionDenied { path } => write!(f, "Permission denied: {}", path),
+            IngestError::Unsupported { path, extension } => {
+                write!(f, "Unsupported file type '{}': {}", extension, path)
+            }
+            IngestError::InvalidEncoding { path } => write!(f, "File is not valid UTF-8 text: {}", path),
+            IngestError::ExtractionFailed { path, reason } => {
+                write!(f, "Could not extract text from {}: {}", path, reason)
+            }
+            IngestError::Io { path, reason } => write!(f, "Could not read {}: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for IngestError {}
+
+impl IngestError {
+    fn from_io(path: &Path, error: &std::io::Error) -> Self {
+        let path = path.display().to_string();
+        match error.kind() {
+            std::io::ErrorKind::NotFound => IngestError::NotFound { path },
+            std::io::ErrorKind::PermissionDenied => IngestError::PermissionDenied { path },
+            _ => IngestError::Io { path, reason: error.to_string() },
+        }
+    }
+
+    // Recovers the typed error from an anyhow chain, classifying stray I/O errors on the way
+    fn classify(path: &Path, error: &anyhow::Error) -> Self {
+        if let Some(ingest_error) = error.downcast_ref::<IngestError>() {
+            return ingest_error.clone();
+        }
+        if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
+            return IngestError::from_io(path, io_error);
+        }
+        IngestError::ExtractionFailed { path: path.display().to_string(), reason: error.to_string() }
+    }
+}
+
+// Windows caps plain paths at 260 characters; the verbatim prefix lifts that for deep folder trees
+#[cfg(windows)]
+fn long_path(path: &Path) -> PathBuf {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => return path.to_path_buf(),
+        }
+    };
+
+    // Verbatim paths skip separator normalisation, so forward slashes must be converted here
+    let wide: Vec<u16> = absolute
+        .as_os_str()
+        .encode_wide()
+        .map(|c| if c == '/' as u16 { '\\' as u16 } else { c })
+        .collect();
+    let verbatim: Vec<u16> = r"\\?\".encode_utf16().collect();
+    if wide.starts_with(&verbatim) {
+        return PathBuf::from(OsString::from_wide(&wide));
+    }
+
+    let unc: Vec<u16> = r"\\".encode_utf16().collect();
+    let prefixed: Vec<u16> = if wide.starts_with(&unc) {
+        r"\\?\UNC\".encode_utf16().chain(wide[2..].iter().copied()).collect()
+    } else {
+        verbatim.into_iter().chain(wide).collect()
+    };
+    PathBuf::from(OsString::from_wide(&prefixed))
+}
+
+#[cfg(not(windows))]
+fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+// Lossless OS-native encoding of a path, so non-UTF-8 file names survive a round trip through SQLite
+#[cfg(unix)]
+fn encode_os_path(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn decode_os_path(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(windows)]
+fn encode_os_path(path: &Path) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str().encode_wide().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+#[cfg(windows)]
+fn decode_os_path(bytes: &[u8]) -> PathBuf {
+    use std::os::windows::ffi::OsStringExt;
+    let wide: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    PathBuf::from(OsString::from_wide(&wide))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn encode_os_path(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn decode_os_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).to_string())
+}
+
+fn file_extension(path: &Path) -> String {
+    path.extension()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Extensions extract_text_from_file handles without a plugin
+const BUILTIN_EXTENSIONS: &[&str] = &[
+    "txt", "md", "pdf", "docx", "csv", "html", "htm", "epub", "pptx", "xlsx", "xlsm", "xls", "ods", "json", "jsonl", "png",
+    "jpg", "jpeg", "tif", "tiff", "eml", "mbox", "rs",
+    "py", "js", "jsx", "mjs", "ts", "tsx", "go",
+];
+
+fn is_supported_file(path: &Path, plugins: &[ExtractorPlugin]) -> bool {
+    let extension = file_extension(path);
+    BUILTIN_EXTENSIONS.contains(&extension.as_str())
+        || plugins
+            .iter()
+            .any(|p| p.enabled && p.manifest.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension)))
+}
+
+async fn extract_text_from_file(path: &Path, options: &ExtractionOptions) -> Result<String> {
+    let extension = file_extension(path);
+    let io_path = long_path(path);
+    let failed = |reason: String| IngestError::ExtractionFailed { path: path.display().to_string(), reason };
 
     match extension.as_str() {
         "txt" | "md" => {
-            Ok(tokio::fs::read_to_string(file_path).await?)
+            let bytes = tokio::fs::read(&io_path).await.map_err(|e| IngestError::from_io(path, &e))?;
+            Ok(String::from_utf8(bytes).map_err(|_| IngestError::InvalidEncoding { path: path.display().to_string() })?)
         }
-        "pdf" => {
-            // Simple PDF text extraction
-            match pdf_extract::extract_text(file_path) {
-                Ok(text) => Ok(text),
-                Err(_) => Ok("Could not extract text from PDF".to_string()),
-            }
+        ext if code_grammar(ext).is_some() => {
+            let bytes = tokio::fs::read(&io_path).await.map_err(|e| IngestError::from_io(path, &e))?;
+            Ok(String::from_utf8(bytes).map_err(|_| IngestError::InvalidEncoding { path: path.display().to_string() })?)
+        }
+        "pdf" => Ok(extract_pdf(&io_path, options).await.map_err(|e| failed(e.to_string()))?.text),
+        "png" | "jpg" | "jpeg" | "tif" | "tiff" => {
+            Ok(ocr_image(&io_path, options).await.map_err(|e| failed(e.to_string()))?)
         }
         "docx" => {
             // Extract text from DOCX
-            match extract_docx_text(file_path).await {
-                Ok(text) => Ok(text),
-                Err(e) => Ok(format!("Could not extract text from DOCX: {}", e)),
-            }
+            Ok(extract_docx_text(&io_path).await.map_err(|e| failed(e.to_string()))?)
         }
         "csv" => {
             // Extract text from CSV
-            match extract_csv_text(file_path).await {
-                Ok(text) => Ok(text),
-                Err(e) => Ok(format!("Could not extract text from CSV: {}", e)),
-            }
+            Ok(extract_csv_text(&io_path).await.map_err(|e| failed(e.to_string()))?)
+        }
+        "html" | "htm" => {
+            // Pages often declare legacy charsets, so stray bytes are replaced rather than rejected
+            let bytes = tokio::fs::read(&io_path).await.map_err(|e| IngestError::from_io(path, &e))?;
+            Ok(html_to_text(&String::from_utf8_lossy(&bytes)).text)
         }
-        _ => Ok(format!("Unsupported file type: {}", extension)),
+        "epub" => Ok(extract_epub(&io_path).map_err(|e| failed(e.to_string()))?.text),
+        "pptx" => Ok(extract_pptx(&io_path).map_err(|e| failed(e.to_string()))?.text),
+        "xlsx" | "xlsm" | "xls" | "ods" => Ok(extract_spreadsheet(&io_path, &[]).map_err(|e| failed(e.to_string()))?.text),
+        "eml" | "mbox" => Ok(extract_email(&io_path).map_err(|e| failed(e.to_string()))?.text),
+        "json" | "jsonl" => {
+            let mapping = JsonFieldMapping::default();
+            Ok(extract_json_records(&io_path, &mapping).map_err(|e| failed(e.to_string()))?.text)
+        }
+        _ => Err(IngestError::Unsupported { path: path.display().to_string(), extension }.into()),
     }
 }
 
-async fn extract_docx_text(file_path: &str) -> Result<String> {
+async fn extract_docx_text(file_path: &Path) -> Result<String> {
     let mut file = std::fs::File::open(file_path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
@@ -282,873 +1285,16675 @@ async fn extract_docx_text(file_path: &str) -> Result<String> {
     }
 }
 
-async fn extract_csv_text(file_path: &str) -> Result<String> {
-    let mut reader = Reader::from_path(file_path)?;
-    let mut text = String::new();
-    
-    // Add headers if available
-    if let Ok(headers) = reader.headers() {
-        text.push_str(&headers.iter().collect::<Vec<_>>().join(" | "));
-        text.push('\n');
-    }
-    
-    // Add data rows
-    for result in reader.records() {
-        let record = result?;
-        text.push_str(&record.iter().collect::<Vec<_>>().join(" | "));
-        text.push('\n');
+// Below this many letters and digits a PDF is treated as scanned
+const OCR_MIN_TEXT_CHARS: usize = 32;
+const OCR_TIMEOUT_SECS: u64 = 300;
+const OCR_RENDER_DPI: &str = "300";
+
+// OCR shells out to tesseract, and to poppler's pdftoppm to rasterise PDF pages
+async fn run_ocr_tool(program: &str, args: &[OsString]) -> Result<Vec<u8>> {
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+
+    let output = tokio::time::timeout(Duration::from_secs(OCR_TIMEOUT_SECS), command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("{} timed out after {}s", program, OCR_TIMEOUT_SECS))?
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!("{} is not installed or not on PATH", program),
+            _ => e.into(),
+        })?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
     }
-    
-    Ok(text)
+    Ok(output.stdout)
 }
 
-// Enhanced embedding generation with multiple model support
-async fn generate_embedding_with_config(text: &str, config: &RAGConfig) -> Result<Vec<f32>> {
-    match &config.embedding_model {
-        EmbeddingModel::HuggingFace { model_name } => {
-            generate_huggingface_embedding(text, model_name).await
-        }
-        EmbeddingModel::OpenAI { api_key, model } => {
-            generate_openai_embedding(text, api_key, model).await
-        }
-        EmbeddingModel::Local { model_path } => {
-            generate_local_embedding(text, model_path).await
-        }
+async fn ocr_image(image_path: &Path, options: &ExtractionOptions) -> Result<String> {
+    let mut args = vec![image_path.as_os_str().to_os_string(), OsString::from("stdout")];
+    if let Some(language) = options.ocr_language.as_deref().filter(|l| !l.trim().is_empty()) {
+        args.push(OsString::from("-l"));
+        args.push(OsString::from(language.trim()));
     }
+    Ok(String::from_utf8_lossy(&run_ocr_tool("tesseract", &args).await?).to_string())
 }
 
-async fn generate_huggingface_embedding(text: &str, model_name: &str) -> Result<Vec<f32>> {
-    // For now, use a simple mock - in production, integrate with HuggingFace API
-    // or load model locally using candle/tch
-    println!("Generating HuggingFace embedding with model: {}", model_name);
-    
-    // Mock embedding that varies based on text content
-    let hash = sha2::Sha256::digest(text.as_bytes());
-    let mut embedding = Vec::with_capacity(384);
-    
-    for (i, &byte) in hash.as_slice().iter().take(24).cycle().take(384).enumerate() {
-        let value = (byte as f32 / 255.0) * 2.0 - 1.0; // Normalize to [-1, 1]
-        let modified = value * (i as f32 * 0.01).sin();
-        embedding.push(modified);
-    }
-    
-    Ok(normalize_vector(embedding))
-}
+async fn ocr_pdf(file_path: &Path, options: &ExtractionOptions) -> Result<Vec<String>> {
+    let render_dir = std::env::temp_dir().join(format!("rag-ocr-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&render_dir)?;
 
-async fn generate_openai_embedding(text: &str, api_key: &str, model: &str) -> Result<Vec<f32>> {
-    let client = reqwest::Client::new();
-    
-    let request_body = serde_json::json!({
-        "input": text,
-        "model": model
-    });
-    
-    let response = client
-        .post("https://api.openai.com/v1/embeddings")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
-    
-    if response.status().is_success() {
-        let response_json: serde_json::Value = response.json().await?;
-        if let Some(data) = response_json["data"].as_array() {
-            if let Some(embedding_data) = data.get(0) {
-                if let Some(embedding_array) = embedding_data["embedding"].as_array() {
-                    let embedding: Vec<f32> = embedding_array
-                        .iter()
-                        .filter_map(|v| v.as_f64().map(|f| f as f32))
-                        .collect();
-                    return Ok(embedding);
-                }
-            }
+    let result = async {
+        let args = vec![
+            OsString::from("-r"),
+            OsString::from(OCR_RENDER_DPI),
+            OsString::from("-png"),
+            file_path.as_os_str().to_os_string(),
+            render_dir.join("page").into_os_string(),
+        ];
+        run_ocr_tool("pdftoppm", &args).await?;
+
+        // pdftoppm zero-pads page numbers, so name order is page order
+        let mut pages: Vec<PathBuf> = std::fs::read_dir(&render_dir)?.filter_map(Result::ok).map(|e| e.path()).collect();
+        pages.sort();
+        let mut text = Vec::with_capacity(pages.len());
+        for page in &pages {
+            text.push(ocr_image(page, options).await?);
         }
+        Ok(text)
     }
-    
-    Err(anyhow::anyhow!("Failed to get embedding from OpenAI API"))
+    .await;
+
+    let _ = std::fs::remove_dir_all(&render_dir);
+    result
 }
 
-async fn generate_local_embedding(text: &str, _model_path: &str) -> Result<Vec<f32>> {
-    // For now, use a sophisticated mock - in production, load local model
-    println!("Generating local embedding from model path");
-    
-    // Create a more sophisticated mock based on text characteristics
-    let words: Vec<&str> = text.split_whitespace().collect();
-    let mut embedding = Vec::with_capacity(384);
-    
-    for i in 0..384 {
-        let mut value = 0.0;
-        
-        // Base value from text length
-        value += (text.len() as f32 / 1000.0).sin();
-        
-        // Add word count influence
-        value += (words.len() as f32 / 100.0).cos();
-        
-        // Add character frequency influence
-        if i < 256 {
-            let char_count = text.chars().filter(|&c| c as u8 == i as u8).count();
-            value += (char_count as f32 / 10.0).sin();
-        }
-        
-        // Add positional encoding
-        value += ((i as f32) / 384.0 * std::f32::consts::PI).sin() * 0.1;
-        
-        embedding.push(value);
-    }
-    
-    Ok(normalize_vector(embedding))
+fn meaningful_chars(text: &str) -> usize {
+    text.chars().filter(|c| c.is_alphanumeric()).count()
 }
 
-fn normalize_vector(mut vector: Vec<f32>) -> Vec<f32> {
-    let magnitude: f32 = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if magnitude > 0.0 {
-        for value in &mut vector {
-            *value /= magnitude;
+// One section per page so chunks can cite the page they came from
+fn pdf_pages_document(pages: Vec<String>, ocr: bool) -> ExtractedDocument {
+    let mut text = String::new();
+    let mut sections = Vec::with_capacity(pages.len());
+    for (index, page) in pages.iter().enumerate() {
+        if page.trim().is_empty() {
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str("\n\n");
         }
+        sections.push(TextSection { start: text.len(), metadata: serde_json::json!({ "page_number": index + 1 }) });
+        text.push_str(page.trim_end());
     }
-    vector
+    ExtractedDocument { text, metadata: serde_json::json!({ "pages": pages.len(), "ocr": ocr }), sections }
 }
 
-// Backward compatibility function
-fn generate_embedding(text: &str) -> Vec<f32> {
-    let config = RAGConfig::default();
-    // Use blocking call for backward compatibility
-    tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(generate_embedding_with_config(text, &config))
-        .unwrap_or_else(|_| vec![0.0; 384])
+// Gaps between glyphs, as multiples of the font size, that separate words and table columns
+const PDF_WORD_GAP: f64 = 0.15;
+const PDF_COLUMN_GAP: f64 = 1.5;
+
+struct PdfGlyph {
+    x: f64,
+    y: f64,
+    right: f64,
+    size: f64,
+    text: String,
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-    
-    if norm_a == 0.0 || norm_b == 0.0 {
-        0.0
-    } else {
-        dot_product / (norm_a * norm_b)
-    }
+// Records where every glyph lands so rows and columns can be rebuilt, which plain extraction loses
+#[derive(Default)]
+struct PdfLayoutOutput {
+    pages: Vec<Vec<PdfGlyph>>,
 }
 
-// ---------- Database Functions -------------------------------------------------
+impl pdf_extract::OutputDev for PdfLayoutOutput {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        _media_box: &pdf_extract::MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), pdf_extract::OutputError> {
+        self.pages.push(Vec::new());
+        Ok(())
+    }
 
-fn init_db(conn: &Connection) -> Result<()> {
-    // Documents table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS documents (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            file_path TEXT,
-            file_type TEXT NOT NULL,
-            content_hash TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL
-        )",
-        [],
-    )?;
+    fn end_page(&mut self) -> Result<(), pdf_extract::OutputError> {
+        Ok(())
+    }
 
-    // Document chunks table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS document_chunks (
-            id TEXT PRIMARY KEY,
-            document_id TEXT NOT NULL,
-            chunk_index INTEGER NOT NULL,
-            content TEXT NOT NULL,
-            embedding BLOB NOT NULL,
-            created_at TEXT NOT NULL,
-            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
+    fn output_character(
+        &mut self,
+        trm: &pdf_extract::Transform,
+        width: f64,
+        _spacing: f64,
+        _font_size: f64,
+        char: &str,
+    ) -> Result<(), pdf_extract::OutputError> {
+        // Spaces are inferred from gaps, since many PDFs position words without emitting them
+        if let (Some(page), false) = (self.pages.last_mut(), char.trim().is_empty()) {
+            page.push(PdfGlyph {
+                x: trm.m31,
+                y: trm.m32,
+                right: trm.m31 + width * trm.m11,
+                size: trm.m22.abs().max(1.0),
+                text: char.to_string(),
+            });
+        }
+        Ok(())
+    }
 
-    // Chat messages table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS chat_messages (
-            id TEXT PRIMARY KEY,
-            content TEXT NOT NULL,
-            role TEXT NOT NULL,
-            document_references TEXT,
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
+    fn begin_word(&mut self) -> Result<(), pdf_extract::OutputError> {
+        Ok(())
+    }
 
-    // Create indexes for better performance
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_chunks_document_id ON document_chunks(document_id)", [])?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_created_at ON chat_messages(created_at)", [])?;
+    fn end_word(&mut self) -> Result<(), pdf_extract::OutputError> {
+        Ok(())
+    }
 
-    Ok(())
+    fn end_line(&mut self) -> Result<(), pdf_extract::OutputError> {
+        Ok(())
+    }
 }
 
-// ---------- Enhanced RAG Commands ----------------------------------------------
-
-#[tauri::command]
-async fn set_rag_config(
-    config: RAGConfig,
-    app: AppHandle,
-) -> Result<(), String> {
-    // Store RAG config in app state
-    app.manage(Arc::new(Mutex::new(config)));
-    Ok(())
+fn markdown_table(rows: &[Vec<String>]) -> String {
+    let row_text = |cells: &[String]| {
+        format!("| {} |\n", cells.iter().map(|c| c.replace('|', "\\|")).collect::<Vec<_>>().join(" | "))
+    };
+    let mut table = row_text(&rows[0]);
+    table.push_str(&format!("|{}\n", " --- |".repeat(rows[0].len())));
+    for row in &rows[1..] {
+        table.push_str(&row_text(row));
+    }
+    table
 }
 
-#[tauri::command]
-async fn get_rag_config(
-    app: AppHandle,
-) -> Result<RAGConfig, String> {
-    match app.try_state::<Arc<Mutex<RAGConfig>>>() {
-        Some(config_state) => {
-            let config = config_state.lock().map_err(|e| e.to_string())?;
-            Ok(config.clone())
+// Consecutive lines that split into the same number (two or more) of columns become a Markdown table
+fn pdf_page_layout_text(mut glyphs: Vec<PdfGlyph>) -> String {
+    // PDF y grows upwards, so top-to-bottom is descending y
+    glyphs.sort_by(|a, b| b.y.partial_cmp(&a.y).unwrap_or(Ordering::Equal).then(a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal)));
+    let mut lines: Vec<Vec<PdfGlyph>> = Vec::new();
+    for glyph in glyphs {
+        match lines.last_mut() {
+            Some(line) if (line[0].y - glyph.y).abs() <= line[0].size * 0.5 => line.push(glyph),
+            _ => lines.push(vec![glyph]),
         }
-        None => Ok(RAGConfig::default()),
     }
-}
 
-#[tauri::command]
-async fn process_document_enhanced(
-    file_path: String,
-    title: Option<String>,
-    config: RAGConfig,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-    app: AppHandle,
-) -> Result<ProcessingResult, String> {
-    let start_time = std::time::Instant::now();
-    
-    let content = extract_text_from_file(&file_path)
-        .await
-            .map_err(|e| e.to_string())?;
+    let rows: Vec<Vec<String>> = lines
+        .into_iter()
+        .map(|mut line| {
+            line.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+            let mut cells = Vec::new();
+            let mut cell = String::new();
+            let mut previous_right: Option<f64> = None;
+            for glyph in &line {
+                if let Some(right) = previous_right {
+                    let gap = glyph.x - right;
+                    if gap > glyph.size * PDF_COLUMN_GAP {
+                        cells.push(std::mem::take(&mut cell));
+                    } else if gap > glyph.size * PDF_WORD_GAP {
+                        cell.push(' ');
+                    }
+                }
+                cell.push_str(&glyph.text);
+                previous_right = Some(glyph.right);
+            }
+            cells.push(cell);
+            cells
+        })
+        .collect();
 
-    let file_name = std::path::Path::new(&file_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("Unknown")
-        .to_string();
-    
-    let doc_title = title.unwrap_or(file_name);
-    let content_hash = calculate_content_hash(&content);
-    let now = Utc::now();
+    let mut text = String::new();
+    let mut start = 0;
+    while start < rows.len() {
+        let columns = rows[start].len();
+        let mut end = start + 1;
+        while columns >= 2 && end < rows.len() && rows[end].len() == columns {
+            end += 1;
+        }
+        if end - start >= 2 {
+            text.push('\n');
+            text.push_str(&markdown_table(&rows[start..end]));
+            text.push('\n');
+        } else {
+            text.push_str(&rows[start].join(" "));
+            text.push('\n');
+        }
+        start = end;
+    }
+    text
+}
 
-    // Extract file type before moving file_path
-    let file_type = std::path::Path::new(&file_path)
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+fn extract_pdf_layout_pages(file_path: &Path) -> Result<Vec<String>> {
+    let document = lopdf::Document::load(file_path)?;
+    let mut output = PdfLayoutOutput::default();
+    pdf_extract::output_doc(&document, &mut output)?;
+    Ok(output.pages.into_iter().map(pdf_page_layout_text).collect())
+}
 
-    let document = Document {
-        id: Uuid::new_v4().to_string(),
-        title: doc_title,
-        content: content.clone(),
-        file_path: Some(file_path),
-        file_type,
-        content_hash,
-        created_at: now,
-        updated_at: now,
+// Scanned PDFs fall back to OCR instead of being embedded as empty or garbage text
+async fn extract_pdf(file_path: &Path, options: &ExtractionOptions) -> Result<ExtractedDocument> {
+    let extracted = if options.pdf_tables {
+        extract_pdf_layout_pages(file_path)
+    } else {
+        pdf_extract::extract_text_by_pages(file_path).map_err(anyhow::Error::from)
     };
+    let has_text_layer =
+        matches!(&extracted, Ok(pages) if pages.iter().map(|p| meaningful_chars(p)).sum::<usize>() >= OCR_MIN_TEXT_CHARS);
+    if has_text_layer || !options.ocr {
+        return Ok(pdf_pages_document(extracted?, false));
+    }
 
-    // Save to database
-    {
-        let db = db_state.lock().map_err(|e| e.to_string())?;
-        db.execute(
-            "INSERT INTO documents (id, title, content, file_path, file_type, content_hash, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                document.id,
-                document.title,
-                document.content,
-                document.file_path,
-                document.file_type,
-                document.content_hash,
-                document.created_at.to_rfc3339(),
-                document.updated_at.to_rfc3339(),
-            ],
-        ).map_err(|e| e.to_string())?;
+    info!("{} has no usable text layer, running OCR", file_path.display());
+    match ocr_pdf(file_path, options).await {
+        Ok(pages) => Ok(pdf_pages_document(pages, true)),
+        // A short but genuine text layer is still better than nothing
+        Err(e) => match extracted {
+            Ok(pages) if pages.iter().any(|p| meaningful_chars(p) > 0) => {
+                warn!("OCR failed for {}, keeping the extracted text: {}", file_path.display(), e);
+                Ok(pdf_pages_document(pages, false))
+            }
+            _ => Err(anyhow::anyhow!("PDF has no text layer and OCR failed: {}", e)),
+        },
     }
+}
 
-    // Process chunks with enhanced configuration
-    let doc_id = document.id.clone();
-    let db_clone = db_state.inner().clone();
-    let app_clone = app.clone();
-    let config_clone = config.clone();
-    
-    let chunks_created = tokio::spawn(async move {
-        process_document_chunks_enhanced(&doc_id, &content, &db_clone, &config_clone).await
-    }).await.map_err(|e| e.to_string())??;
+// One section per spine document; chapter titles come from the table of contents, else the page title
+fn extract_epub(file_path: &Path) -> Result<ExtractedDocument> {
+    let mut book = epub::doc::EpubDoc::new(file_path).map_err(|e| anyhow::anyhow!("Failed to open EPUB: {}", e))?;
 
-    let processing_time = start_time.elapsed().as_millis() as u64;
-    
-    let _ = app_clone.emit("document_processed", &doc_id);
+    let mut toc_titles: HashMap<PathBuf, String> = HashMap::new();
+    let mut points: Vec<&epub::doc::NavPoint> = book.toc.iter().collect();
+    let mut next = 0;
+    while next < points.len() {
+        let point = points[next];
+        let target = point.content.to_string_lossy();
+        let target = target.split('#').next().unwrap_or_default();
+        toc_titles.entry(PathBuf::from(target)).or_insert_with(|| point.label.trim().to_string());
+        points.extend(point.children.iter());
+        next += 1;
+    }
 
-    Ok(ProcessingResult {
-        success: true,
-        message: format!("Successfully processed document: {}", document.title),
-        chunks_created,
-        processing_time_ms: processing_time,
-    })
+    let metadata = serde_json::json!({
+        "title": book.mdata("title"),
+        "author": book.mdata("creator"),
+    });
+    let mut text = String::new();
+    let mut sections = Vec::new();
+    let mut chapter = 0;
+    for page in 0..book.get_num_pages() {
+        book.set_current_page(page);
+        let Some((html, _)) = book.get_current_str() else { continue };
+        let parsed = html_to_text(&html);
+        if parsed.text.trim().is_empty() {
+            continue;
+        }
+
+        chapter += 1;
+        let chapter_title = book
+            .get_current_path()
+            .and_then(|path| toc_titles.get(&path).cloned())
+            .or(parsed.title);
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        sections.push(TextSection {
+            start: text.len(),
+            metadata: serde_json::json!({ "chapter": chapter, "chapter_title": chapter_title }),
+        });
+        text.push_str(&parsed.text);
+    }
+
+    Ok(ExtractedDocument { text, metadata, sections })
 }
 
-async fn process_document_chunks_enhanced(
-    document_id: &str,
-    content: &str,
-    db_state: &Arc<Mutex<Connection>>,
-    config: &RAGConfig,
-) -> Result<usize> {
-    let chunks = chunk_text_with_config(content, config);
-    
-    for (index, chunk_content) in chunks.iter().enumerate() {
-        let embedding = generate_embedding_with_config(chunk_content, config).await?;
-        let embedding_bytes: Vec<u8> = embedding.iter()
-            .flat_map(|f| f.to_le_bytes().to_vec())
-            .collect();
+fn read_zip_entry(archive: &mut zip::ZipArchive<std::fs::File>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content).ok()?;
+    Some(content)
+}
 
-        let chunk = DocumentChunk {
-            id: Uuid::new_v4().to_string(),
-            document_id: document_id.to_string(),
-            chunk_index: index as i32,
-            content: chunk_content.clone(),
-            embedding,
-            created_at: Utc::now(),
-        };
+// Text of every <a:p> paragraph in a DrawingML part (slides, notes)
+fn drawingml_paragraphs(xml: &str) -> Vec<String> {
+    use quick_xml::events::Event;
 
-        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
-        db.execute(
-            "INSERT INTO document_chunks (id, document_id, chunk_index, content, embedding, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![
-                chunk.id,
-                chunk.document_id,
-                chunk.chunk_index,
-                chunk.content,
-                embedding_bytes,
-                chunk.created_at.to_rfc3339(),
-            ],
-        )?;
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.local_name().as_ref() == b"t" => in_text = true,
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"t" => in_text = false,
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"br" => current.push(' '),
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"p" => {
+                let paragraph = current.trim();
+                if !paragraph.is_empty() {
+                    paragraphs.push(paragraph.to_string());
+                }
+                current.clear();
+            }
+            Ok(Event::Text(text)) if in_text => {
+                if let Ok(text) = text.unescape() {
+                    current.push_str(&text);
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
     }
+    paragraphs
+}
 
-    Ok(chunks.len())
+// Speaker notes are linked from the slide's relationships rather than numbered alongside it
+fn pptx_notes_part(archive: &mut zip::ZipArchive<std::fs::File>, slide_number: usize) -> Option<String> {
+    let rels = read_zip_entry(archive, &format!("ppt/slides/_rels/slide{}.xml.rels", slide_number))?;
+    let target = rels
+        .split("Target=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .find(|target| target.contains("notesSlides/"))?;
+    Some(format!("ppt/notesSlides/{}", target.rsplit('/').next()?))
 }
 
-#[tauri::command]
-async fn query_rag_enhanced(
-    query: String,
-    mode: RAGMode,
-    config: RAGConfig,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<RAGResponse, String> {
-    let start_time = std::time::Instant::now();
-    
-    let retrieved_context = match mode {
-        RAGMode::FineTunedOnly => {
-            // Don't retrieve context for fine-tuned only mode
-            Vec::new()
+// One section per slide, numbered as shown in PowerPoint, with speaker notes appended
+fn extract_pptx(file_path: &Path) -> Result<ExtractedDocument> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(file_path)?)?;
+    let mut slide_numbers: Vec<usize> = archive
+        .file_names()
+        .filter_map(|name| name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse().ok())
+        .collect();
+    slide_numbers.sort_unstable();
+
+    let mut text = String::new();
+    let mut sections = Vec::new();
+    for slide_number in slide_numbers {
+        let Some(slide_xml) = read_zip_entry(&mut archive, &format!("ppt/slides/slide{}.xml", slide_number)) else {
+            continue;
+        };
+        let paragraphs = drawingml_paragraphs(&slide_xml);
+        // The notes page repeats the slide number as a placeholder; it is not part of the notes
+        let notes: Vec<String> = pptx_notes_part(&mut archive, slide_number)
+            .and_then(|part| read_zip_entry(&mut archive, &part))
+            .map(|xml| drawingml_paragraphs(&xml))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|paragraph| !paragraph.chars().all(|c| c.is_ascii_digit()))
+            .collect();
+        if paragraphs.is_empty() && notes.is_empty() {
+            continue;
         }
-        RAGMode::FineTunedWithRAG | RAGMode::BaseWithRAG => {
-            // Retrieve context for RAG modes
-            retrieve_context_enhanced(&query, &config, db_state).await?
+
+        if !text.is_empty() {
+            text.push_str("\n\n");
         }
-    };
+        sections.push(TextSection {
+            start: text.len(),
+            metadata: serde_json::json!({ "slide": slide_number, "slide_title": paragraphs.first() }),
+        });
+        text.push_str(&format!("Slide {}\n", slide_number));
+        text.push_str(&paragraphs.join("\n"));
+        if !notes.is_empty() {
+            text.push_str("\n\nNotes:\n");
+            text.push_str(&notes.join("\n"));
+        }
+    }
+
+    Ok(ExtractedDocument { text, metadata: serde_json::json!({}), sections })
+}
+
+// Rows become "Header: value" pairs so a chunk read out of context still says what each number means
+fn spreadsheet_row_text(headers: &[String], row: &[calamine::Data]) -> String {
+    row.iter()
+        .enumerate()
+        .filter(|(_, cell)| !matches!(cell, calamine::Data::Empty))
+        .map(|(column, cell)| match headers.get(column).filter(|header| !header.is_empty()) {
+            Some(header) => format!("{}: {}", header, cell),
+            None => cell.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// One section per sheet; the first non-empty row of each sheet is taken as its header
+fn extract_spreadsheet(file_path: &Path, selected_sheets: &[String]) -> Result<ExtractedDocument> {
+    let mut workbook = calamine::open_workbook_auto(file_path)?;
+    let sheet_names = workbook.sheet_names().to_vec();
+    let wanted = |name: &str| selected_sheets.is_empty() || selected_sheets.iter().any(|s| s.trim().eq_ignore_ascii_case(name));
+
+    let mut text = String::new();
+    let mut sections = Vec::new();
+    for sheet in sheet_names.iter().filter(|name| wanted(name.as_str())) {
+        let range = workbook.worksheet_range(sheet)?;
+        let mut rows = range
+            .rows()
+            .filter(|row| row.iter().any(|cell| !matches!(cell, calamine::Data::Empty)));
+        let Some(header_row) = rows.next() else { continue };
+        let headers: Vec<String> = header_row.iter().map(|cell| cell.to_string().trim().to_string()).collect();
+
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        sections.push(TextSection { start: text.len(), metadata: serde_json::json!({ "sheet": sheet }) });
+        text.push_str(&format!("Sheet: {}\n", sheet));
+        text.push_str(&headers.iter().filter(|h| !h.is_empty()).cloned().collect::<Vec<_>>().join(" | "));
+        for row in rows {
+            text.push('\n');
+            text.push_str(&spreadsheet_row_text(&headers, row));
+        }
+    }
+
+    if sections.is_empty() && !selected_sheets.is_empty() {
+        return Err(anyhow::anyhow!(
+            "None of the selected sheets ({}) exist; available: {}",
+            selected_sheets.join(", "),
+            sheet_names.join(", ")
+        ));
+    }
+    Ok(ExtractedDocument { text, metadata: serde_json::json!({ "sheets": sheet_names }), sections })
+}
+
+// Lets the import dialog offer a sheet picker before a workbook is ingested
+#[tauri::command]
+async fn list_spreadsheet_sheets(file_path: String) -> Result<Vec<String>, String> {
+    let path = long_path(Path::new(&file_path));
+    tokio::task::spawn_blocking(move || {
+        calamine::open_workbook_auto(&path)
+            .map(|workbook| workbook.sheet_names().to_vec())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Splits an mbox on its "From " separator lines, undoing >From quoting in bodies
+fn split_mbox(raw: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut current: Vec<u8> = Vec::new();
+    let mut previous_blank = true;
+    for line in raw.split_inclusive(|&b| b == b'\n') {
+        if previous_blank && line.starts_with(b"From ") {
+            if !current.is_empty() {
+                messages.push(std::mem::take(&mut current));
+            }
+        } else if line.starts_with(b">From ") {
+            current.extend_from_slice(&line[1..]);
+        } else {
+            current.extend_from_slice(line);
+        }
+        previous_blank = line.iter().all(|b| b.is_ascii_whitespace());
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+// Attachment formats that can be read from memory; anything else is listed by name only
+fn email_attachment_text(filename: &str, bytes: &[u8]) -> Option<String> {
+    let text = match file_extension(Path::new(filename)).as_str() {
+        "txt" | "md" | "csv" => String::from_utf8_lossy(bytes).to_string(),
+        "html" | "htm" => html_to_text(&String::from_utf8_lossy(bytes)).text,
+        "pdf" => pdf_extract::extract_text_from_mem(bytes).ok()?,
+        _ => return None,
+    };
+    (!text.trim().is_empty()).then_some(text)
+}
+
+// Collects readable body text, preferring text/plain over HTML alternatives
+fn collect_email_parts(part: &ParsedMail, body: &mut String, attachments: &mut Vec<(String, Option<String>)>) {
+    let disposition = part.get_content_disposition();
+    let filename = disposition.params.get("filename").or_else(|| part.ctype.params.get("name")).cloned();
+    if disposition.disposition == DispositionType::Attachment || (filename.is_some() && part.subparts.is_empty()) {
+        let name = filename.unwrap_or_else(|| "attachment".to_string());
+        let text = part.get_body_raw().ok().and_then(|bytes| email_attachment_text(&name, &bytes));
+        attachments.push((name, text));
+        return;
+    }
+
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    if mimetype == "multipart/alternative" {
+        let preferred = part
+            .subparts
+            .iter()
+            .find(|p| p.ctype.mimetype.eq_ignore_ascii_case("text/plain"))
+            .or_else(|| part.subparts.first());
+        if let Some(preferred) = preferred {
+            collect_email_parts(preferred, body, attachments);
+        }
+    } else if mimetype.starts_with("multipart/") {
+        for subpart in &part.subparts {
+            collect_email_parts(subpart, body, attachments);
+        }
+    } else if mimetype == "text/plain" || mimetype == "text/html" {
+        let Ok(content) = part.get_body() else { return };
+        let text = if mimetype == "text/html" { html_to_text(&content).text } else { content };
+        if !body.is_empty() {
+            body.push_str("\n\n");
+        }
+        body.push_str(text.trim());
+    }
+}
+
+// One section per message, plus one per readable attachment; headers become chunk metadata for filtering
+fn extract_email(file_path: &Path) -> Result<ExtractedDocument> {
+    let raw = std::fs::read(file_path)?;
+    let messages = if file_extension(file_path) == "mbox" { split_mbox(&raw) } else { vec![raw] };
+
+    let mut text = String::new();
+    let mut sections = Vec::new();
+    let mut first_headers = serde_json::Value::Null;
+    for (index, bytes) in messages.iter().enumerate() {
+        let mail = match mailparse::parse_mail(bytes) {
+            Ok(mail) => mail,
+            Err(e) => {
+                warn!("Skipping unparseable message {} in {}: {}", index + 1, file_path.display(), e);
+                continue;
+            }
+        };
+        let subject = mail.headers.get_first_value("Subject");
+        let from = mail.headers.get_first_value("From");
+        let to = mail.headers.get_first_value("To");
+        let date = mail
+            .headers
+            .get_first_value("Date")
+            .and_then(|d| mailparse::dateparse(&d).ok())
+            .and_then(|timestamp| DateTime::<Utc>::from_timestamp(timestamp, 0))
+            .map(|d| d.to_rfc3339());
+
+        let mut body = String::new();
+        let mut attachments = Vec::new();
+        collect_email_parts(&mail, &mut body, &mut attachments);
+
+        let headers = serde_json::json!({
+            "email": index + 1,
+            "subject": subject,
+            "from": from,
+            "to": to,
+            "date": date,
+            "attachments": attachments.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>(),
+        });
+        if first_headers.is_null() {
+            first_headers = headers.clone();
+        }
+
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        sections.push(TextSection { start: text.len(), metadata: headers.clone() });
+        for (label, value) in [("Subject", &subject), ("From", &from), ("To", &to), ("Date", &date)] {
+            if let Some(value) = value {
+                text.push_str(&format!("{}: {}\n", label, value));
+            }
+        }
+        text.push('\n');
+        text.push_str(&body);
+
+        for (name, content) in attachments {
+            let Some(content) = content else { continue };
+            let mut metadata = headers.clone();
+            metadata["attachment"] = serde_json::json!(name);
+            text.push_str("\n\n");
+            sections.push(TextSection { start: text.len(), metadata });
+            text.push_str(&format!("Attachment: {}\n", name));
+            text.push_str(content.trim());
+        }
+    }
+
+    let metadata = if messages.len() == 1 { first_headers } else { serde_json::json!({ "messages": messages.len() }) };
+    Ok(ExtractedDocument { text, metadata, sections })
+}
+
+fn json_field<'a>(record: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let pointer: String = path.trim().split('.').map(|part| format!("/{}", part.replace('~', "~0").replace('/', "~1"))).collect();
+    record.pointer(&pointer).filter(|value| !value.is_null())
+}
+
+fn json_value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(items) if items.iter().all(|item| !item.is_object() && !item.is_array()) => {
+            items.iter().map(json_value_text).collect::<Vec<_>>().join(", ")
+        }
+        other => other.to_string(),
+    }
+}
+
+fn json_record_text(record: &serde_json::Value, mapping: &JsonFieldMapping) -> String {
+    if !mapping.content.is_empty() {
+        return mapping
+            .content
+            .iter()
+            .filter_map(|path| json_field(record, path))
+            .map(json_value_text)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+    }
+    match record.as_object() {
+        Some(fields) => fields
+            .iter()
+            .filter(|(key, value)| {
+                !value.is_null() && mapping.title.as_deref() != Some(key.as_str()) && !mapping.metadata.contains(key)
+            })
+            .map(|(key, value)| format!("{}: {}", key, json_value_text(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => json_value_text(record),
+    }
+}
+
+// One section per ATX heading, carrying the heading breadcrumb ("Install > Linux > Troubleshooting").
+// Lines inside fenced code blocks are never treated as headings.
+fn extract_markdown(file_path: &Path) -> Result<ExtractedDocument> {
+    let text = String::from_utf8(std::fs::read(file_path)?)
+        .map_err(|_| IngestError::InvalidEncoding { path: file_path.display().to_string() })?;
+
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let title = trimmed[level..].trim().trim_end_matches('#').trim();
+        if !(1..=6).contains(&level) || !trimmed[level..].starts_with([' ', '\t']) || title.is_empty() {
+            continue;
+        }
+        headings.retain(|(parent, _)| *parent < level);
+        headings.push((level, title.to_string()));
+        let path = headings.iter().map(|(_, title)| title.as_str()).collect::<Vec<_>>().join(" > ");
+        sections.push(TextSection {
+            start: line_start,
+            metadata: serde_json::json!({ "heading_path": path, "heading_level": level }),
+        });
+    }
+
+    Ok(ExtractedDocument { text, metadata: serde_json::json!({}), sections })
+}
+
+// Tree-sitter grammar and the top-level node kinds that start a new chunk for a source language
+struct CodeGrammar {
+    name: &'static str,
+    language: fn() -> tree_sitter::Language,
+    definitions: &'static [&'static str],
+}
+
+fn code_grammar(extension: &str) -> Option<CodeGrammar> {
+    const JS_DEFINITIONS: &[&str] = &[
+        "function_declaration", "generator_function_declaration", "class_declaration", "lexical_declaration",
+        "export_statement", "interface_declaration", "type_alias_declaration", "enum_declaration",
+    ];
+    let (name, language, definitions): (_, fn() -> tree_sitter::Language, &'static [&'static str]) = match extension {
+        "rs" => ("rust", tree_sitter_rust::language, &[
+            "function_item", "impl_item", "struct_item", "enum_item", "trait_item", "mod_item", "macro_definition",
+            "const_item", "static_item", "type_item",
+        ]),
+        "py" => ("python", tree_sitter_python::language, &["function_definition", "class_definition", "decorated_definition"]),
+        "js" | "jsx" | "mjs" => ("javascript", tree_sitter_javascript::language, JS_DEFINITIONS),
+        "ts" => ("typescript", tree_sitter_typescript::language_typescript, JS_DEFINITIONS),
+        "tsx" => ("tsx", tree_sitter_typescript::language_tsx, JS_DEFINITIONS),
+        "go" => ("go", tree_sitter_go::language, &["function_declaration", "method_declaration", "type_declaration"]),
+        _ => return None,
+    };
+    Some(CodeGrammar { name, language, definitions })
+}
+
+// One section per top-level definition, so functions and classes are chunked whole when they fit.
+// Comments and attributes directly above a definition stay with it.
+fn extract_code(file_path: &Path, grammar: &CodeGrammar) -> Result<ExtractedDocument> {
+    let source = String::from_utf8(std::fs::read(file_path)?)
+        .map_err(|_| IngestError::InvalidEncoding { path: file_path.display().to_string() })?;
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&(grammar.language)())?;
+    let tree = parser
+        .parse(&source, None)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse {} source", grammar.name))?;
+
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let mut sections = vec![TextSection { start: 0, metadata: serde_json::json!({ "language": grammar.name }) }];
+    let mut leading_start: Option<usize> = None;
+    for node in root.named_children(&mut cursor) {
+        let kind = node.kind();
+        if kind.contains("comment") || kind == "attribute_item" {
+            leading_start.get_or_insert(node.start_byte());
+            continue;
+        }
+        let start = leading_start.take().unwrap_or(node.start_byte());
+        if !grammar.definitions.contains(&kind) {
+            continue;
+        }
+
+        // Decorated and exported definitions are labelled by the definition they wrap
+        let definition = node
+            .child_by_field_name("definition")
+            .or_else(|| node.child_by_field_name("declaration"))
+            .unwrap_or(node);
+        let signature: String = source[definition.start_byte()..]
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_end_matches('{')
+            .trim_end()
+            .chars()
+            .take(120)
+            .collect();
+        sections.push(TextSection {
+            start: source[..start].rfind('\n').map_or(0, |i| i + 1),
+            metadata: serde_json::json!({
+                "language": grammar.name,
+                "symbol": signature,
+                "kind": kind,
+                "start_line": node.start_position().row + 1,
+                "end_line": node.end_position().row + 1,
+            }),
+        });
+    }
+
+    Ok(ExtractedDocument { text: source, metadata: serde_json::json!({ "language": grammar.name }), sections })
+}
+
+// A top-level array or a JSONL file yields one section per record; any other JSON value is a single record
+fn extract_json_records(file_path: &Path, mapping: &JsonFieldMapping) -> Result<ExtractedDocument> {
+    let raw = std::fs::read_to_string(file_path)?;
+    let mut skipped_lines = 0;
+    let records: Vec<serde_json::Value> = if file_extension(file_path) == "jsonl" {
+        raw.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(_) => {
+                    skipped_lines += 1;
+                    None
+                }
+            })
+            .collect()
+    } else {
+        match serde_json::from_str(&raw)? {
+            serde_json::Value::Array(records) => records,
+            record => vec![record],
+        }
+    };
+
+    let mut text = String::new();
+    let mut sections = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let body = json_record_text(record, mapping);
+        let title = mapping.title.as_deref().and_then(|path| json_field(record, path)).map(json_value_text);
+        if body.trim().is_empty() && title.is_none() {
+            continue;
+        }
+        let fields: serde_json::Map<String, serde_json::Value> = mapping
+            .metadata
+            .iter()
+            .filter_map(|path| Some((path.clone(), json_field(record, path)?.clone())))
+            .collect();
+
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        sections.push(TextSection {
+            start: text.len(),
+            metadata: serde_json::json!({ "record": index + 1, "record_title": title, "fields": fields }),
+        });
+        if let Some(title) = &title {
+            text.push_str(title);
+            text.push('\n');
+        }
+        text.push_str(&body);
+    }
+
+    if skipped_lines > 0 {
+        warn!("Skipped {} invalid line(s) in {}", skipped_lines, file_path.display());
+    }
+    Ok(ExtractedDocument {
+        text,
+        metadata: serde_json::json!({ "records": records.len(), "skipped_lines": skipped_lines }),
+        sections,
+    })
+}
+
+async fn extract_csv_text(file_path: &Path) -> Result<String> {
+    let mut reader = Reader::from_path(file_path)?;
+    let mut text = String::new();
     
-    let answer = generate_answer_with_mode(&query, &retrieved_context, &mode).await;
-    let processing_time = start_time.elapsed().as_millis() as u64;
+    // Add headers if available
+    if let Ok(headers) = reader.headers() {
+        text.push_str(&headers.iter().collect::<Vec<_>>().join(" | "));
+        text.push('\n');
+    }
     
-    Ok(RAGResponse {
-        answer,
-        retrieved_context,
-        mode_used: mode,
-        processing_time_ms: processing_time,
+    // Add data rows
+    for result in reader.records() {
+        let record = result?;
+        text.push_str(&record.iter().collect::<Vec<_>>().join(" | "));
+        text.push('\n');
+    }
+    
+    Ok(text)
+}
+
+pub struct HtmlPage {
+    pub title: Option<String>,
+    pub text: String,
+    pub links: Vec<String>, // raw href values, resolved by the caller
+}
+
+// Elements whose text never belongs in the extracted content
+const HTML_SKIPPED_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "head", "svg"];
+// Elements that start a new line in the extracted text
+const HTML_BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "main", "header", "footer", "nav", "aside", "li", "ul", "ol",
+    "h1", "h2", "h3", "h4", "h5", "h6", "pre", "blockquote", "table", "tr", "br", "hr", "dt", "dd",
+];
+
+fn collect_html_text(element: ElementRef, out: &mut String) {
+    for child in element.children() {
+        if let Some(child_element) = ElementRef::wrap(child) {
+            let name = child_element.value().name();
+            if HTML_SKIPPED_ELEMENTS.contains(&name) {
+                continue;
+            }
+            // Headings become markdown markers so chunking can keep sections together
+            let heading_level = match name.as_bytes() {
+                [b'h', level @ b'1'..=b'6'] => Some((level - b'0') as usize),
+                _ => None,
+            };
+            let block = HTML_BLOCK_ELEMENTS.contains(&name);
+            if let Some(level) = heading_level {
+                out.push_str("\n\n");
+                out.push_str(&"#".repeat(level));
+                out.push(' ');
+            } else if block {
+                out.push('\n');
+            }
+            collect_html_text(child_element, out);
+            if heading_level.is_some() {
+                out.push_str("\n\n");
+            } else if block {
+                out.push('\n');
+            }
+        } else if let Some(text) = child.value().as_text() {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            if !words.is_empty() {
+                if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+                out.push_str(&words.join(" "));
+            }
+        }
+    }
+}
+
+fn html_to_text(html: &str) -> HtmlPage {
+    let document = Html::parse_document(html);
+    let title_selector = Selector::parse("title").expect("valid selector");
+    let link_selector = Selector::parse("a[href]").expect("valid selector");
+
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|title| title.text().collect::<String>().trim().to_string())
+        .filter(|title| !title.is_empty());
+    let links = document
+        .select(&link_selector)
+        .filter_map(|link| link.value().attr("href"))
+        .map(str::to_string)
+        .collect();
+
+    let mut raw = String::new();
+    collect_html_text(document.root_element(), &mut raw);
+    // Collapse the newlines block elements leave behind into paragraph breaks
+    let text = raw
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    HtmlPage { title, text, links }
+}
+
+// An embedding backend. Implementations live for the whole session in managed state,
+// so they can keep HTTP connection pools or loaded model weights between calls.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn supports(&self, model: &EmbeddingModel) -> bool;
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>>;
+
+    // Backends with a batch endpoint override this; the fallback embeds one text at a time
+    async fn embed_batch(&self, texts: &[String], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text, model).await?);
+        }
+        Ok(embeddings)
+    }
+
+    // Key for provider telemetry and concurrency limits
+    fn telemetry_key(&self, _model: &EmbeddingModel) -> String {
+        self.name().to_string()
+    }
+}
+
+#[derive(Default)]
+struct HuggingFaceEmbeddings {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl EmbeddingProvider for HuggingFaceEmbeddings {
+    fn name(&self) -> &str {
+        "huggingface"
+    }
+
+    fn supports(&self, model: &EmbeddingModel) -> bool {
+        matches!(model, EmbeddingModel::HuggingFace { .. })
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let EmbeddingModel::HuggingFace { model_name, api_key } = model else {
+            return Err(anyhow::anyhow!("huggingface provider cannot embed with {:?}", model));
+        };
+        generate_huggingface_embedding(&self.client, text, model_name, api_key.as_deref()).await
+    }
+
+    async fn embed_batch(&self, texts: &[String], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let EmbeddingModel::HuggingFace { model_name, api_key } = model else {
+            return Err(anyhow::anyhow!("huggingface provider cannot embed with {:?}", model));
+        };
+        generate_huggingface_embeddings(&self.client, texts, model_name, api_key.as_deref()).await
+    }
+}
+
+#[derive(Default)]
+struct OpenAIEmbeddings {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAIEmbeddings {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn supports(&self, model: &EmbeddingModel) -> bool {
+        matches!(model, EmbeddingModel::OpenAI { .. })
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let EmbeddingModel::OpenAI { api_key, model, base_url } = model else {
+            return Err(anyhow::anyhow!("openai provider cannot embed with {:?}", model));
+        };
+        generate_openai_embedding(&self.client, base_url.as_deref(), text, api_key, model).await
+    }
+
+    async fn embed_batch(&self, texts: &[String], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let EmbeddingModel::OpenAI { api_key, model, base_url } = model else {
+            return Err(anyhow::anyhow!("openai provider cannot embed with {:?}", model));
+        };
+        generate_openai_embeddings(&self.client, base_url.as_deref(), texts, api_key, model).await
+    }
+}
+
+#[derive(Default)]
+struct LocalEmbeddings {
+    #[cfg(feature = "local-embeddings")]
+    models: Arc<Mutex<HashMap<String, Arc<LocalEmbedder>>>>,
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalEmbeddings {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn supports(&self, model: &EmbeddingModel) -> bool {
+        matches!(model, EmbeddingModel::Local { .. })
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let EmbeddingModel::Local { model_path } = model else {
+            return Err(anyhow::anyhow!("local provider cannot embed with {:?}", model));
+        };
+        #[cfg(feature = "local-embeddings")]
+        return generate_local_embedding(&self.models, text, model_path).await;
+        #[cfg(not(feature = "local-embeddings"))]
+        return generate_local_embedding(text, model_path).await;
+    }
+
+    async fn embed_batch(&self, texts: &[String], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let EmbeddingModel::Local { model_path } = model else {
+            return Err(anyhow::anyhow!("local provider cannot embed with {:?}", model));
+        };
+        #[cfg(feature = "local-embeddings")]
+        return generate_local_embeddings(&self.models, texts, model_path).await;
+        #[cfg(not(feature = "local-embeddings"))]
+        return generate_local_embeddings(texts, model_path).await;
+    }
+}
+
+// Routes EmbeddingModel::Plugin to whichever JSON-RPC provider plugin it names
+struct PluginEmbeddings;
+
+#[async_trait]
+impl EmbeddingProvider for PluginEmbeddings {
+    fn name(&self) -> &str {
+        "plugin"
+    }
+
+    fn supports(&self, model: &EmbeddingModel) -> bool {
+        matches!(model, EmbeddingModel::Plugin { .. })
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let EmbeddingModel::Plugin { provider, model } = model else {
+            return Err(anyhow::anyhow!("plugin provider cannot embed with {:?}", model));
+        };
+        plugin_embed(provider, model, text).await
+    }
+
+    fn telemetry_key(&self, model: &EmbeddingModel) -> String {
+        match model {
+            EmbeddingModel::Plugin { provider, .. } => provider.clone(),
+            _ => self.name().to_string(),
+        }
+    }
+}
+
+pub struct EmbeddingProviders {
+    providers: RwLock<Vec<Arc<dyn EmbeddingProvider>>>,
+    cache: Option<Arc<DbPool>>, // embedding_cache lookups; None skips caching
+}
+
+impl EmbeddingProviders {
+    fn with_builtin() -> Self {
+        let registry = Self { providers: RwLock::new(Vec::new()), cache: None };
+        registry.register(Arc::new(HuggingFaceEmbeddings::default()));
+        registry.register(Arc::new(OpenAIEmbeddings::default()));
+        registry.register(Arc::new(LocalEmbeddings::default()));
+        registry.register(Arc::new(PluginEmbeddings));
+        registry
+    }
+
+    fn with_cache(mut self, db: Arc<DbPool>) -> Self {
+        self.cache = Some(db);
+        self
+    }
+
+    fn clear_cache(&self) -> Result<usize> {
+        let Some(cache) = &self.cache else { return Ok(0) };
+        let db = cache.lock()?;
+        Ok(db.execute("DELETE FROM embedding_cache", [])?)
+    }
+
+    // Later registrations win, so a backend can be replaced without touching the built-ins
+    pub fn register(&self, provider: Arc<dyn EmbeddingProvider>) {
+        if let Ok(mut providers) = self.providers.write() {
+            providers.insert(0, provider);
+        }
+    }
+
+    fn resolve(&self, model: &EmbeddingModel) -> Result<Arc<dyn EmbeddingProvider>> {
+        let providers = self.providers.read().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        providers
+            .iter()
+            .find(|p| p.supports(model))
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No embedding provider registered for {}", embedding_model_key(model)))
+    }
+
+    // Retries, concurrency limits and telemetry are shared by every backend via call_provider
+    async fn embed(&self, text: &str, config: &RAGConfig) -> Result<Vec<f32>> {
+        let model_key = embedding_model_key(&config.embedding_model);
+        if let Some(cached) = self.cached_embeddings(&[text.to_string()], &model_key).pop().flatten() {
+            return Ok(cached);
+        }
+
+        let provider = self.resolve(&config.embedding_model)?;
+        let key = provider.telemetry_key(&config.embedding_model);
+        let embedding = call_provider(&key, || provider.embed(text, &config.embedding_model)).await?;
+        self.store_cached_embeddings(&[text.to_string()], std::slice::from_ref(&embedding), &model_key);
+        Ok(embedding)
+    }
+
+    // Cached texts are answered from embedding_cache; output order matches input order
+    async fn embed_batch(&self, texts: &[String], config: &RAGConfig) -> Result<Vec<Vec<f32>>> {
+        let model_key = embedding_model_key(&config.embedding_model);
+        let mut embeddings = self.cached_embeddings(texts, &model_key);
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| embeddings[i].is_none()).collect();
+        if missing.is_empty() {
+            return Ok(embeddings.into_iter().flatten().collect());
+        }
+
+        let missing_texts: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+        let fresh = self.embed_uncached(&missing_texts, config).await?;
+        self.store_cached_embeddings(&missing_texts, &fresh, &model_key);
+        for (index, embedding) in missing.into_iter().zip(fresh) {
+            embeddings[index] = Some(embedding);
+        }
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    // Skips the cache lookup but refreshes it, for re-embedding that must not trust earlier vectors
+    async fn embed_batch_fresh(&self, texts: &[String], config: &RAGConfig) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self.embed_uncached(texts, config).await?;
+        self.store_cached_embeddings(texts, &embeddings, &embedding_model_key(&config.embedding_model));
+        Ok(embeddings)
+    }
+
+    // Splits texts into config.embedding_batch_size requests
+    async fn embed_uncached(&self, texts: &[String], config: &RAGConfig) -> Result<Vec<Vec<f32>>> {
+        let provider = self.resolve(&config.embedding_model)?;
+        let key = provider.telemetry_key(&config.embedding_model);
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(config.embedding_batch_size.max(1)) {
+            let batch_embeddings = call_provider(&key, || provider.embed_batch(batch, &config.embedding_model)).await?;
+            if batch_embeddings.len() != batch.len() {
+                return Err(anyhow::anyhow!(
+                    "{} returned {} embeddings for {} texts",
+                    key,
+                    batch_embeddings.len(),
+                    batch.len()
+                ));
+            }
+            embeddings.extend(batch_embeddings);
+        }
+        Ok(embeddings)
+    }
+
+    // A cache miss or an unreadable cache just means the text gets embedded again
+    fn cached_embeddings(&self, texts: &[String], model_key: &str) -> Vec<Option<Vec<f32>>> {
+        let Some(db) = self.cache.as_ref().and_then(|db| db.lock().ok()) else {
+            return vec![None; texts.len()];
+        };
+        texts
+            .iter()
+            .map(|text| {
+                db.query_row(
+                    "SELECT embedding FROM embedding_cache WHERE content_hash = ?1 AND model = ?2",
+                    params![calculate_content_hash(text), model_key],
+                    |row| row.get::<_, Vec<u8>>(0),
+                )
+                .ok()
+                .map(|bytes| decode_embedding(&bytes))
+            })
+            .collect()
+    }
+
+    fn store_cached_embeddings(&self, texts: &[String], embeddings: &[Vec<f32>], model_key: &str) {
+        let Some(db) = self.cache.as_ref().and_then(|db| db.lock().ok()) else {
+            return;
+        };
+        let now = Utc::now().to_rfc3339();
+        for (text, embedding) in texts.iter().zip(embeddings) {
+            let embedding_bytes = encode_embedding(embedding, EmbeddingStorage::Float32);
+            if let Err(e) = db.execute(
+                "INSERT OR REPLACE INTO embedding_cache (content_hash, model, embedding, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![calculate_content_hash(text), model_key, embedding_bytes, now],
+            ) {
+                warn!("Failed to cache embedding: {}", e);
+            }
+        }
+    }
+}
+
+// Cached vectors are only reused for the exact same text and model, so clearing is never required for correctness.
+// The cache lives in the registry's own local pool, which differs from the knowledge base when a shared copy is open.
+#[tauri::command]
+async fn clear_embedding_cache(providers_state: tauri::State<'_, Arc<EmbeddingProviders>>) -> Result<usize, String> {
+    providers_state.clear_cache().map_err(|e| e.to_string())
+}
+
+const HUGGINGFACE_INFERENCE_URL: &str = "https://api-inference.huggingface.co/pipeline/feature-extraction";
+
+// Retries and backoff come from call_provider; this only has to turn one request into a vector
+async fn generate_huggingface_embedding(
+    client: &reqwest::Client,
+    text: &str,
+    model_name: &str,
+    api_key: Option<&str>,
+) -> Result<Vec<f32>> {
+    let mut embeddings = generate_huggingface_embeddings(client, &[text.to_string()], model_name, api_key).await?;
+    embeddings.pop().ok_or_else(|| anyhow::anyhow!("HuggingFace returned no embedding for model {}", model_name))
+}
+
+// One request for the whole batch; the feature-extraction pipeline answers with one entry per input
+async fn generate_huggingface_embeddings(
+    client: &reqwest::Client,
+    texts: &[String],
+    model_name: &str,
+    api_key: Option<&str>,
+) -> Result<Vec<Vec<f32>>> {
+    let request_body = serde_json::json!({
+        "inputs": texts,
+        "options": { "wait_for_model": true }
+    });
+
+    let mut request = client
+        .post(format!("{}/{}", HUGGINGFACE_INFERENCE_URL, model_name))
+        .header("Content-Type", "application/json")
+        .json(&request_body);
+    if let Some(token) = api_key.filter(|t| !t.is_empty()) {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let detail = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(|e| e.to_string()))
+            .unwrap_or(body);
+        return match status.as_u16() {
+            401 | 403 => Err(anyhow::anyhow!(
+                "HuggingFace rejected the access token for {} ({}): {}", model_name, status, detail
+            )),
+            404 => Err(anyhow::anyhow!("HuggingFace model not found: {}", model_name)),
+            _ => Err(anyhow::anyhow!("HuggingFace API error ({}) for {}: {}", status, model_name, detail)),
+        };
+    }
+
+    let response_json: serde_json::Value = response.json().await?;
+    let entries = response_json.as_array().cloned().unwrap_or_default();
+    if entries.len() != texts.len() {
+        return Err(anyhow::anyhow!(
+            "HuggingFace returned {} embeddings for {} inputs with model {}",
+            entries.len(),
+            texts.len(),
+            model_name
+        ));
+    }
+
+    entries
+        .iter()
+        .map(|entry| {
+            let embedding = pool_huggingface_output(entry);
+            if embedding.is_empty() {
+                return Err(anyhow::anyhow!("HuggingFace returned no embedding for model {}", model_name));
+            }
+            Ok(normalize_vector(embedding))
+        })
+        .collect()
+}
+
+// Sentence-transformer models return one pooled vector; raw encoders return one per token
+fn pool_huggingface_output(entry: &serde_json::Value) -> Vec<f32> {
+    match entry.as_array() {
+        Some(values) if values.first().map_or(false, |v| v.is_number()) => values
+            .iter()
+            .filter_map(|v| v.as_f64().map(|f| f as f32))
+            .collect::<Vec<f32>>(),
+        Some(rows) => {
+            // Unwrap a nested batch dimension if present, then mean-pool the token vectors
+            let rows = match rows.first().and_then(|r| r.as_array()) {
+                Some(inner) if inner.first().map_or(false, |v| v.is_array()) => inner.clone(),
+                _ => rows.clone(),
+            };
+            let token_vectors: Vec<Vec<f32>> = rows
+                .iter()
+                .filter_map(|row| row.as_array())
+                .map(|row| row.iter().filter_map(|v| v.as_f64().map(|f| f as f32)).collect())
+                .collect();
+            let dimensions = token_vectors.first().map_or(0, |v| v.len());
+            let mut pooled = vec![0.0f32; dimensions];
+            for vector in &token_vectors {
+                for (sum, value) in pooled.iter_mut().zip(vector) {
+                    *sum += value;
+                }
+            }
+            pooled.iter_mut().for_each(|v| *v /= token_vectors.len().max(1) as f32);
+            pooled
+        }
+        None => Vec::new(),
+    }
+}
+
+// Self-hosted OpenAI-compatible servers often run without a key, so the header is optional
+fn openai_embeddings_request(
+    client: &reqwest::Client,
+    base_url: Option<&str>,
+    api_key: &str,
+) -> reqwest::RequestBuilder {
+    let url = format!("{}/embeddings", base_url.unwrap_or(OPENAI_API_BASE).trim_end_matches('/'));
+    let request = client.post(url).header("Content-Type", "application/json");
+    if api_key.is_empty() {
+        request
+    } else {
+        request.header("Authorization", format!("Bearer {}", api_key))
+    }
+}
+
+async fn generate_openai_embedding(
+    client: &reqwest::Client,
+    base_url: Option<&str>,
+    text: &str,
+    api_key: &str,
+    model: &str,
+) -> Result<Vec<f32>> {
+    let request_body = serde_json::json!({
+        "input": text,
+        "model": model
+    });
+    
+    let response = openai_embeddings_request(client, base_url, api_key)
+        .json(&request_body)
+        .send()
+        .await?;
+    
+    if response.status().is_success() {
+        let response_json: serde_json::Value = response.json().await?;
+        if let Some(data) = response_json["data"].as_array() {
+            if let Some(embedding_data) = data.get(0) {
+                if let Some(embedding_array) = embedding_data["embedding"].as_array() {
+                    let embedding: Vec<f32> = embedding_array
+                        .iter()
+                        .filter_map(|v| v.as_f64().map(|f| f as f32))
+                        .collect();
+                    return Ok(embedding);
+                }
+            }
+        }
+    }
+    
+    Err(anyhow::anyhow!("Failed to get embedding from OpenAI API"))
+}
+
+async fn generate_openai_embeddings(
+    client: &reqwest::Client,
+    base_url: Option<&str>,
+    texts: &[String],
+    api_key: &str,
+    model: &str,
+) -> Result<Vec<Vec<f32>>> {
+    let request_body = serde_json::json!({
+        "input": texts,
+        "model": model
+    });
+
+    let response = openai_embeddings_request(client, base_url, api_key)
+        .json(&request_body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Failed to get embeddings from OpenAI API ({})", response.status()));
+    }
+
+    // Entries carry their input index and are not guaranteed to come back in order
+    let response_json: serde_json::Value = response.json().await?;
+    let mut embeddings: Vec<Option<Vec<f32>>> = vec![None; texts.len()];
+    for entry in response_json["data"].as_array().into_iter().flatten() {
+        let index = entry["index"].as_u64().unwrap_or(u64::MAX) as usize;
+        if let (Some(slot), Some(values)) = (embeddings.get_mut(index), entry["embedding"].as_array()) {
+            *slot = Some(values.iter().filter_map(|v| v.as_f64().map(|f| f as f32)).collect());
+        }
+    }
+
+    embeddings
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow::anyhow!("OpenAI response is missing embeddings for some inputs"))
+}
+
+#[cfg(feature = "local-embeddings")]
+const LOCAL_EMBEDDING_MAX_TOKENS: usize = 512;
+
+#[cfg(feature = "local-embeddings")]
+struct LocalEmbedder {
+    model: candle_transformers::models::bert::BertModel,
+    tokenizer: Tokenizer,
+}
+
+#[cfg(feature = "local-embeddings")]
+impl LocalEmbedder {
+    // model_path is either a folder with config.json, tokenizer.json and model.safetensors,
+    // or a HuggingFace repo id that is downloaded once into the hub cache
+    fn load(model_path: &str) -> Result<Self> {
+        use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+
+        let local_dir = Path::new(model_path);
+        let (config_path, tokenizer_path, weights_path) = if local_dir.is_dir() {
+            (
+                local_dir.join("config.json"),
+                local_dir.join("tokenizer.json"),
+                local_dir.join("model.safetensors"),
+            )
+        } else {
+            let repo = hf_hub::api::sync::Api::new()?.model(model_path.to_string());
+            (repo.get("config.json")?, repo.get("tokenizer.json")?, repo.get("model.safetensors")?)
+        };
+
+        let config: Config = serde_json::from_str(&std::fs::read_to_string(config_path)?)?;
+        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let weights = unsafe {
+            candle_nn::VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, compute_device())?
+        };
+        let model = BertModel::load(weights, &config)?;
+
+        Ok(Self { model, tokenizer })
+    }
+
+    // Mean-pools the last hidden state over all tokens, as sentence-transformers models expect
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let encoding = self.tokenizer.encode(text, true).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let ids = &encoding.get_ids()[..encoding.get_ids().len().min(LOCAL_EMBEDDING_MAX_TOKENS)];
+
+        let token_ids = candle_core::Tensor::new(ids, compute_device())?.unsqueeze(0)?;
+        let token_type_ids = token_ids.zeros_like()?;
+        let hidden = self.model.forward(&token_ids, &token_type_ids, None)?;
+
+        let token_count = hidden.dim(1)? as f64;
+        let pooled = (hidden.sum(1)? / token_count)?.squeeze(0)?;
+        Ok(normalize_vector(pooled.to_vec1::<f32>()?))
+    }
+
+    // Pads the batch to its longest sequence and masks the padding out of the mean
+    fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let device = compute_device();
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let width = encodings
+            .iter()
+            .map(|e| e.get_ids().len().min(LOCAL_EMBEDDING_MAX_TOKENS))
+            .max()
+            .unwrap_or(0);
+        if width == 0 {
+            return Ok(texts.iter().map(|_| Vec::new()).collect());
+        }
+
+        let mut ids = Vec::with_capacity(texts.len() * width);
+        let mut mask = Vec::with_capacity(texts.len() * width);
+        for encoding in &encodings {
+            let tokens = &encoding.get_ids()[..encoding.get_ids().len().min(width)];
+            ids.extend_from_slice(tokens);
+            ids.extend(std::iter::repeat(0u32).take(width - tokens.len()));
+            mask.extend(std::iter::repeat(1u32).take(tokens.len()));
+            mask.extend(std::iter::repeat(0u32).take(width - tokens.len()));
+        }
+
+        let token_ids = candle_core::Tensor::from_vec(ids, (texts.len(), width), device)?;
+        let attention_mask = candle_core::Tensor::from_vec(mask, (texts.len(), width), device)?;
+        let token_type_ids = token_ids.zeros_like()?;
+        let hidden = self.model.forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+
+        let weights = attention_mask.to_dtype(hidden.dtype())?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&weights)?.sum(1)?;
+        let counts = weights.sum(1)?;
+        let pooled = summed.broadcast_div(&counts)?;
+        Ok(pooled.to_vec2::<f32>()?.into_iter().map(normalize_vector).collect())
+    }
+}
+
+// Loaded models are keyed by path, so each is read from disk once per session
+#[cfg(feature = "local-embeddings")]
+fn local_embedder(cache: &Mutex<HashMap<String, Arc<LocalEmbedder>>>, model_path: &str) -> Result<Arc<LocalEmbedder>> {
+    if let Some(embedder) = cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.get(model_path) {
+        return Ok(embedder.clone());
+    }
+
+    info!("Loading local embedding model {} on {}", model_path, compute_device_name());
+    let embedder = Arc::new(LocalEmbedder::load(model_path)?);
+    cache
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?
+        .insert(model_path.to_string(), embedder.clone());
+    Ok(embedder)
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn generate_local_embedding(
+    models: &Arc<Mutex<HashMap<String, Arc<LocalEmbedder>>>>,
+    text: &str,
+    model_path: &str,
+) -> Result<Vec<f32>> {
+    let models = models.clone();
+    let text = text.to_string();
+    let model_path = model_path.to_string();
+
+    // Model loading and inference are CPU/GPU bound, so keep them off the async workers
+    tokio::task::spawn_blocking(move || local_embedder(&models, &model_path)?.embed(&text)).await?
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn generate_local_embeddings(
+    models: &Arc<Mutex<HashMap<String, Arc<LocalEmbedder>>>>,
+    texts: &[String],
+    model_path: &str,
+) -> Result<Vec<Vec<f32>>> {
+    let models = models.clone();
+    let texts = texts.to_vec();
+    let model_path = model_path.to_string();
+
+    tokio::task::spawn_blocking(move || local_embedder(&models, &model_path)?.embed_batch(&texts)).await?
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+async fn generate_local_embedding(_text: &str, model_path: &str) -> Result<Vec<f32>> {
+    Err(anyhow::anyhow!(
+        "Local embedding model {} needs a build with the local-embeddings feature",
+        model_path
+    ))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+async fn generate_local_embeddings(_texts: &[String], model_path: &str) -> Result<Vec<Vec<f32>>> {
+    generate_local_embedding("", model_path).await.map(|embedding| vec![embedding])
+}
+
+const INT8_EMBEDDING_MAGIC: &[u8; 4] = b"EQ8\0";
+
+// Float32 vectors are raw little-endian floats; int8 ones are the magic, an f32 scale, then one byte per dimension
+fn encode_embedding(embedding: &[f32], storage: EmbeddingStorage) -> Vec<u8> {
+    match storage {
+        EmbeddingStorage::Float32 => embedding.iter().flat_map(|f| f.to_le_bytes()).collect(),
+        EmbeddingStorage::Int8 => {
+            let max_abs = embedding.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+            let scale = if max_abs > 0.0 { max_abs / 127.0 } else { 1.0 };
+            let mut bytes = Vec::with_capacity(8 + embedding.len());
+            bytes.extend_from_slice(INT8_EMBEDDING_MAGIC);
+            bytes.extend_from_slice(&scale.to_le_bytes());
+            bytes.extend(embedding.iter().map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8 as u8));
+            bytes
+        }
+    }
+}
+
+fn is_int8_embedding(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && &bytes[..4] == INT8_EMBEDDING_MAGIC
+}
+
+// Int8 vectors are dequantized here, so similarity code only ever sees f32
+fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    let mut embedding = Vec::with_capacity(embedding_dimension(bytes));
+    decode_embedding_into(bytes, &mut embedding);
+    embedding
+}
+
+// Decodes into a caller-owned buffer so scans can reuse one allocation for every row
+fn decode_embedding_into(bytes: &[u8], out: &mut Vec<f32>) {
+    out.clear();
+    if is_int8_embedding(bytes) {
+        let scale = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        out.extend(bytes[8..].iter().map(|&b| b as i8 as f32 * scale));
+        return;
+    }
+    out.extend(
+        bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+    );
+}
+
+fn embedding_dimension(bytes: &[u8]) -> usize {
+    if is_int8_embedding(bytes) {
+        bytes.len() - 8
+    } else {
+        bytes.len() / 4
+    }
+}
+
+fn normalize_vector(mut vector: Vec<f32>) -> Vec<f32> {
+    let magnitude: f32 = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if magnitude > 0.0 {
+        for value in &mut vector {
+            *value /= magnitude;
+        }
+    }
+    vector
+}
+
+// Backward compatibility function; callers are already async, so the provider call is awaited in place
+async fn generate_embedding(providers: &EmbeddingProviders, text: &str, config: &RAGConfig) -> Result<Vec<f32>> {
+    providers.embed(text, config).await
+}
+
+// Documents holding chunks from another embedding space, computed once per chunk change head
+struct EmbeddingSpaceCheck {
+    database: String,
+    head: i64,
+    model_key: String,
+    dimension: usize,
+    mismatched: Vec<(String, Option<String>, Option<i64>, i64)>, // document, model, dimension, chunk count
+}
+
+static EMBEDDING_SPACE_CACHE: OnceLock<Mutex<Option<EmbeddingSpaceCheck>>> = OnceLock::new();
+
+// Scores across vector spaces are meaningless, so any searchable chunk from another model or size is an error.
+// Chunks without a recorded model (e.g. from sync) count as another model. The chunk scan is cached until the
+// chunk change log moves; only the searchable state of the few mismatched documents is checked per query.
+fn check_embedding_compatibility(conn: &Connection, model_key: &str, dimension: usize) -> Result<()> {
+    let database = conn.path().unwrap_or_default().to_string();
+    let head = chunk_change_head(conn);
+    let cache = EMBEDDING_SPACE_CACHE.get_or_init(|| Mutex::new(None));
+    let mut cached = cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let fresh = cached.as_ref().is_some_and(|c| {
+        c.database == database && c.head == head && c.model_key == model_key && c.dimension == dimension
+    });
+    if !fresh {
+        let mut stmt = conn.prepare(
+            "SELECT document_id, embedding_model, embedding_dim, COUNT(*) FROM document_chunks
+             WHERE embedding_model IS NULL OR embedding_model != ?1 OR embedding_dim != ?2
+             GROUP BY document_id, embedding_model, embedding_dim",
+        )?;
+        let rows = stmt.query_map(params![model_key, dimension as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        *cached = Some(EmbeddingSpaceCheck {
+            database,
+            head,
+            model_key: model_key.to_string(),
+            dimension,
+            mismatched: rows.filter_map(Result::ok).collect(),
+        });
+    }
+
+    let Some(check) = cached.as_ref() else { return Ok(()) };
+    let mut searchable = conn.prepare("SELECT 1 FROM documents WHERE id = ?1 AND is_searchable = 1 AND deleted_at IS NULL")?;
+    let mut mismatch: Option<(&Option<String>, &Option<i64>)> = None;
+    let mut count = 0;
+    for (document_id, model, dim, chunks) in &check.mismatched {
+        if searchable.exists(params![document_id])? {
+            // Reported per embedding space, like the first one found
+            if *mismatch.get_or_insert((model, dim)) == (model, dim) {
+                count += chunks;
+            }
+        }
+    }
+    if let Some((model, dim)) = mismatch {
+        return Err(anyhow::anyhow!(
+            "{} chunk(s) were embedded with {} ({} dimensions) but queries use {} ({} dimensions); \
+             re-embed the knowledge base before searching",
+            count,
+            model.as_deref().unwrap_or("an unknown model"),
+            dim.map_or_else(|| "unknown".to_string(), |d| d.to_string()),
+            model_key,
+            dimension
+        ));
+    }
+    Ok(())
+}
+
+// Higher is always more similar; euclidean distance is mapped into (0, 1]
+fn compute_similarity(metric: SimilarityMetric, a: &[f32], b: &[f32]) -> f32 {
+    match metric {
+        SimilarityMetric::Cosine => cosine_similarity(a, b),
+        SimilarityMetric::Dot => a.iter().zip(b.iter()).map(|(x, y)| x * y).sum(),
+        SimilarityMetric::Euclidean => {
+            let distance: f32 = a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt();
+            1.0 / (1.0 + distance)
+        }
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot_product / (norm_a * norm_b)
+    }
+}
+
+// ---------- Database Functions -------------------------------------------------
+
+const DOCUMENT_COLUMNS: &str =
+    "d.id, d.title, d.content, d.file_path, d.file_type, d.content_hash, d.is_searchable, d.is_sensitive, d.metadata, d.created_at, d.updated_at,
+     (SELECT json_group_array(t.name) FROM document_tags dt JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = d.id)";
+
+// Maps the columns listed in DOCUMENT_COLUMNS, starting at `offset`, into a Document
+fn document_from_row(row: &rusqlite::Row, offset: usize) -> rusqlite::Result<Document> {
+    Ok(Document {
+        id: row.get(offset)?,
+        title: row.get(offset + 1)?,
+        content: row.get(offset + 2)?,
+        file_path: row.get(offset + 3)?,
+        file_type: row.get(offset + 4)?,
+        content_hash: row.get(offset + 5)?,
+        is_searchable: row.get(offset + 6)?,
+        is_sensitive: row.get(offset + 7)?,
+        metadata: row
+            .get::<_, Option<String>>(offset + 8)?
+            .and_then(|m| serde_json::from_str(&m).ok())
+            .unwrap_or_else(|| serde_json::json!({})),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(offset + 9)?)
+            .unwrap()
+            .with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(offset + 10)?)
+            .unwrap()
+            .with_timezone(&Utc),
+        tags: row
+            .get::<_, Option<String>>(offset + 11)?
+            .and_then(|t| serde_json::from_str(&t).ok())
+            .unwrap_or_default(),
+    })
+}
+
+// Chunk content hashes let re-indexing reuse embeddings for unchanged chunks. With an
+// embedding key the vector is stored once in shared_embeddings and the chunk row only references it.
+fn insert_chunk(
+    conn: &Connection,
+    chunk: &DocumentChunk,
+    embedding_bytes: &[u8],
+    embedding_key: Option<&str>,
+    embedding_model: &str,
+) -> rusqlite::Result<usize> {
+    // Cached statements are prepared once per connection rather than per chunk
+    if let Some(key) = embedding_key {
+        conn.prepare_cached("INSERT OR IGNORE INTO shared_embeddings (key, embedding, created_at) VALUES (?1, ?2, ?3)")?
+            .execute(params![key, embedding_bytes, Utc::now().to_rfc3339()])?;
+    }
+
+    conn.prepare_cached(
+        "INSERT INTO document_chunks (id, document_id, chunk_index, content, content_hash, embedding, embedding_key,
+                                      embedding_model, embedding_dim, metadata, parent_chunk_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+    )?
+    .execute(params![
+            chunk.id,
+            chunk.document_id,
+            chunk.chunk_index,
+            chunk.content,
+            calculate_content_hash(&chunk.content),
+            if embedding_key.is_some() { &[][..] } else { embedding_bytes },
+            embedding_key,
+            embedding_model,
+            embedding_dimension(embedding_bytes) as i64,
+            (!chunk.metadata.is_null()).then(|| chunk.metadata.to_string()),
+            chunk.parent_chunk_id,
+            chunk.created_at.to_rfc3339(),
+        ])
+}
+
+// Sections are kept with the document so re-chunking from stored text keeps chunk metadata
+fn store_document_sections(conn: &Connection, document_id: &str, sections: &[TextSection]) -> rusqlite::Result<usize> {
+    let sections = (!sections.is_empty()).then(|| serde_json::to_string(sections).unwrap_or_default());
+    conn.execute("UPDATE documents SET sections = ?1 WHERE id = ?2", params![sections, document_id])
+}
+
+// Where a chunk sits inside its document, for citations like "book.epub (Chapter 3: Storms)"
+fn section_label(metadata: &serde_json::Value) -> Option<String> {
+    if let Some(path) = metadata.get("heading_path").and_then(|p| p.as_str()) {
+        return Some(path.to_string());
+    }
+    if let Some(page) = metadata.get("page_number").and_then(|p| p.as_u64()) {
+        return Some(format!("Page {}", page));
+    }
+    if let Some(symbol) = metadata.get("symbol").and_then(|s| s.as_str()) {
+        let line = metadata.get("start_line").and_then(|l| l.as_u64()).unwrap_or(1);
+        return Some(format!("{} (line {})", symbol, line));
+    }
+    if metadata.get("email").is_some() {
+        let subject = metadata.get("subject").and_then(|s| s.as_str()).unwrap_or("(no subject)");
+        return Some(match metadata.get("attachment").and_then(|a| a.as_str()) {
+            Some(attachment) => format!("Email: {} ({})", subject, attachment),
+            None => format!("Email: {}", subject),
+        });
+    }
+    if let Some(slide) = metadata.get("slide").and_then(|s| s.as_u64()) {
+        return Some(format!("Slide {}", slide));
+    }
+    if let Some(sheet) = metadata.get("sheet").and_then(|s| s.as_str()) {
+        return Some(format!("Sheet {}", sheet));
+    }
+    if let Some(record) = metadata.get("record").and_then(|r| r.as_u64()) {
+        return Some(match metadata.get("record_title").and_then(|t| t.as_str()) {
+            Some(title) => format!("Record {}: {}", record, title),
+            None => format!("Record {}", record),
+        });
+    }
+    let chapter_title = metadata.get("chapter_title").and_then(|t| t.as_str());
+    match (metadata.get("chapter").and_then(|c| c.as_u64()), chapter_title) {
+        (Some(chapter), Some(title)) => Some(format!("Chapter {}: {}", chapter, title)),
+        (Some(chapter), None) => Some(format!("Chapter {}", chapter)),
+        (None, Some(title)) => Some(title.to_string()),
+        (None, None) => None,
+    }
+}
+
+fn chunk_source_info(file_path: Option<String>, chunk_metadata: Option<String>) -> String {
+    let source = file_path.unwrap_or_else(|| "Unknown source".to_string());
+    let label = chunk_metadata
+        .and_then(|metadata| serde_json::from_str::<serde_json::Value>(&metadata).ok())
+        .and_then(|metadata| section_label(&metadata));
+    match label {
+        Some(label) => format!("{} ({})", source, label),
+        None => source,
+    }
+}
+
+fn load_document_sections(conn: &Connection, document_id: &str) -> Vec<TextSection> {
+    conn.query_row("SELECT sections FROM documents WHERE id = ?1", params![document_id], |row| {
+        row.get::<_, Option<String>>(0)
+    })
+    .ok()
+    .flatten()
+    .and_then(|sections| serde_json::from_str(&sections).ok())
+    .unwrap_or_default()
+}
+
+// Identifies the vector space a chunk was embedded into, without any credentials
+fn embedding_model_key(model: &EmbeddingModel) -> String {
+    match model {
+        EmbeddingModel::HuggingFace { model_name, .. } => format!("huggingface:{}", model_name),
+        EmbeddingModel::OpenAI { model, base_url: None, .. } => format!("openai:{}", model),
+        EmbeddingModel::OpenAI { model, base_url: Some(base_url), .. } => {
+            format!("openai:{}/{}", base_url.trim_end_matches('/'), model)
+        }
+        EmbeddingModel::Local { model_path } => format!("local:{}", model_path),
+        EmbeddingModel::Plugin { provider, model } => format!("plugin:{}/{}", provider, model),
+    }
+}
+
+fn shared_embedding_key(model: &EmbeddingModel, chunk_content: &str) -> String {
+    calculate_content_hash(&format!("{}\n{}", embedding_model_key(model), chunk_content))
+}
+
+fn find_shared_embedding(conn: &Connection, key: &str) -> Option<Vec<u8>> {
+    conn.query_row(
+        "SELECT embedding FROM shared_embeddings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+// Most recent live document with this content hash; sensitive content is withheld from the copy returned
+fn find_duplicate_document(conn: &Connection, content_hash: &str) -> Option<Document> {
+    let mut document = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM documents d WHERE d.content_hash = ?1 AND d.deleted_at IS NULL
+                 ORDER BY d.created_at DESC LIMIT 1",
+                DOCUMENT_COLUMNS
+            ),
+            params![content_hash],
+            |row| document_from_row(row, 0),
+        )
+        .ok()?;
+    if document.is_sensitive {
+        document.content.clear();
+    }
+    Some(document)
+}
+
+fn insert_document(conn: &Connection, document: &Document) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO documents (id, title, content, file_path, file_type, content_hash, is_searchable, is_sensitive, metadata, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            document.id,
+            document.title,
+            document.content,
+            document.file_path,
+            document.file_type,
+            document.content_hash,
+            document.is_searchable,
+            document.is_sensitive,
+            document.metadata.to_string(),
+            document.created_at.to_rfc3339(),
+            document.updated_at.to_rfc3339(),
+        ],
+    )
+}
+
+const DB_BUSY_TIMEOUT_SECS: u64 = 5;
+const DB_CACHE_SIZE_KIB: i64 = 20_000;
+const DB_POOL_SIZE: u32 = 8;
+const DB_CHECKOUT_TIMEOUT_SECS: u64 = 30;
+
+type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+// Only for the local database: pooled connections write concurrently with each other and with the embedding
+// cache. WAL lets readers run alongside a writer, and the busy timeout makes a second writer wait instead of
+// failing with "database is locked". Write transactions begin IMMEDIATE: a deferred one that upgrades from read
+// to write gets SQLITE_BUSY straight away, without the busy handler ever running.
+// WAL is recorded in the file header and its index lives in shared memory, which readers on other machines
+// can't map; copies that leave this machine are switched back to the rollback journal (see backup_to_file).
+fn configure_connection(conn: &Connection) -> rusqlite::Result<()> {
+    conn.busy_timeout(Duration::from_secs(DB_BUSY_TIMEOUT_SECS))?;
+    let journal_mode: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+    if !journal_mode.eq_ignore_ascii_case("wal") {
+        // SQLite reports the mode it kept, e.g. while another process holds the file in rollback mode
+        warn!("Database stays in {} journal mode", journal_mode);
+    }
+    // NORMAL only risks the last transactions on power loss in WAL mode, never corruption
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.pragma_update(None, "cache_size", -DB_CACHE_SIZE_KIB)?;
+    conn.pragma_update(None, "temp_store", "MEMORY")?;
+    Ok(())
+}
+
+// Shared by every command in place of a single mutex-guarded connection, so ingestion, retrieval and chat
+// each check out their own connection instead of queueing behind one lock.
+pub struct DbPool {
+    pool: RwLock<r2d2::Pool<SqliteConnectionManager>>, // swapped when another knowledge base is opened
+}
+
+impl DbPool {
+    fn open(path: &Path, max_size: u32) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| configure_connection(conn));
+        Self::build(manager, max_size)
+    }
+
+    // Several desktops read the same file while a curator updates it, so wait out their write locks. The journal
+    // mode is left as the file has it: switching to WAL needs write access, and its index can't be shared over
+    // a network filesystem anyway.
+    fn open_read_only(path: &Path) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(path)
+            .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .with_init(|conn| conn.busy_timeout(Duration::from_secs(10)));
+        Self::build(manager, DB_POOL_SIZE)
+    }
+
+    fn build(manager: SqliteConnectionManager, max_size: u32) -> Result<Self> {
+        let pool = r2d2::Pool::builder()
+            .max_size(max_size)
+            .min_idle(Some(1))
+            .connection_timeout(Duration::from_secs(DB_CHECKOUT_TIMEOUT_SECS))
+            .build(manager)?;
+        Ok(DbPool { pool: RwLock::new(pool) })
+    }
+
+    // Checks out a connection; it returns to the pool when dropped, like the guard this replaced
+    fn lock(&self) -> Result<PooledConnection, r2d2::Error> {
+        let pool = self.pool.read().unwrap_or_else(std::sync::PoisonError::into_inner).clone();
+        pool.get()
+    }
+
+    // Connections already checked out keep using the previous database until they are dropped
+    fn replace(&self, other: DbPool) {
+        let pool = other.pool.into_inner().unwrap_or_else(std::sync::PoisonError::into_inner);
+        *self.pool.write().unwrap_or_else(std::sync::PoisonError::into_inner) = pool;
+    }
+}
+
+// Databases from before evaluation sets were folded into golden_questions kept them in eval_sets/eval_cases
+fn migrate_eval_cases(conn: &Connection) -> Result<()> {
+    let legacy: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('eval_sets', 'eval_cases')",
+        [],
+        |row| row.get(0),
+    )?;
+    if legacy < 2 {
+        return Ok(());
+    }
+    conn.execute_batch(
+        "BEGIN IMMEDIATE;
+         INSERT OR IGNORE INTO golden_questions (id, question, expected_document_ids, created_at, set_id, expected_answer)
+             SELECT c.id, c.question, c.expected_document_ids, c.created_at, s.name, c.expected_answer
+             FROM eval_cases c JOIN eval_sets s ON s.id = c.set_id;
+         UPDATE eval_runs SET set_id = (SELECT name FROM eval_sets WHERE id = eval_runs.set_id)
+             WHERE set_id IN (SELECT id FROM eval_sets);
+         DROP TABLE eval_cases;
+         DROP TABLE eval_sets;
+         COMMIT;",
+    )?;
+    Ok(())
+}
+
+// Adds a column to an existing table when upgrading a database created by an older version
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(Result::ok)
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+fn init_db(conn: &Connection) -> Result<()> {
+    // Documents table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS documents (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            file_path TEXT,
+            file_type TEXT NOT NULL,
+            content_hash TEXT NOT NULL,
+            is_searchable INTEGER NOT NULL DEFAULT 1,
+            is_sensitive INTEGER NOT NULL DEFAULT 0,
+            metadata TEXT,
+            file_mtime INTEGER,
+            file_hash TEXT,
+            file_path_raw BLOB,
+            deleted_at TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "documents", "is_searchable", "INTEGER NOT NULL DEFAULT 1")?;
+    add_column_if_missing(conn, "documents", "is_sensitive", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "documents", "metadata", "TEXT")?;
+    add_column_if_missing(conn, "documents", "file_mtime", "INTEGER")?;
+    add_column_if_missing(conn, "documents", "file_hash", "TEXT")?;
+    add_column_if_missing(conn, "documents", "file_path_raw", "BLOB")?;
+    add_column_if_missing(conn, "documents", "deleted_at", "TEXT")?;
+    add_column_if_missing(conn, "documents", "sections", "TEXT")?;
+    add_column_if_missing(conn, "documents", "language", "TEXT")?;
+
+    // Document chunks table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS document_chunks (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            content_hash TEXT,
+            embedding BLOB NOT NULL,
+            embedding_key TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "document_chunks", "content_hash", "TEXT")?;
+    add_column_if_missing(conn, "document_chunks", "embedding_key", "TEXT")?;
+    add_column_if_missing(conn, "document_chunks", "embedding_model", "TEXT")?;
+    add_column_if_missing(conn, "document_chunks", "embedding_dim", "INTEGER")?;
+    add_column_if_missing(conn, "document_chunks", "metadata", "TEXT")?;
+    add_column_if_missing(conn, "document_chunks", "parent_chunk_id", "TEXT")?;
+
+    // Larger passages behind small chunks; never embedded, only swapped in before prompting
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS parent_chunks (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            metadata TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Vectors shared by identical chunks across documents, keyed by model + chunk content
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shared_embeddings (
+            key TEXT PRIMARY KEY,
+            embedding BLOB NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS shared_embeddings_release AFTER DELETE ON document_chunks
+         WHEN OLD.embedding_key IS NOT NULL
+         BEGIN
+             DELETE FROM shared_embeddings WHERE key = OLD.embedding_key
+                 AND NOT EXISTS (SELECT 1 FROM document_chunks WHERE embedding_key = OLD.embedding_key);
+         END",
+        [],
+    )?;
+
+    // Chat messages table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_messages (
+            id TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            role TEXT NOT NULL,
+            document_references TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_sessions (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "chat_messages", "session_id", "TEXT")?;
+    add_column_if_missing(conn, "chat_messages", "reply_to", "TEXT")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS message_feedback (
+            message_id TEXT PRIMARY KEY,
+            rating INTEGER NOT NULL,
+            comment TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // Messages from before sessions existed are kept together in one session
+    let orphaned: i64 = conn.query_row("SELECT COUNT(*) FROM chat_messages WHERE session_id IS NULL", [], |row| row.get(0))?;
+    if orphaned > 0 {
+        let session_id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+            params![session_id, "Earlier conversation", now],
+        )?;
+        conn.execute("UPDATE chat_messages SET session_id = ?1 WHERE session_id IS NULL", params![session_id])?;
+    }
+
+    // Encryption profile: salt for key derivation plus a check value to verify the passphrase
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS encryption_profile (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            salt TEXT NOT NULL,
+            key_check TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Retrieval query log for analytics
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS query_log (
+            id TEXT PRIMARY KEY,
+            query TEXT NOT NULL,
+            source TEXT NOT NULL,
+            filters TEXT NOT NULL,
+            result_count INTEGER NOT NULL,
+            top_score REAL,
+            latency_ms INTEGER NOT NULL,
+            followed_up INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "query_log", "session_id", "TEXT")?;
+
+    // Append-only audit log of security-relevant actions
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            action TEXT NOT NULL,
+            target TEXT,
+            details TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+         BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+         BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END",
+        [],
+    )?;
+
+    // Sync bookkeeping: key/value state plus the vector clock of every replicated document
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_documents (
+            document_id TEXT PRIMARY KEY,
+            clock TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            deleted INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT
+        )",
+        [],
+    )?;
+    // Tag set as of the last push or pull, the base for merging tag edits from both sides
+    add_column_if_missing(conn, "sync_documents", "tags", "TEXT")?;
+
+    // Diverging edits detected by sync/import, kept until the user picks a resolution
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_conflicts (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL,
+            source TEXT NOT NULL,
+            local_entry TEXT,
+            remote_entry TEXT NOT NULL,
+            remote_payload TEXT,
+            detected_at TEXT NOT NULL,
+            resolved_at TEXT,
+            resolution TEXT
+        )",
+        [],
+    )?;
+
+    // Outbound webhooks and their delivery log
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhooks (
+            id TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            events TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id TEXT PRIMARY KEY,
+            webhook_id TEXT NOT NULL,
+            event TEXT NOT NULL,
+            attempt INTEGER NOT NULL,
+            success INTEGER NOT NULL,
+            status_code INTEGER,
+            error TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Registered extractor plugins (manifests live on disk, registration lives here)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS extractor_plugins (
+            name TEXT PRIMARY KEY,
+            manifest_path TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            registered_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Registered embedding/generation provider plugins
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS provider_plugins (
+            name TEXT PRIMARY KEY,
+            manifest_path TEXT NOT NULL,
+            registered_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Slot positions of chunk vectors in the memory-mapped embedding store. A deleted or re-embedded chunk
+    // releases its slot, and the next catch-up writes into the freed slot before growing the file.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS embedding_slots (
+            chunk_id TEXT PRIMARY KEY,
+            slot INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS embedding_slots_release AFTER DELETE ON document_chunks
+         BEGIN
+             DELETE FROM embedding_slots WHERE chunk_id = OLD.id;
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS embedding_slots_reembed AFTER UPDATE OF embedding, embedding_key ON document_chunks
+         BEGIN
+             DELETE FROM embedding_slots WHERE chunk_id = OLD.id;
+         END",
+        [],
+    )?;
+    // Slots of chunks deleted before the triggers existed
+    conn.execute("DELETE FROM embedding_slots WHERE chunk_id NOT IN (SELECT id FROM document_chunks)", [])?;
+
+    // Age-based expiry rules scoped to a tag, collection or file type
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS retention_rules (
+            id TEXT PRIMARY KEY,
+            scope TEXT NOT NULL,
+            value TEXT NOT NULL,
+            max_age_days INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Golden questions with the documents a correct retrieval must surface. The config regression check
+    // uses all of them; those with a set_id also form a named evaluation set, optionally with an expected answer
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS golden_questions (
+            id TEXT PRIMARY KEY,
+            question TEXT NOT NULL,
+            expected_document_ids TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "golden_questions", "set_id", "TEXT")?;
+    add_column_if_missing(conn, "golden_questions", "expected_answer", "TEXT")?;
+    migrate_eval_cases(conn)?;
+
+    // Scored runs of an evaluation set, keyed by the set's name
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS eval_runs (
+            id TEXT PRIMARY KEY,
+            set_id TEXT NOT NULL,
+            config TEXT NOT NULL,
+            recall_at_k REAL NOT NULL,
+            mrr REAL NOT NULL,
+            answer_similarity REAL,
+            mean_latency_ms INTEGER NOT NULL,
+            results TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Per-chunk usage counters, keyed by content so they survive re-indexing
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chunk_stats (
+            document_id TEXT NOT NULL,
+            chunk_key TEXT NOT NULL,
+            retrieval_count INTEGER NOT NULL DEFAULT 0,
+            accepted_citation_count INTEGER NOT NULL DEFAULT 0,
+            last_retrieved_at TEXT,
+            PRIMARY KEY (document_id, chunk_key)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_stats_cleanup AFTER DELETE ON documents
+         BEGIN
+             DELETE FROM chunk_stats WHERE document_id = OLD.id;
+         END",
+        [],
+    )?;
+
+    // Questions answered by a chunk, embedded as additional retrieval keys for that chunk
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS qa_keys (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL,
+            chunk_id TEXT NOT NULL,
+            question TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS qa_keys_release AFTER DELETE ON document_chunks
+         BEGIN
+             DELETE FROM qa_keys WHERE chunk_id = OLD.id;
+         END",
+        [],
+    )?;
+
+    // People, organizations and dates, linked to the chunks that mention them
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS entities (
+            id TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            name TEXT NOT NULL,
+            normalized TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE (kind, normalized)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS entity_mentions (
+            entity_id TEXT NOT NULL,
+            document_id TEXT NOT NULL,
+            chunk_id TEXT NOT NULL,
+            mention_count INTEGER NOT NULL,
+            PRIMARY KEY (entity_id, chunk_id),
+            FOREIGN KEY (entity_id) REFERENCES entities (id) ON DELETE CASCADE,
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS entity_mentions_release AFTER DELETE ON document_chunks
+         BEGIN
+             DELETE FROM entity_mentions WHERE chunk_id = OLD.id;
+         END",
+        [],
+    )?;
+
+    // Embedding layer cache, independent of stored chunks so it survives deleting and re-adding a file
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS embedding_cache (
+            content_hash TEXT NOT NULL,
+            model TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (content_hash, model)
+        )",
+        [],
+    )?;
+
+    // Decaying per-profile interest in a document, built from opens and accepted citations
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS document_affinity (
+            profile TEXT NOT NULL,
+            document_id TEXT NOT NULL,
+            score REAL NOT NULL,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (profile, document_id),
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Integer rowids for the vec0 index tables, which cannot key on the text chunk id
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vec_chunk_rows (
+            id INTEGER PRIMARY KEY,
+            chunk_id TEXT NOT NULL UNIQUE
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS vec_chunk_rows_release AFTER DELETE ON document_chunks
+         BEGIN
+             DELETE FROM vec_chunk_rows WHERE chunk_id = OLD.id;
+         END",
+        [],
+    )?;
+
+    // Chunk writes since the in-memory HNSW index was built, consumed as it catches up
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chunk_changes (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            chunk_id TEXT NOT NULL,
+            op TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_insert AFTER INSERT ON document_chunks
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) VALUES (NEW.id, 'insert');
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_update AFTER UPDATE OF embedding, embedding_key ON document_chunks
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) VALUES (NEW.id, 'update');
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_delete AFTER DELETE ON document_chunks
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) VALUES (OLD.id, 'delete');
+         END",
+        [],
+    )?;
+    // Shared stores carry each chunk's title, source and tags, so edits to those re-send the chunk.
+    // 'payload' changes leave the vector alone, which lets the HNSW graph skip them.
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_document AFTER UPDATE OF title, file_path, is_sensitive ON documents
+         WHEN OLD.title IS NOT NEW.title OR OLD.file_path IS NOT NEW.file_path OR OLD.is_sensitive IS NOT NEW.is_sensitive
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) SELECT id, 'payload' FROM document_chunks WHERE document_id = NEW.id;
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_tag_insert AFTER INSERT ON document_tags
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) SELECT id, 'payload' FROM document_chunks WHERE document_id = NEW.document_id;
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_changes_tag_delete AFTER DELETE ON document_tags
+         BEGIN
+             INSERT INTO chunk_changes (chunk_id, op) SELECT id, 'payload' FROM document_chunks WHERE document_id = OLD.document_id;
+         END",
+        [],
+    )?;
+    // How far each vector store has consumed chunk_changes; the log is pruned up to the slowest one
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vector_store_sync (
+            store_key TEXT PRIMARY KEY,
+            last_change INTEGER NOT NULL,
+            synced_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    // The HNSW graph lives in memory and is rebuilt, so its position from a previous run is meaningless
+    conn.execute("DELETE FROM vector_store_sync WHERE store_key = ?1", params![HNSW_CHANGE_CURSOR])?;
+    prune_chunk_changes(conn)?;
+
+    // BM25 keyword index over chunk text. Sensitive chunks are stored encrypted and left out.
+    let fts_exists: bool = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE name = 'chunk_fts'",
+        [],
+        |row| row.get::<_, i64>(0).map(|count| count > 0),
+    )?;
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS chunk_fts USING fts5(content, chunk_id UNINDEXED, tokenize = 'unicode61')",
+        [],
+    )?;
+    if !fts_exists {
+        conn.execute(
+            "INSERT INTO chunk_fts (rowid, content, chunk_id)
+             SELECT dc.rowid, dc.content, dc.id FROM document_chunks dc
+             JOIN documents d ON d.id = dc.document_id WHERE d.is_sensitive = 0",
+            [],
+        )?;
+    }
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_fts_insert AFTER INSERT ON document_chunks
+         WHEN NOT EXISTS (SELECT 1 FROM documents WHERE id = NEW.document_id AND is_sensitive = 1)
+         BEGIN
+             INSERT INTO chunk_fts (rowid, content, chunk_id) VALUES (NEW.rowid, NEW.content, NEW.id);
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_fts_delete AFTER DELETE ON document_chunks
+         BEGIN
+             DELETE FROM chunk_fts WHERE rowid = OLD.rowid;
+         END",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS chunk_fts_update AFTER UPDATE OF content ON document_chunks
+         BEGIN
+             DELETE FROM chunk_fts WHERE rowid = OLD.rowid;
+             INSERT INTO chunk_fts (rowid, content, chunk_id)
+             SELECT NEW.rowid, NEW.content, NEW.id
+             WHERE NOT EXISTS (SELECT 1 FROM documents WHERE id = NEW.document_id AND is_sensitive = 1);
+         END",
+        [],
+    )?;
+
+    // Named tags shared across documents
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS document_tags (
+            document_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (document_id, tag_id),
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    add_column_if_missing(conn, "document_tags", "auto", "INTEGER NOT NULL DEFAULT 0")?;
+    // Tags used to live in documents.metadata.tags; move them over once
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (id, name, created_at)
+         SELECT lower(hex(randomblob(16))), trim(t.value), ?1
+         FROM documents d, json_each(json_extract(d.metadata, '$.tags')) t
+         WHERE json_valid(d.metadata) AND trim(t.value) != ''",
+        params![Utc::now().to_rfc3339()],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO document_tags (document_id, tag_id, created_at)
+         SELECT d.id, tg.id, ?1
+         FROM documents d, json_each(json_extract(d.metadata, '$.tags')) t
+         JOIN tags tg ON tg.name = trim(t.value)
+         WHERE json_valid(d.metadata)",
+        params![Utc::now().to_rfc3339()],
+    )?;
+    conn.execute(
+        "UPDATE documents SET metadata = json_remove(metadata, '$.tags')
+         WHERE json_valid(metadata) AND json_extract(metadata, '$.tags') IS NOT NULL",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS watched_folders (
+            id TEXT PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            recursive INTEGER NOT NULL DEFAULT 1,
+            filter TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS llm_api_keys (
+            provider TEXT PRIMARY KEY,
+            api_key TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // One summary per document, embedded so it can also be matched at retrieval time
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS document_summaries (
+            document_id TEXT PRIMARY KEY,
+            summary TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            generated INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // HTTP tools the chat model may call; built-in tools are not stored
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_tools (
+            name TEXT PRIMARY KEY,
+            description TEXT NOT NULL,
+            parameters TEXT NOT NULL,
+            url TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_templates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            system_prompt TEXT,
+            template TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Chunks from before dimension tracking: the size is recoverable, the model name is not
+    conn.execute(
+        "UPDATE document_chunks SET embedding_dim = COALESCE(
+             (SELECT length(se.embedding) FROM shared_embeddings se WHERE se.key = document_chunks.embedding_key),
+             length(embedding)) / 4
+         WHERE embedding_dim IS NULL",
+        [],
+    )?;
+
+    // Create indexes for better performance
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_documents_content_hash ON documents(content_hash)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chunks_document_id ON document_chunks(document_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chunks_embedding_key ON document_chunks(embedding_key)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chunks_parent_chunk_id ON document_chunks(parent_chunk_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_parent_chunks_document_id ON parent_chunks(document_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_qa_keys_chunk_id ON qa_keys(chunk_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_entity_mentions_chunk_id ON entity_mentions(chunk_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_entity_mentions_document_id ON entity_mentions(document_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_document_tags_tag_id ON document_tags(tag_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_created_at ON chat_messages(created_at)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_messages_session_id ON chat_messages(session_id, created_at)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_query_log_created_at ON query_log(created_at)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_golden_questions_set_id ON golden_questions(set_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_eval_runs_set_id ON eval_runs(set_id, created_at)", [])?;
+
+    Ok(())
+}
+
+// A query counts as followed up when the user asks something else within this window
+const QUERY_FOLLOW_UP_WINDOW_SECS: i64 = 600;
+
+fn log_query(
+    conn: &Connection,
+    query: &str,
+    source: &str,
+    session_id: Option<&str>,
+    filters: serde_json::Value,
+    result_count: usize,
+    top_score: Option<f32>,
+    latency_ms: u64,
+) -> Result<()> {
+    let now = Utc::now();
+    let window_start = now - chrono::Duration::seconds(QUERY_FOLLOW_UP_WINDOW_SECS);
+
+    // Mark the previous query from the same source and session as followed up if the user kept going
+    conn.execute(
+        "UPDATE query_log SET followed_up = 1
+         WHERE id = (SELECT id FROM query_log WHERE source = ?2 AND session_id IS ?3 ORDER BY created_at DESC LIMIT 1)
+           AND created_at >= ?1",
+        params![window_start.to_rfc3339(), source, session_id],
+    )?;
+
+    conn.execute(
+        "INSERT INTO query_log (id, query, source, session_id, filters, result_count, top_score, latency_ms, followed_up,
+                               created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, ?9)",
+        params![
+            Uuid::new_v4().to_string(),
+            query,
+            source,
+            session_id,
+            filters.to_string(),
+            result_count as i64,
+            top_score,
+            latency_ms as i64,
+            now.to_rfc3339(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn get_query_history(
+    limit: Option<usize>,
+    only_empty: Option<bool>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<QueryLogEntry>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let empty_filter = if only_empty.unwrap_or(false) { "WHERE result_count = 0" } else { "" };
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT id, query, source, filters, result_count, top_score, latency_ms, followed_up, created_at, session_id
+             FROM query_log {} ORDER BY created_at DESC LIMIT ?1",
+            empty_filter
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let entry_iter = stmt
+        .query_map(params![limit.unwrap_or(100) as i64], |row| {
+            let filters_str: String = row.get(3)?;
+            Ok(QueryLogEntry {
+                id: row.get(0)?,
+                query: row.get(1)?,
+                source: row.get(2)?,
+                session_id: row.get(9)?,
+                filters: serde_json::from_str(&filters_str).unwrap_or(serde_json::Value::Null),
+                result_count: row.get::<_, i64>(4)? as usize,
+                top_score: row.get(5)?,
+                latency_ms: row.get::<_, i64>(6)? as u64,
+                followed_up: row.get(7)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(8)?)
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e)))?
+                    .with_timezone(&Utc),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(entry_iter.filter_map(Result::ok).collect())
+}
+
+// ---------- Enhanced RAG Commands ----------------------------------------------
+
+// With regression_check_on_change the golden set is run against the old and new config first; a change that
+// loses hits is refused (the report goes out as config_regression_report) unless accept_regressions is set
+#[tauri::command]
+async fn set_rag_config(
+    config: RAGConfig,
+    accept_regressions: Option<bool>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if config.vector_backend == VectorBackend::Hnsw {
+        check_hnsw_metric(config.similarity_metric).map_err(|e| e.to_string())?;
+    }
+    if config.vector_backend == VectorBackend::SqliteVec {
+        vec_index_metric(config.similarity_metric).map_err(|e| e.to_string())?;
+    }
+    if config.regression_check_on_change && !accept_regressions.unwrap_or(false) {
+        let current = match app.try_state::<Arc<Mutex<RAGConfig>>>() {
+            Some(config_state) => Some(config_state.lock().map_err(|e| e.to_string())?.clone()),
+            None => None,
+        };
+        if let (Some(current), Some(providers)) = (current, app.try_state::<Arc<EmbeddingProviders>>()) {
+            let report = run_regression_check(db_state.inner(), providers.inner(), &current, &config)
+                .await
+                .map_err(|e| e.to_string())?;
+            let _ = app.emit("config_regression_report", &report);
+            if report.regressions > 0 {
+                return Err(format!(
+                    "{} golden question(s) no longer find their expected documents (hit rate {:.0}% -> {:.0}%); \
+                     apply again with accept_regressions to keep the change",
+                    report.regressions,
+                    report.hit_rate_before * 100.0,
+                    report.hit_rate_after * 100.0
+                ));
+            }
+        }
+    }
+    // Store RAG config in app state
+    let previous = match app.try_state::<Arc<Mutex<RAGConfig>>>() {
+        Some(config_state) => {
+            let mut current = config_state.lock().map_err(|e| e.to_string())?;
+            Some(std::mem::replace(&mut *current, config.clone()))
+        }
+        None => {
+            app.manage(Arc::new(Mutex::new(config.clone())));
+            None
+        }
+    };
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    record_audit(&db, "settings_changed", Some("rag_config"), serde_json::json!({
+        "chunk_size": config.chunk_size,
+        "chunk_overlap": config.chunk_overlap,
+        "top_k": config.top_k,
+        "similarity_threshold": config.similarity_threshold,
+    }));
+
+    // Never write the key itself to the audit log, only that it changed
+    let api_key = |c: &RAGConfig| match &c.embedding_model {
+        EmbeddingModel::OpenAI { api_key, .. } => Some(api_key.clone()),
+        EmbeddingModel::HuggingFace { api_key, .. } => api_key.clone(),
+        _ => None,
+    };
+    if previous.as_ref().and_then(api_key) != api_key(&config) {
+        record_audit(&db, "secret_modified", Some("embedding_api_key"), serde_json::json!({}));
+    }
+    let llm_key = |c: &RAGConfig| c.llm_provider.as_ref().and_then(|p| p.api_key().map(str::to_string));
+    if previous.as_ref().and_then(llm_key) != llm_key(&config) {
+        record_audit(&db, "secret_modified", Some("llm_api_key"), serde_json::json!({}));
+    }
+
+    if config.vector_backend == VectorBackend::Hnsw {
+        warm_hnsw_index(&app, &config);
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_rag_config(
+    app: AppHandle,
+) -> Result<RAGConfig, String> {
+    match app.try_state::<Arc<Mutex<RAGConfig>>>() {
+        Some(config_state) => {
+            let config = config_state.lock().map_err(|e| e.to_string())?;
+            Ok(config.clone())
+        }
+        None => Ok(RAGConfig::default()),
+    }
+}
+
+#[tauri::command]
+async fn process_document_enhanced(
+    file_path: String,
+    title: Option<String>,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<ProcessingResult, String> {
+    let start_time = std::time::Instant::now();
+    
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+    let (document, chunks_created) = ingest_file(
+        Path::new(&file_path),
+        title,
+        &config,
+        providers_state.inner(),
+        db_state.inner(),
+        &plugins,
+    )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let processing_time = start_time.elapsed().as_millis() as u64;
+    
+    let _ = app.emit("document_processed", &document.id);
+    dispatch_webhook_event(db_state.inner(), "document.processed", serde_json::json!({
+        "document_id": document.id,
+        "title": document.title,
+        "chunks_created": chunks_created,
+    }));
+
+    Ok(ProcessingResult {
+        success: true,
+        message: format!("Successfully processed document: {}", document.title),
+        chunks_created,
+        processing_time_ms: processing_time,
+    })
+}
+
+// Extracts, stores, chunks and embeds a single file; shared by every ingestion entry point
+async fn ingest_file(
+    file_path: &Path,
+    title: Option<String>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    db_state: &Arc<DbPool>,
+    plugins: &[ExtractorPlugin],
+) -> Result<(Document, usize)> {
+    {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        ensure_writable(&db)?;
+    }
+    let extracted = extract_document(file_path, plugins, &config.extraction).await?;
+    let content = extracted.text;
+
+    // Display strings only; the exact OS path is kept separately in file_path_raw
+    let file_name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+    
+    let doc_title = title.unwrap_or(file_name);
+    let content_hash = calculate_content_hash(&content);
+    let now = Utc::now();
+
+    let file_type = file_path
+        .extension()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let document = Document {
+        id: Uuid::new_v4().to_string(),
+        title: doc_title,
+        content: content.clone(),
+        file_path: Some(file_path.display().to_string()),
+        file_type,
+        content_hash,
+        is_searchable: true,
+        is_sensitive: false,
+        metadata: extracted.metadata,
+        tags: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    // Save to database
+    let config = &{
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        insert_document(&db, &document)?;
+        db.execute(
+            "UPDATE documents SET file_path_raw = ?1 WHERE id = ?2",
+            params![encode_os_path(file_path), document.id],
+        )?;
+        store_document_sections(&db, &document.id, &extracted.sections)?;
+        if let Ok((mtime, file_hash)) = file_fingerprint(file_path) {
+            store_file_fingerprint(&db, &document.id, mtime, &file_hash)?;
+        }
+        record_audit(&db, "document_added", Some(&document.id), serde_json::json!({
+            "title": document.title,
+            "file_path": document.file_path,
+        }));
+        apply_document_language(&db, &document.id, &content, config)?
+    };
+
+    // Process chunks with enhanced configuration
+    let chunks_created =
+        process_document_chunks_enhanced(&document.id, &content, &extracted.sections, db_state, config, providers).await?;
+    if config.qa_extraction != QaExtraction::Off {
+        // Q/A keys only add recall, so a failure here must not fail the import
+        if let Err(e) = index_qa_pairs(&document.id, &content, db_state, config, providers).await {
+            warn!("Q/A extraction failed for {}: {}", document.id, e);
+        }
+    }
+    if config.summarize_on_ingest {
+        if let Err(e) =
+            summarize_document_text(&document.id, &document.title, &content, None, db_state, config, providers).await
+        {
+            warn!("Summarization failed for {}: {}", document.id, e);
+        }
+    }
+    if config.auto_tagging != AutoTagging::Off {
+        if let Err(e) = auto_tag_document_text(&document.id, &content, db_state, config).await {
+            warn!("Auto-tagging failed for {}: {}", document.id, e);
+        }
+    }
+    if config.entity_extraction != EntityExtraction::Off {
+        if let Err(e) = index_entities(&document.id, db_state, config).await {
+            warn!("Entity extraction failed for {}: {}", document.id, e);
+        }
+    }
+
+    Ok((document, chunks_created))
+}
+
+const CHUNK_INSERT_BATCH_SIZE: usize = 500;
+
+async fn process_document_chunks_enhanced(
+    document_id: &str,
+    content: &str,
+    sections: &[TextSection],
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<usize> {
+    let ChunkedDocument { parents, chunks, parent_ids } = chunk_document_with_parents(content, sections, config, providers).await;
+    let (chunks, chunk_metadata): (Vec<String>, Vec<serde_json::Value>) = chunks.into_iter().unzip();
+    if !parents.is_empty() {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        insert_parent_chunks(&db, document_id, &parents)?;
+    }
+
+    // The same chunk uploaded elsewhere already has a vector for this model
+    let embedding_keys: Vec<String> = chunks
+        .iter()
+        .map(|chunk_content| shared_embedding_key(&config.embedding_model, chunk_content))
+        .collect();
+    let mut embeddings: Vec<Option<Vec<u8>>> = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        embedding_keys.iter().map(|key| find_shared_embedding(&db, key)).collect()
+    };
+    embed_missing_chunks(&chunks, &mut embeddings, config, providers, true).await?;
+
+    let model_key = embedding_model_key(&config.embedding_model);
+    let rows: Vec<(usize, DocumentChunk, Vec<u8>)> = chunks
+        .iter()
+        .zip(embeddings)
+        .zip(chunk_metadata)
+        .zip(parent_ids)
+        .enumerate()
+        .map(|(index, (((chunk_content, embedding_bytes), metadata), parent_chunk_id))| {
+            let chunk = DocumentChunk {
+                id: Uuid::new_v4().to_string(),
+                document_id: document_id.to_string(),
+                chunk_index: index as i32,
+                content: chunk_content.clone(),
+                embedding: Vec::new(),
+                metadata,
+                parent_chunk_id,
+                created_at: Utc::now(),
+            };
+            (index, chunk, embedding_bytes.unwrap_or_default())
+        })
+        .collect();
+
+    // Autocommitting every row syncs the journal per chunk; a transaction per batch syncs once
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    for batch in rows.chunks(CHUNK_INSERT_BATCH_SIZE) {
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for (index, chunk, embedding_bytes) in batch {
+            insert_chunk(&tx, chunk, embedding_bytes, Some(&embedding_keys[*index]), &model_key)?;
+        }
+        tx.commit()?;
+    }
+
+    Ok(chunks.len())
+}
+
+// Fills the empty slots with freshly embedded vectors, batching the provider calls; returns how many were embedded
+async fn embed_missing_chunks(
+    chunks: &[String],
+    embeddings: &mut [Option<Vec<u8>>],
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    use_cache: bool,
+) -> Result<usize> {
+    let missing: Vec<usize> = (0..chunks.len()).filter(|&i| embeddings[i].is_none()).collect();
+    let texts: Vec<String> = missing.iter().map(|&i| chunks[i].clone()).collect();
+    let vectors = if use_cache {
+        providers.embed_batch(&texts, config).await?
+    } else {
+        providers.embed_batch_fresh(&texts, config).await?
+    };
+
+    for (index, vector) in missing.iter().zip(vectors) {
+        embeddings[*index] = Some(encode_embedding(&vector, config.embedding_storage));
+    }
+    Ok(missing.len())
+}
+
+// request_id lets the UI abort the whole retrieval + generation run with cancel_generation
+#[tauri::command]
+async fn query_rag_enhanced(
+    query: String,
+    mode: RAGMode,
+    config: RAGConfig,
+    answer_style: Option<AnswerStyle>,
+    response_schema: Option<serde_json::Value>,
+    session_id: Option<String>,
+    request_id: Option<String>,
+    history: Option<Vec<LLMMessage>>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+    generations_state: tauri::State<'_, Arc<ActiveGenerations>>,
+) -> Result<RAGResponse, String> {
+    let guard = request_id.map(|id| generations_state.register(id));
+    run_cancellable(
+        guard.as_ref(),
+        answer_rag_query(
+            query,
+            mode,
+            config,
+            answer_style,
+            response_schema,
+            session_id,
+            history.unwrap_or_default(),
+            db_state,
+            key_state,
+            store_state,
+            providers_state,
+            session_state,
+            hnsw_state,
+            vector_stores_state,
+        ),
+    )
+    .await
+}
+
+// Prior turns of the conversation, oldest first, are used to resolve follow-up questions
+async fn answer_rag_query(
+    query: String,
+    mode: RAGMode,
+    config: RAGConfig,
+    answer_style: Option<AnswerStyle>,
+    response_schema: Option<serde_json::Value>,
+    session_id: Option<String>,
+    history: Vec<LLMMessage>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<RAGResponse, String> {
+    let start_time = std::time::Instant::now();
+    let answer_style = answer_style.unwrap_or_default();
+    // Reject a bad schema or missing model before spending time on retrieval
+    let structured_target = match &response_schema {
+        Some(schema) => {
+            schema_violations(schema, &serde_json::Value::Null).map_err(|e| e.to_string())?;
+            Some(generation_target(&config).ok_or("Structured output needs a generation provider")?)
+        }
+        None => None,
+    };
+    let prompt_template = match &config.prompt_template {
+        Some(template_id) => {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            Some(load_prompt_template(&db, template_id).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    let recent_history = history_window(&history, config.history_turns);
+    let history_text = format_history(recent_history);
+    // A rewrite already resolves references to the conversation, so it replaces condensing
+    let standalone_query = match generation_target(&config) {
+        Some(target) if config.query_rewrite && matches!(mode, RAGMode::FineTunedWithRAG | RAGMode::BaseWithRAG) => {
+            Some(rewrite_query(&query, recent_history, &target).await).filter(|rewritten| rewritten != &query)
+        }
+        target if !recent_history.is_empty() => Some(condense_query(&query, recent_history, target.as_ref()).await),
+        _ => None,
+    };
+    let retrieval_query = standalone_query.as_deref().unwrap_or(&query);
+    let query_language = detect_language(retrieval_query);
+    let translation_target = match config.translation {
+        Translation::Off => None,
+        _ => generation_target(&config),
+    };
+    let translated_query = match (&translation_target, &query_language) {
+        (Some(target), Some(language)) if config.translation == Translation::Query => {
+            let corpus_language = {
+                let db = db_state.lock().map_err(|e| e.to_string())?;
+                dominant_language(&db)
+            };
+            match corpus_language.filter(|code| code != &language.code) {
+                Some(code) => match translate_text(target, retrieval_query, &language_name(&code)).await {
+                    Ok(translated) if !translated.is_empty() => Some(translated),
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("Failed to translate query: {}", e);
+                        None
+                    }
+                },
+                None => None,
+            }
+        }
+        _ => None,
+    };
+    let retrieval_query = translated_query.as_deref().unwrap_or(retrieval_query);
+    
+    let retrieved_context = match mode {
+        RAGMode::FineTunedOnly => {
+            // Don't retrieve context for fine-tuned only mode
+            Vec::new()
+        }
+        RAGMode::FineTunedWithRAG | RAGMode::BaseWithRAG => {
+            // Retrieve context for RAG modes
+            let retrieval_start = std::time::Instant::now();
+            let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+            // Over-fetch so slots freed by collapsing overlapping passages go to distinct evidence
+            let candidate_pool = config.reranker.as_ref().map_or(0, |r| r.candidates).max(config.top_k * 2);
+            let candidate_config = RAGConfig { top_k: candidate_pool, ..config.clone() };
+            let variations = match generation_target(&config) {
+                Some(target) if config.multi_query > 0 => {
+                    generate_query_variations(retrieval_query, config.multi_query, &target).await
+                }
+                _ => Vec::new(),
+            };
+            let mut rankings = Vec::with_capacity(variations.len() + 1);
+            for variant in std::iter::once(retrieval_query).chain(variations.iter().map(String::as_str)) {
+                rankings.push(
+                    retrieve_context_enhanced(
+                        variant,
+                        &candidate_config,
+                        db_state.clone(),
+                        content_key.as_ref(),
+                        store_state.inner(),
+                        hnsw_state.inner(),
+                        vector_stores_state.inner(),
+                        providers_state.inner(),
+                        None,
+                    )
+                    .await?,
+                );
+            }
+            let mut candidates = match rankings.len() {
+                1 => rankings.pop().unwrap_or_default(),
+                _ => fuse_query_rankings(rankings, candidate_config.top_k),
+            };
+            if let Some(session_id) = &session_id {
+                candidates.extend(
+                    search_session_attachments(session_state.inner(), session_id, retrieval_query, &candidate_config, providers_state.inner())
+                        .await
+                        .map_err(|e| e.to_string())?,
+                );
+                candidates.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+                candidates.truncate(candidate_config.top_k);
+            }
+            let mut results = deduplicate_context(candidates);
+            if let Some(reranker) = &config.reranker {
+                // A reranker outage should cost ranking quality, not the answer; results stay in fused order
+                if let Err(e) = rerank_results(reranker, retrieval_query, &mut results).await {
+                    warn!("Reranking failed, keeping the fused ranking: {}", e);
+                }
+            }
+            if let Some(profile) = &config.personalization_profile {
+                let db = db_state.lock().map_err(|e| e.to_string())?;
+                if let Err(e) = apply_personalization(&db, &mut results, profile) {
+                    warn!("Failed to apply personalization: {}", e);
+                }
+            }
+            if active_parent_chunk_size(&config).is_some() {
+                let db = db_state.lock().map_err(|e| e.to_string())?;
+                results = expand_to_parents(&db, results, content_key.as_ref()).map_err(|e| e.to_string())?;
+            }
+            match config.diversity_lambda {
+                Some(lambda) => {
+                    let vectors: HashMap<String, Vec<f32>> = {
+                        let db = db_state.lock().map_err(|e| e.to_string())?;
+                        let chunk_ids: Vec<String> = results.iter().map(|r| r.chunk_id.clone()).collect();
+                        load_chunk_vectors(&db, &chunk_ids).map_err(|e| e.to_string())?.into_iter().collect()
+                    };
+                    results = diversify_context(results, &vectors, lambda, config.top_k);
+                }
+                None => results.truncate(config.top_k),
+            }
+
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            // History and usage stats cannot be written to a shared read-only copy
+            if !is_read_only(&db) {
+                if let Err(e) = log_query(
+                    &db,
+                    &query,
+                    "rag_query",
+                    session_id.as_deref(),
+                    serde_json::json!({
+                        "mode": mode,
+                        "top_k": config.top_k,
+                        "similarity_threshold": config.similarity_threshold,
+                        "similarity_metric": config.similarity_metric,
+                        "answer_style": answer_style,
+                    }),
+                    results.len(),
+                    results.first().map(|r| r.similarity_score),
+                    retrieval_start.elapsed().as_millis() as u64,
+                ) {
+                    warn!("Failed to log query: {}", e);
+                }
+                let chunk_ids: Vec<&str> = results.iter().map(|r| r.chunk_id.as_str()).collect();
+                if let Err(e) = record_chunk_retrievals(&db, &chunk_ids) {
+                    warn!("Failed to update chunk stats: {}", e);
+                }
+            }
+
+            results
+        }
+    };
+    
+    // Only what fits the model's window is sent, and returned, as context
+    let retrieved_context = match generation_target(&config) {
+        Some(target) if answer_style != AnswerStyle::QuotesOnly => pack_context(
+            retrieved_context,
+            &target,
+            &config,
+            prompt_template.as_ref(),
+            &query,
+            &history_text,
+            answer_style,
+        ),
+        _ => retrieved_context,
+    };
+    // The model reads translated passages, while the response keeps the originals for citation
+    let prompt_context = match (&translation_target, &query_language) {
+        (Some(target), Some(language)) if config.translation == Translation::Context => {
+            translate_context(target, &retrieved_context, language).await
+        }
+        _ => retrieved_context.clone(),
+    };
+
+    let structured = match (&structured_target, &response_schema) {
+        (Some(target), Some(schema)) => Some(
+            generate_structured_answer(target, &query, &prompt_context, schema, answer_style)
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        _ => None,
+    };
+    let answer = match (generation_target(&config), answer_style) {
+        _ if structured.is_some() => serde_json::to_string_pretty(&structured).map_err(|e| e.to_string())?,
+        // Quotes are lifted verbatim from the context, so there is nothing for a model to write
+        (_, AnswerStyle::QuotesOnly) => quoted_answer(&retrieved_context),
+        (Some(target), _) => {
+            let context_text = prompt_context
+                .iter()
+                .enumerate()
+                .map(|(i, r)| context_entry(i, r))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            let messages = prompt_messages(
+                prompt_template.as_ref(),
+                &[
+                    ("context", &context_text),
+                    ("question", &query),
+                    ("history", &history_text),
+                    ("instructions", answer_style.instructions()),
+                ],
+            );
+            let answer = generate_chat(&target, &messages, &generation_options(&config, answer_style))
+                .await
+                .map_err(|e| e.to_string())?;
+            answer_style.post_process(answer)
+        }
+        (None, AnswerStyle::Concise) if !retrieved_context.is_empty() && prompt_template.is_none() => {
+            concise_mock_answer(&retrieved_context)
+        }
+        (None, _) => {
+            generate_answer_with_mode(&query, &retrieved_context, &mode, prompt_template.as_ref(), &history_text).await
+        }
+    };
+    let citations = extract_citations(&answer, &retrieved_context);
+    // Quotes and structured output are not free text to check
+    let checks_grounding = config.grounding_check
+        && structured.is_none()
+        && answer_style != AnswerStyle::QuotesOnly
+        && !retrieved_context.is_empty();
+    let grounding = if checks_grounding {
+        check_grounding(&answer, &prompt_context, &config, providers_state.inner())
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to check answer grounding: {}", e);
+                None
+            })
+    } else {
+        None
+    };
+    let (grounding_score, unsupported_sentences) = grounding.map_or((None, Vec::new()), |(score, unsupported)| (Some(score), unsupported));
+    let processing_time = start_time.elapsed().as_millis() as u64;
+    
+    Ok(RAGResponse {
+        answer,
+        retrieved_context,
+        mode_used: mode,
+        answer_style,
+        citations,
+        grounding_score,
+        unsupported_sentences,
+        structured,
+        standalone_query,
+        query_language: query_language.map(|language| language.code),
+        translated_query,
+        processing_time_ms: processing_time,
+    })
+}
+
+// Heap entry ordered so the weakest score sits on top of a BinaryHeap
+struct ScoredRow {
+    score: f32,
+    row_id: i64,
+}
+
+impl PartialEq for ScoredRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredRow {}
+
+impl PartialOrd for ScoredRow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredRow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score)
+    }
+}
+
+// Keeps the best `limit` rows seen so far without holding on to the rest
+struct TopK {
+    limit: usize,
+    heap: BinaryHeap<ScoredRow>,
+}
+
+impl TopK {
+    fn new(limit: usize) -> Self {
+        Self { limit, heap: BinaryHeap::with_capacity(limit + 1) }
+    }
+
+    fn offer(&mut self, score: f32, row_id: i64) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.heap.len() < self.limit {
+            self.heap.push(ScoredRow { score, row_id });
+        } else if self.heap.peek().is_some_and(|weakest| score > weakest.score) {
+            self.heap.pop();
+            self.heap.push(ScoredRow { score, row_id });
+        }
+    }
+
+    // Best score first
+    fn into_sorted(self) -> Vec<(f32, i64)> {
+        self.heap.into_sorted_vec().into_iter().map(|row| (row.score, row.row_id)).collect()
+    }
+}
+
+// Where one retrieval spent its time, in milliseconds; filled in only when a benchmark passes it in
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetrievalTimings {
+    pub embedding_ms: f64, // always a fresh provider call, never an embedding_cache hit
+    pub candidates_ms: f64, // vector index or remote store lookup; zero for a linear scan
+    pub scan_ms: f64,       // reading and decoding stored vectors
+    pub scoring_ms: f64,
+    pub sorting_ms: f64,
+    pub hydration_ms: f64, // loading (and decrypting) the text of the winners
+    pub fusion_ms: f64,    // Q/A key, summary and keyword matches merged into the ranking
+    pub total_ms: f64,
+}
+
+fn elapsed_ms(start: std::time::Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+async fn retrieve_context_enhanced(
+    query: &str,
+    config: &RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    content_key: Option<&ContentKey>,
+    embedding_store: &Arc<Mutex<EmbeddingStore>>,
+    hnsw: &Mutex<Option<HnswIndex>>,
+    vector_stores: &VectorStores,
+    providers: &EmbeddingProviders,
+    mut timings: Option<&mut RetrievalTimings>,
+) -> Result<Vec<RetrievalResult>, String> {
+    let config = &{
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        routed_embedding_config(&db, config).map_err(|e| e.to_string())?
+    };
+    // Per-row stage clocks only run when a benchmark asks for them
+    let timed = timings.is_some();
+    let mut stage_start = std::time::Instant::now();
+    // Benchmarked queries were usually embedded before, so a timed run skips embedding_cache to measure the model
+    let query_embedding = if timed {
+        providers.embed_uncached(&[query.to_string()], config).await.map(|mut e| e.pop().unwrap_or_default())
+    } else {
+        providers.embed(query, config).await
+    }
+    .map_err(|e| e.to_string())?;
+    if let Some(t) = timings.as_deref_mut() {
+        t.embedding_ms = elapsed_ms(stage_start);
+        stage_start = std::time::Instant::now();
+    }
+
+    // Remote stores are awaited, so they are queried before the connection is locked
+    let external = vector_stores
+        .candidates(db_state.inner(), config, &query_embedding)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    check_embedding_compatibility(&db, &embedding_model_key(&config.embedding_model), query_embedding.len())
+        .map_err(|e| e.to_string())?;
+
+    // The sidecar store records its slots in the database, so a read-only copy scans BLOBs
+    if config.mmap_embeddings && config.vector_backend == VectorBackend::Linear && !is_read_only(&db) {
+        let mut store = embedding_store.lock().map_err(|e| e.to_string())?;
+        catch_up_embedding_store(&db, &mut store).map_err(|e| e.to_string())?;
+        // The sidecar scan scores and ranks as it reads, so it is timed as one stage
+        let scan_start = std::time::Instant::now();
+        let mut results = scan_embedding_store(&db, &store, &query_embedding, config, content_key)
+            .map_err(|e| e.to_string())?;
+        if let Some(t) = timings.as_deref_mut() {
+            t.scan_ms = elapsed_ms(scan_start);
+            stage_start = std::time::Instant::now();
+        }
+        merge_qa_matches(&db, &mut results, &query_embedding, config, content_key).map_err(|e| e.to_string())?;
+        merge_summary_matches(&db, &mut results, &query_embedding, config, content_key).map_err(|e| e.to_string())?;
+        fuse_keyword_matches(&db, &mut results, query, config).map_err(|e| e.to_string())?;
+        if let Some(t) = timings {
+            t.fusion_ms = elapsed_ms(stage_start);
+        }
+        return Ok(results);
+    }
+
+    let (candidates, remote) = match external {
+        Some(external) => (Some(external.local_ids), external.remote),
+        None => (
+            vector_index_candidates(
+                &db,
+                &config.vector_backend,
+                config.similarity_metric,
+                hnsw,
+                &query_embedding,
+                config.top_k,
+            )
+            .map_err(|e| e.to_string())?,
+            Vec::new(),
+        ),
+    };
+    if let Some(t) = timings.as_deref_mut() {
+        t.candidates_ms = elapsed_ms(stage_start);
+        stage_start = std::time::Instant::now();
+    }
+    // Only rowids and vectors are read while scanning; text is loaded for the winners afterwards
+    let mut top = TopK::new(config.top_k);
+    let (mut scoring, mut sorting) = (std::time::Duration::ZERO, std::time::Duration::ZERO);
+    {
+        let mut stmt = db
+            .prepare(&format!(
+                "SELECT dc.rowid, COALESCE(se.embedding, dc.embedding), dc.id
+                 FROM document_chunks dc
+                 JOIN documents d ON dc.document_id = d.id
+                 LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+                 WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND (d.is_sensitive = 0 OR ?1)
+                   AND (?2 IS NULL OR dc.id IN (SELECT value FROM json_each(?2))){}{}",
+                tag_filter_clause(3),
+                chunk_filter_clause(&config.chunk_filter)
+            ))
+            .map_err(|e| e.to_string())?;
+        let mut rows = stmt
+            .query(params![content_key.is_some(), candidates, tag_filter_value(&config.tags)])
+            .map_err(|e| e.to_string())?;
+
+        let mut chunk_embedding = Vec::with_capacity(query_embedding.len());
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let row_id: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let embedding_bytes = row
+                .get_ref(1)
+                .map_err(|e| e.to_string())?
+                .as_blob()
+                .map_err(|e| e.to_string())?;
+            decode_embedding_into(embedding_bytes, &mut chunk_embedding);
+
+            if chunk_embedding.len() != query_embedding.len() {
+                let chunk_id: String = row.get(2).map_err(|e| e.to_string())?;
+                return Err(format!(
+                    "Chunk {} has a {}-dimensional embedding but the query has {}; re-index the knowledge base",
+                    chunk_id,
+                    chunk_embedding.len(),
+                    query_embedding.len()
+                ));
+            }
+            let scoring_start = timed.then(std::time::Instant::now);
+            let similarity = compute_similarity(config.similarity_metric, &query_embedding, &chunk_embedding);
+            let sorting_start = timed.then(std::time::Instant::now);
+            if similarity > config.similarity_threshold {
+                top.offer(similarity, row_id);
+            }
+            if let (Some(scored_at), Some(sorted_at)) = (scoring_start, sorting_start) {
+                scoring += sorted_at - scored_at;
+                sorting += sorted_at.elapsed();
+            }
+        }
+    }
+    let ranking_start = timed.then(std::time::Instant::now);
+    let ranked = top.into_sorted();
+    if let (Some(t), Some(ranking_start)) = (timings.as_deref_mut(), ranking_start) {
+        sorting += ranking_start.elapsed();
+        t.scoring_ms = scoring.as_secs_f64() * 1000.0;
+        t.sorting_ms = sorting.as_secs_f64() * 1000.0;
+        // Whatever the loop spent outside scoring and ranking went to reading and decoding rows
+        t.scan_ms = stage_start.elapsed().saturating_sub(scoring + sorting).as_secs_f64() * 1000.0;
+        stage_start = std::time::Instant::now();
+    }
+
+    let mut results = Vec::with_capacity(config.top_k);
+    let mut stmt = db
+        .prepare(
+            "SELECT dc.id, dc.content, d.title, d.file_path, d.is_sensitive, dc.metadata
+             FROM document_chunks dc
+             JOIN documents d ON dc.document_id = d.id
+             WHERE dc.rowid = ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    for (similarity, row_id) in ranked {
+        let (chunk_id, content, doc_title, file_path, is_sensitive, chunk_metadata): (
+            String,
+            String,
+            String,
+            Option<String>,
+            bool,
+            Option<String>,
+        ) = stmt
+            .query_row(params![row_id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })
+            .map_err(|e| e.to_string())?;
+
+        // Sensitive chunk text is only decrypted for candidates that made the cut
+        let content = match (is_sensitive, content_key) {
+            (false, _) => content,
+            (true, Some(key)) => match decrypt_text(key, &content) {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Failed to decrypt chunk {}: {}", chunk_id, e);
+                    continue;
+                }
+            },
+            (true, None) => continue,
+        };
+
+        results.push(RetrievalResult {
+            chunk_id,
+            content,
+            document_title: doc_title,
+            similarity_score: similarity,
+            source_info: chunk_source_info(file_path, chunk_metadata),
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        });
+    }
+    // Chunks only the shared store holds compete with the local ones on the same score
+    if !remote.is_empty() {
+        results.extend(remote.into_iter().map(RemoteChunk::into_result));
+        results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+        results.truncate(config.top_k);
+    }
+    if let Some(t) = timings.as_deref_mut() {
+        t.hydration_ms = elapsed_ms(stage_start);
+        stage_start = std::time::Instant::now();
+    }
+
+    merge_qa_matches(&db, &mut results, &query_embedding, config, content_key).map_err(|e| e.to_string())?;
+    merge_summary_matches(&db, &mut results, &query_embedding, config, content_key).map_err(|e| e.to_string())?;
+    fuse_keyword_matches(&db, &mut results, query, config).map_err(|e| e.to_string())?;
+    if let Some(t) = timings {
+        t.fusion_ms = elapsed_ms(stage_start);
+    }
+
+    Ok(results)
+}
+
+// Passages sharing at least this many characters at a chunk boundary are stitched together
+const CONTEXT_MIN_BOUNDARY_OVERLAP: usize = 20;
+// Word-set similarity above which two passages count as near-duplicates
+const CONTEXT_DUPLICATE_JACCARD: f32 = 0.8;
+
+// Length in bytes of the longest suffix of `a` that is also a prefix of `b`
+fn boundary_overlap(a: &str, b: &str) -> usize {
+    let max = a.len().min(b.len());
+    b.char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| end <= max)
+        .filter(|&end| a.ends_with(&b[..end]))
+        .last()
+        .unwrap_or(0)
+}
+
+fn word_jaccard(a: &str, b: &str) -> f32 {
+    let words_a: std::collections::HashSet<String> = a.unicode_words().map(|w| w.to_lowercase()).collect();
+    let words_b: std::collections::HashSet<String> = b.unicode_words().map(|w| w.to_lowercase()).collect();
+    let union = words_a.union(&words_b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    words_a.intersection(&words_b).count() as f32 / union as f32
+}
+
+// Tries to fold `other` into `kept`; returns false when the passages are distinct
+fn merge_passage(kept: &mut RetrievalResult, other: &RetrievalResult) -> bool {
+    let same_source = kept.document_title == other.document_title && kept.source_info == other.source_info;
+
+    let merged_content = if kept.content.contains(other.content.as_str()) {
+        None
+    } else if other.content.contains(kept.content.as_str()) {
+        Some(other.content.clone())
+    } else if same_source && boundary_overlap(&kept.content, &other.content) >= CONTEXT_MIN_BOUNDARY_OVERLAP {
+        let overlap = boundary_overlap(&kept.content, &other.content);
+        Some(format!("{}{}", kept.content, &other.content[overlap..]))
+    } else if same_source && boundary_overlap(&other.content, &kept.content) >= CONTEXT_MIN_BOUNDARY_OVERLAP {
+        let overlap = boundary_overlap(&other.content, &kept.content);
+        Some(format!("{}{}", other.content, &kept.content[overlap..]))
+    } else if word_jaccard(&kept.content, &other.content) >= CONTEXT_DUPLICATE_JACCARD {
+        None
+    } else {
+        return false;
+    };
+
+    if let Some(content) = merged_content {
+        kept.content = content;
+    }
+    // Near-duplicates from different documents keep every source as a citation; the anchor's own source stays
+    // untouched so later same-source comparisons still work
+    let sources = std::iter::once(PassageSource {
+        document_title: other.document_title.clone(),
+        source_info: other.source_info.clone(),
+    })
+    .chain(other.merged_sources.iter().cloned());
+    for source in sources {
+        let is_anchor = source.document_title == kept.document_title && source.source_info == kept.source_info;
+        if !is_anchor && !kept.merged_sources.contains(&source) {
+            kept.merged_sources.push(source);
+        }
+    }
+    kept.similarity_score = kept.similarity_score.max(other.similarity_score);
+    kept.merged_chunk_ids.push(other.chunk_id.clone());
+    kept.merged_chunk_ids.extend(other.merged_chunk_ids.iter().cloned());
+    true
+}
+
+// Collapses overlapping or near-identical passages, keeping the best-scoring one as the anchor
+fn deduplicate_context(results: Vec<RetrievalResult>) -> Vec<RetrievalResult> {
+    let mut kept: Vec<RetrievalResult> = Vec::with_capacity(results.len());
+    for result in results {
+        if !kept.iter_mut().any(|existing| merge_passage(existing, &result)) {
+            kept.push(result);
+        }
+    }
+    kept
+}
+
+// Swaps each matched child chunk for its parent passage; siblings of the same parent collapse into the best-scoring
+// one. Results without a parent, such as session attachments or chunks indexed before parents existed, pass through.
+fn expand_to_parents(
+    conn: &Connection,
+    results: Vec<RetrievalResult>,
+    content_key: Option<&ContentKey>,
+) -> Result<Vec<RetrievalResult>> {
+    let mut stmt = conn.prepare(
+        "SELECT pc.id, pc.content, d.is_sensitive FROM document_chunks dc
+         JOIN parent_chunks pc ON pc.id = dc.parent_chunk_id
+         JOIN documents d ON d.id = pc.document_id
+         WHERE dc.id = ?1",
+    )?;
+    let mut expanded: Vec<RetrievalResult> = Vec::with_capacity(results.len());
+    let mut parent_slots: HashMap<String, usize> = HashMap::new();
+    for mut result in results {
+        let mut rows = stmt.query(params![result.chunk_id])?;
+        let parent = match rows.next()? {
+            Some(row) => Some((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?)),
+            None => None,
+        };
+        let Some((parent_id, content, is_sensitive)) = parent else {
+            expanded.push(result);
+            continue;
+        };
+        if let Some(&slot) = parent_slots.get(&parent_id) {
+            let kept = &mut expanded[slot];
+            kept.similarity_score = kept.similarity_score.max(result.similarity_score);
+            kept.merged_chunk_ids.push(result.chunk_id);
+            kept.merged_chunk_ids.extend(result.merged_chunk_ids);
+            for source in result.merged_sources {
+                if !kept.merged_sources.contains(&source) {
+                    kept.merged_sources.push(source);
+                }
+            }
+            continue;
+        }
+        // A locked or undecryptable parent leaves the already-decrypted child in place
+        let content = match (is_sensitive, content_key) {
+            (false, _) => Some(content),
+            (true, Some(key)) => decrypt_text(key, &content).ok(),
+            (true, None) => None,
+        };
+        if let Some(content) = content {
+            result.content = content;
+            parent_slots.insert(parent_id, expanded.len());
+        }
+        expanded.push(result);
+    }
+    Ok(expanded)
+}
+
+// Greedy Maximal Marginal Relevance: each pick trades relevance against similarity to passages
+// already chosen. Passages without a stored vector (session attachments) fall back to word overlap.
+fn diversify_context(
+    mut candidates: Vec<RetrievalResult>,
+    vectors: &HashMap<String, Vec<f32>>,
+    lambda: f32,
+    top_k: usize,
+) -> Vec<RetrievalResult> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    // Scores from fusion, reranking and personalization differ in scale, so compare them min-max normalised
+    let (min, max) = candidates.iter().fold((f32::MAX, f32::MIN), |(lo, hi), r| {
+        (lo.min(r.similarity_score), hi.max(r.similarity_score))
+    });
+    let relevance = |r: &RetrievalResult| if max > min { (r.similarity_score - min) / (max - min) } else { 1.0 };
+    let redundancy = |a: &RetrievalResult, b: &RetrievalResult| match (vectors.get(&a.chunk_id), vectors.get(&b.chunk_id)) {
+        (Some(x), Some(y)) if x.len() == y.len() => cosine_similarity(x, y),
+        _ => word_jaccard(&a.content, &b.content),
+    };
+
+    let mut selected: Vec<RetrievalResult> = Vec::with_capacity(top_k.min(candidates.len()));
+    while selected.len() < top_k && !candidates.is_empty() {
+        let (best, _) = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let max_redundancy = selected.iter().map(|s| redundancy(candidate, s)).fold(0.0f32, f32::max);
+                (index, lambda * relevance(candidate) - (1.0 - lambda) * max_redundancy)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        selected.push(candidates.swap_remove(best));
+    }
+    selected
+}
+
+// Stand-in answers when no generation backend is configured; a selected template replaces them
+async fn generate_answer_with_mode(
+    query: &str,
+    context: &[RetrievalResult],
+    mode: &RAGMode,
+    template: Option<&PromptTemplate>,
+    history: &str,
+) -> String {
+    let default_template = match mode {
+        RAGMode::FineTunedOnly => FINE_TUNED_MOCK_TEMPLATE,
+        RAGMode::FineTunedWithRAG if context.is_empty() => FINE_TUNED_NO_CONTEXT_MOCK_TEMPLATE,
+        RAGMode::FineTunedWithRAG => FINE_TUNED_RAG_MOCK_TEMPLATE,
+        RAGMode::BaseWithRAG if context.is_empty() => BASE_NO_CONTEXT_MOCK_TEMPLATE,
+        RAGMode::BaseWithRAG => BASE_RAG_MOCK_TEMPLATE,
+    };
+    let context_text = context
+        .iter()
+        .map(|r| match mode {
+            RAGMode::FineTunedWithRAG => format!("From {}: {}", r.document_title, r.content),
+            _ => r.content.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let sources = context.iter().map(|r| r.document_title.clone()).collect::<Vec<_>>().join(", ");
+
+    render_prompt_template(
+        template.map_or(default_template, |t| t.template.as_str()),
+        &[("context", &context_text), ("question", query), ("history", history), ("sources", &sources)],
+    )
+}
+
+impl AnswerStyle {
+    fn instructions(&self) -> &'static str {
+        match self {
+            AnswerStyle::Concise => "Reply in a single short paragraph of at most three sentences.",
+            AnswerStyle::Detailed => {
+                "Give a thorough answer and cite the supporting passages by their [number] after each claim."
+            }
+            AnswerStyle::QuotesOnly => "Reply only with verbatim quotes from the context, each followed by its [number].",
+        }
+    }
+
+    // Lower temperatures for the styles that must stay close to the source text
+    fn generation_params(&self) -> serde_json::Value {
+        match self {
+            AnswerStyle::Concise => serde_json::json!({ "temperature": 0.2, "max_tokens": 200 }),
+            AnswerStyle::Detailed => serde_json::json!({ "temperature": 0.7, "max_tokens": 1024 }),
+            AnswerStyle::QuotesOnly => serde_json::json!({ "temperature": 0.0, "max_tokens": 1024 }),
+        }
+    }
+
+    fn post_process(&self, answer: String) -> String {
+        match self {
+            // Models often ignore length limits; keep only the first paragraph
+            AnswerStyle::Concise => answer
+                .split("\n\n")
+                .map(str::trim)
+                .find(|p| !p.is_empty())
+                .unwrap_or_default()
+                .to_string(),
+            AnswerStyle::Detailed | AnswerStyle::QuotesOnly => answer.trim().to_string(),
+        }
+    }
+}
+
+// The answer style's defaults with the config's sampling settings on top
+fn generation_options(config: &RAGConfig, answer_style: AnswerStyle) -> serde_json::Value {
+    let mut options = answer_style.generation_params();
+    if let Some(temperature) = config.temperature {
+        options["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        options["max_tokens"] = serde_json::json!(max_tokens);
+    }
+    if let Some(top_p) = config.top_p {
+        options["top_p"] = serde_json::json!(top_p);
+    }
+    if let Some(frequency_penalty) = config.frequency_penalty {
+        options["frequency_penalty"] = serde_json::json!(frequency_penalty);
+    }
+    if !config.stop.is_empty() {
+        options["stop"] = serde_json::json!(config.stop);
+    }
+    options
+}
+
+// Reads [1], [1, 3] and [2-4] markers; numbers outside the context are ignored
+fn extract_citations(answer: &str, context: &[RetrievalResult]) -> Vec<Citation> {
+    let mut markers = std::collections::BTreeSet::new();
+    for part in answer.split('[').skip(1) {
+        let Some((inside, _)) = part.split_once(']') else {
+            continue;
+        };
+        if inside.is_empty() || !inside.chars().all(|c| c.is_ascii_digit() || matches!(c, ',' | '-' | ' ')) {
+            continue;
+        }
+        for item in inside.split(',').map(str::trim) {
+            let range = match item.split_once('-') {
+                Some((start, end)) => start.trim().parse::<usize>().ok().zip(end.trim().parse::<usize>().ok()),
+                None => item.parse::<usize>().ok().map(|n| (n, n)),
+            };
+            if let Some((start, end)) = range {
+                markers.extend(start.max(1)..=end.min(context.len()));
+            }
+        }
+    }
+
+    markers
+        .into_iter()
+        .map(|marker| {
+            let result = &context[marker - 1];
+            Citation {
+                marker,
+                chunk_id: result.chunk_id.clone(),
+                document_title: result.document_title.clone(),
+                source_info: result.source_info.clone(),
+                merged_sources: result.merged_sources.clone(),
+            }
+        })
+        .collect()
+}
+
+fn quoted_answer(context: &[RetrievalResult]) -> String {
+    if context.is_empty() {
+        return "No passages in the knowledge base matched this question.".to_string();
+    }
+
+    let quotes = context
+        .iter()
+        .enumerate()
+        .map(|(i, r)| format!("> {}\n[{}]", r.content.trim().replace('\n', "\n> "), i + 1))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let citations = context
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let sources = std::iter::once(format!("{} ({})", r.document_title, r.source_info))
+                .chain(r.merged_sources.iter().map(|s| format!("{} ({})", s.document_title, s.source_info)))
+                .collect::<Vec<_>>();
+            format!("[{}] {}", i + 1, sources.join("; "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("{}\n\nSources:\n{}", quotes, citations)
+}
+
+// Without a generation provider, a concise answer is the opening of the best-matching passage
+fn concise_mock_answer(context: &[RetrievalResult]) -> String {
+    let best = &context[0];
+    let summary = best
+        .content
+        .unicode_sentences()
+        .take(2)
+        .collect::<String>();
+    format!("{} [{}]", summary.trim(), best.document_title)
+}
+
+#[tauri::command]
+async fn test_rag_query(
+    query: String,
+    config: RAGConfig,
+    session_id: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<RAGResponse, String> {
+    // This is specifically for testing - always use BaseWithRAG mode
+    answer_rag_query(
+        query,
+        RAGMode::BaseWithRAG,
+        config,
+        None,
+        None,
+        session_id,
+        Vec::new(),
+        db_state,
+        key_state,
+        store_state,
+        providers_state,
+        session_state,
+        hnsw_state,
+        vector_stores_state,
+    )
+    .await
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigComparison {
+    pub query: String,
+    pub response_a: RAGResponse,
+    pub response_b: RAGResponse,
+    pub shared_chunk_ids: Vec<String>, // retrieved under both configs
+    pub only_a_chunk_ids: Vec<String>,
+    pub only_b_chunk_ids: Vec<String>,
+    pub index_differs: bool, // chunking or embedding differs; both still searched the stored index
+}
+
+// Runs one query under two configs, one after the other so neither's latency includes the other's work
+#[tauri::command]
+async fn compare_configs(
+    query: String,
+    config_a: RAGConfig,
+    config_b: RAGConfig,
+    session_id: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<ConfigComparison, String> {
+    let index_differs = config_changes_index(&config_a, &config_b);
+    let mut responses = Vec::with_capacity(2);
+    for (label, config) in [("A", config_a), ("B", config_b)] {
+        let response = answer_rag_query(
+            query.clone(),
+            config.mode.clone(),
+            config,
+            None,
+            None,
+            session_id.clone(),
+            Vec::new(),
+            db_state.clone(),
+            key_state.clone(),
+            store_state.clone(),
+            providers_state.clone(),
+            session_state.clone(),
+            hnsw_state.clone(),
+            vector_stores_state.clone(),
+        )
+        .await
+        .map_err(|e| format!("Config {}: {}", label, e))?;
+        responses.push(response);
+    }
+    let response_b = responses.pop().unwrap();
+    let response_a = responses.pop().unwrap();
+
+    let chunk_ids = |response: &RAGResponse| -> Vec<String> {
+        response.retrieved_context.iter().map(|r| r.chunk_id.clone()).collect()
+    };
+    let (ids_a, ids_b) = (chunk_ids(&response_a), chunk_ids(&response_b));
+    Ok(ConfigComparison {
+        query,
+        shared_chunk_ids: ids_a.iter().filter(|id| ids_b.contains(id)).cloned().collect(),
+        only_a_chunk_ids: ids_a.iter().filter(|id| !ids_b.contains(id)).cloned().collect(),
+        only_b_chunk_ids: ids_b.iter().filter(|id| !ids_a.contains(id)).cloned().collect(),
+        response_a,
+        response_b,
+        index_differs,
+    })
+}
+
+// ---------- Original Tauri Commands --------------------------------------------
+
+#[tauri::command]
+async fn upload_document(
+    file_path: String,
+    title: Option<String>,
+    on_duplicate: Option<DuplicatePolicy>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<UploadResult, String> {
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let extracted = extract_document(Path::new(&file_path), &plugins, &config.extraction)
+        .await
+        .map_err(|e| e.to_string())?;
+    let content = extracted.text;
+    let sections = extracted.sections;
+    
+    let file_name = std::path::Path::new(&file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown")
+        .to_string();
+    
+    let doc_title = title.unwrap_or(file_name);
+    let content_hash = calculate_content_hash(&content);
+    let now = Utc::now();
+
+    let policy = on_duplicate.unwrap_or_default();
+    // Extract file type before moving file_path
+    let file_type = std::path::Path::new(&file_path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    // The duplicate lookup and the insert share one IMMEDIATE transaction, so two concurrent
+    // uploads of the same file can't both miss the other and store two copies
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+    let mut metadata = extracted.metadata;
+    if let Some(existing) = find_duplicate_document(&tx, &content_hash) {
+        match policy {
+            DuplicatePolicy::Skip => {
+                return Ok(UploadResult {
+                    duplicate_of: Some(existing.id.clone()),
+                    document: existing,
+                    status: UploadStatus::Duplicate,
+                });
+            }
+            DuplicatePolicy::Link => {
+                tx.execute(
+                    "UPDATE documents SET
+                         metadata = json_insert(
+                             json_set(COALESCE(metadata, '{}'), '$.linked_paths',
+                                      json(COALESCE(json_extract(metadata, '$.linked_paths'), '[]'))),
+                             '$.linked_paths[#]', ?1),
+                         updated_at = ?2
+                     WHERE id = ?3 AND NOT EXISTS (
+                         SELECT 1 FROM json_each(COALESCE(json_extract(metadata, '$.linked_paths'), '[]')) WHERE value = ?1)",
+                    params![file_path, now.to_rfc3339(), existing.id],
+                )
+                .map_err(|e| e.to_string())?;
+                record_audit(&tx, "document_linked", Some(&existing.id), serde_json::json!({ "file_path": file_path }));
+                tx.commit().map_err(|e| e.to_string())?;
+                return Ok(UploadResult {
+                    duplicate_of: Some(existing.id.clone()),
+                    document: existing,
+                    status: UploadStatus::Linked,
+                });
+            }
+            DuplicatePolicy::Version => {
+                let version = existing.metadata.get("version").and_then(|v| v.as_u64()).unwrap_or(1) + 1;
+                if let Some(fields) = metadata.as_object_mut() {
+                    fields.insert("version".to_string(), serde_json::json!(version));
+                    fields.insert("previous_version".to_string(), serde_json::json!(existing.id));
+                } else {
+                    metadata = serde_json::json!({ "version": version, "previous_version": existing.id });
+                }
+            }
+        }
+    }
+    let superseded = metadata.get("previous_version").and_then(|v| v.as_str()).map(str::to_string);
+
+    let document = Document {
+        id: Uuid::new_v4().to_string(),
+        title: doc_title,
+        content: content.clone(),
+        file_path: Some(file_path),
+        file_type,
+        content_hash,
+        is_searchable: true,
+        is_sensitive: false,
+        metadata,
+        tags: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    // Save to database
+    insert_document(&tx, &document).map_err(|e| e.to_string())?;
+    store_document_sections(&tx, &document.id, &sections).map_err(|e| e.to_string())?;
+    // The superseded version stays in the library but drops out of retrieval
+    if let Some(previous_id) = &superseded {
+        tx.execute(
+            "UPDATE documents SET is_searchable = 0, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), previous_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    record_audit(&tx, "document_added", Some(&document.id), serde_json::json!({
+        "title": document.title,
+        "file_path": document.file_path,
+        "previous_version": superseded,
+    }));
+    tx.commit().map_err(|e| e.to_string())?;
+    drop(db);
+
+    // Process chunks in background
+    let doc_id = document.id.clone();
+    let db_clone = db_state.inner().clone();
+    let app_clone = app.clone();
+    let providers = providers_state.inner().clone();
+    
+    tokio::spawn(async move {
+        match process_document_chunks_enhanced(&doc_id, &content, &sections, &db_clone, &config, &providers).await {
+            Ok(_) => {
+                dispatch_webhook_event(&db_clone, "document.processed", serde_json::json!({ "document_id": doc_id }));
+            }
+            Err(e) => {
+                eprintln!("Error processing chunks: {}", e);
+                dispatch_webhook_event(&db_clone, "alert", serde_json::json!({
+                    "kind": "document_processing_failed",
+                    "document_id": doc_id,
+                    "error": e.to_string(),
+                }));
+            }
+        }
+        let _ = app_clone.emit("document_processed", &doc_id);
+    });
+
+    Ok(UploadResult {
+        document,
+        status: if superseded.is_some() { UploadStatus::Versioned } else { UploadStatus::Created },
+        duplicate_of: superseded,
+    })
+}
+
+#[tauri::command]
+fn get_documents(
+    tags: Option<Vec<String>>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<Vec<Document>, String> {
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT {} FROM documents d WHERE d.deleted_at IS NULL{} ORDER BY d.created_at DESC",
+            DOCUMENT_COLUMNS,
+            tag_filter_clause(1)
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let document_iter = stmt
+        .query_map(params![tag_filter_value(&tags.unwrap_or_default())], |row| document_from_row(row, 0))
+        .map_err(|e| e.to_string())?;
+
+    let documents: Vec<Document> = document_iter
+        .filter_map(Result::ok)
+        .map(|mut doc| {
+            // Locked sensitive documents are listed without their content
+            if doc.is_sensitive {
+                doc.content = content_key
+                    .as_ref()
+                    .and_then(|key| decrypt_text(key, &doc.content).ok())
+                    .unwrap_or_default();
+            }
+            doc
+        })
+        .collect();
+
+    Ok(documents)
+}
+
+#[tauri::command]
+async fn search_documents(
+    query: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+) -> Result<Vec<SearchResult>, String> {
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let (metric, backend, top_k, tags, chunk_filter) = (
+        config.similarity_metric,
+        config.vector_backend.clone(),
+        config.top_k,
+        config.tags.clone(),
+        config.chunk_filter.clone(),
+    );
+    let start_time = std::time::Instant::now();
+    let query_embedding = generate_embedding(&providers_state, &query, &config)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut results = Vec::new();
+
+    let external = vector_stores_state
+        .candidates(db_state.inner(), &config, &query_embedding)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let (candidates, remote) = match external {
+        Some(external) => (Some(external.local_ids), external.remote),
+        None => (
+            vector_index_candidates(&db, &backend, metric, hnsw_state.inner(), &query_embedding, top_k)
+                .map_err(|e| e.to_string())?,
+            Vec::new(),
+        ),
+    };
+
+    // Get all candidate chunks with their embeddings
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT dc.document_id, dc.content, COALESCE(se.embedding, dc.embedding), {}
+             FROM document_chunks dc
+             JOIN documents d ON dc.document_id = d.id
+             LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+             WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND (d.is_sensitive = 0 OR ?1)
+               AND (?2 IS NULL OR dc.id IN (SELECT value FROM json_each(?2))){}{}",
+            DOCUMENT_COLUMNS,
+            tag_filter_clause(3),
+            chunk_filter_clause(&chunk_filter)
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let chunk_iter = stmt
+        .query_map(params![content_key.is_some(), candidates, tag_filter_value(&tags)], |row| {
+            let embedding_bytes: Vec<u8> = row.get(2)?;
+            let embedding = decode_embedding(&embedding_bytes);
+
+            Ok((
+                row.get::<_, String>(1)?, // chunk content
+                embedding,
+                document_from_row(row, 3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut doc_results: HashMap<String, (Document, Vec<String>, f32)> = HashMap::new();
+
+    for chunk_result in chunk_iter {
+        if let Ok((chunk_content, chunk_embedding, mut document)) = chunk_result {
+            let similarity = compute_similarity(metric, &query_embedding, &chunk_embedding);
+            
+            if similarity > 0.3 { // Threshold for relevance
+                let chunk_content = if document.is_sensitive {
+                    let Some(key) = content_key.as_ref() else { continue };
+                    match (decrypt_text(key, &chunk_content), decrypt_text(key, &document.content)) {
+                        (Ok(chunk_text), Ok(doc_text)) => {
+                            document.content = doc_text;
+                            chunk_text
+                        }
+                        _ => {
+                            warn!("Failed to decrypt sensitive document {}", document.id);
+                            continue;
+                        }
+                    }
+                } else {
+                    chunk_content
+                };
+
+                doc_results
+                    .entry(document.id.clone())
+                    .and_modify(|(_, chunks, max_sim)| {
+                        chunks.push(chunk_content.clone());
+                        *max_sim = max_sim.max(similarity);
+                    })
+                    .or_insert((document, vec![chunk_content], similarity));
+            }
+        }
+    }
+
+    // Chunks from a shared store stand in for documents this install does not have
+    for chunk in remote.into_iter().filter(|chunk| chunk.score > 0.3) {
+        let now = Utc::now();
+        doc_results
+            .entry(chunk.payload.document_id.clone())
+            .and_modify(|(_, chunks, max_sim)| {
+                chunks.push(chunk.payload.content.clone());
+                *max_sim = max_sim.max(chunk.score);
+            })
+            .or_insert_with(|| {
+                let document = Document {
+                    id: chunk.payload.document_id.clone(),
+                    title: chunk.payload.document_title.clone(),
+                    content: String::new(),
+                    file_path: None,
+                    file_type: "shared".to_string(),
+                    content_hash: String::new(),
+                    is_searchable: true,
+                    is_sensitive: false,
+                    metadata: serde_json::json!({ "source_info": chunk.payload.source_info }),
+                    tags: chunk.payload.tags.clone(),
+                    created_at: now,
+                    updated_at: now,
+                };
+                (document, vec![chunk.payload.content.clone()], chunk.score)
+            });
+    }
+
+    // Convert to SearchResult vector and sort by similarity
+    for (_, (document, chunks, similarity)) in doc_results {
+        results.push(SearchResult {
+            document,
+            relevant_chunks: chunks,
+            similarity_score: similarity,
+        });
+    }
+
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    results.truncate(10); // Return top 10 results
+
+    // Nothing is logged against a shared read-only copy
+    if !is_read_only(&db) {
+        if let Err(e) = log_query(
+            &db,
+            &query,
+            "search",
+            None,
+            serde_json::json!({ "threshold": 0.3, "limit": 10 }),
+            results.len(),
+            results.first().map(|r| r.similarity_score),
+            start_time.elapsed().as_millis() as u64,
+        ) {
+            warn!("Failed to log query: {}", e);
+        }
+    }
+
+    Ok(results)
+}
+
+// Without a session_id the most recently active session is continued
+#[tauri::command]
+async fn chat_with_documents(
+    message: String,
+    session_id: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+) -> Result<ChatResponse, String> {
+    // A shared read-only copy keeps no chat history, so there is no session to attach to
+    let session_id = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        if is_read_only(&db) {
+            None
+        } else {
+            Some(resolve_chat_session(&db, session_id.as_deref(), &message).map_err(|e| e.to_string())?)
+        }
+    };
+
+    // First, search for relevant documents
+    let search_results = search_documents(
+        message.clone(),
+        db_state.clone(),
+        key_state.clone(),
+        config_state.clone(),
+        hnsw_state.clone(),
+        vector_stores_state.clone(),
+        providers_state.clone(),
+    )
+    .await?;
+    
+    // Save user message
+    let user_msg = ChatMessage {
+        id: Uuid::new_v4().to_string(),
+        content: message.clone(),
+        role: "user".to_string(),
+        document_references: search_results.iter().map(|r| r.document.id.clone()).collect(),
+        session_id: session_id.clone(),
+        reply_to: None,
+        created_at: Utc::now(),
+    };
+
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let mut attachments = match &session_id {
+        Some(session_id) => {
+            search_session_attachments(session_state.inner(), session_id, &message, &config, providers_state.inner())
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        None => Vec::new(),
+    };
+    attachments.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    attachments.truncate(config.top_k);
+
+    let context = search_results
+        .iter()
+        .flat_map(|r| r.relevant_chunks.iter())
+        .chain(attachments.iter().map(|r| &r.content))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let (ai_response, tool_calls) = match generation_target(&config) {
+        Some(target) => {
+            let tools = {
+                let db = db_state.lock().map_err(|e| e.to_string())?;
+                resolve_chat_tools(&db, &config.chat_tools).map_err(|e| e.to_string())?
+            };
+            let messages = prompt_messages(
+                None,
+                &[
+                    ("context", &context),
+                    ("question", &message),
+                    ("history", ""),
+                    ("instructions", AnswerStyle::default().instructions()),
+                ],
+            );
+            // The search tool goes through the same document search as the chat itself
+            let search = |query: String| {
+                let (db_state, key_state, config_state) = (db_state.clone(), key_state.clone(), config_state.clone());
+                let (hnsw_state, vector_stores_state) = (hnsw_state.clone(), vector_stores_state.clone());
+                let providers_state = providers_state.clone();
+                async move {
+                    let results = search_documents(
+                        query,
+                        db_state,
+                        key_state,
+                        config_state,
+                        hnsw_state,
+                        vector_stores_state,
+                        providers_state,
+                    )
+                        .await
+                        .map_err(|e| anyhow::anyhow!(e))?;
+                    Ok::<_, anyhow::Error>(
+                        results
+                            .iter()
+                            .flat_map(|r| r.relevant_chunks.iter().map(move |chunk| format!("From {}: {}", r.document.title, chunk)))
+                            .collect::<Vec<_>>()
+                            .join("\n\n"),
+                    )
+                }
+            };
+            run_with_tools(&target, messages, &tools, &generation_options(&config, AnswerStyle::default()), search)
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        // Mock implementation when no generation backend is configured
+        None if context.is_empty() => (
+            "I don't have any relevant documents to answer your question. Please upload some documents first.".to_string(),
+            Vec::new(),
+        ),
+        None => (
+            format!(
+                "Based on the uploaded documents, here's what I found:\n\n{}\n\nThis information comes from {} document(s) in your knowledge base.",
+                context,
+                search_results.len()
+            ),
+            Vec::new(),
+        ),
+    };
+
+    let assistant_msg = ChatMessage {
+        id: Uuid::new_v4().to_string(),
+        content: ai_response,
+        role: "assistant".to_string(),
+        document_references: search_results.iter().map(|r| r.document.id.clone()).collect(),
+        session_id: session_id.clone(),
+        reply_to: Some(user_msg.id.clone()),
+        created_at: Utc::now(),
+    };
+
+    // Save both messages to database
+    if session_id.is_some() {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        for msg in [&user_msg, &assistant_msg] {
+            insert_chat_message(&db, msg).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(ChatResponse {
+        message: assistant_msg,
+        sources: search_results,
+        tool_calls,
+    })
+}
+
+// Without a session_id the most recently active session is returned
+#[tauri::command]
+fn get_chat_history(
+    session_id: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<ChatMessage>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let session_id = match session_id {
+        Some(session_id) => session_id,
+        None => match latest_chat_session(&db).map_err(|e| e.to_string())? {
+            Some(session_id) => session_id,
+            None => return Ok(Vec::new()),
+        },
+    };
+    load_chat_messages(&db, &session_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_document(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let deleted = {
+        let mut db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+        let deleted = delete_document_rows(&tx, &document_id).map_err(|e| e.to_string())?;
+        if deleted > 0 {
+            record_audit(&tx, "document_deleted", Some(&document_id), serde_json::json!({}));
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        deleted > 0
+    };
+
+    // The sync manifest turns the missing row into a tombstone on the next run
+    if deleted {
+        dispatch_webhook_event(db_state.inner(), "document.deleted", serde_json::json!({ "document_id": document_id }));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn set_document_searchable(
+    document_id: String,
+    searchable: bool,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+
+    // Parked documents keep their chunks and embeddings; retrieval simply skips them
+    let updated = db
+        .execute(
+            "UPDATE documents SET is_searchable = ?1, updated_at = ?2 WHERE id = ?3",
+            params![searchable, Utc::now().to_rfc3339(), document_id],
+        )
+        .map_err(|e| e.to_string())?;
+
+    if updated == 0 {
+        return Err(format!("Document not found: {}", document_id));
+    }
+
+    record_audit(&db, "settings_changed", Some(&document_id), serde_json::json!({ "is_searchable": searchable }));
+    Ok(())
+}
+
+// Only the fields passed are changed; an empty collection clears it. Chunks and embeddings are untouched
+#[tauri::command]
+fn update_document_metadata(
+    document_id: String,
+    title: Option<String>,
+    tags: Option<Vec<String>>,
+    collection: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let title = match title.map(|t| t.trim().to_string()) {
+        Some(t) if t.is_empty() => return Err("Title must not be empty".to_string()),
+        other => other,
+    };
+    let tags = tags
+        .map(|tags| tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>>>())
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+
+    let updated = tx
+        .execute(
+            "UPDATE documents SET updated_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            params![Utc::now().to_rfc3339(), document_id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("Document not found: {}", document_id));
+    }
+
+    if let Some(title) = &title {
+        tx.execute("UPDATE documents SET title = ?1 WHERE id = ?2", params![title, document_id])
+            .map_err(|e| e.to_string())?;
+    }
+    match collection.as_deref().map(str::trim) {
+        Some("") => tx.execute(
+            "UPDATE documents SET metadata = json_remove(COALESCE(metadata, '{}'), '$.collection') WHERE id = ?1",
+            params![document_id],
+        ),
+        Some(collection) => tx.execute(
+            "UPDATE documents SET metadata = json_set(COALESCE(metadata, '{}'), '$.collection', ?1) WHERE id = ?2",
+            params![collection, document_id],
+        ),
+        None => Ok(0),
+    }
+    .map_err(|e| e.to_string())?;
+    if let Some(tags) = &tags {
+        tx.execute("DELETE FROM document_tags WHERE document_id = ?1", params![document_id])
+            .map_err(|e| e.to_string())?;
+        for tag in tags {
+            attach_tag(&tx, &document_id, tag).map_err(|e| e.to_string())?;
+        }
+    }
+
+    record_audit(&tx, "settings_changed", Some(&document_id), serde_json::json!({
+        "title": title,
+        "tags": tags,
+        "collection": collection,
+    }));
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn run_fine_tune(
+    config: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let script_path = app
+        .path_resolver()
+        .resolve_resource("../backend/fine_tune.py")
+        .ok_or("Script not found")?;
+
+        let mut child = Command::new("python3")
+        .arg(script_path)
+        .arg(config)
+            .stdout(Stdio::piped())
+            .spawn()
+        .map_err(|e| e.to_string())?;
+
+        if let Some(stdout) = child.stdout.take() {
+        let app_handle = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut reader = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                let _ = app_handle.emit("fine_tune_log", line);
+            }
+        });
+    }
+
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    dispatch_webhook_event(db_state.inner(), "fine_tune.completed", serde_json::json!({
+        "success": status.success(),
+        "exit_code": status.code(),
+    }));
+    Ok(())
+}
+
+// ---------- Chat Sessions --------------------------------------------------------
+
+const DEFAULT_CHAT_SESSION_TITLE: &str = "New chat";
+const CHAT_SESSION_TITLE_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub id: String,
+    pub title: String,
+    pub message_count: usize,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn latest_chat_session(conn: &Connection) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT id FROM chat_sessions ORDER BY updated_at DESC LIMIT 1")?;
+    let mut rows = stmt.query([])?;
+    Ok(rows.next()?.map(|row| row.get::<_, String>(0)).transpose()?)
+}
+
+fn insert_chat_session(conn: &Connection, title: &str) -> Result<ChatSession> {
+    let now = Utc::now();
+    let session = ChatSession {
+        id: Uuid::new_v4().to_string(),
+        title: title.to_string(),
+        message_count: 0,
+        created_at: now,
+        updated_at: now,
+    };
+    conn.execute(
+        "INSERT INTO chat_sessions (id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+        params![session.id, session.title, session.created_at.to_rfc3339(), session.updated_at.to_rfc3339()],
+    )?;
+    Ok(session)
+}
+
+// The first question names an untitled session
+fn session_title_from_message(message: &str) -> String {
+    let title: String = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    match title.char_indices().nth(CHAT_SESSION_TITLE_CHARS) {
+        Some((end, _)) => format!("{}…", title[..end].trim_end()),
+        None => title,
+    }
+}
+
+// Checks an explicit session, else picks the latest one, else starts the first
+fn resolve_chat_session(conn: &Connection, session_id: Option<&str>, message: &str) -> Result<String> {
+    let session_id = match session_id {
+        Some(session_id) => {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM chat_sessions WHERE id = ?1)",
+                params![session_id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(anyhow::anyhow!("Chat session not found: {}", session_id));
+            }
+            session_id.to_string()
+        }
+        None => match latest_chat_session(conn)? {
+            Some(session_id) => session_id,
+            None => insert_chat_session(conn, DEFAULT_CHAT_SESSION_TITLE)?.id,
+        },
+    };
+    conn.execute(
+        "UPDATE chat_sessions SET title = ?1
+         WHERE id = ?2 AND title = ?3 AND NOT EXISTS (SELECT 1 FROM chat_messages WHERE session_id = ?2)",
+        params![session_title_from_message(message), session_id, DEFAULT_CHAT_SESSION_TITLE],
+    )?;
+    Ok(session_id)
+}
+
+fn load_chat_messages(conn: &Connection, session_id: &str) -> Result<Vec<ChatMessage>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, role, document_references, session_id, created_at, reply_to FROM chat_messages
+         WHERE session_id = ?1 ORDER BY created_at ASC",
+    )?;
+
+    let message_iter = stmt.query_map(params![session_id], |row| {
+        let doc_refs_str: Option<String> = row.get(3)?;
+        let document_references: Vec<String> = doc_refs_str
+            .and_then(|refs| serde_json::from_str(&refs).ok())
+            .unwrap_or_default();
+
+        Ok(ChatMessage {
+            id: row.get(0)?,
+            content: row.get(1)?,
+            role: row.get(2)?,
+            document_references,
+            session_id: row.get(4)?,
+            reply_to: row.get(6)?,
+            created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+                .unwrap()
+                .with_timezone(&Utc),
+        })
+    })?;
+
+    Ok(message_iter.filter_map(Result::ok).collect())
+}
+
+// Also bumps the session so it sorts as the most recently active
+fn insert_chat_message(conn: &Connection, message: &ChatMessage) -> Result<()> {
+    conn.execute(
+        "INSERT INTO chat_messages (id, content, role, document_references, session_id, reply_to, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            message.id,
+            message.content,
+            message.role,
+            serde_json::to_string(&message.document_references)?,
+            message.session_id,
+            message.reply_to,
+            message.created_at.to_rfc3339(),
+        ],
+    )?;
+    conn.execute(
+        "UPDATE chat_sessions SET updated_at = ?1 WHERE id = ?2",
+        params![message.created_at.to_rfc3339(), message.session_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+fn create_chat_session(
+    title: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<ChatSession, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let title = title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    insert_chat_session(&db, title.as_deref().unwrap_or(DEFAULT_CHAT_SESSION_TITLE)).map_err(|e| e.to_string())
+}
+
+// Most recently active first
+#[tauri::command]
+fn list_chat_sessions(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<ChatSession>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT s.id, s.title, s.created_at, s.updated_at, COUNT(m.id)
+             FROM chat_sessions s LEFT JOIN chat_messages m ON m.session_id = s.id
+             GROUP BY s.id ORDER BY s.updated_at DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let sessions = stmt
+        .query_map([], |row| {
+            Ok(ChatSession {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(2)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+                updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+                message_count: row.get::<_, i64>(4)? as usize,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(sessions)
+}
+
+#[tauri::command]
+fn rename_chat_session(
+    session_id: String,
+    title: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("A chat session needs a title".to_string());
+    }
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let renamed = db
+        .execute("UPDATE chat_sessions SET title = ?1 WHERE id = ?2", params![title, session_id])
+        .map_err(|e| e.to_string())?;
+
+    if renamed == 0 {
+        return Err(format!("Chat session not found: {}", session_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_chat_session(
+    session_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+) -> Result<(), String> {
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM message_feedback WHERE message_id IN (SELECT id FROM chat_messages WHERE session_id = ?1)",
+        params![session_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM chat_messages WHERE session_id = ?1", params![session_id])
+        .map_err(|e| e.to_string())?;
+    let removed = tx
+        .execute("DELETE FROM chat_sessions WHERE id = ?1", params![session_id])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Chat session not found: {}", session_id));
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    session_state.lock().map_err(|e| e.to_string())?.sessions.remove(&session_id);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TranscriptFormat {
+    #[serde(rename = "markdown")]
+    Markdown,
+    #[serde(rename = "html")]
+    Html,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_transcript(
+    title: &str,
+    messages: &[ChatMessage],
+    document_titles: &HashMap<String, String>,
+    format: TranscriptFormat,
+) -> String {
+    let exported_at = Utc::now().format("%Y-%m-%d %H:%M UTC");
+    let speaker = |message: &ChatMessage| if message.role == "assistant" { "Assistant" } else { "User" };
+    // Documents deleted since the conversation are still listed, by id
+    let sources = |message: &ChatMessage| -> Vec<String> {
+        message
+            .document_references
+            .iter()
+            .map(|id| document_titles.get(id).cloned().unwrap_or_else(|| id.clone()))
+            .collect()
+    };
+
+    match format {
+        TranscriptFormat::Markdown => {
+            let mut out = format!("# {}\n\n_Exported {}_\n", title, exported_at);
+            for message in messages {
+                out.push_str(&format!(
+                    "\n### {} — {}\n\n{}\n",
+                    speaker(message),
+                    message.created_at.format("%Y-%m-%d %H:%M UTC"),
+                    message.content.trim()
+                ));
+                let sources = sources(message);
+                if message.role == "assistant" && !sources.is_empty() {
+                    out.push_str("\n**Sources:**\n\n");
+                    for source in sources {
+                        out.push_str(&format!("- {}\n", source));
+                    }
+                }
+            }
+            out
+        }
+        TranscriptFormat::Html => {
+            let mut body = String::new();
+            for message in messages {
+                body.push_str(&format!(
+                    "<section class=\"{}\">\n<h3>{} <time>{}</time></h3>\n<div class=\"content\">{}</div>\n",
+                    escape_html(&message.role),
+                    speaker(message),
+                    message.created_at.format("%Y-%m-%d %H:%M UTC"),
+                    escape_html(message.content.trim())
+                ));
+                let sources = sources(message);
+                if message.role == "assistant" && !sources.is_empty() {
+                    body.push_str("<p class=\"sources\">Sources:</p>\n<ul>\n");
+                    for source in sources {
+                        body.push_str(&format!("<li>{}</li>\n", escape_html(&source)));
+                    }
+                    body.push_str("</ul>\n");
+                }
+                body.push_str("</section>\n");
+            }
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+                 body {{ font-family: sans-serif; max-width: 48rem; margin: 2rem auto; line-height: 1.5; }}\n\
+                 section {{ border-top: 1px solid #ddd; padding: 0.5rem 0; }}\n\
+                 time {{ color: #888; font-size: 0.8em; font-weight: normal; }}\n\
+                 .content {{ white-space: pre-wrap; }}\n\
+                 .assistant {{ background: #f7f7f9; padding: 0.5rem 1rem; }}\n\
+                 </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p><em>Exported {exported_at}</em></p>\n{body}</body>\n</html>\n",
+                title = escape_html(title),
+                exported_at = exported_at,
+                body = body
+            )
+        }
+    }
+}
+
+// Writes a shareable transcript with timestamps and the documents each answer drew on
+#[tauri::command]
+fn export_chat_session(
+    session_id: String,
+    format: TranscriptFormat,
+    path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let (title, messages, document_titles) = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        let title: String = db
+            .query_row("SELECT title FROM chat_sessions WHERE id = ?1", params![session_id], |row| row.get(0))
+            .map_err(|_| format!("Chat session not found: {}", session_id))?;
+        let messages = load_chat_messages(&db, &session_id).map_err(|e| e.to_string())?;
+
+        let mut document_titles = HashMap::new();
+        for document_id in messages.iter().flat_map(|m| &m.document_references) {
+            if document_titles.contains_key(document_id) {
+                continue;
+            }
+            if let Ok(title) = db.query_row(
+                "SELECT title FROM documents WHERE id = ?1",
+                params![document_id],
+                |row| row.get::<_, String>(0),
+            ) {
+                document_titles.insert(document_id.clone(), title);
+            }
+        }
+        (title, messages, document_titles)
+    };
+
+    let path = Path::new(&path);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, render_transcript(&title, &messages, &document_titles, format)).map_err(|e| e.to_string())
+}
+
+// Settings to change when regenerating; anything unset keeps the current config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegenerateOverrides {
+    #[serde(default)]
+    pub mode: Option<RAGMode>,
+    #[serde(default)]
+    pub answer_style: Option<AnswerStyle>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+// Answers the question behind an assistant message again and stores the result alongside the original
+#[tauri::command]
+async fn regenerate_answer(
+    message_id: String,
+    overrides: Option<RegenerateOverrides>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<ChatMessage, String> {
+    let overrides = overrides.unwrap_or_default();
+    let (session_id, question, history) = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        let session_id: Option<String> = db
+            .query_row(
+                "SELECT session_id FROM chat_messages WHERE id = ?1 AND role = 'assistant'",
+                params![message_id],
+                |row| row.get(0),
+            )
+            .map_err(|_| format!("Assistant message not found: {}", message_id))?;
+        let session_id = session_id.ok_or("Only messages in a chat session can be regenerated")?;
+        let messages = load_chat_messages(&db, &session_id).map_err(|e| e.to_string())?;
+
+        // Older answers carry no reply_to; the closest user message before them is the question
+        let position = messages.iter().position(|m| m.id == message_id).ok_or("Assistant message not found")?;
+        let original = &messages[position];
+        let question_index = match &original.reply_to {
+            Some(reply_to) => messages.iter().position(|m| &m.id == reply_to),
+            None => messages[..position].iter().rposition(|m| m.role == "user"),
+        }
+        .ok_or("No user message precedes this answer")?;
+
+        let question = messages[question_index].clone();
+        let history: Vec<LLMMessage> = messages[..question_index]
+            .iter()
+            .map(|m| LLMMessage::new(&m.role, m.content.clone()))
+            .collect();
+        (session_id, question, history)
+    };
+
+    let config = {
+        let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+        RAGConfig {
+            temperature: overrides.temperature.or(config.temperature),
+            max_tokens: overrides.max_tokens.or(config.max_tokens),
+            ..config
+        }
+    };
+    let response = answer_rag_query(
+        question.content.clone(),
+        overrides.mode.unwrap_or(RAGMode::BaseWithRAG),
+        config,
+        overrides.answer_style,
+        None,
+        Some(session_id),
+        history,
+        db_state.clone(),
+        key_state,
+        store_state,
+        providers_state,
+        session_state,
+        hnsw_state,
+        vector_stores_state,
+    )
+    .await?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut document_references: Vec<String> = Vec::new();
+    for result in &response.retrieved_context {
+        if let Ok(document_id) = db.query_row(
+            "SELECT document_id FROM document_chunks WHERE id = ?1",
+            params![result.chunk_id],
+            |row| row.get::<_, String>(0),
+        ) {
+            if !document_references.contains(&document_id) {
+                document_references.push(document_id);
+            }
+        }
+    }
+    let answer = ChatMessage {
+        id: Uuid::new_v4().to_string(),
+        content: response.answer,
+        role: "assistant".to_string(),
+        document_references,
+        session_id: question.session_id.clone(),
+        reply_to: Some(question.id.clone()),
+        created_at: Utc::now(),
+    };
+    insert_chat_message(&db, &answer).map_err(|e| e.to_string())?;
+    Ok(answer)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MessageRating {
+    #[serde(rename = "up")]
+    Up,
+    #[serde(rename = "down")]
+    Down,
+}
+
+impl MessageRating {
+    fn score(self) -> i64 {
+        match self {
+            MessageRating::Up => 1,
+            MessageRating::Down => -1,
+        }
+    }
+
+    fn from_score(score: i64) -> Self {
+        if score > 0 { MessageRating::Up } else { MessageRating::Down }
+    }
+}
+
+// A rated answer with the question it responds to, as used for fine-tuning data and evaluation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageFeedback {
+    pub message_id: String,
+    pub session_id: Option<String>,
+    pub question: Option<String>,
+    pub answer: String,
+    pub document_references: Vec<String>,
+    pub rating: MessageRating,
+    pub comment: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Rating again replaces the earlier rating; no rating clears it
+#[tauri::command]
+fn rate_message(
+    message_id: String,
+    rating: Option<MessageRating>,
+    comment: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let is_answer: bool = db
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM chat_messages WHERE id = ?1 AND role = 'assistant')",
+            params![message_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !is_answer {
+        return Err(format!("Assistant message not found: {}", message_id));
+    }
+
+    let Some(rating) = rating else {
+        db.execute("DELETE FROM message_feedback WHERE message_id = ?1", params![message_id])
+            .map_err(|e| e.to_string())?;
+        return Ok(());
+    };
+    let comment = comment.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    let now = Utc::now().to_rfc3339();
+    db.execute(
+        "INSERT INTO message_feedback (message_id, rating, comment, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+         ON CONFLICT(message_id) DO UPDATE SET rating = excluded.rating, comment = excluded.comment, updated_at = excluded.updated_at",
+        params![message_id, rating.score(), comment, now],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Older answers without reply_to are paired with the closest earlier user message in their session
+fn load_message_feedback(conn: &Connection, rating: Option<MessageRating>) -> Result<Vec<MessageFeedback>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.message_id, m.session_id, m.content, m.document_references, f.rating, f.comment, f.updated_at,
+                COALESCE(
+                    (SELECT q.content FROM chat_messages q WHERE q.id = m.reply_to),
+                    (SELECT q.content FROM chat_messages q
+                     WHERE q.session_id = m.session_id AND q.role = 'user' AND q.created_at <= m.created_at
+                     ORDER BY q.created_at DESC LIMIT 1)
+                )
+         FROM message_feedback f JOIN chat_messages m ON m.id = f.message_id
+         WHERE ?1 IS NULL OR f.rating = ?1
+         ORDER BY f.updated_at DESC",
+    )?;
+    let rows = stmt.query_map(params![rating.map(MessageRating::score)], |row| {
+        let document_references: Option<String> = row.get(3)?;
+        Ok(MessageFeedback {
+            message_id: row.get(0)?,
+            session_id: row.get(1)?,
+            answer: row.get(2)?,
+            document_references: document_references
+                .and_then(|refs| serde_json::from_str(&refs).ok())
+                .unwrap_or_default(),
+            rating: MessageRating::from_score(row.get(4)?),
+            comment: row.get(5)?,
+            updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?)
+                .unwrap()
+                .with_timezone(&Utc),
+            question: row.get(7)?,
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+#[tauri::command]
+fn list_message_feedback(
+    rating: Option<MessageRating>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<MessageFeedback>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_message_feedback(&db, rating).map_err(|e| e.to_string())
+}
+
+// ---------- Audit Log ----------------------------------------------------------
+
+// Audit failures are logged rather than propagated so they never block the audited action
+fn record_audit(conn: &Connection, action: &str, target: Option<&str>, details: serde_json::Value) {
+    if let Err(e) = conn.execute(
+        "INSERT INTO audit_log (action, target, details, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![action, target, details.to_string(), Utc::now().to_rfc3339()],
+    ) {
+        error!("Failed to write audit entry '{}': {}", action, e);
+    }
+}
+
+fn record_audit_state(
+    db_state: &Arc<DbPool>,
+    action: &str,
+    target: Option<&str>,
+    details: serde_json::Value,
+) {
+    match db_state.lock() {
+        Ok(db) => record_audit(&db, action, target, details),
+        Err(e) => error!("Failed to write audit entry '{}': {}", action, e),
+    }
+}
+
+#[tauri::command]
+fn get_audit_log(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    action: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<AuditEntry>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT id, action, target, details, created_at FROM audit_log
+             WHERE created_at >= ?1 AND created_at <= ?2 AND (?3 IS NULL OR action = ?3)
+             ORDER BY id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let from = from.map(|t| t.to_rfc3339()).unwrap_or_default();
+    let to = to.unwrap_or_else(Utc::now).to_rfc3339();
+
+    let entry_iter = stmt
+        .query_map(params![from, to, action], |row| {
+            let details_str: String = row.get(3)?;
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                action: row.get(1)?,
+                target: row.get(2)?,
+                details: serde_json::from_str(&details_str).unwrap_or(serde_json::Value::Null),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(entry_iter.filter_map(Result::ok).collect())
+}
+
+// ---------- Content Encryption -------------------------------------------------
+
+const KEY_DERIVATION_ROUNDS: u32 = 200_000;
+const KEY_CHECK_PLAINTEXT: &str = "rag-app-content-key";
+
+/// Per-profile AES-256 key, held in memory only while the session is unlocked
+#[derive(Clone)]
+pub struct ContentKey([u8; 32]);
+
+fn derive_content_key(passphrase: &str, salt: &[u8]) -> ContentKey {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KEY_DERIVATION_ROUNDS, &mut key);
+    ContentKey(key)
+}
+
+// Encrypts to hex(nonce || ciphertext) so the result fits the existing TEXT columns
+fn encrypt_text(key: &ContentKey, plaintext: &str) -> Result<String> {
+    let cipher = Aes256Gcm::new_from_slice(&key.0).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let nonce_bytes: [u8; 12] = rand::random();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+    let mut payload = nonce_bytes.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(hex::encode(payload))
+}
+
+fn decrypt_text(key: &ContentKey, encoded: &str) -> Result<String> {
+    let payload = hex::decode(encoded)?;
+    if payload.len() < 12 {
+        return Err(anyhow::anyhow!("Encrypted payload is too short"));
+    }
+
+    let (nonce_bytes, ciphertext) = payload.split_at(12);
+    let cipher = Aes256Gcm::new_from_slice(&key.0).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Decryption failed: wrong key or corrupted data"))?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+// Re-writes a document's content, chunk, parent chunk and summary text through `transform` inside one transaction.
+// Marking a document sensitive also drops what was derived from its plaintext in that transaction
+fn rewrite_document_text(
+    conn: &mut Connection,
+    document_id: &str,
+    sensitive: bool,
+    transform: impl Fn(&str) -> Result<String>,
+) -> Result<()> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    let content: String = tx.query_row(
+        "SELECT content FROM documents WHERE id = ?1",
+        params![document_id],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        "UPDATE documents SET content = ?1, is_sensitive = ?2, updated_at = ?3 WHERE id = ?4",
+        params![transform(&content)?, sensitive, Utc::now().to_rfc3339(), document_id],
+    )?;
+
+    let chunks: Vec<(String, String)> = {
+        let mut stmt = tx.prepare("SELECT id, content FROM document_chunks WHERE document_id = ?1")?;
+        let rows = stmt.query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(|r| r.ok()).collect()
+    };
+    for (chunk_id, chunk_content) in chunks {
+        tx.execute(
+            "UPDATE document_chunks SET content = ?1 WHERE id = ?2",
+            params![transform(&chunk_content)?, chunk_id],
+        )?;
+    }
+
+    let parents: Vec<(String, String)> = {
+        let mut stmt = tx.prepare("SELECT id, content FROM parent_chunks WHERE document_id = ?1")?;
+        let rows = stmt.query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(|r| r.ok()).collect()
+    };
+    for (parent_id, parent_content) in parents {
+        tx.execute(
+            "UPDATE parent_chunks SET content = ?1 WHERE id = ?2",
+            params![transform(&parent_content)?, parent_id],
+        )?;
+    }
+
+    let summary: Option<String> = {
+        let mut stmt = tx.prepare("SELECT summary FROM document_summaries WHERE document_id = ?1")?;
+        let mut rows = stmt.query(params![document_id])?;
+        rows.next()?.map(|row| row.get(0)).transpose()?
+    };
+    if let Some(summary) = summary {
+        tx.execute(
+            "UPDATE document_summaries SET summary = ?1 WHERE document_id = ?2",
+            params![transform(&summary)?, document_id],
+        )?;
+    }
+
+    if sensitive {
+        // Q/A keys hold plaintext questions; they are rebuilt on the next re-import if needed
+        tx.execute("DELETE FROM qa_keys WHERE document_id = ?1", params![document_id])?;
+        tx.execute("DELETE FROM entity_mentions WHERE document_id = ?1", params![document_id])?;
+        // Auto tags are derived from the plaintext, so they would leak its topics; manual tags are the user's own
+        tx.execute("DELETE FROM document_tags WHERE document_id = ?1 AND auto = 1", params![document_id])?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+#[tauri::command]
+fn unlock_sensitive_documents(
+    passphrase: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+
+    let profile: Option<(String, String)> = db
+        .query_row("SELECT salt, key_check FROM encryption_profile WHERE id = 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .ok();
+
+    let key = match profile {
+        Some((salt, key_check)) => {
+            let salt = hex::decode(salt).map_err(|e| e.to_string())?;
+            let key = derive_content_key(&passphrase, &salt);
+            match decrypt_text(&key, &key_check) {
+                Ok(check) if check == KEY_CHECK_PLAINTEXT => key,
+                _ => return Err("Incorrect passphrase".to_string()),
+            }
+        }
+        None => {
+            // First unlock creates the profile key from this passphrase
+            ensure_writable(&db).map_err(|e| e.to_string())?;
+            let salt: [u8; 16] = rand::random();
+            let key = derive_content_key(&passphrase, &salt);
+            let key_check = encrypt_text(&key, KEY_CHECK_PLAINTEXT).map_err(|e| e.to_string())?;
+            db.execute(
+                "INSERT INTO encryption_profile (id, salt, key_check, created_at) VALUES (1, ?1, ?2, ?3)",
+                params![hex::encode(salt), key_check, Utc::now().to_rfc3339()],
+            )
+            .map_err(|e| e.to_string())?;
+            record_audit(&db, "secret_modified", Some("encryption_profile"), serde_json::json!({ "created": true }));
+            key
+        }
+    };
+
+    *key_state.lock().map_err(|e| e.to_string())? = Some(key);
+    record_audit(&db, "sensitive_unlocked", None, serde_json::json!({}));
+    info!("Sensitive documents unlocked for this session");
+    Ok(())
+}
+
+#[tauri::command]
+fn lock_sensitive_documents(
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<(), String> {
+    *key_state.lock().map_err(|e| e.to_string())? = None;
+    info!("Sensitive documents locked");
+    Ok(())
+}
+
+#[tauri::command]
+fn set_document_sensitive(
+    document_id: String,
+    sensitive: bool,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<(), String> {
+    let key = key_state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .clone()
+        .ok_or("Unlock sensitive documents before changing encryption")?;
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+
+    let currently_sensitive: bool = db
+        .query_row(
+            "SELECT is_sensitive FROM documents WHERE id = ?1",
+            params![document_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| format!("Document not found: {}", document_id))?;
+
+    if currently_sensitive == sensitive {
+        return Ok(());
+    }
+
+    // Embeddings stay in plaintext so similarity scoring works without the key
+    let result = if sensitive {
+        rewrite_document_text(&mut db, &document_id, true, |text| encrypt_text(&key, text))
+    } else {
+        rewrite_document_text(&mut db, &document_id, false, |text| decrypt_text(&key, text))
+    };
+
+    result.map_err(|e| e.to_string())?;
+    record_audit(&db, "settings_changed", Some(&document_id), serde_json::json!({ "is_sensitive": sensitive }));
+    Ok(())
+}
+
+// ---------- Extractor Plugins ----------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PluginKind {
+    #[serde(rename = "executable")]
+    Executable,
+    #[serde(rename = "wasm")]
+    Wasm,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractorPluginManifest {
+    pub name: String,
+    pub version: String,
+    pub extensions: Vec<String>,
+    pub kind: PluginKind,
+    pub command: String, // executable or .wasm module, relative to the manifest directory
+    #[serde(default)]
+    pub args: Vec<String>, // "{path}" is replaced with the file being extracted
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractorPlugin {
+    pub manifest: ExtractorPluginManifest,
+    pub manifest_path: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractedDocument {
+    pub text: String,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub sections: Vec<TextSection>,
+}
+
+// A span of the extracted text sharing citation metadata, e.g. one EPUB chapter; it runs until the next section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextSection {
+    pub start: usize, // byte offset into the text
+    pub metadata: serde_json::Value,
+}
+
+const DEFAULT_PLUGIN_TIMEOUT_SECS: u64 = 120;
+
+fn read_plugin_manifest(manifest_path: &str) -> Result<ExtractorPluginManifest> {
+    let manifest: ExtractorPluginManifest = serde_json::from_str(&std::fs::read_to_string(manifest_path)?)?;
+    if manifest.extensions.is_empty() {
+        return Err(anyhow::anyhow!("Plugin '{}' does not declare any extensions", manifest.name));
+    }
+    Ok(manifest)
+}
+
+fn load_extractor_plugins(conn: &Connection) -> Result<Vec<ExtractorPlugin>> {
+    let mut stmt = conn.prepare("SELECT manifest_path, enabled FROM extractor_plugins ORDER BY name")?;
+    let rows: Vec<(String, bool)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut plugins = Vec::new();
+    for (manifest_path, enabled) in rows {
+        match read_plugin_manifest(&manifest_path) {
+            Ok(manifest) => plugins.push(ExtractorPlugin { manifest, manifest_path, enabled }),
+            Err(e) => warn!("Skipping extractor plugin at {}: {}", manifest_path, e),
+        }
+    }
+    Ok(plugins)
+}
+
+async fn run_extractor_plugin(plugin: &ExtractorPlugin, file_path: &Path) -> Result<ExtractedDocument> {
+    let manifest = &plugin.manifest;
+    let plugin_dir = std::path::Path::new(&plugin.manifest_path)
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+    let target = plugin_dir.join(&manifest.command);
+
+    // Substitute the path as an OsStr so non-UTF-8 file names reach the plugin intact
+    let args: Vec<OsString> = manifest
+        .args
+        .iter()
+        .map(|a| {
+            let mut arg = OsString::new();
+            for (i, part) in a.split("{path}").enumerate() {
+                if i > 0 {
+                    arg.push(file_path.as_os_str());
+                }
+                arg.push(part);
+            }
+            arg
+        })
+        .collect();
+
+    let mut command = match manifest.kind {
+        PluginKind::Executable => Command::new(&target),
+        PluginKind::Wasm => {
+            // WASM extractors run sandboxed under wasmtime with read access to the file's folder only
+            let file_dir = file_path.parent().unwrap_or(Path::new("."));
+            let mut dir_arg = OsString::from("--dir=");
+            dir_arg.push(file_dir.as_os_str());
+            let mut cmd = Command::new("wasmtime");
+            cmd.arg("run").arg(dir_arg).arg(&target).arg("--");
+            cmd
+        }
+    };
+    command
+        .args(if args.is_empty() { vec![file_path.as_os_str().to_os_string()] } else { args })
+        .current_dir(&plugin_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let timeout = Duration::from_secs(manifest.timeout_secs.unwrap_or(DEFAULT_PLUGIN_TIMEOUT_SECS));
+    let output = tokio::time::timeout(timeout, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("Plugin '{}' timed out after {:?}", manifest.name, timeout))??;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Plugin '{}' failed: {}",
+            manifest.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // Plugins print {"text": ..., "metadata": {...}}; plain text output is accepted too
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    Ok(serde_json::from_str(&stdout).unwrap_or(ExtractedDocument {
+        text: stdout,
+        metadata: serde_json::Value::Null,
+        sections: Vec::new(),
+    }))
+}
+
+// Plugins registered for the file's extension take precedence over built-in extractors
+async fn extract_document(
+    file_path: &Path,
+    plugins: &[ExtractorPlugin],
+    options: &ExtractionOptions,
+) -> Result<ExtractedDocument> {
+    let extension = file_extension(file_path);
+
+    let plugin = plugins
+        .iter()
+        .find(|p| p.enabled && p.manifest.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension)));
+
+    if let Some(plugin) = plugin {
+        info!("Extracting {} with plugin '{}'", file_path.display(), plugin.manifest.name);
+        let mut extracted = run_extractor_plugin(plugin, file_path).await?;
+        if extracted.metadata.is_null() {
+            extracted.metadata = serde_json::json!({});
+        }
+        extracted.metadata["extractor"] = serde_json::json!(plugin.manifest.name);
+        return Ok(extracted);
+    }
+
+    // Formats that take extraction options or return sections alongside the text
+    let structured = match extension.as_str() {
+        "pdf" => Some(extract_pdf(&long_path(file_path), options).await),
+        "png" | "jpg" | "jpeg" | "tif" | "tiff" => Some(
+            ocr_image(&long_path(file_path), options)
+                .await
+                .map(|text| ExtractedDocument { text, metadata: serde_json::json!({ "ocr": true }), sections: Vec::new() }),
+        ),
+        "md" => Some(extract_markdown(&long_path(file_path))),
+        "epub" => Some(extract_epub(&long_path(file_path))),
+        "pptx" => Some(extract_pptx(&long_path(file_path))),
+        "xlsx" | "xlsm" | "xls" | "ods" => Some(extract_spreadsheet(&long_path(file_path), &options.sheets)),
+        "json" | "jsonl" => Some(extract_json_records(&long_path(file_path), &options.json_fields)),
+        "eml" | "mbox" => Some(extract_email(&long_path(file_path))),
+        ext => code_grammar(ext).map(|grammar| extract_code(&long_path(file_path), &grammar)),
+    };
+    if let Some(extracted) = structured {
+        return extracted.map_err(|e| {
+            IngestError::ExtractionFailed { path: file_path.display().to_string(), reason: e.to_string() }.into()
+        });
+    }
+
+    Ok(ExtractedDocument {
+        text: extract_text_from_file(file_path, options).await?,
+        metadata: serde_json::json!({}),
+        sections: Vec::new(),
+    })
+}
+
+fn reload_plugin_state(conn: &Connection, plugin_state: &Arc<Mutex<Vec<ExtractorPlugin>>>) -> Result<Vec<ExtractorPlugin>> {
+    let plugins = load_extractor_plugins(conn)?;
+    *plugin_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))? = plugins.clone();
+    Ok(plugins)
+}
+
+#[tauri::command]
+fn register_extractor_plugin(
+    manifest_path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+) -> Result<ExtractorPlugin, String> {
+    let manifest = read_plugin_manifest(&manifest_path).map_err(|e| e.to_string())?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO extractor_plugins (name, manifest_path, enabled, registered_at) VALUES (?1, ?2, 1, ?3)
+         ON CONFLICT(name) DO UPDATE SET manifest_path = excluded.manifest_path, enabled = 1",
+        params![manifest.name, manifest_path, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| e.to_string())?;
+    record_audit(&db, "plugin_registered", Some(&manifest.name), serde_json::json!({
+        "manifest_path": manifest_path,
+        "extensions": manifest.extensions,
+    }));
+
+    reload_plugin_state(&db, plugin_state.inner()).map_err(|e| e.to_string())?;
+    Ok(ExtractorPlugin { manifest, manifest_path, enabled: true })
+}
+
+#[tauri::command]
+fn unregister_extractor_plugin(
+    name: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute("DELETE FROM extractor_plugins WHERE name = ?1", params![name])
+        .map_err(|e| e.to_string())?;
+    record_audit(&db, "plugin_removed", Some(&name), serde_json::json!({}));
+    reload_plugin_state(&db, plugin_state.inner()).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_extractor_plugins(
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+) -> Result<Vec<ExtractorPlugin>, String> {
+    Ok(plugin_state.lock().map_err(|e| e.to_string())?.clone())
+}
+
+// ---------- Provider Plugins -----------------------------------------------------
+
+const PROVIDER_MAX_ATTEMPTS: u32 = 3;
+const PROVIDER_MAX_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ProviderTransport {
+    #[serde(rename = "http")]
+    Http { url: String }, // JSON-RPC 2.0 over HTTP POST
+    #[serde(rename = "stdio")]
+    Stdio { command: String, #[serde(default)] args: Vec<String> }, // newline-delimited JSON-RPC 2.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderPluginManifest {
+    pub name: String,
+    pub version: String,
+    pub transport: ProviderTransport,
+    pub capabilities: Vec<String>, // "embeddings" | "generation"
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub builtin: bool,
+    pub capabilities: Vec<String>,
+    pub models: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderTelemetry {
+    pub calls: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub total_latency_ms: u64,
+    pub last_error: Option<String>,
+}
+
+struct ProviderPlugin {
+    manifest: ProviderPluginManifest,
+    manifest_dir: PathBuf,
+    // Lazily spawned, long-running child for stdio providers
+    stdio: Arc<tokio::sync::Mutex<Option<StdioRpcClient>>>,
+}
+
+struct StdioRpcClient {
+    child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    stdout: tokio::io::Lines<BufReader<tokio::process::ChildStdout>>,
+    next_id: u64,
+}
+
+// Embedding calls happen deep inside ingestion and retrieval, so the registry, limits
+// and telemetry live in process-wide statics rather than being threaded through every call
+static PROVIDER_PLUGINS: OnceLock<Mutex<HashMap<String, Arc<ProviderPlugin>>>> = OnceLock::new();
+static PROVIDER_LIMITS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Semaphore>>>> = OnceLock::new();
+static PROVIDER_TELEMETRY: OnceLock<Mutex<HashMap<String, ProviderTelemetry>>> = OnceLock::new();
+
+fn provider_plugins() -> &'static Mutex<HashMap<String, Arc<ProviderPlugin>>> {
+    PROVIDER_PLUGINS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn record_provider_call(provider: &str, latency_ms: u64, attempts: u32, error: Option<String>) {
+    let telemetry = PROVIDER_TELEMETRY.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut telemetry) = telemetry.lock() {
+        let stats = telemetry.entry(provider.to_string()).or_default();
+        stats.calls += 1;
+        stats.retries += attempts.saturating_sub(1) as u64;
+        stats.total_latency_ms += latency_ms;
+        if error.is_some() {
+            stats.failures += 1;
+            stats.last_error = error;
+        }
+    }
+}
+
+/// Shared call layer for built-in and plugin providers: concurrency limit, retry with
+/// exponential backoff, and per-provider telemetry
+async fn call_provider<T, F, Fut>(provider: &str, mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let semaphore = {
+        let limits = PROVIDER_LIMITS.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut limits = limits.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        limits
+            .entry(provider.to_string())
+            .or_insert_with(|| Arc::new(tokio::sync::Semaphore::new(PROVIDER_MAX_CONCURRENCY)))
+            .clone()
+    };
+    let _permit = semaphore.acquire_owned().await?;
+
+    let start_time = std::time::Instant::now();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match op().await {
+            Ok(value) => {
+                record_provider_call(provider, start_time.elapsed().as_millis() as u64, attempt, None);
+                return Ok(value);
+            }
+            Err(e) if attempt < PROVIDER_MAX_ATTEMPTS => {
+                debug!("Provider {} attempt {} failed: {}", provider, attempt, e);
+                tokio::time::sleep(Duration::from_millis(500 * (1 << (attempt - 1)))).await;
+            }
+            Err(e) => {
+                record_provider_call(provider, start_time.elapsed().as_millis() as u64, attempt, Some(e.to_string()));
+                return Err(e);
+            }
+        }
+    }
+}
+
+impl ProviderPlugin {
+    async fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let response = match &self.manifest.transport {
+            ProviderTransport::Http { url } => {
+                let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+                reqwest::Client::new()
+                    .post(url)
+                    .json(&request)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<serde_json::Value>()
+                    .await?
+            }
+            ProviderTransport::Stdio { command, args } => {
+                let mut guard = self.stdio.lock().await;
+                if guard.is_none() {
+                    let mut child = Command::new(self.manifest_dir.join(command))
+                        .args(args)
+                        .current_dir(&self.manifest_dir)
+                        .stdin(Stdio::piped())
+                        .stdout(Stdio::piped())
+                        .kill_on_drop(true)
+                        .spawn()?;
+                    let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("Provider stdin unavailable"))?;
+                    let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("Provider stdout unavailable"))?;
+                    *guard = Some(StdioRpcClient { child, stdin, stdout: BufReader::new(stdout).lines(), next_id: 1 });
+                }
+
+                let client = guard.as_mut().expect("stdio client initialised above");
+                let id = client.next_id;
+                client.next_id += 1;
+
+                let mut line = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string();
+                line.push('\n');
+                let exchange = async {
+                    client.stdin.write_all(line.as_bytes()).await?;
+                    client.stdin.flush().await?;
+                    let reply = client
+                        .stdout
+                        .next_line()
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("Provider '{}' closed its output", self.manifest.name))?;
+                    Ok::<_, anyhow::Error>(serde_json::from_str::<serde_json::Value>(&reply)?)
+                };
+
+                match exchange.await {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        // Drop the broken process so the next call respawns it
+                        if let Some(mut client) = guard.take() {
+                            let _ = client.child.kill().await;
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::anyhow!("Provider '{}' error: {}", self.manifest.name, error));
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Provider '{}' returned no result", self.manifest.name))
+    }
+}
+
+fn find_provider_plugin(name: &str, capability: &str) -> Result<Arc<ProviderPlugin>> {
+    let plugins = provider_plugins().lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let plugin = plugins
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Provider plugin '{}' is not registered", name))?;
+    if !plugin.manifest.capabilities.iter().any(|c| c == capability) {
+        return Err(anyhow::anyhow!("Provider '{}' does not support {}", name, capability));
+    }
+    Ok(plugin)
+}
+
+async fn plugin_embed(provider: &str, model: &str, text: &str) -> Result<Vec<f32>> {
+    let plugin = find_provider_plugin(provider, "embeddings")?;
+    let result = plugin
+        .call("embed", serde_json::json!({ "model": model, "texts": [text] }))
+        .await?;
+    let embedding = result["embeddings"][0]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Provider '{}' returned no embedding", provider))?
+        .iter()
+        .filter_map(|v| v.as_f64().map(|f| f as f32))
+        .collect();
+    Ok(embedding)
+}
+
+async fn plugin_generate(target: &ProviderModel, prompt: &str, options: &serde_json::Value) -> Result<String> {
+    let plugin = find_provider_plugin(&target.provider, "generation")?;
+    call_provider(&target.provider, || {
+        let plugin = plugin.clone();
+        async move {
+            let result = plugin
+                .call("generate", serde_json::json!({
+                    "model": target.model,
+                    "messages": [{ "role": "user", "content": prompt }],
+                    "options": options,
+                }))
+                .await?;
+            result["text"]
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Provider '{}' returned no text", target.provider))
+        }
+    })
+    .await
+}
+
+fn register_provider_in_memory(manifest_path: &str) -> Result<ProviderPluginManifest> {
+    let manifest: ProviderPluginManifest = serde_json::from_str(&std::fs::read_to_string(manifest_path)?)?;
+    let manifest_dir = std::path::Path::new(manifest_path)
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+
+    provider_plugins()
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?
+        .insert(manifest.name.clone(), Arc::new(ProviderPlugin {
+            manifest: manifest.clone(),
+            manifest_dir,
+            stdio: Arc::new(tokio::sync::Mutex::new(None)),
+        }));
+    Ok(manifest)
+}
+
+fn load_provider_plugins(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT manifest_path FROM provider_plugins")?;
+    let paths: Vec<String> = stmt.query_map([], |row| row.get(0))?.filter_map(Result::ok).collect();
+    for path in paths {
+        if let Err(e) = register_provider_in_memory(&path) {
+            warn!("Skipping provider plugin at {}: {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn register_provider_plugin(
+    manifest_path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<ProviderInfo, String> {
+    let manifest = register_provider_in_memory(&manifest_path).map_err(|e| e.to_string())?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO provider_plugins (name, manifest_path, registered_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET manifest_path = excluded.manifest_path",
+        params![manifest.name, manifest_path, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| e.to_string())?;
+    record_audit(&db, "plugin_registered", Some(&manifest.name), serde_json::json!({
+        "manifest_path": manifest_path,
+        "capabilities": manifest.capabilities,
+    }));
+
+    Ok(ProviderInfo {
+        name: manifest.name,
+        builtin: false,
+        capabilities: manifest.capabilities,
+        models: manifest.models,
+    })
+}
+
+#[tauri::command]
+fn unregister_provider_plugin(
+    name: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    provider_plugins().lock().map_err(|e| e.to_string())?.remove(&name);
+
+    db.execute("DELETE FROM provider_plugins WHERE name = ?1", params![name])
+        .map_err(|e| e.to_string())?;
+    record_audit(&db, "plugin_removed", Some(&name), serde_json::json!({}));
+    Ok(())
+}
+
+#[tauri::command]
+fn list_providers() -> Result<Vec<ProviderInfo>, String> {
+    let mut providers = vec![
+        ProviderInfo {
+            name: "huggingface".to_string(),
+            builtin: true,
+            capabilities: vec!["embeddings".to_string()],
+            models: vec!["sentence-transformers/all-MiniLM-L6-v2".to_string()],
+        },
+        ProviderInfo {
+            name: "openai".to_string(),
+            builtin: true,
+            capabilities: vec!["embeddings".to_string(), "generation".to_string()],
+            models: vec![
+                "text-embedding-3-small".to_string(),
+                "text-embedding-ada-002".to_string(),
+                "gpt-4o-mini".to_string(),
+                "gpt-4o".to_string(),
+            ],
+        },
+        ProviderInfo {
+            name: "local".to_string(),
+            builtin: true,
+            capabilities: vec!["embeddings".to_string()],
+            models: Vec::new(),
+        },
+        ProviderInfo {
+            name: "ollama".to_string(),
+            builtin: true,
+            capabilities: vec!["generation".to_string()],
+            models: Vec::new(),
+        },
+        ProviderInfo {
+            name: "anthropic".to_string(),
+            builtin: true,
+            capabilities: vec!["generation".to_string()],
+            models: vec!["claude-3-5-haiku-latest".to_string(), "claude-3-5-sonnet-latest".to_string()],
+        },
+        ProviderInfo {
+            name: "gemini".to_string(),
+            builtin: true,
+            capabilities: vec!["generation".to_string()],
+            models: vec!["gemini-1.5-flash".to_string(), "gemini-1.5-pro".to_string()],
+        },
+        ProviderInfo {
+            name: "mistral".to_string(),
+            builtin: true,
+            capabilities: vec!["generation".to_string()],
+            models: vec!["mistral-small-latest".to_string(), "mistral-large-latest".to_string()],
+        },
+    ];
+
+    let plugins = provider_plugins().lock().map_err(|e| e.to_string())?;
+    for plugin in plugins.values() {
+        providers.push(ProviderInfo {
+            name: plugin.manifest.name.clone(),
+            builtin: false,
+            capabilities: plugin.manifest.capabilities.clone(),
+            models: plugin.manifest.models.clone(),
+        });
+    }
+
+    Ok(providers)
+}
+
+#[tauri::command]
+fn get_provider_telemetry() -> Result<HashMap<String, ProviderTelemetry>, String> {
+    let telemetry = PROVIDER_TELEMETRY.get_or_init(|| Mutex::new(HashMap::new()));
+    Ok(telemetry.lock().map_err(|e| e.to_string())?.clone())
+}
+
+// ---------- LLM Providers ---------------------------------------------------------
+
+const LLM_TIMEOUT_SECS: u64 = 120;
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+const MISTRAL_API_BASE: &str = "https://api.mistral.ai/v1";
+const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+// Anthropic requires max_tokens on every request
+const DEFAULT_LLM_MAX_TOKENS: u32 = 1024;
+
+// Providers whose keys can be stored once with set_llm_api_key instead of in every config
+const KEYED_LLM_PROVIDERS: &[&str] = &["openai", "anthropic", "gemini", "mistral"];
+
+// Built-in chat backends, called directly without a provider plugin.
+// An empty api_key falls back to the key stored for that provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LLMProvider {
+    #[serde(rename = "openai")]
+    OpenAI {
+        #[serde(default)]
+        api_key: String,
+        model: String,
+        // Any OpenAI-compatible server (LM Studio, vLLM, llamafile, ...); api.openai.com when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base_url: Option<String>,
+    },
+    #[serde(rename = "ollama")]
+    Ollama {
+        #[serde(default = "default_ollama_url")]
+        base_url: String,
+        model: String,
+    },
+    #[serde(rename = "anthropic")]
+    Anthropic {
+        #[serde(default)]
+        api_key: String,
+        model: String,
+    },
+    #[serde(rename = "gemini")]
+    Gemini {
+        #[serde(default)]
+        api_key: String,
+        model: String,
+    },
+    #[serde(rename = "mistral")]
+    Mistral {
+        #[serde(default)]
+        api_key: String,
+        model: String,
+    },
+}
+
+fn default_ollama_url() -> String {
+    DEFAULT_OLLAMA_URL.to_string()
+}
+
+impl LLMProvider {
+    fn name(&self) -> &'static str {
+        match self {
+            LLMProvider::OpenAI { .. } => "openai",
+            LLMProvider::Ollama { .. } => "ollama",
+            LLMProvider::Anthropic { .. } => "anthropic",
+            LLMProvider::Gemini { .. } => "gemini",
+            LLMProvider::Mistral { .. } => "mistral",
+        }
+    }
+
+    fn model(&self) -> &str {
+        match self {
+            LLMProvider::OpenAI { model, .. }
+            | LLMProvider::Ollama { model, .. }
+            | LLMProvider::Anthropic { model, .. }
+            | LLMProvider::Gemini { model, .. }
+            | LLMProvider::Mistral { model, .. } => model,
+        }
+    }
+
+    fn telemetry_key(&self) -> String {
+        format!("{}:{}", self.name(), self.model())
+    }
+
+    fn api_key(&self) -> Option<&str> {
+        match self {
+            LLMProvider::OpenAI { api_key, .. }
+            | LLMProvider::Anthropic { api_key, .. }
+            | LLMProvider::Gemini { api_key, .. }
+            | LLMProvider::Mistral { api_key, .. } => Some(api_key),
+            LLMProvider::Ollama { .. } => None,
+        }
+    }
+
+    fn api_key_mut(&mut self) -> Option<&mut String> {
+        match self {
+            LLMProvider::OpenAI { api_key, .. }
+            | LLMProvider::Anthropic { api_key, .. }
+            | LLMProvider::Gemini { api_key, .. }
+            | LLMProvider::Mistral { api_key, .. } => Some(api_key),
+            LLMProvider::Ollama { .. } => None,
+        }
+    }
+
+    // The config's own key wins over the stored one
+    fn resolved_api_key(&self) -> Result<String> {
+        if let Some(key) = self.api_key().filter(|k| !k.is_empty()) {
+            return Ok(key.to_string());
+        }
+        llm_api_keys()
+            .read()
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?
+            .get(self.name())
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No API key configured for {}", self.name()))
+    }
+}
+
+static LLM_API_KEYS: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+
+fn llm_api_keys() -> &'static RwLock<HashMap<String, String>> {
+    LLM_API_KEYS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn load_llm_api_keys(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT provider, api_key FROM llm_api_keys")?;
+    let keys: HashMap<String, String> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(Result::ok)
+        .collect();
+    *llm_api_keys().write().map_err(|e| anyhow::anyhow!(e.to_string()))? = keys;
+    Ok(())
+}
+
+fn check_keyed_llm_provider(provider: &str) -> Result<(), String> {
+    if KEYED_LLM_PROVIDERS.contains(&provider) {
+        Ok(())
+    } else {
+        Err(format!("Unknown LLM provider '{}', expected one of: {}", provider, KEYED_LLM_PROVIDERS.join(", ")))
+    }
+}
+
+#[tauri::command]
+fn set_llm_api_key(
+    provider: String,
+    api_key: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    check_keyed_llm_provider(&provider)?;
+    let api_key = api_key.trim().to_string();
+    if api_key.is_empty() {
+        return Err("API key cannot be empty".to_string());
+    }
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT OR REPLACE INTO llm_api_keys (provider, api_key, updated_at) VALUES (?1, ?2, ?3)",
+        params![provider, api_key, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| e.to_string())?;
+    llm_api_keys().write().map_err(|e| e.to_string())?.insert(provider.clone(), api_key);
+    record_audit(&db, "secret_modified", Some("llm_api_key"), serde_json::json!({ "provider": provider }));
+    Ok(())
+}
+
+#[tauri::command]
+fn remove_llm_api_key(
+    provider: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute("DELETE FROM llm_api_keys WHERE provider = ?1", params![provider])
+        .map_err(|e| e.to_string())?;
+    llm_api_keys().write().map_err(|e| e.to_string())?.remove(&provider);
+    record_audit(&db, "secret_modified", Some("llm_api_key"), serde_json::json!({ "provider": provider, "removed": true }));
+    Ok(())
+}
+
+// Only the provider names; stored keys never leave the backend
+#[tauri::command]
+fn list_llm_api_keys() -> Result<Vec<String>, String> {
+    let mut providers: Vec<String> = llm_api_keys().read().map_err(|e| e.to_string())?.keys().cloned().collect();
+    providers.sort();
+    Ok(providers)
+}
+
+// Fills in a stripped LLM key from the current config when both use the same backend
+fn restore_llm_api_key(config: &mut RAGConfig, current: &RAGConfig) {
+    let (Some(provider), Some(current)) = (config.llm_provider.as_mut(), current.llm_provider.as_ref()) else {
+        return;
+    };
+    if std::mem::discriminant(&*provider) != std::mem::discriminant(current) {
+        return;
+    }
+    if let (Some(api_key), Some(current_key)) = (provider.api_key_mut(), current.api_key()) {
+        if api_key.is_empty() {
+            *api_key = current_key.to_string();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl LLMMessage {
+    fn new(role: &str, content: impl Into<String>) -> Self {
+        Self { role: role.to_string(), content: content.into() }
+    }
+}
+
+// Who writes answers: the built-in backend when configured, else the generation provider plugin
+#[derive(Debug, Clone)]
+enum GenerationTarget {
+    Llm(LLMProvider),
+    Plugin(ProviderModel),
+}
+
+fn generation_target(config: &RAGConfig) -> Option<GenerationTarget> {
+    config
+        .llm_provider
+        .clone()
+        .map(GenerationTarget::Llm)
+        .or_else(|| config.generation_provider.clone().map(GenerationTarget::Plugin))
+}
+
+fn llm_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(LLM_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+// Options use the provider plugin protocol's keys (temperature, max_tokens, response_format, ...)
+async fn generate_text(target: &GenerationTarget, prompt: &str, options: &serde_json::Value) -> Result<String> {
+    generate_chat(target, &[LLMMessage::new("user", prompt)], options).await
+}
+
+// Plugins take a single prompt, so the messages are flattened for them
+async fn generate_chat(target: &GenerationTarget, messages: &[LLMMessage], options: &serde_json::Value) -> Result<String> {
+    match target {
+        GenerationTarget::Plugin(target) => {
+            let prompt = messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n\n");
+            plugin_generate(target, &prompt, options).await
+        }
+        GenerationTarget::Llm(provider) => llm_chat(provider, messages, options).await,
+    }
+}
+
+async fn llm_chat(provider: &LLMProvider, messages: &[LLMMessage], options: &serde_json::Value) -> Result<String> {
+    let api_key = match provider {
+        LLMProvider::Ollama { .. } => String::new(),
+        // Self-hosted servers usually run without a key
+        LLMProvider::OpenAI { base_url: Some(_), .. } => provider.resolved_api_key().unwrap_or_default(),
+        _ => provider.resolved_api_key()?,
+    };
+    let api_key = api_key.as_str();
+    call_provider(&provider.telemetry_key(), || async move {
+        match provider {
+            LLMProvider::OpenAI { model, base_url: None, .. } => {
+                openai_compatible_chat("OpenAI", OPENAI_API_BASE, api_key, model, messages, options).await
+            }
+            LLMProvider::OpenAI { model, base_url: Some(base_url), .. } => {
+                openai_compatible_chat("OpenAI-compatible server", base_url, api_key, model, messages, options).await
+            }
+            LLMProvider::Mistral { model, .. } => {
+                openai_compatible_chat("Mistral", MISTRAL_API_BASE, api_key, model, messages, options).await
+            }
+            LLMProvider::Ollama { base_url, model } => ollama_chat(base_url, model, messages, options).await,
+            LLMProvider::Anthropic { model, .. } => anthropic_chat(api_key, model, messages, options).await,
+            LLMProvider::Gemini { model, .. } => gemini_chat(api_key, model, messages, options).await,
+        }
+    })
+    .await
+}
+
+async fn read_llm_reply(label: &str, response: reqwest::Response) -> Result<serde_json::Value> {
+    let status = response.status();
+    if !status.is_success() {
+        let detail = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("{} chat request failed ({}): {}", label, status, detail.trim()));
+    }
+    Ok(response.json().await?)
+}
+
+// System messages go in a separate field for Anthropic and Gemini
+fn split_system_messages(messages: &[LLMMessage]) -> (Option<String>, Vec<&LLMMessage>) {
+    let system: Vec<&str> = messages.iter().filter(|m| m.role == "system").map(|m| m.content.as_str()).collect();
+    let rest = messages.iter().filter(|m| m.role != "system").collect();
+    ((!system.is_empty()).then(|| system.join("\n\n")), rest)
+}
+
+async fn openai_compatible_chat(
+    label: &str,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    messages: &[LLMMessage],
+    options: &serde_json::Value,
+) -> Result<String> {
+    let mut body = serde_json::json!({ "model": model, "messages": messages });
+    for key in ["temperature", "max_tokens", "top_p", "frequency_penalty", "stop"] {
+        if let Some(value) = options.get(key).filter(|v| !v.is_null()) {
+            body[key] = value.clone();
+        }
+    }
+    if let Some(format) = options.get("response_format").filter(|f| f["type"] == "json_schema") {
+        body["response_format"] = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": format["schema"] },
+        });
+    }
+
+    let mut request = llm_client().post(format!("{}/chat/completions", base_url.trim_end_matches('/')));
+    if !api_key.is_empty() {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not reach {} at {}: {}", label, base_url, e))?;
+    let reply = read_llm_reply(label, response).await?;
+    reply["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("{} returned no message content", label))
+}
+
+async fn anthropic_chat(api_key: &str, model: &str, messages: &[LLMMessage], options: &serde_json::Value) -> Result<String> {
+    let (system, messages) = split_system_messages(messages);
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": messages,
+        "max_tokens": options.get("max_tokens").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_LLM_MAX_TOKENS as u64),
+    });
+    if let Some(system) = system {
+        body["system"] = serde_json::json!(system);
+    }
+    for key in ["temperature", "top_p"] {
+        if let Some(value) = options.get(key).filter(|v| !v.is_null()) {
+            body[key] = value.clone();
+        }
+    }
+    if let Some(stop) = options.get("stop").filter(|v| !v.is_null()) {
+        body["stop_sequences"] = stop.clone();
+    }
+
+    let response = llm_client()
+        .post(ANTHROPIC_API_URL)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_API_VERSION)
+        .json(&body)
+        .send()
+        .await?;
+    let reply = read_llm_reply("Anthropic", response).await?;
+    let text: String = reply["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    if text.is_empty() {
+        return Err(anyhow::anyhow!("Anthropic returned no text content"));
+    }
+    Ok(text)
+}
+
+// Gemini calls the assistant role "model" and takes sampling settings under generationConfig
+async fn gemini_chat(api_key: &str, model: &str, messages: &[LLMMessage], options: &serde_json::Value) -> Result<String> {
+    let (system, messages) = split_system_messages(messages);
+    let contents: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|m| {
+            let role = if m.role == "assistant" { "model" } else { "user" };
+            serde_json::json!({ "role": role, "parts": [{ "text": m.content }] })
+        })
+        .collect();
+    let mut generation_config = serde_json::Map::new();
+    for (key, gemini_key) in [
+        ("temperature", "temperature"),
+        ("max_tokens", "maxOutputTokens"),
+        ("top_p", "topP"),
+        ("frequency_penalty", "frequencyPenalty"),
+        ("stop", "stopSequences"),
+    ] {
+        if let Some(value) = options.get(key).filter(|v| !v.is_null()) {
+            generation_config.insert(gemini_key.to_string(), value.clone());
+        }
+    }
+    if options.get("response_format").is_some_and(|f| f["type"] == "json_schema") {
+        generation_config.insert("responseMimeType".to_string(), serde_json::json!("application/json"));
+    }
+    let mut body = serde_json::json!({ "contents": contents, "generationConfig": generation_config });
+    if let Some(system) = system {
+        body["systemInstruction"] = serde_json::json!({ "parts": [{ "text": system }] });
+    }
+
+    let response = llm_client()
+        .post(format!("{}/models/{}:generateContent", GEMINI_API_BASE, model))
+        .header("x-goog-api-key", api_key)
+        .json(&body)
+        .send()
+        .await?;
+    let reply = read_llm_reply("Gemini", response).await?;
+    let text: String = reply["candidates"][0]["content"]["parts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| part["text"].as_str())
+        .collect();
+    if text.is_empty() {
+        return Err(anyhow::anyhow!("Gemini returned no text content"));
+    }
+    Ok(text)
+}
+
+// Ollama takes sampling settings under "options" and calls the token limit num_predict
+async fn ollama_chat(base_url: &str, model: &str, messages: &[LLMMessage], options: &serde_json::Value) -> Result<String> {
+    let mut model_options = serde_json::Map::new();
+    for (key, ollama_key) in [
+        ("temperature", "temperature"),
+        ("max_tokens", "num_predict"),
+        ("top_p", "top_p"),
+        ("frequency_penalty", "frequency_penalty"),
+        ("stop", "stop"),
+    ] {
+        if let Some(value) = options.get(key).filter(|v| !v.is_null()) {
+            model_options.insert(ollama_key.to_string(), value.clone());
+        }
+    }
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": messages,
+        "stream": false,
+        "options": model_options,
+    });
+    if let Some(format) = options.get("response_format").filter(|f| f["type"] == "json_schema") {
+        body["format"] = format["schema"].clone();
+    }
+
+    let response = llm_client()
+        .post(format!("{}/api/chat", base_url.trim_end_matches('/')))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not reach Ollama at {}: {}", base_url, e))?;
+    let reply = read_llm_reply("Ollama", response).await?;
+    reply["message"]["content"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Ollama returned no message content"))
+}
+
+// ---------- Generation Cancellation -----------------------------------------------
+
+const GENERATION_CANCELLED: &str = "Generation cancelled";
+
+// Cancellation tokens for in-flight queries, keyed by the caller's request id
+#[derive(Default)]
+pub struct ActiveGenerations {
+    tokens: Mutex<HashMap<String, (Uuid, CancellationToken)>>,
+}
+
+impl ActiveGenerations {
+    fn register(self: &Arc<Self>, request_id: String) -> GenerationGuard {
+        let run_id = Uuid::new_v4();
+        let token = CancellationToken::new();
+        if let Ok(mut tokens) = self.tokens.lock() {
+            // A reused id cancels the run it replaces rather than leaving it unreachable
+            if let Some((_, previous)) = tokens.insert(request_id.clone(), (run_id, token.clone())) {
+                previous.cancel();
+            }
+        }
+        GenerationGuard { generations: self.clone(), request_id, run_id, token }
+    }
+
+    fn cancel(&self, request_id: &str) -> bool {
+        match self.tokens.lock().ok().and_then(|mut tokens| tokens.remove(request_id)) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Unregisters the token when the run finishes, however it finishes
+struct GenerationGuard {
+    generations: Arc<ActiveGenerations>,
+    request_id: String,
+    run_id: Uuid,
+    token: CancellationToken,
+}
+
+impl Drop for GenerationGuard {
+    fn drop(&mut self) {
+        if let Ok(mut tokens) = self.generations.tokens.lock() {
+            if tokens.get(&self.request_id).is_some_and(|(run_id, _)| *run_id == self.run_id) {
+                tokens.remove(&self.request_id);
+            }
+        }
+    }
+}
+
+// Dropping the pipeline future aborts whatever it is awaiting, including in-flight HTTP requests
+async fn run_cancellable<T>(
+    guard: Option<&GenerationGuard>,
+    work: impl std::future::Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    let Some(guard) = guard else {
+        return work.await;
+    };
+    tokio::select! {
+        result = work => result,
+        _ = guard.token.cancelled() => {
+            info!("Cancelled generation {}", guard.request_id);
+            Err(GENERATION_CANCELLED.to_string())
+        }
+    }
+}
+
+// Returns false when nothing with that id is running
+#[tauri::command]
+fn cancel_generation(
+    request_id: String,
+    generations_state: tauri::State<'_, Arc<ActiveGenerations>>,
+) -> Result<bool, String> {
+    Ok(generations_state.cancel(&request_id))
+}
+
+// ---------- Chat Tools ----------------------------------------------------------
+
+const MAX_TOOL_ROUNDS: usize = 5;
+const TOOL_OUTPUT_LIMIT: usize = 4000; // characters of a tool result fed back to the model
+const TOOL_TIMEOUT_SECS: u64 = 20;
+const BUILTIN_CHAT_TOOLS: &[&str] = &["calculator", "search_documents", "fetch_url"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTool {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value, // JSON schema for the arguments object
+    #[serde(default)]
+    pub url: Option<String>, // registered tools get their arguments POSTed here as JSON; None for built-ins
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+// One tool call made while answering, in the order the model made them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallTrace {
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub output: String,
+    pub error: bool,
+    pub duration_ms: u64,
+}
+
+fn builtin_chat_tools() -> Vec<ChatTool> {
+    let tool = |name: &str, description: &str, parameters: serde_json::Value| ChatTool {
+        name: name.to_string(),
+        description: description.to_string(),
+        parameters,
+        url: None,
+        builtin: true,
+    };
+    vec![
+        tool(
+            "calculator",
+            "Evaluates an arithmetic expression such as (12.5 * 4) / 3 or sqrt(2) ^ 2.",
+            serde_json::json!({
+                "type": "object",
+                "properties": { "expression": { "type": "string" } },
+                "required": ["expression"],
+            }),
+        ),
+        tool(
+            "search_documents",
+            "Searches the knowledge base and returns the most relevant passages.",
+            serde_json::json!({
+                "type": "object",
+                "properties": { "query": { "type": "string" } },
+                "required": ["query"],
+            }),
+        ),
+        tool(
+            "fetch_url",
+            "Fetches a web page over http(s) and returns its text.",
+            serde_json::json!({
+                "type": "object",
+                "properties": { "url": { "type": "string" } },
+                "required": ["url"],
+            }),
+        ),
+    ]
+}
+
+fn tool_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(CRAWL_USER_AGENT)
+            .timeout(Duration::from_secs(TOOL_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+fn load_registered_tools(conn: &Connection) -> Result<Vec<ChatTool>> {
+    let mut stmt = conn.prepare("SELECT name, description, parameters, url FROM chat_tools ORDER BY name")?;
+    let rows = stmt.query_map([], |row| {
+        let parameters: String = row.get(2)?;
+        Ok(ChatTool {
+            name: row.get(0)?,
+            description: row.get(1)?,
+            parameters: serde_json::from_str(&parameters).unwrap_or_else(|_| serde_json::json!({ "type": "object" })),
+            url: row.get(3)?,
+            builtin: false,
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// The tools named in the config, in config order; names that no longer exist are skipped
+fn resolve_chat_tools(conn: &Connection, names: &[String]) -> Result<Vec<ChatTool>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let available: Vec<ChatTool> = builtin_chat_tools().into_iter().chain(load_registered_tools(conn)?).collect();
+    Ok(names
+        .iter()
+        .filter_map(|name| {
+            let tool = available.iter().find(|tool| &tool.name == name).cloned();
+            if tool.is_none() {
+                warn!("Chat tool '{}' is not registered", name);
+            }
+            tool
+        })
+        .collect())
+}
+
+// Any provider can follow this: a reply that is only {"tool": ..., "arguments": ...} is a call, anything else is the answer
+fn tool_instructions(tools: &[ChatTool]) -> String {
+    let listing = tools
+        .iter()
+        .map(|tool| format!("- {}: {} Arguments schema: {}", tool.name, tool.description, tool.parameters))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "You can call these tools:\n{}\n\n\
+         To call a tool, reply with only a JSON object of the form {{\"tool\": \"<name>\", \"arguments\": {{...}}}} \
+         and nothing else. The result comes back in the next message. Call one tool at a time. \
+         When you have what you need, reply with the final answer as plain text.",
+        listing
+    )
+}
+
+fn parse_tool_call<'a>(reply: &str, tools: &'a [ChatTool]) -> Option<(&'a ChatTool, serde_json::Value)> {
+    let payload = extract_json_payload(reply)?;
+    let name = payload.get("tool")?.as_str()?;
+    let tool = tools.iter().find(|tool| tool.name == name)?;
+    let arguments = payload.get("arguments").cloned().unwrap_or_else(|| serde_json::json!({}));
+    Some((tool, arguments))
+}
+
+fn string_argument<'a>(arguments: &'a serde_json::Value, key: &str) -> Result<&'a str> {
+    arguments[key].as_str().ok_or_else(|| anyhow::anyhow!("missing string argument '{}'", key))
+}
+
+async fn call_http_tool(tool: &ChatTool, url: &str, arguments: &serde_json::Value) -> Result<String> {
+    let response = tool_client().post(url).json(arguments).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("{} failed ({}): {}", tool.name, status, body.trim()));
+    }
+    Ok(body)
+}
+
+// `search` runs the knowledge base lookup so the caller decides how retrieval is done
+async fn execute_chat_tool<F, Fut>(tool: &ChatTool, arguments: &serde_json::Value, search: &F) -> Result<String>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String>>,
+{
+    let violations = schema_violations(&tool.parameters, arguments)?;
+    if !violations.is_empty() {
+        return Err(anyhow::anyhow!("invalid arguments: {}", violations.join("; ")));
+    }
+    match (tool.name.as_str(), &tool.url) {
+        (_, Some(url)) => call_http_tool(tool, url, arguments).await,
+        ("calculator", None) => {
+            let expression = string_argument(arguments, "expression")?;
+            let value = meval::eval_str(expression).map_err(|e| anyhow::anyhow!("could not evaluate '{}': {}", expression, e))?;
+            Ok(value.to_string())
+        }
+        ("search_documents", None) => search(string_argument(arguments, "query")?.to_string()).await,
+        ("fetch_url", None) => {
+            let url = reqwest::Url::parse(string_argument(arguments, "url")?)?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(anyhow::anyhow!("only http and https URLs can be fetched"));
+            }
+            let (final_url, page) = fetch_web_page(tool_client(), &url).await?;
+            Ok(format!("{}\n\n{}", page.title.unwrap_or_else(|| final_url.to_string()), page.text))
+        }
+        (name, None) => Err(anyhow::anyhow!("tool '{}' has no implementation", name)),
+    }
+}
+
+// Lets the model call tools for up to MAX_TOOL_ROUNDS turns, then returns its answer and what it called
+async fn run_with_tools<F, Fut>(
+    target: &GenerationTarget,
+    mut messages: Vec<LLMMessage>,
+    tools: &[ChatTool],
+    options: &serde_json::Value,
+    search: F,
+) -> Result<(String, Vec<ToolCallTrace>)>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<String>>,
+{
+    if tools.is_empty() {
+        return Ok((generate_chat(target, &messages, options).await?, Vec::new()));
+    }
+    messages.insert(0, LLMMessage::new("system", tool_instructions(tools)));
+
+    let mut traces = Vec::new();
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let reply = generate_chat(target, &messages, options).await?;
+        let Some((tool, arguments)) = parse_tool_call(&reply, tools) else {
+            return Ok((reply, traces));
+        };
+        let started = std::time::Instant::now();
+        let (output, error) = match execute_chat_tool(tool, &arguments, &search).await {
+            Ok(output) => (output.chars().take(TOOL_OUTPUT_LIMIT).collect::<String>(), false),
+            Err(e) => (format!("Error: {}", e), true),
+        };
+        debug!("Tool {} called with {} (error: {})", tool.name, arguments, error);
+        messages.push(LLMMessage::new("assistant", reply));
+        messages.push(LLMMessage::new("user", format!("Result of {}:\n{}", tool.name, output)));
+        traces.push(ToolCallTrace {
+            tool: tool.name.clone(),
+            arguments,
+            output,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+
+    messages.push(LLMMessage::new("user", "Answer now as plain text without calling any more tools."));
+    Ok((generate_chat(target, &messages, options).await?, traces))
+}
+
+#[tauri::command]
+fn register_chat_tool(
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+    url: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<ChatTool, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("Tool names may only use letters, digits and underscores".to_string());
+    }
+    if BUILTIN_CHAT_TOOLS.contains(&name.as_str()) {
+        return Err(format!("'{}' is a built-in tool", name));
+    }
+    // Compiling the schema against an empty instance rejects schemas that are unusable
+    schema_violations(&parameters, &serde_json::json!({})).map_err(|e| e.to_string())?;
+    let parsed = reqwest::Url::parse(&url).map_err(|e| format!("Invalid tool URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("Tool URLs must use http or https".to_string());
+    }
+
+    let tool = ChatTool { name, description, parameters, url: Some(url), builtin: false };
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT OR REPLACE INTO chat_tools (name, description, parameters, url, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![tool.name, tool.description, tool.parameters.to_string(), tool.url, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| e.to_string())?;
+    record_audit(&db, "chat_tool_registered", Some(&tool.name), serde_json::json!({ "url": tool.url }));
+    Ok(tool)
+}
+
+#[tauri::command]
+fn remove_chat_tool(name: String, db_state: tauri::State<'_, Arc<DbPool>>) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM chat_tools WHERE name = ?1", params![name])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Chat tool not found: {}", name));
+    }
+    record_audit(&db, "chat_tool_removed", Some(&name), serde_json::json!({}));
+    Ok(())
+}
+
+// Built-ins first; set RAGConfig.chat_tools to the names the model may call
+#[tauri::command]
+fn list_chat_tools(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<ChatTool>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let registered = load_registered_tools(&db).map_err(|e| e.to_string())?;
+    Ok(builtin_chat_tools().into_iter().chain(registered).collect())
+}
+
+// ---------- Agentic Retrieval -----------------------------------------------------
+
+const DEFAULT_AGENT_STEPS: usize = 3;
+const MAX_AGENT_STEPS: usize = 6;
+const AGENT_PASSAGE_PREVIEW: usize = 400; // characters of each passage shown when planning the next search
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AgentAction {
+    #[serde(rename = "search")]
+    Search,
+    #[serde(rename = "answer")]
+    Answer,
+}
+
+// Emitted as an "agent_step" event as the loop runs, and returned in full with the answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub request_id: Option<String>,
+    pub step: usize,
+    pub action: AgentAction,
+    pub query: Option<String>, // the search issued; None for the answer step
+    pub new_passages: usize,   // passages this search found that earlier searches had not
+    pub total_passages: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentResponse {
+    pub answer: String,
+    pub steps: Vec<AgentStep>,
+    pub retrieved_context: Vec<RetrievalResult>,
+    pub citations: Vec<Citation>,
+    pub processing_time_ms: u64,
+}
+
+// Asks the model whether the evidence answers the question; Some(query) is the next search to run
+async fn next_agent_query(
+    target: &GenerationTarget,
+    question: &str,
+    searches: &[String],
+    passages: &[RetrievalResult],
+) -> Option<String> {
+    let searched = searches
+        .iter()
+        .enumerate()
+        .map(|(i, q)| format!("{}. {}", i + 1, q))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let evidence = passages
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let preview: String = r.content.chars().take(AGENT_PASSAGE_PREVIEW).collect();
+            format!("[{}] From {}: {}", i + 1, r.document_title, preview)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let prompt = format!(
+        "You are researching a question by searching a document collection.\n\nQuestion: {}\n\n\
+         Searches so far:\n{}\n\nPassages found so far:\n{}\n\n\
+         If the passages are enough to answer the question, reply with {{\"action\": \"answer\"}}. \
+         Otherwise reply with {{\"action\": \"search\", \"query\": \"...\"}} to look up the missing piece, \
+         for example a person, place or term that a passage mentions. Reply with JSON only.",
+        question,
+        searched,
+        if evidence.is_empty() { "(none)".to_string() } else { evidence }
+    );
+    let reply = match generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.0, "max_tokens": 150 })).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            warn!("Agent planning failed, answering with the passages found so far: {}", e);
+            return None;
+        }
+    };
+    let decision = extract_json_payload(&reply)?;
+    if decision["action"] != "search" {
+        return None;
+    }
+    // A repeated search would find nothing new, so the loop ends instead
+    decision["query"]
+        .as_str()
+        .map(|query| query.trim().to_string())
+        .filter(|query| !query.is_empty() && !searches.iter().any(|s| s.eq_ignore_ascii_case(query)))
+}
+
+async fn run_agentic_query(
+    query: String,
+    config: RAGConfig,
+    max_steps: usize,
+    session_id: Option<String>,
+    request_id: Option<String>,
+    app: &AppHandle,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<AgentResponse, String> {
+    let start_time = std::time::Instant::now();
+    let target = generation_target(&config).ok_or("Agentic retrieval needs a generation provider")?;
+    let prompt_template = match &config.prompt_template {
+        Some(template_id) => {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            Some(load_prompt_template(&db, template_id).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+
+    let mut passages: Vec<RetrievalResult> = Vec::new();
+    let mut searches: Vec<String> = Vec::new();
+    let mut steps: Vec<AgentStep> = Vec::new();
+    let mut next_query = Some(query.clone());
+    while let Some(search) = next_query.take() {
+        let search_start = std::time::Instant::now();
+        let mut found = retrieve_context_enhanced(
+            &search,
+            &config,
+            db_state.clone(),
+            content_key.as_ref(),
+            store_state.inner(),
+            hnsw_state.inner(),
+            vector_stores_state.inner(),
+            providers_state.inner(),
+            None,
+        )
+        .await?;
+        if let Some(session_id) = &session_id {
+            found.extend(
+                search_session_attachments(session_state.inner(), session_id, &search, &config, providers_state.inner())
+                    .await
+                    .map_err(|e| e.to_string())?,
+            );
+            found.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+            found.truncate(config.top_k);
+        }
+        {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            if !is_read_only(&db) {
+                if let Err(e) = log_query(
+                    &db,
+                    &search,
+                    "agentic",
+                    session_id.as_deref(),
+                    serde_json::json!({ "top_k": config.top_k, "step": searches.len() + 1 }),
+                    found.len(),
+                    found.first().map(|r| r.similarity_score),
+                    search_start.elapsed().as_millis() as u64,
+                ) {
+                    warn!("Failed to log query: {}", e);
+                }
+            }
+        }
+        let before = passages.len();
+        for result in found {
+            if !passages.iter().any(|p| p.chunk_id == result.chunk_id) {
+                passages.push(result);
+            }
+        }
+        searches.push(search.clone());
+
+        let step = AgentStep {
+            request_id: request_id.clone(),
+            step: steps.len() + 1,
+            action: AgentAction::Search,
+            query: Some(search),
+            new_passages: passages.len() - before,
+            total_passages: passages.len(),
+        };
+        let _ = app.emit("agent_step", &step);
+        steps.push(step);
+
+        if searches.len() < max_steps {
+            next_query = next_agent_query(&target, &query, &searches, &passages).await;
+        }
+    }
+
+    let context = pack_context(
+        deduplicate_context(passages),
+        &target,
+        &config,
+        prompt_template.as_ref(),
+        &query,
+        "",
+        AnswerStyle::default(),
+    );
+    let step = AgentStep {
+        request_id,
+        step: steps.len() + 1,
+        action: AgentAction::Answer,
+        query: None,
+        new_passages: 0,
+        total_passages: context.len(),
+    };
+    let _ = app.emit("agent_step", &step);
+    steps.push(step);
+
+    let context_text = context
+        .iter()
+        .enumerate()
+        .map(|(i, r)| context_entry(i, r))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let messages = prompt_messages(
+        prompt_template.as_ref(),
+        &[
+            ("context", &context_text),
+            ("question", &query),
+            ("history", ""),
+            ("instructions", AnswerStyle::default().instructions()),
+        ],
+    );
+    let answer = generate_chat(&target, &messages, &generation_options(&config, AnswerStyle::default()))
+        .await
+        .map_err(|e| e.to_string())?;
+    let answer = AnswerStyle::default().post_process(answer);
+    let citations = extract_citations(&answer, &context);
+
+    Ok(AgentResponse {
+        answer,
+        steps,
+        retrieved_context: context,
+        citations,
+        processing_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+// Multi-hop questions: the model may issue up to max_steps searches, each informed by what the last ones found
+#[tauri::command]
+async fn query_rag_agentic(
+    query: String,
+    config: RAGConfig,
+    max_steps: Option<usize>,
+    session_id: Option<String>,
+    request_id: Option<String>,
+    app: AppHandle,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+    generations_state: tauri::State<'_, Arc<ActiveGenerations>>,
+) -> Result<AgentResponse, String> {
+    let guard = request_id.clone().map(|id| generations_state.register(id));
+    run_cancellable(
+        guard.as_ref(),
+        run_agentic_query(
+            query,
+            config,
+            max_steps.unwrap_or(DEFAULT_AGENT_STEPS).clamp(1, MAX_AGENT_STEPS),
+            session_id,
+            request_id,
+            &app,
+            db_state,
+            key_state,
+            store_state,
+            providers_state,
+            session_state,
+            hnsw_state,
+            vector_stores_state,
+        ),
+    )
+    .await
+}
+
+// ---------- Webhooks -----------------------------------------------------------
+
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>, // "document.processed" | "document.deleted" | "documents.expired" | "fine_tune.completed" | "alert" | "*"
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub attempt: u32,
+    pub success: bool,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn sign_webhook_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn log_webhook_delivery(
+    db_state: &Arc<DbPool>,
+    webhook_id: &str,
+    event: &str,
+    attempt: u32,
+    status_code: Option<u16>,
+    error: Option<&str>,
+) {
+    let Ok(db) = db_state.lock() else { return };
+    if let Err(e) = db.execute(
+        "INSERT INTO webhook_deliveries (id, webhook_id, event, attempt, success, status_code, error, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            Uuid::new_v4().to_string(),
+            webhook_id,
+            event,
+            attempt,
+            error.is_none(),
+            status_code,
+            error,
+            Utc::now().to_rfc3339(),
+        ],
+    ) {
+        warn!("Failed to log webhook delivery: {}", e);
+    }
+}
+
+async fn deliver_webhook(db_state: Arc<DbPool>, webhook: Webhook, event: String, body: Vec<u8>) {
+    let client = reqwest::Client::new();
+    let signature = sign_webhook_payload(&webhook.secret, &body);
+
+    for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+        let result = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-RAG-Event", &event)
+            .header("X-RAG-Signature", format!("sha256={}", signature))
+            .body(body.clone())
+            .timeout(Duration::from_secs(15))
+            .send()
+            .await;
+
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("HTTP {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        log_webhook_delivery(&db_state, &webhook.id, &event, attempt, status_code, error.as_deref());
+        if error.is_none() {
+            return;
+        }
+
+        if attempt < WEBHOOK_MAX_ATTEMPTS {
+            // Exponential backoff: 1s, 2s, 4s, 8s
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+        }
+    }
+
+    warn!("Webhook {} gave up on event {} after {} attempts", webhook.id, event, WEBHOOK_MAX_ATTEMPTS);
+}
+
+fn load_webhooks(conn: &Connection) -> Result<Vec<Webhook>> {
+    let mut stmt = conn.prepare("SELECT id, url, secret, events, enabled, created_at FROM webhooks ORDER BY created_at")?;
+    let webhooks = stmt
+        .query_map([], |row| {
+            let events: String = row.get(3)?;
+            Ok(Webhook {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                secret: row.get(2)?,
+                events: serde_json::from_str(&events).unwrap_or_default(),
+                enabled: row.get(4)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(webhooks)
+}
+
+// Fire-and-forget: every subscribed webhook gets its own delivery task
+fn dispatch_webhook_event(db_state: &Arc<DbPool>, event: &str, data: serde_json::Value) {
+    let webhooks = match db_state.lock() {
+        Ok(db) => load_webhooks(&db).unwrap_or_default(),
+        Err(_) => return,
+    };
+
+    let body = serde_json::json!({
+        "event": event,
+        "data": data,
+        "timestamp": Utc::now().to_rfc3339(),
+    })
+    .to_string()
+    .into_bytes();
+
+    for webhook in webhooks {
+        if !webhook.enabled || !webhook.events.iter().any(|e| e == event || e == "*") {
+            continue;
+        }
+        tauri::async_runtime::spawn(deliver_webhook(
+            db_state.clone(),
+            webhook,
+            event.to_string(),
+            body.clone(),
+        ));
+    }
+}
+
+#[tauri::command]
+fn add_webhook(
+    url: String,
+    events: Vec<String>,
+    secret: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Webhook, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("Webhook URL must start with http:// or https://".to_string());
+    }
+
+    let webhook = Webhook {
+        id: Uuid::new_v4().to_string(),
+        url,
+        secret: secret.unwrap_or_else(|| hex::encode(rand::random::<[u8; 32]>())),
+        events,
+        enabled: true,
+        created_at: Utc::now(),
+    };
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO webhooks (id, url, secret, events, enabled, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            webhook.id,
+            webhook.url,
+            webhook.secret,
+            serde_json::to_string(&webhook.events).map_err(|e| e.to_string())?,
+            webhook.enabled,
+            webhook.created_at.to_rfc3339(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    record_audit(&db, "settings_changed", Some(&webhook.id), serde_json::json!({
+        "webhook_url": webhook.url,
+        "events": webhook.events,
+    }));
+    record_audit(&db, "secret_modified", Some("webhook_secret"), serde_json::json!({ "webhook_id": webhook.id }));
+
+    Ok(webhook)
+}
+
+#[tauri::command]
+fn remove_webhook(
+    webhook_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute("DELETE FROM webhooks WHERE id = ?1", params![webhook_id])
+        .map_err(|e| e.to_string())?;
+    record_audit(&db, "settings_changed", Some(&webhook_id), serde_json::json!({ "webhook_removed": true }));
+    Ok(())
+}
+
+#[tauri::command]
+fn list_webhooks(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<Webhook>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut webhooks = load_webhooks(&db).map_err(|e| e.to_string())?;
+    // The secret is only shown once, by add_webhook
+    for webhook in &mut webhooks {
+        webhook.secret = MASKED_SECRET.to_string();
+    }
+    Ok(webhooks)
+}
+
+#[tauri::command]
+fn get_webhook_deliveries(
+    webhook_id: Option<String>,
+    limit: Option<usize>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<WebhookDelivery>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT id, webhook_id, event, attempt, success, status_code, error, created_at
+             FROM webhook_deliveries
+             WHERE ?1 IS NULL OR webhook_id = ?1
+             ORDER BY created_at DESC LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let delivery_iter = stmt
+        .query_map(params![webhook_id, limit.unwrap_or(100) as i64], |row| {
+            Ok(WebhookDelivery {
+                id: row.get(0)?,
+                webhook_id: row.get(1)?,
+                event: row.get(2)?,
+                attempt: row.get(3)?,
+                success: row.get(4)?,
+                status_code: row.get(5)?,
+                error: row.get(6)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(delivery_iter.filter_map(Result::ok).collect())
+}
+
+// ---------- Knowledge Base Sync -------------------------------------------------
+
+pub type VectorClock = BTreeMap<String, u64>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SyncTarget {
+    #[serde(rename = "folder")]
+    Folder { path: String },
+    #[serde(rename = "webdav")]
+    WebDav { url: String, username: Option<String>, password: Option<String> },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncManifestEntry {
+    pub clock: VectorClock,
+    pub fingerprint: String,
+    pub deleted: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncManifest {
+    pub documents: HashMap<String, SyncManifestEntry>,
+    pub settings: Option<SyncManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedChunk {
+    pub id: String,
+    pub chunk_index: i32,
+    pub content: String,
+    pub embedding: String, // hex-encoded little-endian f32 BLOB
+    #[serde(default)]
+    pub embedding_model: Option<String>, // model key; None from devices that predate it
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedDocument {
+    pub document: Document,
+    pub chunks: Vec<SyncedChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ConflictStrategy {
+    #[serde(rename = "keep_mine")]
+    KeepMine,
+    #[serde(rename = "keep_theirs")]
+    KeepTheirs,
+    #[serde(rename = "keep_both")]
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: String,
+    pub document_id: String,
+    pub source: String, // "sync" | "import"
+    pub local_title: Option<String>,
+    pub remote_title: Option<String>,
+    pub local_deleted: bool,
+    pub remote_deleted: bool,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub deleted_local: usize,
+    pub deleted_remote: usize,
+    pub merged: usize,
+    pub conflicts: usize,
+    pub settings_updated: bool,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatus {
+    pub device_id: String,
+    pub target: Option<SyncTarget>,
+    pub last_synced_at: Option<String>,
+    pub tracked_documents: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClockOrdering {
+    Equal,
+    Before,
+    After,
+    Concurrent,
+}
+
+fn compare_clocks(local: &VectorClock, remote: &VectorClock) -> ClockOrdering {
+    let mut local_ahead = false;
+    let mut remote_ahead = false;
+
+    for device in local.keys().chain(remote.keys()) {
+        let l = local.get(device).copied().unwrap_or(0);
+        let r = remote.get(device).copied().unwrap_or(0);
+        if l > r {
+            local_ahead = true;
+        } else if r > l {
+            remote_ahead = true;
+        }
+    }
+
+    match (local_ahead, remote_ahead) {
+        (false, false) => ClockOrdering::Equal,
+        (true, false) => ClockOrdering::After,
+        (false, true) => ClockOrdering::Before,
+        (true, true) => ClockOrdering::Concurrent,
+    }
+}
+
+fn merge_clocks(a: &VectorClock, b: &VectorClock) -> VectorClock {
+    let mut merged = a.clone();
+    for (device, &counter) in b {
+        let entry = merged.entry(device.clone()).or_insert(0);
+        *entry = (*entry).max(counter);
+    }
+    merged
+}
+
+fn get_sync_value(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row("SELECT value FROM sync_state WHERE key = ?1", params![key], |row| row.get(0))
+        .ok()
+}
+
+fn set_sync_value(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_state (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+fn sync_device_id(conn: &Connection) -> Result<String> {
+    if let Some(device_id) = get_sync_value(conn, "device_id") {
+        return Ok(device_id);
+    }
+    let device_id = Uuid::new_v4().to_string();
+    set_sync_value(conn, "device_id", &device_id)?;
+    Ok(device_id)
+}
+
+// Covers the fields that replicate, tags included; file_path is device-specific and left out
+fn document_fingerprint(doc: &Document) -> String {
+    let mut tags = doc.tags.clone();
+    tags.sort();
+    calculate_content_hash(&format!(
+        "{}|{}|{}|{}|{}",
+        doc.content_hash,
+        doc.title,
+        doc.file_type,
+        doc.is_searchable,
+        tags.join(",")
+    ))
+}
+
+fn local_document_fingerprint(conn: &Connection, document_id: &str) -> Option<String> {
+    conn.query_row(
+        &format!("SELECT {} FROM documents d WHERE d.id = ?1", DOCUMENT_COLUMNS),
+        params![document_id],
+        |row| document_from_row(row, 0),
+    )
+    .ok()
+    .map(|doc| document_fingerprint(&doc))
+}
+
+// Every copy of the config that leaves the device (sync, archives, pipeline manifests) goes through
+// here; keys stay on the device they were entered on and restore_api_keys fills them back in
+fn strip_config_secrets(config: &RAGConfig) -> RAGConfig {
+    let mut config = config.clone();
+    for model in std::iter::once(&mut config.embedding_model).chain(config.multilingual_embedding_model.as_mut()) {
+        match model {
+            EmbeddingModel::OpenAI { api_key, .. } => api_key.clear(),
+            EmbeddingModel::HuggingFace { api_key, .. } => *api_key = None,
+            _ => {}
+        }
+    }
+    if let VectorBackend::Qdrant { api_key, .. } = &mut config.vector_backend {
+        *api_key = None;
+    }
+    if let Some(RerankerConfig { model: RerankerModel::Cohere { api_key, .. }, .. }) = &mut config.reranker {
+        api_key.clear();
+    }
+    if let Some(api_key) = config.llm_provider.as_mut().and_then(LLMProvider::api_key_mut) {
+        api_key.clear();
+    }
+    config
+}
+
+fn bump_clock(clock: &mut VectorClock, device_id: &str) {
+    *clock.entry(device_id.to_string()).or_insert(0) += 1;
+}
+
+// Compares every local document against its last synced fingerprint and bumps this
+// device's clock entry for anything that changed (including deletions) since then
+fn collect_local_sync_entries(conn: &Connection, device_id: &str) -> Result<HashMap<String, SyncManifestEntry>> {
+    let mut entries: HashMap<String, SyncManifestEntry> = {
+        let mut stmt = conn.prepare("SELECT document_id, clock, fingerprint, deleted, updated_at FROM sync_documents")?;
+        let rows = stmt.query_map([], |row| {
+            let clock: String = row.get(1)?;
+            let updated_at: Option<String> = row.get(4)?;
+            Ok((
+                row.get::<_, String>(0)?,
+                SyncManifestEntry {
+                    clock: serde_json::from_str(&clock).unwrap_or_default(),
+                    fingerprint: row.get(2)?,
+                    deleted: row.get(3)?,
+                    updated_at: updated_at
+                        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                        .map(|t| t.with_timezone(&Utc)),
+                },
+            ))
+        })?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    // Sensitive and trashed documents are withheld: they stay out of the manifest rather than
+    // looking deleted, so peers keep their copies and nothing is pulled over the local one
+    let withheld = withheld_sync_documents(conn)?;
+    let local_docs: Vec<(String, String, DateTime<Utc>)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM documents d WHERE d.is_sensitive = 0 AND d.deleted_at IS NULL",
+            DOCUMENT_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], |row| document_from_row(row, 0))?;
+        rows.filter_map(Result::ok)
+            .map(|doc| (doc.id.clone(), document_fingerprint(&doc), doc.updated_at))
+            .collect()
+    };
+
+    let mut present = std::collections::HashSet::new();
+    for (id, fingerprint, updated_at) in local_docs {
+        present.insert(id.clone());
+        let entry = entries.entry(id).or_default();
+        if entry.fingerprint != fingerprint || entry.deleted {
+            bump_clock(&mut entry.clock, device_id);
+            entry.fingerprint = fingerprint;
+            entry.deleted = false;
+            entry.updated_at = Some(updated_at);
+        }
+    }
+
+    // Only a document that is gone from the table altogether becomes a tombstone
+    entries.retain(|id, _| !withheld.contains(id));
+    for (id, entry) in entries.iter_mut() {
+        if !present.contains(id) && !entry.deleted {
+            bump_clock(&mut entry.clock, device_id);
+            entry.deleted = true;
+            entry.updated_at = Some(Utc::now());
+        }
+    }
+
+    for (id, entry) in &entries {
+        store_sync_entry(conn, id, entry)?;
+    }
+
+    Ok(entries)
+}
+
+fn withheld_sync_documents(conn: &Connection) -> Result<std::collections::HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT id FROM documents WHERE is_sensitive = 1 OR deleted_at IS NOT NULL")?;
+    let ids = stmt.query_map([], |row| row.get::<_, String>(0))?.filter_map(Result::ok).collect();
+    Ok(ids)
+}
+
+fn store_sync_entry(conn: &Connection, document_id: &str, entry: &SyncManifestEntry) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_documents (document_id, clock, fingerprint, deleted, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(document_id) DO UPDATE SET
+            clock = excluded.clock, fingerprint = excluded.fingerprint,
+            deleted = excluded.deleted, updated_at = excluded.updated_at",
+        params![
+            document_id,
+            serde_json::to_string(&entry.clock)?,
+            entry.fingerprint,
+            entry.deleted,
+            entry.updated_at.map(|t| t.to_rfc3339()),
+        ],
+    )?;
+    Ok(())
+}
+
+fn export_synced_document(conn: &Connection, document_id: &str) -> Result<SyncedDocument> {
+    let document = conn.query_row(
+        &format!("SELECT {} FROM documents d WHERE d.id = ?1", DOCUMENT_COLUMNS),
+        params![document_id],
+        |row| document_from_row(row, 0),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT dc.id, dc.chunk_index, dc.content, COALESCE(se.embedding, dc.embedding), dc.created_at, dc.embedding_model
+         FROM document_chunks dc
+         LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+         WHERE dc.document_id = ?1 ORDER BY dc.chunk_index",
+    )?;
+    let chunks = stmt
+        .query_map(params![document_id], |row| {
+            Ok(SyncedChunk {
+                id: row.get(0)?,
+                chunk_index: row.get(1)?,
+                content: row.get(2)?,
+                embedding: hex::encode(row.get::<_, Vec<u8>>(3)?),
+                embedding_model: row.get(5)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e)))?
+                    .with_timezone(&Utc),
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(SyncedDocument { document, chunks })
+}
+
+// Documents arriving from another device are never sensitive; those are withheld at the source
+fn upsert_document_row(conn: &Connection, doc: &Document) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO documents (id, title, content, file_path, file_type, content_hash, is_searchable, is_sensitive, metadata, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+         ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title, content = excluded.content, file_path = excluded.file_path,
+            file_type = excluded.file_type, content_hash = excluded.content_hash,
+            is_searchable = excluded.is_searchable, metadata = excluded.metadata,
+            updated_at = excluded.updated_at",
+        params![
+            doc.id,
+            doc.title,
+            doc.content,
+            doc.file_path,
+            doc.file_type,
+            doc.content_hash,
+            doc.is_searchable,
+            false,
+            doc.metadata.to_string(),
+            doc.created_at.to_rfc3339(),
+            doc.updated_at.to_rfc3339(),
+        ],
+    )
+}
+
+fn apply_synced_document(conn: &mut Connection, synced: &SyncedDocument) -> Result<()> {
+    let doc = &synced.document;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    tx.execute("DELETE FROM document_chunks WHERE document_id = ?1", params![doc.id])?;
+    tx.execute("DELETE FROM parent_chunks WHERE document_id = ?1", params![doc.id])?;
+    upsert_document_row(&tx, doc)?;
+
+    // Tags are merged against the set from the last sync: local additions and removals made
+    // since then survive, everything else follows the incoming document
+    let local_tags = document_tag_names(&tx, &doc.id)?;
+    let merged = merge_tag_sets(synced_tags(&tx, &doc.id).as_deref(), &local_tags, &doc.tags);
+    tx.execute("DELETE FROM document_tags WHERE document_id = ?1", params![doc.id])?;
+    for tag in &merged {
+        attach_tag(&tx, &doc.id, tag)?;
+    }
+    record_synced_tags(&tx, &doc.id, &merged)?;
+
+    for chunk in &synced.chunks {
+        let embedding_bytes = hex::decode(&chunk.embedding)?;
+        tx.execute(
+            "INSERT INTO document_chunks (id, document_id, chunk_index, content, content_hash, embedding, embedding_dim,
+                                          embedding_model, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                chunk.id,
+                doc.id,
+                chunk.chunk_index,
+                chunk.content,
+                calculate_content_hash(&chunk.content),
+                embedding_bytes,
+                embedding_dimension(&embedding_bytes) as i64,
+                chunk.embedding_model,
+                chunk.created_at.to_rfc3339(),
+            ],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+fn document_tag_names(conn: &Connection, document_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name FROM document_tags dt JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = ?1 ORDER BY t.name",
+    )?;
+    let rows = stmt.query_map(params![document_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+fn synced_tags(conn: &Connection, document_id: &str) -> Option<Vec<String>> {
+    conn.query_row("SELECT tags FROM sync_documents WHERE document_id = ?1", params![document_id], |row| {
+        row.get::<_, Option<String>>(0)
+    })
+    .ok()
+    .flatten()
+    .and_then(|tags| serde_json::from_str(&tags).ok())
+}
+
+fn record_synced_tags(conn: &Connection, document_id: &str, tags: &[String]) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_documents (document_id, clock, fingerprint, tags) VALUES (?1, '{}', '', ?2)
+         ON CONFLICT(document_id) DO UPDATE SET tags = excluded.tags",
+        params![document_id, serde_json::to_string(tags)?],
+    )?;
+    Ok(())
+}
+
+// Three-way merge of tag sets; without a base (never synced) nothing is dropped from either side
+fn merge_tag_sets(base: Option<&[String]>, local: &[String], incoming: &[String]) -> Vec<String> {
+    let mut merged: Vec<String> = match base {
+        Some(base) => incoming
+            .iter()
+            .filter(|tag| local.contains(tag) || !base.contains(tag))
+            .chain(local.iter().filter(|tag| !base.contains(tag)))
+            .cloned()
+            .collect(),
+        None => incoming.iter().chain(local).cloned().collect(),
+    };
+    merged.sort();
+    merged.dedup();
+    merged
+}
+
+// Every delete goes through here. foreign_keys is off, so ON DELETE CASCADE never fires and dependent
+// rows are removed explicitly; chunk triggers take care of FTS, qa keys, mentions, embedding slots and the
+// change log.
+fn delete_document_rows(conn: &Connection, document_id: &str) -> rusqlite::Result<usize> {
+    for table in [
+        "document_chunks",
+        "parent_chunks",
+        "qa_keys",
+        "entity_mentions",
+        "document_tags",
+        "document_summaries",
+        "document_affinity",
+    ] {
+        conn.execute(&format!("DELETE FROM {} WHERE document_id = ?1", table), params![document_id])?;
+    }
+    conn.execute("DELETE FROM documents WHERE id = ?1", params![document_id])
+}
+
+impl SyncTarget {
+    async fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            SyncTarget::Folder { path } => {
+                let file = std::path::Path::new(path).join(name);
+                match tokio::fs::read(&file).await {
+                    Ok(bytes) => Ok(Some(bytes)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            SyncTarget::WebDav { url, username, password } => {
+                let mut request = reqwest::Client::new().get(format!("{}/{}", url.trim_end_matches('/'), name));
+                if let Some(user) = username {
+                    request = request.basic_auth(user, password.as_ref());
+                }
+                let response = request.send().await?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                if !response.status().is_success() {
+                    return Err(anyhow::anyhow!("WebDAV GET {} failed: {}", name, response.status()));
+                }
+                Ok(Some(response.bytes().await?.to_vec()))
+            }
+        }
+    }
+
+    async fn write(&self, name: &str, bytes: Vec<u8>) -> Result<()> {
+        match self {
+            SyncTarget::Folder { path } => {
+                let file = std::path::Path::new(path).join(name);
+                if let Some(parent) = file.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                // Write then rename so other devices never see a half-written file
+                let tmp = file.with_extension("tmp");
+                tokio::fs::write(&tmp, bytes).await?;
+                tokio::fs::rename(&tmp, &file).await?;
+                Ok(())
+            }
+            SyncTarget::WebDav { url, username, password } => {
+                let client = reqwest::Client::new();
+                let base = url.trim_end_matches('/');
+
+                if let Some((dir, _)) = name.rsplit_once('/') {
+                    let mut mkcol = client.request(reqwest::Method::from_bytes(b"MKCOL")?, format!("{}/{}", base, dir));
+                    if let Some(user) = username {
+                        mkcol = mkcol.basic_auth(user, password.as_ref());
+                    }
+                    // 405 means the collection already exists
+                    let _ = mkcol.send().await?;
+                }
+
+                let mut request = client.put(format!("{}/{}", base, name)).body(bytes);
+                if let Some(user) = username {
+                    request = request.basic_auth(user, password.as_ref());
+                }
+                let response = request.send().await?;
+                if !response.status().is_success() {
+                    return Err(anyhow::anyhow!("WebDAV PUT {} failed: {}", name, response.status()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn read_sync_manifest(target: &SyncTarget) -> Result<SyncManifest> {
+    match target.read("manifest.json").await? {
+        Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        None => Ok(SyncManifest::default()),
+    }
+}
+
+fn sync_document_path(document_id: &str) -> String {
+    format!("documents/{}.json", document_id)
+}
+
+async fn run_sync(
+    target: &SyncTarget,
+    db_state: &Arc<DbPool>,
+    config_state: &Arc<Mutex<RAGConfig>>,
+) -> Result<SyncReport> {
+    let start_time = std::time::Instant::now();
+    let mut report = SyncReport::default();
+
+    let (device_id, local_entries, withheld) = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let device_id = sync_device_id(&db)?;
+        let entries = collect_local_sync_entries(&db, &device_id)?;
+        (device_id, entries, withheld_sync_documents(&db)?)
+    };
+
+    let mut manifest = read_sync_manifest(target).await?;
+
+    let mut ids: Vec<String> = local_entries.keys().chain(manifest.documents.keys()).cloned().collect();
+    ids.sort();
+    ids.dedup();
+
+    for id in ids {
+        if withheld.contains(&id) {
+            continue;
+        }
+        let local = local_entries.get(&id).cloned();
+        let remote = manifest.documents.get(&id).cloned();
+
+        if has_open_conflict(db_state, &id)? {
+            // Leave the document alone until the user resolves the existing conflict
+            continue;
+        }
+
+        let (push, pull, resolved) = match (&local, &remote) {
+            (Some(_), None) => (true, false, None),
+            (None, Some(_)) => (false, true, None),
+            (Some(l), Some(r)) => match compare_clocks(&l.clock, &r.clock) {
+                ClockOrdering::Equal => (false, false, None),
+                ClockOrdering::After => (true, false, None),
+                ClockOrdering::Before => (false, true, None),
+                ClockOrdering::Concurrent => {
+                    let mut clock = merge_clocks(&l.clock, &r.clock);
+                    if l.fingerprint == r.fingerprint && l.deleted == r.deleted {
+                        // Both devices made the same change; just converge the clocks
+                        report.merged += 1;
+                        (false, false, Some(clock))
+                    } else {
+                        // Concurrent edits: park the remote version as a conflict instead of overwriting
+                        let payload = if r.deleted {
+                            None
+                        } else {
+                            let bytes = target.read(&sync_document_path(&id)).await?;
+                            bytes.map(|b| serde_json::from_slice::<SyncedDocument>(&b)).transpose()?
+                        };
+                        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                        record_sync_conflict(&db, &id, "sync", Some(l), r, payload.as_ref())?;
+                        report.conflicts += 1;
+                        continue;
+                    }
+                }
+            },
+            (None, None) => (false, false, None),
+        };
+
+        if push {
+            let mut entry = local.clone().unwrap_or_default();
+            if let Some(clock) = &resolved {
+                entry.clock = clock.clone();
+            }
+            if !entry.deleted {
+                let synced = {
+                    let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                    export_synced_document(&db, &id)?
+                };
+                target.write(&sync_document_path(&id), serde_json::to_vec(&synced)?).await?;
+                let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                record_synced_tags(&db, &id, &synced.document.tags)?;
+                report.pushed += 1;
+            } else {
+                report.deleted_remote += 1;
+            }
+
+            let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            store_sync_entry(&db, &id, &entry)?;
+            manifest.documents.insert(id.clone(), entry);
+        } else if pull {
+            let mut entry = remote.clone().unwrap_or_default();
+            if let Some(clock) = &resolved {
+                entry.clock = clock.clone();
+            }
+            if entry.deleted {
+                let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                delete_document_rows(&db, &id)?;
+                store_sync_entry(&db, &id, &entry)?;
+                report.deleted_local += 1;
+            } else {
+                let bytes = target
+                    .read(&sync_document_path(&id))
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("Document {} is listed in the manifest but missing", id))?;
+                let synced: SyncedDocument = serde_json::from_slice(&bytes)?;
+
+                let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                apply_synced_document(&mut db, &synced)?;
+                store_sync_entry(&db, &id, &entry)?;
+                report.pulled += 1;
+            }
+            manifest.documents.insert(id.clone(), entry);
+        } else if let Some(clock) = resolved {
+            let mut entry = local.clone().unwrap_or_default();
+            entry.clock = clock;
+            let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            store_sync_entry(&db, &id, &entry)?;
+            manifest.documents.insert(id.clone(), entry);
+        }
+    }
+
+    report.settings_updated = sync_settings(target, db_state, config_state, &device_id, &mut manifest).await?;
+
+    target.write("manifest.json", serde_json::to_vec_pretty(&manifest)?).await?;
+
+    {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        set_sync_value(&db, "last_synced_at", &Utc::now().to_rfc3339())?;
+    }
+
+    report.duration_ms = start_time.elapsed().as_millis() as u64;
+    Ok(report)
+}
+
+// Returns true when the remote settings replaced the local ones
+async fn sync_settings(
+    target: &SyncTarget,
+    db_state: &Arc<DbPool>,
+    config_state: &Arc<Mutex<RAGConfig>>,
+    device_id: &str,
+    manifest: &mut SyncManifest,
+) -> Result<bool> {
+    let local_config = config_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.clone();
+    let shareable = strip_config_secrets(&local_config);
+    let fingerprint = calculate_content_hash(&serde_json::to_string(&shareable)?);
+
+    let mut local = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut entry: SyncManifestEntry = get_sync_value(&db, "settings_entry")
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default();
+        if entry.fingerprint != fingerprint {
+            bump_clock(&mut entry.clock, device_id);
+            entry.fingerprint = fingerprint;
+            entry.updated_at = Some(Utc::now());
+        }
+        entry
+    };
+
+    let remote = manifest.settings.clone();
+    let ordering = remote
+        .as_ref()
+        .map(|r| compare_clocks(&local.clock, &r.clock))
+        .unwrap_or(ClockOrdering::After);
+    let local_wins = match (ordering, &remote) {
+        (ClockOrdering::Concurrent, Some(r)) => local.updated_at >= r.updated_at,
+        (ordering, _) => ordering != ClockOrdering::Before,
+    };
+
+    let mut updated = false;
+    if ordering == ClockOrdering::Equal {
+        // Nothing changed on either side
+    } else if local_wins {
+        if let Some(r) = &remote {
+            local.clock = merge_clocks(&local.clock, &r.clock);
+        }
+        target.write("settings.json", serde_json::to_vec_pretty(&shareable)?).await?;
+    } else if let Some(r) = remote {
+        let bytes = target
+            .read("settings.json")
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("settings.json is listed in the manifest but missing"))?;
+        let mut incoming: RAGConfig = serde_json::from_slice(&bytes)?;
+
+        // Keep this device's API keys where the provider is unchanged
+        restore_api_keys(&mut incoming, &local_config);
+
+        *config_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))? = incoming;
+        local = SyncManifestEntry {
+            clock: merge_clocks(&local.clock, &r.clock),
+            ..r
+        };
+        updated = true;
+    }
+
+    let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    set_sync_value(&db, "settings_entry", &serde_json::to_string(&local)?)?;
+    manifest.settings = Some(local);
+
+    Ok(updated)
+}
+
+fn has_open_conflict(db_state: &Arc<DbPool>, document_id: &str) -> Result<bool> {
+    let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let count: i64 = db.query_row(
+        "SELECT COUNT(*) FROM sync_conflicts WHERE document_id = ?1 AND resolved_at IS NULL",
+        params![document_id],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn record_sync_conflict(
+    conn: &Connection,
+    document_id: &str,
+    source: &str,
+    local: Option<&SyncManifestEntry>,
+    remote: &SyncManifestEntry,
+    remote_payload: Option<&SyncedDocument>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sync_conflicts (id, document_id, source, local_entry, remote_entry, remote_payload, detected_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            Uuid::new_v4().to_string(),
+            document_id,
+            source,
+            local.map(serde_json::to_string).transpose()?,
+            serde_json::to_string(remote)?,
+            remote_payload.map(serde_json::to_string).transpose()?,
+            Utc::now().to_rfc3339(),
+        ],
+    )?;
+    warn!("Conflicting edits detected for document {} during {}", document_id, source);
+    Ok(())
+}
+
+#[tauri::command]
+fn list_conflicts(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<SyncConflict>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT c.id, c.document_id, c.source, d.title, c.remote_payload, c.remote_entry, c.detected_at
+             FROM sync_conflicts c
+             LEFT JOIN documents d ON d.id = c.document_id
+             WHERE c.resolved_at IS NULL
+             ORDER BY c.detected_at ASC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let conflict_iter = stmt
+        .query_map([], |row| {
+            let local_title: Option<String> = row.get(3)?;
+            let remote_payload: Option<String> = row.get(4)?;
+            let remote_entry: String = row.get(5)?;
+            let remote_entry: SyncManifestEntry = serde_json::from_str(&remote_entry).unwrap_or_default();
+
+            Ok(SyncConflict {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                source: row.get(2)?,
+                local_deleted: local_title.is_none(),
+                local_title,
+                remote_title: remote_payload
+                    .and_then(|p| serde_json::from_str::<SyncedDocument>(&p).ok())
+                    .map(|p| p.document.title),
+                remote_deleted: remote_entry.deleted,
+                detected_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(conflict_iter.filter_map(Result::ok).collect())
+}
+
+fn resolve_sync_conflict(conn: &mut Connection, conflict_id: &str, strategy: &ConflictStrategy) -> Result<()> {
+    let (document_id, local_entry, remote_entry, remote_payload): (String, Option<String>, String, Option<String>) = conn
+        .query_row(
+            "SELECT document_id, local_entry, remote_entry, remote_payload FROM sync_conflicts
+             WHERE id = ?1 AND resolved_at IS NULL",
+            params![conflict_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(|_| anyhow::anyhow!("Conflict not found: {}", conflict_id))?;
+
+    let device_id = sync_device_id(conn)?;
+    let local: SyncManifestEntry = local_entry
+        .map(|e| serde_json::from_str(&e))
+        .transpose()?
+        .unwrap_or_default();
+    let remote: SyncManifestEntry = serde_json::from_str(&remote_entry)?;
+    let remote_payload: Option<SyncedDocument> = remote_payload.map(|p| serde_json::from_str(&p)).transpose()?;
+    let mut merged = merge_clocks(&local.clock, &remote.clock);
+
+    match strategy {
+        ConflictStrategy::KeepMine => {
+            // A clock that dominates both sides makes the next sync push the local version
+            bump_clock(&mut merged, &device_id);
+            store_sync_entry(conn, &document_id, &SyncManifestEntry { clock: merged, ..local })?;
+        }
+        ConflictStrategy::KeepTheirs => {
+            match &remote_payload {
+                Some(payload) => apply_synced_document(conn, payload)?,
+                None => {
+                    delete_document_rows(conn, &document_id)?;
+                }
+            }
+            store_sync_entry(conn, &document_id, &SyncManifestEntry { clock: merged, ..remote })?;
+        }
+        ConflictStrategy::KeepBoth => {
+            // The incoming version becomes a new document; the local one wins the original id
+            if let Some(payload) = &remote_payload {
+                let copy_id = Uuid::new_v4().to_string();
+                let mut copy = payload.clone();
+                copy.document.id = copy_id.clone();
+                copy.document.title = format!("{} (conflicted copy)", payload.document.title);
+                for chunk in &mut copy.chunks {
+                    chunk.id = Uuid::new_v4().to_string();
+                }
+                apply_synced_document(conn, &copy)?;
+            }
+            bump_clock(&mut merged, &device_id);
+            store_sync_entry(conn, &document_id, &SyncManifestEntry { clock: merged, ..local })?;
+        }
+    }
+
+    conn.execute(
+        "UPDATE sync_conflicts SET resolved_at = ?1, resolution = ?2 WHERE id = ?3",
+        params![Utc::now().to_rfc3339(), serde_json::to_string(strategy)?, conflict_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+fn resolve_conflict(
+    conflict_id: String,
+    strategy: ConflictStrategy,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    resolve_sync_conflict(&mut db, &conflict_id, &strategy).map_err(|e| e.to_string())?;
+    record_audit(&db, "conflict_resolved", Some(&conflict_id), serde_json::json!({ "strategy": strategy }));
+    Ok(())
+}
+
+const KEYCHAIN_SERVICE: &str = "rag-app";
+const MASKED_SECRET: &str = "********";
+
+// The WebDAV password lives in the OS keychain; sync_state only ever holds the URL and username
+fn webdav_password_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYCHAIN_SERVICE, "webdav_password")?)
+}
+
+fn store_webdav_password(password: Option<&str>) -> Result<()> {
+    let entry = webdav_password_entry()?;
+    match password {
+        Some(password) => entry.set_password(password)?,
+        None => match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        },
+    }
+    Ok(())
+}
+
+// Targets saved by older versions still carry the password inline; it is moved to the keychain on first read
+fn load_sync_target(conn: &Connection) -> Result<Option<SyncTarget>> {
+    let Some(mut target) = get_sync_value(conn, "target").and_then(|v| serde_json::from_str::<SyncTarget>(&v).ok()) else {
+        return Ok(None);
+    };
+    if let SyncTarget::WebDav { url, username, password: Some(legacy) } = &target {
+        store_webdav_password(Some(legacy))?;
+        let persisted = SyncTarget::WebDav { url: url.clone(), username: username.clone(), password: None };
+        set_sync_value(conn, "target", &serde_json::to_string(&persisted)?)?;
+    } else if let SyncTarget::WebDav { password, .. } = &mut target {
+        match webdav_password_entry()?.get_password() {
+            Ok(stored) => *password = Some(stored),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Some(target))
+}
+
+#[tauri::command]
+fn set_sync_target(
+    target: Option<SyncTarget>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+
+    match &target {
+        Some(t) => {
+            let mut persisted = t.clone();
+            match &mut persisted {
+                // The masked value from get_sync_status means the password was left untouched
+                SyncTarget::WebDav { password, .. } => {
+                    if password.as_deref() != Some(MASKED_SECRET) {
+                        store_webdav_password(password.as_deref()).map_err(|e| e.to_string())?;
+                    }
+                    *password = None;
+                }
+                SyncTarget::Folder { .. } => store_webdav_password(None).map_err(|e| e.to_string())?,
+            }
+            set_sync_value(&db, "target", &serde_json::to_string(&persisted).map_err(|e| e.to_string())?)
+                .map_err(|e| e.to_string())?;
+        }
+        None => {
+            db.execute("DELETE FROM sync_state WHERE key = 'target'", [])
+                .map_err(|e| e.to_string())?;
+            store_webdav_password(None).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let kind = match &target {
+        Some(SyncTarget::Folder { .. }) => "folder",
+        Some(SyncTarget::WebDav { .. }) => "webdav",
+        None => "disabled",
+    };
+    record_audit(&db, "settings_changed", Some("sync_target"), serde_json::json!({ "target": kind }));
+    if let Some(SyncTarget::WebDav { password: Some(password), .. }) = &target {
+        if password != MASKED_SECRET {
+            record_audit(&db, "secret_modified", Some("webdav_password"), serde_json::json!({}));
+        }
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+fn get_sync_status(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<SyncStatus, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+
+    let mut target = load_sync_target(&db).map_err(|e| e.to_string())?;
+    if let Some(SyncTarget::WebDav { password, .. }) = &mut target {
+        // Never hand the stored password back to the UI
+        *password = password.as_ref().map(|_| MASKED_SECRET.to_string());
+    }
+
+    let tracked_documents: i64 = db
+        .query_row("SELECT COUNT(*) FROM sync_documents WHERE deleted = 0", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    Ok(SyncStatus {
+        device_id: sync_device_id(&db).map_err(|e| e.to_string())?,
+        target,
+        last_synced_at: get_sync_value(&db, "last_synced_at"),
+        tracked_documents: tracked_documents as usize,
+    })
+}
+
+#[tauri::command]
+async fn sync_now(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    app: AppHandle,
+) -> Result<SyncReport, String> {
+    let target: SyncTarget = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        load_sync_target(&db)
+            .map_err(|e| e.to_string())?
+            .ok_or("Sync is not configured")?
+    };
+
+    info!("Starting knowledge base sync");
+    let report = run_sync(&target, db_state.inner(), config_state.inner())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!("Sync finished: {:?}", report);
+    let _ = app.emit("sync_completed", &report);
+    Ok(report)
+}
+
+// ---------- Incremental Indexing -----------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReindexStatus {
+    #[serde(rename = "added")]
+    Added,
+    #[serde(rename = "unchanged")]
+    Unchanged,
+    #[serde(rename = "touched")]
+    Touched, // file timestamp or bytes changed but the extracted text did not
+    #[serde(rename = "updated")]
+    Updated,
+    #[serde(rename = "missing")]
+    Missing,
+    #[serde(rename = "skipped")]
+    Skipped,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexResult {
+    pub file_path: String,
+    pub document_id: Option<String>,
+    pub status: ReindexStatus,
+    pub chunks_reused: usize,
+    pub chunks_embedded: usize,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub error: Option<IngestError>,
+}
+
+fn file_mtime(file_path: &Path) -> Result<i64> {
+    let modified = std::fs::metadata(long_path(file_path))
+        .map_err(|e| IngestError::from_io(file_path, &e))?
+        .modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0))
+}
+
+fn file_fingerprint(file_path: &Path) -> Result<(i64, String)> {
+    let mtime = file_mtime(file_path)?;
+
+    let mut hasher = Sha256::new();
+    let mut file = std::fs::File::open(long_path(file_path)).map_err(|e| IngestError::from_io(file_path, &e))?;
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok((mtime, hex::encode(hasher.finalize())))
+}
+
+fn store_file_fingerprint(conn: &Connection, document_id: &str, mtime: i64, file_hash: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE documents SET file_mtime = ?1, file_hash = ?2 WHERE id = ?3",
+        params![mtime, file_hash, document_id],
+    )?;
+    Ok(())
+}
+
+// Re-chunks a document, embedding only chunks whose content hash is new under the configured model;
+// with `reuse` off every chunk is embedded afresh. Returns (reused, embedded)
+async fn rechunk_document_incremental(
+    document_id: &str,
+    content: &str,
+    sections: &[TextSection],
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    reuse: bool,
+) -> Result<(usize, usize)> {
+    let model_key = embedding_model_key(&config.embedding_model);
+    // Keyed by (content_hash, model): a vector from another model is never carried over
+    let existing: HashMap<String, Vec<u8>> = if reuse {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut stmt = db.prepare(
+            "SELECT dc.content_hash, COALESCE(se.embedding, dc.embedding) FROM document_chunks dc
+             LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+             WHERE dc.document_id = ?1 AND dc.content_hash IS NOT NULL AND dc.embedding_model = ?2",
+        )?;
+        let rows = stmt.query_map(params![document_id, model_key], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    } else {
+        HashMap::new()
+    };
+
+    let ChunkedDocument { parents, chunks, parent_ids } = chunk_document_with_parents(content, sections, config, providers).await;
+    let (chunks, chunk_metadata): (Vec<String>, Vec<serde_json::Value>) = chunks.into_iter().unzip();
+    let embedding_keys: Vec<String> = chunks
+        .iter()
+        .map(|chunk_content| shared_embedding_key(&config.embedding_model, chunk_content))
+        .collect();
+    let mut embeddings: Vec<Option<Vec<u8>>> = if reuse {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        chunks
+            .iter()
+            .zip(&embedding_keys)
+            .map(|(chunk_content, key)| {
+                find_shared_embedding(&db, key).or_else(|| existing.get(&calculate_content_hash(chunk_content)).cloned())
+            })
+            .collect()
+    } else {
+        vec![None; chunks.len()]
+    };
+    let embedded = embed_missing_chunks(&chunks, &mut embeddings, config, providers, reuse).await?;
+    let reused = chunks.len() - embedded;
+
+    let mut new_chunks = Vec::new();
+    for (index, ((((chunk_content, embedding_bytes), embedding_key), metadata), parent_chunk_id)) in
+        chunks.into_iter().zip(embeddings).zip(embedding_keys).zip(chunk_metadata).zip(parent_ids).enumerate()
+    {
+        let embedding_bytes = embedding_bytes.unwrap_or_default();
+        new_chunks.push((
+            DocumentChunk {
+                id: Uuid::new_v4().to_string(),
+                document_id: document_id.to_string(),
+                chunk_index: index as i32,
+                content: chunk_content,
+                embedding: Vec::new(),
+                metadata,
+                parent_chunk_id,
+                created_at: Utc::now(),
+            },
+            embedding_bytes,
+            embedding_key,
+        ));
+    }
+
+    // Swap old chunks for new ones atomically so retrieval never sees a half-indexed document
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute("DELETE FROM document_chunks WHERE document_id = ?1", params![document_id])?;
+    tx.execute("DELETE FROM parent_chunks WHERE document_id = ?1", params![document_id])?;
+    insert_parent_chunks(&tx, document_id, &parents)?;
+    for (chunk, embedding_bytes, embedding_key) in &new_chunks {
+        insert_chunk(&tx, chunk, embedding_bytes, Some(embedding_key), &model_key)?;
+    }
+    store_document_sections(&tx, document_id, sections)?;
+    tx.commit()?;
+
+    Ok((reused, embedded))
+}
+
+// `force` re-extracts and re-chunks even when the file looks unchanged, e.g. after a chunking change
+async fn reindex_document_incremental(
+    document_id: &str,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    db_state: &Arc<DbPool>,
+    plugins: &[ExtractorPlugin],
+    force: bool,
+) -> Result<ReindexResult> {
+    let (file_path, file_path_raw, stored_mtime, stored_hash, content_hash, is_sensitive): (
+        Option<String>,
+        Option<Vec<u8>>,
+        Option<i64>,
+        Option<String>,
+        String,
+        bool,
+    ) = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        db.query_row(
+            "SELECT file_path, file_path_raw, file_mtime, file_hash, content_hash, is_sensitive FROM documents WHERE id = ?1",
+            params![document_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )?
+    };
+
+    // Documents ingested before raw paths were recorded fall back to the display path
+    let file_path = match (file_path_raw, file_path) {
+        (Some(raw), _) => decode_os_path(&raw),
+        (None, Some(path)) => PathBuf::from(path),
+        (None, None) => return Err(anyhow::anyhow!("Document {} has no source file", document_id)),
+    };
+    let mut result = ReindexResult {
+        file_path: file_path.display().to_string(),
+        document_id: Some(document_id.to_string()),
+        status: ReindexStatus::Unchanged,
+        chunks_reused: 0,
+        chunks_embedded: 0,
+        message: None,
+        error: None,
+    };
+
+    if is_sensitive {
+        // Re-chunking would write plaintext chunk text next to encrypted content
+        result.status = ReindexStatus::Skipped;
+        result.message = Some("Sensitive documents are not re-indexed automatically".to_string());
+        return Ok(result);
+    }
+
+    if !long_path(&file_path).exists() {
+        result.status = ReindexStatus::Missing;
+        return Ok(result);
+    }
+
+    // Cheap check first: an untouched mtime means nothing to do
+    let mtime = file_mtime(&file_path)?;
+    if !force && stored_mtime == Some(mtime) {
+        return Ok(result);
+    }
+
+    let (mtime, file_hash) = file_fingerprint(&file_path)?;
+    if !force && stored_hash.as_deref() == Some(file_hash.as_str()) {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        store_file_fingerprint(&db, document_id, mtime, &file_hash)?;
+        result.status = ReindexStatus::Touched;
+        return Ok(result);
+    }
+
+    let extracted = extract_document(&file_path, plugins, &config.extraction).await?;
+    let new_content_hash = calculate_content_hash(&extracted.text);
+    if !force && new_content_hash == content_hash {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        store_file_fingerprint(&db, document_id, mtime, &file_hash)?;
+        result.status = ReindexStatus::Touched;
+        return Ok(result);
+    }
+
+    let config = &{
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        apply_document_language(&db, document_id, &extracted.text, config)?
+    };
+    let (reused, embedded) =
+        rechunk_document_incremental(document_id, &extracted.text, &extracted.sections, db_state, config, providers, !force)
+            .await?;
+    if config.qa_extraction != QaExtraction::Off {
+        if let Err(e) = index_qa_pairs(document_id, &extracted.text, db_state, config, providers).await {
+            warn!("Q/A extraction failed for {}: {}", document_id, e);
+        }
+    }
+    if config.summarize_on_ingest {
+        let title: String = {
+            let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            db.query_row("SELECT title FROM documents WHERE id = ?1", params![document_id], |row| row.get(0))?
+        };
+        if let Err(e) =
+            summarize_document_text(document_id, &title, &extracted.text, None, db_state, config, providers).await
+        {
+            warn!("Summarization failed for {}: {}", document_id, e);
+        }
+    }
+    if config.auto_tagging != AutoTagging::Off {
+        if let Err(e) = auto_tag_document_text(document_id, &extracted.text, db_state, config).await {
+            warn!("Auto-tagging failed for {}: {}", document_id, e);
+        }
+    }
+    if config.entity_extraction != EntityExtraction::Off {
+        if let Err(e) = index_entities(document_id, db_state, config).await {
+            warn!("Entity extraction failed for {}: {}", document_id, e);
+        }
+    }
+
+    {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        db.execute(
+            "UPDATE documents SET content = ?1, content_hash = ?2, metadata = ?3, updated_at = ?4 WHERE id = ?5",
+            params![
+                extracted.text,
+                new_content_hash,
+                extracted.metadata.to_string(),
+                Utc::now().to_rfc3339(),
+                document_id,
+            ],
+        )?;
+        store_file_fingerprint(&db, document_id, mtime, &file_hash)?;
+    }
+
+    result.status = ReindexStatus::Updated;
+    result.chunks_reused = reused;
+    result.chunks_embedded = embedded;
+    Ok(result)
+}
+
+// Re-imports a file path: known files are re-indexed incrementally, new ones ingested
+async fn reimport_file(
+    file_path: &Path,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    db_state: &Arc<DbPool>,
+    plugins: &[ExtractorPlugin],
+) -> Result<ReindexResult> {
+    let existing_id: Option<String> = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        ensure_writable(&db)?;
+        db.query_row(
+            "SELECT id FROM documents
+             WHERE (file_path_raw = ?1 OR (file_path_raw IS NULL AND file_path = ?2)) AND deleted_at IS NULL
+             ORDER BY created_at DESC LIMIT 1",
+            params![encode_os_path(file_path), file_path.display().to_string()],
+            |row| row.get(0),
+        )
+        .ok()
+    };
+
+    match existing_id {
+        Some(document_id) => reindex_document_incremental(&document_id, config, providers, db_state, plugins, false).await,
+        None => {
+            let (document, chunks_created) = ingest_file(file_path, None, config, providers, db_state, plugins).await?;
+            Ok(ReindexResult {
+                file_path: file_path.display().to_string(),
+                document_id: Some(document.id),
+                status: ReindexStatus::Added,
+                chunks_reused: 0,
+                chunks_embedded: chunks_created,
+                message: None,
+                error: None,
+            })
+        }
+    }
+}
+
+#[tauri::command]
+async fn reimport_files(
+    file_paths: Vec<String>,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<Vec<ReindexResult>, String> {
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+    let mut results = Vec::with_capacity(file_paths.len());
+
+    for file_path in file_paths.iter().map(PathBuf::from) {
+        let result = match reimport_file(&file_path, &config, providers_state.inner(), db_state.inner(), &plugins).await {
+            Ok(result) => result,
+            Err(e) => ReindexResult {
+                file_path: file_path.display().to_string(),
+                document_id: None,
+                status: ReindexStatus::Failed,
+                chunks_reused: 0,
+                chunks_embedded: 0,
+                message: Some(e.to_string()),
+                error: Some(IngestError::classify(&file_path, &e)),
+            },
+        };
+
+        if matches!(result.status, ReindexStatus::Added | ReindexStatus::Updated) {
+            if let Some(document_id) = &result.document_id {
+                let _ = app.emit("document_processed", document_id);
+            }
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+// Re-extracts a document from its source file and rebuilds its chunks, whether or not the file changed
+#[tauri::command]
+async fn reprocess_document(
+    document_id: String,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<ReindexResult, String> {
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+
+    let result = reindex_document_incremental(
+        &document_id,
+        &config,
+        providers_state.inner(),
+        db_state.inner(),
+        &plugins,
+        true,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if matches!(result.status, ReindexStatus::Updated) {
+        record_audit_state(&db_state, "reprocess_document", Some(&document_id), serde_json::json!({
+            "chunks_reused": result.chunks_reused,
+            "chunks_embedded": result.chunks_embedded,
+        }));
+        let _ = app.emit("document_processed", &document_id);
+    }
+    Ok(result)
+}
+
+// ---------- Retention Policies ---------------------------------------------------
+
+const RETENTION_CHECK_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RetentionScope {
+    #[serde(rename = "tag")]
+    Tag, // matches documents whose metadata "tags" array contains the value
+    #[serde(rename = "collection")]
+    Collection, // matches documents whose metadata "collection" equals the value
+    #[serde(rename = "file_type")]
+    FileType,
+}
+
+impl RetentionScope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RetentionScope::Tag => "tag",
+            RetentionScope::Collection => "collection",
+            RetentionScope::FileType => "file_type",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tag" => Some(RetentionScope::Tag),
+            "collection" => Some(RetentionScope::Collection),
+            "file_type" => Some(RetentionScope::FileType),
+            _ => None,
+        }
+    }
+
+    // Tags come from document_tags; metadata.tags was migrated there and no longer exists
+    fn matches(&self, value: &str, file_type: &str, tags: &[String], metadata: &serde_json::Value) -> bool {
+        match self {
+            RetentionScope::Tag => tags.iter().any(|tag| tag == value),
+            RetentionScope::Collection => metadata.get("collection").and_then(|c| c.as_str()) == Some(value),
+            RetentionScope::FileType => file_type.eq_ignore_ascii_case(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRule {
+    pub id: String,
+    pub scope: RetentionScope,
+    pub value: String,
+    pub max_age_days: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpiredDocument {
+    pub document_id: String,
+    pub title: String,
+    pub rule_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+fn load_retention_rules(conn: &Connection) -> Result<Vec<RetentionRule>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, scope, value, max_age_days, created_at FROM retention_rules ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, u32>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+
+    Ok(rows
+        .filter_map(Result::ok)
+        .filter_map(|(id, scope, value, max_age_days, created_at)| {
+            Some(RetentionRule {
+                id,
+                scope: RetentionScope::parse(&scope)?,
+                value,
+                max_age_days,
+                created_at: DateTime::parse_from_rfc3339(&created_at).ok()?.with_timezone(&Utc),
+            })
+        })
+        .collect())
+}
+
+// Soft-deletes every live document older than a matching rule allows; the shortest matching rule wins
+fn apply_retention_policies(conn: &Connection) -> Result<Vec<ExpiredDocument>> {
+    let rules = load_retention_rules(conn)?;
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let now = Utc::now();
+    let candidates: Vec<(String, String, String, Option<String>, String, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT d.id, d.title, d.file_type, d.metadata, d.created_at,
+                    (SELECT json_group_array(t.name) FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+                     WHERE dt.document_id = d.id)
+             FROM documents d WHERE d.deleted_at IS NULL",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut expired = Vec::new();
+    for (document_id, title, file_type, metadata, created_at, tags) in candidates {
+        let Ok(created_at) = DateTime::parse_from_rfc3339(&created_at).map(|t| t.with_timezone(&Utc)) else {
+            continue;
+        };
+        let metadata: serde_json::Value = metadata
+            .and_then(|m| serde_json::from_str(&m).ok())
+            .unwrap_or(serde_json::Value::Null);
+        let tags: Vec<String> = serde_json::from_str(&tags).unwrap_or_default();
+
+        let rule = rules
+            .iter()
+            .filter(|rule| rule.scope.matches(&rule.value, &file_type, &tags, &metadata))
+            .min_by_key(|rule| rule.max_age_days);
+        let Some(rule) = rule else { continue };
+        if now - created_at < chrono::Duration::days(rule.max_age_days as i64) {
+            continue;
+        }
+
+        conn.execute(
+            "UPDATE documents SET deleted_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), document_id],
+        )?;
+        record_audit(conn, "document_expired", Some(&document_id), serde_json::json!({
+            "title": title,
+            "rule_id": rule.id,
+            "scope": rule.scope.as_str(),
+            "value": rule.value,
+            "max_age_days": rule.max_age_days,
+        }));
+        expired.push(ExpiredDocument {
+            document_id,
+            title,
+            rule_id: rule.id.clone(),
+            created_at,
+        });
+    }
+
+    Ok(expired)
+}
+
+fn run_retention_job(db_state: &Arc<DbPool>, app: &AppHandle) -> Result<Vec<ExpiredDocument>> {
+    let expired = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        // Expiry on a shared copy is the curator's job
+        if is_read_only(&db) {
+            return Ok(Vec::new());
+        }
+        apply_retention_policies(&db)?
+    };
+
+    if !expired.is_empty() {
+        info!("Retention policies expired {} document(s)", expired.len());
+        let _ = app.emit("documents_expired", &expired);
+        dispatch_webhook_event(db_state, "documents.expired", serde_json::json!({ "documents": expired }));
+    }
+    Ok(expired)
+}
+
+fn spawn_retention_job(db_state: Arc<DbPool>, app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = run_retention_job(&db_state, &app) {
+                warn!("Retention job failed: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(RETENTION_CHECK_INTERVAL_SECS)).await;
+        }
+    });
+}
+
+#[tauri::command]
+fn add_retention_rule(
+    scope: RetentionScope,
+    value: String,
+    max_age_days: u32,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<RetentionRule, String> {
+    if max_age_days == 0 {
+        return Err("Retention period must be at least one day".to_string());
+    }
+
+    let rule = RetentionRule {
+        id: Uuid::new_v4().to_string(),
+        scope,
+        value,
+        max_age_days,
+        created_at: Utc::now(),
+    };
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO retention_rules (id, scope, value, max_age_days, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![rule.id, rule.scope.as_str(), rule.value, rule.max_age_days, rule.created_at.to_rfc3339()],
+    )
+    .map_err(|e| e.to_string())?;
+
+    record_audit(&db, "settings_changed", Some(&rule.id), serde_json::json!({
+        "retention_rule": "added",
+        "scope": rule.scope.as_str(),
+        "value": rule.value,
+        "max_age_days": rule.max_age_days,
+    }));
+    Ok(rule)
+}
+
+#[tauri::command]
+fn remove_retention_rule(
+    rule_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM retention_rules WHERE id = ?1", params![rule_id])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Retention rule not found: {}", rule_id));
+    }
+
+    record_audit(&db, "settings_changed", Some(&rule_id), serde_json::json!({ "retention_rule": "removed" }));
+    Ok(())
+}
+
+#[tauri::command]
+fn list_retention_rules(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<RetentionRule>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_retention_rules(&db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_retention_now(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<Vec<ExpiredDocument>, String> {
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    run_retention_job(db_state.inner(), &app).map_err(|e| e.to_string())
+}
+
+// Expired documents keep their chunks until deleted, so restoring is just clearing the marker
+#[tauri::command]
+fn restore_document(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let restored = db
+        .execute(
+            "UPDATE documents SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2 AND deleted_at IS NOT NULL",
+            params![Utc::now().to_rfc3339(), document_id],
+        )
+        .map_err(|e| e.to_string())?;
+
+    if restored == 0 {
+        return Err(format!("No expired document with id {}", document_id));
+    }
+
+    record_audit(&db, "document_restored", Some(&document_id), serde_json::json!({}));
+    Ok(())
+}
+
+// ---------- Demo Content -------------------------------------------------------
+
+const DEMO_COLLECTION: &str = "demo";
+
+// Bundled into the binary so the demo works before any resources are resolved
+const DEMO_DOCUMENTS: &[(&str, &str)] = &[
+    ("retrieval-augmented-generation.md", include_str!("../demo/retrieval-augmented-generation.md")),
+    ("chunking-guide.md", include_str!("../demo/chunking-guide.md")),
+    ("privacy-and-retention.md", include_str!("../demo/privacy-and-retention.md")),
+];
+
+const DEMO_QUESTIONS: &[&str] = &[
+    "How does retrieval-augmented generation answer a question?",
+    "What chunk size and overlap should I start with?",
+    "What happens to sensitive documents during sync?",
+    "How can I make clipboard captures expire automatically?",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemoContent {
+    pub documents: Vec<ReindexResult>,
+    pub suggested_questions: Vec<String>,
+}
+
+// Demo files are written to the data folder and ingested like any user file, so loading twice is a no-op
+#[tauri::command]
+async fn load_demo_content(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<DemoContent, String> {
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+
+    let demo_dir = app_data_dir(&app).map_err(|e| e.to_string())?.join("demo");
+    std::fs::create_dir_all(&demo_dir).map_err(|e| e.to_string())?;
+
+    let mut documents = Vec::with_capacity(DEMO_DOCUMENTS.len());
+    for (file_name, content) in DEMO_DOCUMENTS {
+        let file_path = demo_dir.join(file_name);
+        // Rewriting identical bytes would bump the mtime and force a needless hash check
+        if std::fs::read_to_string(&file_path).ok().as_deref() != Some(*content) {
+            std::fs::write(&file_path, content).map_err(|e| e.to_string())?;
+        }
+
+        let result = reimport_file(&file_path, &config, providers_state.inner(), db_state.inner(), &plugins)
+            .await
+            .map_err(|e| format!("Failed to load demo document {}: {}", file_name, e))?;
+
+        if let Some(document_id) = &result.document_id {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            db.execute(
+                "UPDATE documents SET metadata = json_set(COALESCE(metadata, '{}'), '$.collection', ?1) WHERE id = ?2",
+                params![DEMO_COLLECTION, document_id],
+            )
+            .map_err(|e| e.to_string())?;
+
+            if matches!(result.status, ReindexStatus::Added | ReindexStatus::Updated) {
+                let _ = app.emit("document_processed", document_id);
+            }
+        }
+        documents.push(result);
+    }
+
+    Ok(DemoContent {
+        documents,
+        suggested_questions: DEMO_QUESTIONS.iter().map(|q| q.to_string()).collect(),
+    })
+}
+
+// ---------- Retrieval Regression Checks ------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenQuestion {
+    pub id: String,
+    pub question: String,
+    pub expected_document_ids: Vec<String>,
+    #[serde(default)]
+    pub set_id: Option<String>, // the evaluation set the question belongs to, if any
+    #[serde(default)]
+    pub expected_answer: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenQuestionOutcome {
+    pub question_id: String,
+    pub question: String,
+    pub hit_before: bool,
+    pub hit_after: bool,
+    pub score_before: Option<f32>, // best similarity of an expected document's chunk
+    pub score_after: Option<f32>,
+    pub score_delta: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegressionReport {
+    pub questions: usize,
+    pub hit_rate_before: f32,
+    pub hit_rate_after: f32,
+    pub mean_score_delta: f32,
+    pub regressions: usize, // questions that were hits before and are misses after
+    pub reindexed: bool,    // the new config needed a shadow index to evaluate
+    pub outcomes: Vec<GoldenQuestionOutcome>,
+}
+
+struct IndexedChunk {
+    document_id: String,
+    embedding: Vec<f32>,
+}
+
+// Chunking or embedding changes invalidate the stored vectors; threshold, top-k and metric do not
+fn config_changes_index(old: &RAGConfig, new: &RAGConfig) -> bool {
+    embedding_model_key(&old.embedding_model) != embedding_model_key(&new.embedding_model)
+        || old.chunk_size != new.chunk_size
+        || old.chunk_overlap != new.chunk_overlap
+        || old.chunk_unit != new.chunk_unit
+        || active_parent_chunk_size(old) != active_parent_chunk_size(new)
+}
+
+// All golden questions, or only those of one evaluation set
+fn load_golden_questions(conn: &Connection, set_id: Option<&str>) -> Result<Vec<GoldenQuestion>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, question, expected_document_ids, set_id, expected_answer, created_at
+         FROM golden_questions WHERE (?1 IS NULL OR set_id = ?1) ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map(params![set_id], |row| {
+        let expected: String = row.get(2)?;
+        Ok(GoldenQuestion {
+            id: row.get(0)?,
+            question: row.get(1)?,
+            expected_document_ids: serde_json::from_str(&expected).unwrap_or_default(),
+            set_id: row.get(3)?,
+            expected_answer: row.get(4)?,
+            created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, Box::new(e)))?
+                .with_timezone(&Utc),
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+fn insert_golden_question(conn: &Connection, golden: &GoldenQuestion) -> Result<()> {
+    conn.execute(
+        "INSERT INTO golden_questions (id, question, expected_document_ids, set_id, expected_answer, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            golden.id,
+            golden.question,
+            serde_json::to_string(&golden.expected_document_ids)?,
+            golden.set_id,
+            golden.expected_answer,
+            golden.created_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(())
+}
+
+// Sensitive documents are left out so the result does not depend on whether they are unlocked
+fn load_retrieval_index(conn: &Connection) -> Result<Vec<IndexedChunk>> {
+    let mut stmt = conn.prepare(
+        "SELECT dc.document_id, COALESCE(se.embedding, dc.embedding)
+         FROM document_chunks dc
+         JOIN documents d ON dc.document_id = d.id
+         LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+         WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND d.is_sensitive = 0",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let bytes: Vec<u8> = row.get(1)?;
+        Ok(IndexedChunk {
+            document_id: row.get(0)?,
+            embedding: decode_embedding(&bytes),
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// Re-chunks and embeds the corpus in memory under a proposed config without touching stored chunks
+async fn build_shadow_index(
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<Vec<IndexedChunk>> {
+    let documents: Vec<(String, String)> = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut stmt = db.prepare(
+            "SELECT id, content FROM documents WHERE is_searchable = 1 AND deleted_at IS NULL AND is_sensitive = 0",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    warm_tokenizer(config).await;
+    let mut index = Vec::new();
+    for (document_id, content) in documents {
+        for chunk_content in chunk_text_with_config(&content, config) {
+            let shared = {
+                let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                find_shared_embedding(&db, &shared_embedding_key(&config.embedding_model, &chunk_content))
+            };
+            let embedding = match shared {
+                Some(bytes) => decode_embedding(&bytes),
+                None => providers.embed(&chunk_content, config).await?,
+            };
+            index.push(IndexedChunk { document_id: document_id.clone(), embedding });
+        }
+    }
+    Ok(index)
+}
+
+// Returns whether an expected document made the top-k, and the best score any of its chunks reached
+fn evaluate_golden_question(
+    index: &[IndexedChunk],
+    query_embedding: &[f32],
+    expected_document_ids: &[String],
+    config: &RAGConfig,
+) -> (bool, Option<f32>) {
+    let mut scored: Vec<(&str, f32)> = index
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.document_id.as_str(),
+                compute_similarity(config.similarity_metric, query_embedding, &chunk.embedding),
+            )
+        })
+        .collect();
+
+    let expected_score = scored
+        .iter()
+        .filter(|(id, _)| expected_document_ids.iter().any(|e| e == id))
+        .map(|(_, score)| *score)
+        .fold(None, |best: Option<f32>, score| Some(best.map_or(score, |b| b.max(score))));
+
+    scored.retain(|(_, score)| *score > config.similarity_threshold);
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    scored.truncate(config.top_k);
+    let hit = scored.iter().any(|(id, _)| expected_document_ids.iter().any(|e| e == id));
+
+    (hit, expected_score)
+}
+
+async fn run_regression_check(
+    db_state: &Arc<DbPool>,
+    providers: &EmbeddingProviders,
+    current: &RAGConfig,
+    proposed: &RAGConfig,
+) -> Result<RegressionReport> {
+    let (questions, current_index) = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        (load_golden_questions(&db, None)?, load_retrieval_index(&db)?)
+    };
+    // Evaluation cases that only check the answer have nothing to say about retrieval
+    let questions: Vec<GoldenQuestion> =
+        questions.into_iter().filter(|q| !q.expected_document_ids.is_empty()).collect();
+
+    let reindexed = config_changes_index(current, proposed);
+    let proposed_index = if reindexed { Some(build_shadow_index(db_state, proposed, providers).await?) } else { None };
+    let proposed_index = proposed_index.as_deref().unwrap_or(&current_index);
+    let same_model = embedding_model_key(&current.embedding_model) == embedding_model_key(&proposed.embedding_model);
+
+    let mut outcomes = Vec::with_capacity(questions.len());
+    for question in &questions {
+        let before_embedding = providers.embed(&question.question, current).await?;
+        let after_embedding = if same_model {
+            before_embedding.clone()
+        } else {
+            providers.embed(&question.question, proposed).await?
+        };
+
+        let (hit_before, score_before) =
+            evaluate_golden_question(&current_index, &before_embedding, &question.expected_document_ids, current);
+        let (hit_after, score_after) =
+            evaluate_golden_question(proposed_index, &after_embedding, &question.expected_document_ids, proposed);
+
+        outcomes.push(GoldenQuestionOutcome {
+            question_id: question.id.clone(),
+            question: question.question.clone(),
+            hit_before,
+            hit_after,
+            score_before,
+            score_after,
+            score_delta: score_before.zip(score_after).map(|(before, after)| after - before),
+        });
+    }
+
+    let count = outcomes.len().max(1) as f32;
+    let deltas: Vec<f32> = outcomes.iter().filter_map(|o| o.score_delta).collect();
+    Ok(RegressionReport {
+        questions: outcomes.len(),
+        hit_rate_before: outcomes.iter().filter(|o| o.hit_before).count() as f32 / count,
+        hit_rate_after: outcomes.iter().filter(|o| o.hit_after).count() as f32 / count,
+        mean_score_delta: if deltas.is_empty() { 0.0 } else { deltas.iter().sum::<f32>() / deltas.len() as f32 },
+        regressions: outcomes.iter().filter(|o| o.hit_before && !o.hit_after).count(),
+        reindexed,
+        outcomes,
+    })
+}
+
+#[tauri::command]
+fn add_golden_question(
+    question: String,
+    expected_document_ids: Vec<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<GoldenQuestion, String> {
+    if expected_document_ids.is_empty() {
+        return Err("A golden question needs at least one expected document".to_string());
+    }
+
+    let golden = GoldenQuestion {
+        id: Uuid::new_v4().to_string(),
+        question,
+        expected_document_ids,
+        set_id: None,
+        expected_answer: None,
+        created_at: Utc::now(),
+    };
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    insert_golden_question(&db, &golden).map_err(|e| e.to_string())?;
+
+    Ok(golden)
+}
+
+#[tauri::command]
+fn remove_golden_question(
+    question_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM golden_questions WHERE id = ?1", params![question_id])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Golden question not found: {}", question_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_golden_questions(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<GoldenQuestion>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_golden_questions(&db, None).map_err(|e| e.to_string())
+}
+
+// Dry run of a proposed config against the golden set; nothing is applied until set_rag_config
+#[tauri::command]
+async fn check_config_regression(
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+) -> Result<RegressionReport, String> {
+    let current = config_state.lock().map_err(|e| e.to_string())?.clone();
+    run_regression_check(db_state.inner(), providers_state.inner(), &current, &config)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ---------- RAG Evaluation -------------------------------------------------------
+
+// An evaluation set is the golden questions sharing a set_id; the set exists while it has questions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalSet {
+    pub id: String,
+    pub case_count: usize,
+    pub created_at: DateTime<Utc>, // when its first question was added
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCaseResult {
+    pub case_id: String,
+    pub question: String,
+    pub answer: String,
+    pub retrieved_document_ids: Vec<String>, // in rank order, one entry per retrieved passage
+    pub recall: f32,                         // share of expected documents retrieved
+    pub reciprocal_rank: f32,                // 1/rank of the first passage from an expected document, 0 when none
+    pub answer_similarity: Option<f32>,      // embedding similarity to the expected answer, when there is one
+    pub latency_ms: u64,
+    pub error: Option<String>, // the pipeline failed; the case scores zero
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalRun {
+    pub id: String,
+    pub set_id: String,
+    pub config: RAGConfig, // kept so runs under different configs can be compared
+    pub recall_at_k: f32,
+    pub mrr: f32,
+    pub answer_similarity: Option<f32>, // mean over cases with an expected answer
+    pub mean_latency_ms: u64,
+    pub results: Vec<EvalCaseResult>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn eval_run_from_row(row: &rusqlite::Row) -> rusqlite::Result<EvalRun> {
+    let config: String = row.get(2)?;
+    let results: String = row.get(7)?;
+    Ok(EvalRun {
+        id: row.get(0)?,
+        set_id: row.get(1)?,
+        config: serde_json::from_str(&config).unwrap_or_default(),
+        recall_at_k: row.get(3)?,
+        mrr: row.get(4)?,
+        answer_similarity: row.get(5)?,
+        mean_latency_ms: row.get::<_, i64>(6)? as u64,
+        results: serde_json::from_str(&results).unwrap_or_default(),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(8)?)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e)))?
+            .with_timezone(&Utc),
+    })
+}
+
+// Maps each retrieved passage to its document; merged chunks count as retrieved at the passage's rank
+fn retrieved_documents(conn: &Connection, context: &[RetrievalResult]) -> Result<Vec<Vec<String>>> {
+    let mut stmt = conn.prepare("SELECT document_id FROM document_chunks WHERE id = ?1")?;
+    let mut ranked = Vec::with_capacity(context.len());
+    for result in context {
+        let mut document_ids: Vec<String> = Vec::new();
+        for chunk_id in std::iter::once(&result.chunk_id).chain(&result.merged_chunk_ids) {
+            let mut rows = stmt.query(params![chunk_id])?;
+            // Session attachments have no stored chunk and belong to no document
+            if let Some(document_id) = rows.next()?.map(|row| row.get::<_, String>(0)).transpose()? {
+                if !document_ids.contains(&document_id) {
+                    document_ids.push(document_id);
+                }
+            }
+        }
+        ranked.push(document_ids);
+    }
+    Ok(ranked)
+}
+
+// Recall over the expected documents and the reciprocal rank of the first passage that hits one
+fn retrieval_metrics(ranked: &[Vec<String>], expected: &[String]) -> (f32, f32) {
+    if expected.is_empty() {
+        return (0.0, 0.0);
+    }
+    let found = expected.iter().filter(|id| ranked.iter().any(|ids| ids.contains(id))).count();
+    let reciprocal_rank = ranked
+        .iter()
+        .position(|ids| ids.iter().any(|id| expected.contains(id)))
+        .map_or(0.0, |rank| 1.0 / (rank + 1) as f32);
+    (found as f32 / expected.len() as f32, reciprocal_rank)
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
+
+#[tauri::command]
+fn delete_eval_set(set_id: String, db_state: tauri::State<'_, Arc<DbPool>>) -> Result<(), String> {
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+    let removed = tx
+        .execute("DELETE FROM golden_questions WHERE set_id = ?1", params![set_id])
+        .map_err(|e| e.to_string())?;
+    if removed == 0 {
+        return Err(format!("Evaluation set not found: {}", set_id));
+    }
+    tx.execute("DELETE FROM eval_runs WHERE set_id = ?1", params![set_id]).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_eval_sets(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<EvalSet>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT set_id, MIN(created_at), COUNT(*) FROM golden_questions
+             WHERE set_id IS NOT NULL GROUP BY set_id ORDER BY MIN(created_at) ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(EvalSet {
+                id: row.get(0)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?)
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e)))?
+                    .with_timezone(&Utc),
+                case_count: row.get::<_, i64>(2)? as usize,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// Adds a golden question to the named set, creating the set with its first question; remove it with
+// remove_golden_question
+#[tauri::command]
+fn add_eval_case(
+    set_id: String,
+    question: String,
+    expected_answer: Option<String>,
+    expected_document_ids: Vec<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<GoldenQuestion, String> {
+    let set_id = set_id.trim().to_string();
+    if set_id.is_empty() {
+        return Err("An evaluation set needs a name".to_string());
+    }
+    let expected_answer = expected_answer.filter(|answer| !answer.trim().is_empty());
+    if expected_answer.is_none() && expected_document_ids.is_empty() {
+        return Err("An evaluation case needs an expected answer or expected documents".to_string());
+    }
+
+    let case = GoldenQuestion {
+        id: Uuid::new_v4().to_string(),
+        question,
+        expected_document_ids,
+        set_id: Some(set_id),
+        expected_answer,
+        created_at: Utc::now(),
+    };
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    insert_golden_question(&db, &case).map_err(|e| e.to_string())?;
+    Ok(case)
+}
+
+#[tauri::command]
+fn list_eval_cases(
+    set_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<GoldenQuestion>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_golden_questions(&db, Some(&set_id)).map_err(|e| e.to_string())
+}
+
+// Runs every case of a set through the full pipeline under `config` (the active config when None) and stores the run
+#[tauri::command]
+async fn run_evaluation(
+    set_id: String,
+    config: Option<RAGConfig>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<EvalRun, String> {
+    let config = match config {
+        Some(config) => config,
+        None => config_state.lock().map_err(|e| e.to_string())?.clone(),
+    };
+    let cases = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        load_golden_questions(&db, Some(&set_id)).map_err(|e| e.to_string())?
+    };
+    if cases.is_empty() {
+        return Err("The evaluation set has no cases".to_string());
+    }
+
+    let mut results = Vec::with_capacity(cases.len());
+    for case in &cases {
+        let started = std::time::Instant::now();
+        let response = answer_rag_query(
+            case.question.clone(),
+            config.mode.clone(),
+            config.clone(),
+            None,
+            None,
+            None,
+            Vec::new(),
+            db_state.clone(),
+            key_state.clone(),
+            store_state.clone(),
+            providers_state.clone(),
+            session_state.clone(),
+            hnsw_state.clone(),
+            vector_stores_state.clone(),
+        )
+        .await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                results.push(EvalCaseResult {
+                    case_id: case.id.clone(),
+                    question: case.question.clone(),
+                    answer: String::new(),
+                    retrieved_document_ids: Vec::new(),
+                    recall: 0.0,
+                    reciprocal_rank: 0.0,
+                    answer_similarity: case.expected_answer.as_ref().map(|_| 0.0),
+                    latency_ms,
+                    error: Some(e),
+                });
+                continue;
+            }
+        };
+
+        let ranked = {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            retrieved_documents(&db, &response.retrieved_context).map_err(|e| e.to_string())?
+        };
+        let (recall, reciprocal_rank) = retrieval_metrics(&ranked, &case.expected_document_ids);
+        let answer_similarity = match &case.expected_answer {
+            Some(expected) => {
+                let expected = providers_state.embed(expected, &config).await.map_err(|e| e.to_string())?;
+                let actual = providers_state.embed(&response.answer, &config).await.map_err(|e| e.to_string())?;
+                Some(cosine_similarity(&expected, &actual))
+            }
+            None => None,
+        };
+        results.push(EvalCaseResult {
+            case_id: case.id.clone(),
+            question: case.question.clone(),
+            answer: response.answer,
+            retrieved_document_ids: ranked.into_iter().flatten().collect(),
+            recall,
+            reciprocal_rank,
+            answer_similarity,
+            latency_ms,
+            error: None,
+        });
+    }
+
+    // Retrieval metrics only cover cases that name expected documents
+    let retrieval_cases: Vec<&EvalCaseResult> = results
+        .iter()
+        .zip(&cases)
+        .filter(|(_, case)| !case.expected_document_ids.is_empty())
+        .map(|(result, _)| result)
+        .collect();
+    let run = EvalRun {
+        id: Uuid::new_v4().to_string(),
+        set_id,
+        recall_at_k: mean(retrieval_cases.iter().map(|r| r.recall)).unwrap_or(0.0),
+        mrr: mean(retrieval_cases.iter().map(|r| r.reciprocal_rank)).unwrap_or(0.0),
+        answer_similarity: mean(results.iter().filter_map(|r| r.answer_similarity)),
+        mean_latency_ms: results.iter().map(|r| r.latency_ms).sum::<u64>() / results.len() as u64,
+        config,
+        results,
+        created_at: Utc::now(),
+    };
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO eval_runs (id, set_id, config, recall_at_k, mrr, answer_similarity, mean_latency_ms, results, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            run.id,
+            run.set_id,
+            serde_json::to_string(&run.config).map_err(|e| e.to_string())?,
+            run.recall_at_k,
+            run.mrr,
+            run.answer_similarity,
+            run.mean_latency_ms as i64,
+            serde_json::to_string(&run.results).map_err(|e| e.to_string())?,
+            run.created_at.to_rfc3339(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    record_audit(&db, "evaluation_run", Some(&run.set_id), serde_json::json!({
+        "run_id": run.id,
+        "recall_at_k": run.recall_at_k,
+        "mrr": run.mrr,
+    }));
+
+    Ok(run)
+}
+
+// Newest first, so the latest run can be compared with the ones before it
+#[tauri::command]
+fn list_eval_runs(set_id: String, db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<EvalRun>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT id, set_id, config, recall_at_k, mrr, answer_similarity, mean_latency_ms, results, created_at
+             FROM eval_runs WHERE set_id = ?1 ORDER BY created_at DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![set_id], eval_run_from_row).map_err(|e| e.to_string())?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+#[tauri::command]
+fn delete_eval_run(run_id: String, db_state: tauri::State<'_, Arc<DbPool>>) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM eval_runs WHERE id = ?1", params![run_id])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Evaluation run not found: {}", run_id));
+    }
+    Ok(())
+}
+
+// ---------- Retrieval Benchmark --------------------------------------------------
+
+const MAX_BENCHMARK_QUERIES: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalBenchmark {
+    pub queries: usize,
+    pub chunks: usize, // chunks visible to retrieval when the benchmark ran
+    pub backend: VectorBackend,
+    pub mean: RetrievalTimings,
+    pub p50_total_ms: f64,
+    pub p95_total_ms: f64,
+    pub max_total_ms: f64,
+    pub queries_per_second: f64,
+}
+
+// Recent distinct queries from the log, topped up with the opening words of random chunks
+fn benchmark_queries(conn: &Connection, count: usize) -> Result<Vec<String>> {
+    let mut queries: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT query FROM query_log GROUP BY query ORDER BY MAX(created_at) DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![count as i64], |row| row.get(0))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    if queries.len() < count {
+        let mut stmt = conn.prepare(
+            "SELECT dc.content FROM document_chunks dc
+             JOIN documents d ON dc.document_id = d.id
+             WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND d.is_sensitive = 0
+             ORDER BY RANDOM() LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![(count - queries.len()) as i64], |row| row.get::<_, String>(0))?;
+        queries.extend(
+            rows.filter_map(Result::ok)
+                .map(|content| content.split_whitespace().take(12).collect::<Vec<_>>().join(" ")),
+        );
+    }
+    queries.retain(|query| !query.trim().is_empty());
+    Ok(queries)
+}
+
+// Nearest-rank percentile of an ascending slice
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        len => sorted[((len - 1) as f64 * p).round() as usize],
+    }
+}
+
+// Times retrieval alone, stage by stage, under `config` (the active config when None); nothing is logged
+#[tauri::command]
+async fn benchmark_retrieval(
+    num_queries: usize,
+    config: Option<RAGConfig>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    vector_stores_state: tauri::State<'_, Arc<VectorStores>>,
+) -> Result<RetrievalBenchmark, String> {
+    let config = match config {
+        Some(config) => config,
+        None => config_state.lock().map_err(|e| e.to_string())?.clone(),
+    };
+    let (queries, chunks) = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        let queries = benchmark_queries(&db, num_queries.clamp(1, MAX_BENCHMARK_QUERIES)).map_err(|e| e.to_string())?;
+        let chunks: i64 = db
+            .query_row(
+                "SELECT COUNT(*) FROM document_chunks dc JOIN documents d ON dc.document_id = d.id
+                 WHERE d.is_searchable = 1 AND d.deleted_at IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        (queries, chunks as usize)
+    };
+    if queries.is_empty() {
+        return Err("Nothing to benchmark: no logged queries and no indexed chunks".to_string());
+    }
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+
+    let started = std::time::Instant::now();
+    let mut runs: Vec<RetrievalTimings> = Vec::with_capacity(queries.len());
+    for query in &queries {
+        let mut timings = RetrievalTimings::default();
+        let query_start = std::time::Instant::now();
+        retrieve_context_enhanced(
+            query,
+            &config,
+            db_state.clone(),
+            content_key.as_ref(),
+            store_state.inner(),
+            hnsw_state.inner(),
+            vector_stores_state.inner(),
+            providers_state.inner(),
+            Some(&mut timings),
+        )
+        .await?;
+        timings.total_ms = elapsed_ms(query_start);
+        runs.push(timings);
+    }
+    let wall_seconds = started.elapsed().as_secs_f64();
+
+    let count = runs.len() as f64;
+    let mean_of = |stage: fn(&RetrievalTimings) -> f64| runs.iter().map(stage).sum::<f64>() / count;
+    let mean = RetrievalTimings {
+        embedding_ms: mean_of(|t| t.embedding_ms),
+        candidates_ms: mean_of(|t| t.candidates_ms),
+        scan_ms: mean_of(|t| t.scan_ms),
+        scoring_ms: mean_of(|t| t.scoring_ms),
+        sorting_ms: mean_of(|t| t.sorting_ms),
+        hydration_ms: mean_of(|t| t.hydration_ms),
+        fusion_ms: mean_of(|t| t.fusion_ms),
+        total_ms: mean_of(|t| t.total_ms),
+    };
+    let mut totals: Vec<f64> = runs.iter().map(|t| t.total_ms).collect();
+    totals.sort_by(|a, b| a.total_cmp(b));
+
+    Ok(RetrievalBenchmark {
+        queries: runs.len(),
+        chunks,
+        backend: config.vector_backend,
+        mean,
+        p50_total_ms: percentile(&totals, 0.5),
+        p95_total_ms: percentile(&totals, 0.95),
+        max_total_ms: totals.last().copied().unwrap_or(0.0),
+        queries_per_second: if wall_seconds > 0.0 { count / wall_seconds } else { 0.0 },
+    })
+}
+
+// ---------- Chunk Usage Statistics -----------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkStats {
+    pub chunk_id: String,
+    pub chunk_index: i32,
+    pub preview: Option<String>, // withheld for sensitive documents
+    pub retrieval_count: u64,
+    pub accepted_citation_count: u64,
+    pub last_retrieved_at: Option<DateTime<Utc>>,
+}
+
+// Legacy chunks without a content hash fall back to their id as the stats key
+const CHUNK_STATS_KEY: &str = "COALESCE(content_hash, id)";
+
+fn record_chunk_retrievals(conn: &Connection, chunk_ids: &[&str]) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    for chunk_id in chunk_ids {
+        conn.execute(
+            &format!(
+                "INSERT INTO chunk_stats (document_id, chunk_key, retrieval_count, last_retrieved_at)
+                 SELECT document_id, {}, 1, ?2 FROM document_chunks WHERE id = ?1
+                 ON CONFLICT(document_id, chunk_key) DO UPDATE SET
+                     retrieval_count = retrieval_count + 1,
+                     last_retrieved_at = excluded.last_retrieved_at",
+                CHUNK_STATS_KEY
+            ),
+            params![chunk_id, now],
+        )?;
+    }
+    Ok(())
+}
+
+// Called for the chunks cited by an answer the user accepted (thumbs-up)
+fn record_accepted_citations(conn: &Connection, chunk_ids: &[String]) -> Result<()> {
+    for chunk_id in chunk_ids {
+        conn.execute(
+            &format!(
+                "INSERT INTO chunk_stats (document_id, chunk_key, accepted_citation_count)
+                 SELECT document_id, {}, 1 FROM document_chunks WHERE id = ?1
+                 ON CONFLICT(document_id, chunk_key) DO UPDATE SET
+                     accepted_citation_count = accepted_citation_count + 1",
+                CHUNK_STATS_KEY
+            ),
+            params![chunk_id],
+        )?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn accept_answer(
+    cited_chunk_ids: Vec<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<(), String> {
+    let profile = config_state.lock().map_err(|e| e.to_string())?.personalization_profile.clone();
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    record_accepted_citations(&db, &cited_chunk_ids).map_err(|e| e.to_string())?;
+    if let Some(profile) = profile {
+        record_citation_affinity(&db, &profile, &cited_chunk_ids).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_chunk_stats(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<ChunkStats>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT dc.id, dc.chunk_index, dc.content, d.is_sensitive,
+                    COALESCE(cs.retrieval_count, 0), COALESCE(cs.accepted_citation_count, 0), cs.last_retrieved_at
+             FROM document_chunks dc
+             JOIN documents d ON d.id = dc.document_id
+             LEFT JOIN chunk_stats cs
+                 ON cs.document_id = dc.document_id AND cs.chunk_key = COALESCE(dc.content_hash, dc.id)
+             WHERE dc.document_id = ?1
+             ORDER BY dc.chunk_index",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let stats_iter = stmt
+        .query_map(params![document_id], |row| {
+            let content: String = row.get(2)?;
+            let is_sensitive: bool = row.get(3)?;
+            let last_retrieved_at: Option<String> = row.get(6)?;
+            Ok(ChunkStats {
+                chunk_id: row.get(0)?,
+                chunk_index: row.get(1)?,
+                preview: (!is_sensitive).then(|| content.chars().take(120).collect()),
+                retrieval_count: row.get::<_, i64>(4)? as u64,
+                accepted_citation_count: row.get::<_, i64>(5)? as u64,
+                last_retrieved_at: last_retrieved_at
+                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                    .map(|t| t.with_timezone(&Utc)),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(stats_iter.filter_map(Result::ok).collect())
+}
+
+// ---------- Q/A Pair Extraction ----------------------------------------------------
+
+const QA_MIN_QUESTION_LEN: usize = 8;
+const QA_MAX_GENERATED_PER_CHUNK: usize = 3;
+
+// Strips list markers, headings and "Q:" style prefixes from a line
+fn strip_qa_prefix(line: &str) -> &str {
+    let line = line.trim().trim_start_matches(|c: char| c == '#' || c == '*' || c == '-' || c == '>').trim();
+    let line = line.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches(['.', ')']).trim();
+    for prefix in ["Question:", "Answer:", "Q:", "A:", "Q.", "A."] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            return rest.trim();
+        }
+    }
+    line.trim_end_matches('*').trim()
+}
+
+fn is_question_line(line: &str) -> bool {
+    let cleaned = strip_qa_prefix(line);
+    cleaned.ends_with('?') && cleaned.chars().count() >= QA_MIN_QUESTION_LEN
+}
+
+// Finds explicit FAQ-style questions and returns each with the first line of its answer
+fn extract_faq_pairs(text: &str) -> Vec<(String, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut pairs = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_question_line(lines[i]) {
+            i += 1;
+            continue;
+        }
+
+        let question = strip_qa_prefix(lines[i]).to_string();
+        let mut j = i + 1;
+        while j < lines.len() && lines[j].trim().is_empty() {
+            j += 1;
+        }
+        if j < lines.len() && !is_question_line(lines[j]) {
+            pairs.push((question, strip_qa_prefix(lines[j]).to_string()));
+        }
+        i = j.max(i + 1);
+    }
+    pairs
+}
+
+async fn generate_chunk_questions(target: &GenerationTarget, chunk: &str) -> Result<Vec<String>> {
+    let prompt = format!(
+        "List up to {} questions that the passage below answers. Write one question per line and nothing else.\n\nPassage:\n{}",
+        QA_MAX_GENERATED_PER_CHUNK, chunk
+    );
+    let reply = generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.3, "max_tokens": 200 })).await?;
+    Ok(reply
+        .lines()
+        .filter(|line| is_question_line(line))
+        .map(|line| strip_qa_prefix(line).to_string())
+        .take(QA_MAX_GENERATED_PER_CHUNK)
+        .collect())
+}
+
+// Replaces the document's Q/A keys; returns how many questions were indexed
+async fn index_qa_pairs(
+    document_id: &str,
+    content: &str,
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<usize> {
+    let chunks: Vec<(String, String)> = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut stmt = db.prepare("SELECT id, content FROM document_chunks WHERE document_id = ?1 ORDER BY chunk_index")?;
+        let rows = stmt.query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut keys: Vec<(String, String)> = Vec::new(); // (chunk_id, question)
+    match (config.qa_extraction, generation_target(config)) {
+        (QaExtraction::Off, _) => return Ok(0),
+        (QaExtraction::Generated, Some(target)) => {
+            for (chunk_id, chunk_content) in &chunks {
+                for question in generate_chunk_questions(&target, chunk_content).await? {
+                    keys.push((chunk_id.clone(), question));
+                }
+            }
+        }
+        (QaExtraction::Generated, None) | (QaExtraction::FaqSections, _) => {
+            if config.qa_extraction == QaExtraction::Generated {
+                warn!("Q/A generation needs a generation provider; falling back to FAQ sections");
+            }
+            // Point each question at the chunk holding the start of its answer
+            for (question, answer) in extract_faq_pairs(content) {
+                let anchor: String = answer.chars().take(40).collect();
+                let chunk = chunks
+                    .iter()
+                    .find(|(_, c)| c.contains(anchor.as_str()))
+                    .or_else(|| chunks.iter().find(|(_, c)| c.contains(question.as_str())));
+                if let Some((chunk_id, _)) = chunk {
+                    keys.push((chunk_id.clone(), question));
+                }
+            }
+        }
+    }
+
+    let mut rows = Vec::with_capacity(keys.len());
+    for (chunk_id, question) in keys {
+        let embedding = providers.embed(&question, config).await?;
+        rows.push((chunk_id, question, embedding));
+    }
+
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute("DELETE FROM qa_keys WHERE document_id = ?1", params![document_id])?;
+    for (chunk_id, question, embedding) in &rows {
+        let embedding_bytes = encode_embedding(embedding, config.embedding_storage);
+        tx.execute(
+            "INSERT INTO qa_keys (id, document_id, chunk_id, question, embedding, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                Uuid::new_v4().to_string(),
+                document_id,
+                chunk_id,
+                question,
+                embedding_bytes,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(rows.len())
+}
+
+// A question key that matches the query surfaces its answer chunk, keeping the better of the two scores
+fn merge_qa_matches(
+    conn: &Connection,
+    results: &mut Vec<RetrievalResult>,
+    query_embedding: &[f32],
+    config: &RAGConfig,
+    content_key: Option<&ContentKey>,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT q.chunk_id, q.embedding, dc.content, d.title, d.file_path, d.is_sensitive, dc.metadata
+         FROM qa_keys q
+         JOIN document_chunks dc ON dc.id = q.chunk_id
+         JOIN documents d ON d.id = dc.document_id
+         WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND (d.is_sensitive = 0 OR ?1){}{}",
+        tag_filter_clause(2),
+        chunk_filter_clause(&config.chunk_filter)
+    ))?;
+    let rows = stmt.query_map(params![content_key.is_some(), tag_filter_value(&config.tags)], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Vec<u8>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, bool>(5)?,
+            row.get::<_, Option<String>>(6)?,
+        ))
+    })?;
+
+    for (chunk_id, embedding_bytes, content, doc_title, file_path, is_sensitive, chunk_metadata) in rows.filter_map(Result::ok) {
+        let embedding = decode_embedding(&embedding_bytes);
+        if embedding.len() != query_embedding.len() {
+            continue; // left over from a previous model until the document is re-embedded
+        }
+        let similarity = compute_similarity(config.similarity_metric, query_embedding, &embedding);
+        if similarity <= config.similarity_threshold {
+            continue;
+        }
+
+        if let Some(existing) = results.iter_mut().find(|r| r.chunk_id == chunk_id) {
+            existing.similarity_score = existing.similarity_score.max(similarity);
+            continue;
+        }
+
+        let content = match (is_sensitive, content_key) {
+            (false, _) => content,
+            (true, Some(key)) => decrypt_text(key, &content)?,
+            (true, None) => continue,
+        };
+        results.push(RetrievalResult {
+            chunk_id,
+            content,
+            document_title: doc_title,
+            similarity_score: similarity,
+            source_info: chunk_source_info(file_path, chunk_metadata),
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        });
+    }
+
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    results.truncate(config.top_k);
+    Ok(())
+}
+
+// ---------- Document Summaries ----------------------------------------------------
+
+const SUMMARY_SECTION_CHARS: usize = 12000; // text summarized per model call before partial summaries are combined
+const SUMMARY_LEAD_SENTENCES: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSummary {
+    pub document_id: String,
+    pub document_title: String,
+    pub summary: String,
+    pub generated: bool, // written by a model; false for the lead-sentence fallback
+    pub created_at: DateTime<Utc>,
+}
+
+// Extractive fallback when no generation backend is configured
+fn lead_sentences(text: &str) -> String {
+    text.unicode_sentences()
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .take(SUMMARY_LEAD_SENTENCES)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Long documents are summarized section by section, then the partial summaries are combined
+async fn generate_summary(target: &GenerationTarget, text: &str) -> Result<String> {
+    let options = serde_json::json!({ "temperature": 0.2, "max_tokens": 400 });
+    let mut partials = Vec::new();
+    for section in chunk_text(text, SUMMARY_SECTION_CHARS, 0) {
+        let prompt = format!(
+            "Summarize the following text in one short paragraph. Reply with the summary only.\n\nText:\n{}",
+            section
+        );
+        partials.push(generate_text(target, &prompt, &options).await?.trim().to_string());
+    }
+    if partials.len() <= 1 {
+        return Ok(partials.pop().unwrap_or_default());
+    }
+    let prompt = format!(
+        "These are summaries of consecutive parts of one document. Combine them into a single short paragraph \
+         that summarizes the whole document. Reply with the summary only.\n\n{}",
+        partials.join("\n\n")
+    );
+    Ok(generate_text(target, &prompt, &options).await?.trim().to_string())
+}
+
+// Replaces the document's summary; with a content key the stored text is encrypted like the document itself
+async fn summarize_document_text(
+    document_id: &str,
+    document_title: &str,
+    content: &str,
+    content_key: Option<&ContentKey>,
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<DocumentSummary> {
+    let (summary, generated) = match generation_target(config) {
+        Some(target) => (generate_summary(&target, content).await?, true),
+        None => (lead_sentences(content), false),
+    };
+    if summary.is_empty() {
+        return Err(anyhow::anyhow!("Document {} has no text to summarize", document_id));
+    }
+    let embedding = providers.embed(&summary, config).await?;
+    let stored = match content_key {
+        Some(key) => encrypt_text(key, &summary)?,
+        None => summary.clone(),
+    };
+
+    let document_summary = DocumentSummary {
+        document_id: document_id.to_string(),
+        document_title: document_title.to_string(),
+        summary,
+        generated,
+        created_at: Utc::now(),
+    };
+    let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    db.execute(
+        "INSERT OR REPLACE INTO document_summaries (document_id, summary, embedding, generated, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            document_id,
+            stored,
+            encode_embedding(&embedding, config.embedding_storage),
+            generated,
+            document_summary.created_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(document_summary)
+}
+
+// Summaries of sensitive documents are left out while they are locked
+fn load_document_summaries(
+    conn: &Connection,
+    document_id: Option<&str>,
+    content_key: Option<&ContentKey>,
+) -> Result<Vec<DocumentSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.document_id, d.title, s.summary, s.generated, s.created_at, d.is_sensitive
+         FROM document_summaries s
+         JOIN documents d ON d.id = s.document_id
+         WHERE d.deleted_at IS NULL AND (?1 IS NULL OR s.document_id = ?1)
+         ORDER BY d.title",
+    )?;
+    let rows = stmt.query_map(params![document_id], |row| {
+        Ok((
+            DocumentSummary {
+                document_id: row.get(0)?,
+                document_title: row.get(1)?,
+                summary: row.get(2)?,
+                generated: row.get(3)?,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            },
+            row.get::<_, bool>(5)?,
+        ))
+    })?;
+
+    let mut summaries = Vec::new();
+    for (mut summary, is_sensitive) in rows.filter_map(Result::ok) {
+        match (is_sensitive, content_key) {
+            (false, _) => {}
+            (true, Some(key)) => summary.summary = decrypt_text(key, &summary.summary)?,
+            (true, None) => continue,
+        }
+        summaries.push(summary);
+    }
+    Ok(summaries)
+}
+
+// A summary that matches the query joins the results as a passage of its own, for questions about whole documents
+fn merge_summary_matches(
+    conn: &Connection,
+    results: &mut Vec<RetrievalResult>,
+    query_embedding: &[f32],
+    config: &RAGConfig,
+    content_key: Option<&ContentKey>,
+) -> Result<()> {
+    // Summaries carry no chunk metadata, so they never match an active chunk filter
+    if !config.summary_retrieval || config.chunk_filter != ChunkFilter::default() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT s.document_id, s.summary, s.embedding, d.title, d.file_path, d.is_sensitive
+         FROM document_summaries s
+         JOIN documents d ON d.id = s.document_id
+         WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND (d.is_sensitive = 0 OR ?1){}",
+        tag_filter_clause(2)
+    ))?;
+    let rows = stmt.query_map(params![content_key.is_some(), tag_filter_value(&config.tags)], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Vec<u8>>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, bool>(5)?,
+        ))
+    })?;
+
+    for (document_id, summary, embedding_bytes, doc_title, file_path, is_sensitive) in rows.filter_map(Result::ok) {
+        let embedding = decode_embedding(&embedding_bytes);
+        if embedding.len() != query_embedding.len() {
+            continue; // written under a previous model; summarize the document again to refresh it
+        }
+        let similarity = compute_similarity(config.similarity_metric, query_embedding, &embedding);
+        if similarity <= config.similarity_threshold {
+            continue;
+        }
+        let summary = match (is_sensitive, content_key) {
+            (false, _) => summary,
+            (true, Some(key)) => decrypt_text(key, &summary)?,
+            (true, None) => continue,
+        };
+        results.push(RetrievalResult {
+            chunk_id: format!("summary:{}", document_id),
+            content: summary,
+            document_title: doc_title,
+            similarity_score: similarity,
+            source_info: format!("{} (summary)", chunk_source_info(file_path, None)),
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        });
+    }
+
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    results.truncate(config.top_k);
+    Ok(())
+}
+
+#[tauri::command]
+async fn summarize_document(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+) -> Result<DocumentSummary, String> {
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let (title, content, is_sensitive): (String, String, bool) = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        db.query_row(
+            "SELECT title, content, is_sensitive FROM documents WHERE id = ?1 AND deleted_at IS NULL",
+            params![document_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|_| format!("Document not found: {}", document_id))?
+    };
+    let (content, key) = match (is_sensitive, content_key.as_ref()) {
+        (false, _) => (content, None),
+        (true, Some(key)) => (decrypt_text(key, &content).map_err(|e| e.to_string())?, Some(key)),
+        (true, None) => return Err("Unlock sensitive documents to summarize this one".to_string()),
+    };
+
+    summarize_document_text(&document_id, &title, &content, key, db_state.inner(), &config, providers_state.inner())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_document_summary(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<Option<DocumentSummary>, String> {
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut summaries = load_document_summaries(&db, Some(&document_id), content_key.as_ref()).map_err(|e| e.to_string())?;
+    Ok(summaries.pop())
+}
+
+#[tauri::command]
+fn list_document_summaries(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+) -> Result<Vec<DocumentSummary>, String> {
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_document_summaries(&db, None, content_key.as_ref()).map_err(|e| e.to_string())
+}
+
+// ---------- Entity Extraction -----------------------------------------------------
+
+const MAX_ENTITY_CHARS: usize = 80;
+const DEFAULT_ENTITY_LIMIT: usize = 50;
+const ENTITY_PERSON_TITLES: &[&str] = &["mr", "mrs", "ms", "dr", "prof", "sir"];
+const ENTITY_ORG_SUFFIXES: &[&str] = &[
+    "inc", "corp", "corporation", "co", "company", "ltd", "llc", "plc", "gmbh", "ag", "group", "holdings",
+    "university", "bank", "foundation", "institute", "agency", "association",
+];
+// Capitalized only because they start a sentence
+const ENTITY_SENTENCE_OPENERS: &[&str] = &[
+    "the", "a", "an", "in", "on", "at", "for", "to", "if", "as", "by", "this", "that", "these", "those", "when",
+    "after", "before", "while", "our", "we", "it", "he", "she", "they",
+];
+const MONTH_NAMES: &[&str] = &[
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum EntityExtraction {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "rules")]
+    Rules, // capitalization, title/suffix and date patterns; fast but loose about people
+    #[serde(rename = "llm")]
+    Llm, // entities listed by the generation provider for each chunk
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntityKind {
+    #[serde(rename = "person")]
+    Person,
+    #[serde(rename = "organization")]
+    Organization,
+    #[serde(rename = "date")]
+    Date,
+}
+
+impl EntityKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EntityKind::Person => "person",
+            EntityKind::Organization => "organization",
+            EntityKind::Date => "date",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "person" => Some(EntityKind::Person),
+            "organization" => Some(EntityKind::Organization),
+            "date" => Some(EntityKind::Date),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityDocument {
+    pub document_id: String,
+    pub document_title: String,
+    pub chunk_ids: Vec<String>,
+    pub mention_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityInfo {
+    pub id: String,
+    pub name: String, // first spelling seen
+    pub kind: EntityKind,
+    pub mention_count: usize,
+    pub documents: Vec<EntityDocument>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelatedEntity {
+    pub id: String,
+    pub name: String,
+    pub kind: EntityKind,
+    pub shared_chunks: usize, // chunks mentioning both entities
+}
+
+struct ExtractedEntity {
+    kind: EntityKind,
+    name: String,
+    key: String,
+}
+
+impl ExtractedEntity {
+    fn new(kind: EntityKind, name: &str) -> Self {
+        ExtractedEntity { kind, name: name.to_string(), key: entity_key(kind, name) }
+    }
+}
+
+// Exact-match key: case and spacing are ignored, and dates compare in ISO form
+fn entity_key(kind: EntityKind, name: &str) -> String {
+    if kind == EntityKind::Date {
+        if let Some(iso) = parse_date_mention(name) {
+            return iso;
+        }
+    }
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// ISO dates, "15 March 2024", "March 15, 2024" and "March 2024"; month-only dates keep a YYYY-MM key
+fn parse_date_mention(text: &str) -> Option<String> {
+    let text = text.trim().trim_end_matches(|c: char| !c.is_alphanumeric());
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Some(date.format("%Y-%m-%d").to_string());
+    }
+    let cleaned = text.replace(',', "");
+    for format in ["%d %B %Y", "%B %d %Y"] {
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(&cleaned, format) {
+            return Some(date.format("%Y-%m-%d").to_string());
+        }
+    }
+    match cleaned.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [month, year] => {
+            let month = MONTH_NAMES.iter().position(|name| name.eq_ignore_ascii_case(month))?;
+            let year: i32 = year.parse().ok().filter(|year| (1000..=9999).contains(year))?;
+            Some(format!("{:04}-{:02}", year, month + 1))
+        }
+        _ => None,
+    }
+}
+
+fn entity_token(token: &str) -> &str {
+    let token = token.trim_matches(|c: char| !c.is_alphanumeric() && c != '&');
+    token.strip_suffix("'s").or_else(|| token.strip_suffix("’s")).unwrap_or(token)
+}
+
+fn is_capitalized(word: &str) -> bool {
+    word.chars().next().map_or(false, char::is_uppercase)
+}
+
+// Organizations end in a company-style suffix; people are two or three name-shaped words, or any name after a title
+fn classify_name(words: &[&str], titled: bool) -> Option<EntityKind> {
+    let last = words.last()?.to_lowercase();
+    if words.len() >= 2 && ENTITY_ORG_SUFFIXES.contains(&last.as_str()) {
+        return Some(EntityKind::Organization);
+    }
+    let name_shaped = words.iter().all(|word| {
+        word.chars().count() > 1
+            && word.chars().skip(1).all(|c| c.is_lowercase() || c == '-' || c == '\'')
+            && !MONTH_NAMES.contains(&word.to_lowercase().as_str())
+    });
+    let person_length = if titled { 1..=3 } else { 2..=3 };
+    (name_shaped && person_length.contains(&words.len())).then_some(EntityKind::Person)
+}
+
+fn extract_name_entities(sentence: &str, entities: &mut Vec<ExtractedEntity>) {
+    let tokens: Vec<&str> = sentence.split_whitespace().collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        let titled = ENTITY_PERSON_TITLES.contains(&entity_token(tokens[i]).to_lowercase().as_str());
+        let start = if titled { i + 1 } else { i };
+        let mut words: Vec<&str> = Vec::new();
+        let mut j = start;
+        while j < tokens.len() {
+            let word = entity_token(tokens[j]);
+            // "Bank of America", "Johnson & Johnson"
+            let connector = !words.is_empty()
+                && matches!(word, "of" | "&")
+                && tokens.get(j + 1).map_or(false, |next| is_capitalized(entity_token(next)));
+            if word.is_empty() || !(is_capitalized(word) || connector) {
+                break;
+            }
+            words.push(word);
+            j += 1;
+            if tokens[j - 1].ends_with(|c: char| matches!(c, ',' | ';' | ':' | '.' | '!' | '?' | ')' | '"')) {
+                break;
+            }
+        }
+        if start == 0 && words.first().map_or(false, |w| ENTITY_SENTENCE_OPENERS.contains(&w.to_lowercase().as_str())) {
+            words.remove(0);
+        }
+        if let Some(kind) = classify_name(&words, titled) {
+            let name = words.join(" ");
+            if name.chars().count() <= MAX_ENTITY_CHARS {
+                entities.push(ExtractedEntity::new(kind, &name));
+            }
+        }
+        i = j.max(i + 1);
+    }
+}
+
+fn extract_date_entities(sentence: &str, entities: &mut Vec<ExtractedEntity>) {
+    let tokens: Vec<&str> = sentence.split_whitespace().collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        let matched = (1..=3).rev().filter(|len| i + len <= tokens.len()).find_map(|len| {
+            let phrase = tokens[i..i + len].join(" ");
+            let phrase = phrase.trim_matches(|c: char| !c.is_alphanumeric()).to_string();
+            parse_date_mention(&phrase).map(|_| (len, phrase))
+        });
+        match matched {
+            Some((len, phrase)) => {
+                entities.push(ExtractedEntity::new(EntityKind::Date, &phrase));
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+}
+
+fn extract_entities(text: &str) -> Vec<ExtractedEntity> {
+    let mut entities = Vec::new();
+    for sentence in text.unicode_sentences() {
+        extract_name_entities(sentence, &mut entities);
+        extract_date_entities(sentence, &mut entities);
+    }
+    entities
+}
+
+async fn generate_chunk_entities(target: &GenerationTarget, chunk: &str) -> Result<Vec<ExtractedEntity>> {
+    let prompt = format!(
+        "List the people, organizations and dates mentioned in the passage below. Write one per line as \
+         \"person: <name>\", \"organization: <name>\" or \"date: <date>\" and nothing else.\n\nPassage:\n{}",
+        chunk
+    );
+    let reply = generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.0, "max_tokens": 300 })).await?;
+    Ok(reply
+        .lines()
+        .filter_map(|line| {
+            let (kind, name) = line.split_once(':')?;
+            let kind = EntityKind::parse(&kind.trim().trim_start_matches(['-', '*']).trim().to_lowercase())?;
+            let name = name.trim().trim_matches('"');
+            (!name.is_empty() && name.chars().count() <= MAX_ENTITY_CHARS).then(|| ExtractedEntity::new(kind, name))
+        })
+        .collect())
+}
+
+// Replaces the document's entity mentions; returns how many (entity, chunk) links were stored.
+// Entity names are stored in plain text, so sensitive documents are skipped.
+async fn index_entities(document_id: &str, db_state: &Arc<DbPool>, config: &RAGConfig) -> Result<usize> {
+    let (is_sensitive, chunks): (bool, Vec<(String, String)>) = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let is_sensitive =
+            db.query_row("SELECT is_sensitive FROM documents WHERE id = ?1", params![document_id], |row| row.get(0))?;
+        let mut stmt = db.prepare("SELECT id, content FROM document_chunks WHERE document_id = ?1 ORDER BY chunk_index")?;
+        let rows = stmt.query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        (is_sensitive, rows.filter_map(Result::ok).collect())
+    };
+    if is_sensitive || config.entity_extraction == EntityExtraction::Off {
+        return Ok(0);
+    }
+    let target = match config.entity_extraction {
+        EntityExtraction::Llm => {
+            let target = generation_target(config);
+            if target.is_none() {
+                warn!("Entity extraction needs a generation provider; falling back to rules");
+            }
+            target
+        }
+        _ => None,
+    };
+
+    let mut mentions: Vec<(String, ExtractedEntity, usize)> = Vec::new(); // (chunk_id, entity, mentions in the chunk)
+    for (chunk_id, content) in &chunks {
+        let found = match &target {
+            Some(target) => generate_chunk_entities(target, content).await?,
+            None => extract_entities(content),
+        };
+        let mut counted: Vec<(ExtractedEntity, usize)> = Vec::new();
+        for entity in found {
+            match counted.iter_mut().find(|(seen, _)| seen.kind == entity.kind && seen.key == entity.key) {
+                Some((_, count)) => *count += 1,
+                None => counted.push((entity, 1)),
+            }
+        }
+        mentions.extend(counted.into_iter().map(|(entity, count)| (chunk_id.clone(), entity, count)));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute("DELETE FROM entity_mentions WHERE document_id = ?1", params![document_id])?;
+    for (chunk_id, entity, count) in &mentions {
+        tx.execute(
+            "INSERT OR IGNORE INTO entities (id, kind, name, normalized, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![Uuid::new_v4().to_string(), entity.kind.as_str(), entity.name, entity.key, now],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO entity_mentions (entity_id, document_id, chunk_id, mention_count)
+             SELECT id, ?1, ?2, ?3 FROM entities WHERE kind = ?4 AND normalized = ?5",
+            params![document_id, chunk_id, *count as i64, entity.kind.as_str(), entity.key],
+        )?;
+    }
+    tx.commit()?;
+    Ok(mentions.len())
+}
+
+fn load_entity_documents(conn: &Connection, entity_id: &str) -> Result<Vec<EntityDocument>> {
+    let mut stmt = conn.prepare(
+        "SELECT d.id, d.title, group_concat(m.chunk_id), SUM(m.mention_count)
+         FROM entity_mentions m
+         JOIN documents d ON d.id = m.document_id
+         WHERE m.entity_id = ?1 AND d.deleted_at IS NULL
+         GROUP BY d.id ORDER BY SUM(m.mention_count) DESC, d.title",
+    )?;
+    let rows = stmt.query_map(params![entity_id], |row| {
+        Ok(EntityDocument {
+            document_id: row.get(0)?,
+            document_title: row.get(1)?,
+            chunk_ids: row.get::<_, String>(2)?.split(',').map(str::to_string).collect(),
+            mention_count: row.get::<_, i64>(3)? as usize,
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// Re-extracts entities now, using rules when extraction is off in the config
+#[tauri::command]
+async fn extract_document_entities(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<usize, String> {
+    let mut config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    if config.entity_extraction == EntityExtraction::Off {
+        config.entity_extraction = EntityExtraction::Rules;
+    }
+    {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        let is_sensitive: bool = db
+            .query_row(
+                "SELECT is_sensitive FROM documents WHERE id = ?1 AND deleted_at IS NULL",
+                params![document_id],
+                |row| row.get(0),
+            )
+            .map_err(|_| format!("Document not found: {}", document_id))?;
+        if is_sensitive {
+            return Err("Entities are not extracted from sensitive documents".to_string());
+        }
+    }
+    index_entities(&document_id, db_state.inner(), &config).await.map_err(|e| e.to_string())
+}
+
+// Exact match on the entity name (case and spacing ignored, dates in any supported form);
+// without a name, the most mentioned entities are listed
+#[tauri::command]
+fn query_entities(
+    name: Option<String>,
+    kind: Option<EntityKind>,
+    limit: Option<usize>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<EntityInfo>, String> {
+    let keys = name.as_deref().map(|name| (entity_key(EntityKind::Person, name), entity_key(EntityKind::Date, name)));
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT e.id, e.name, e.kind, SUM(m.mention_count) FROM entities e
+             JOIN entity_mentions m ON m.entity_id = e.id
+             JOIN documents d ON d.id = m.document_id AND d.deleted_at IS NULL
+             WHERE (?1 IS NULL OR e.normalized IN (?1, ?2)) AND (?3 IS NULL OR e.kind = ?3)
+             GROUP BY e.id ORDER BY SUM(m.mention_count) DESC, e.name LIMIT ?4",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(String, String, String, i64)> = stmt
+        .query_map(
+            params![
+                keys.as_ref().map(|k| k.0.as_str()),
+                keys.as_ref().map(|k| k.1.as_str()),
+                kind.map(|k| k.as_str()),
+                limit.unwrap_or(DEFAULT_ENTITY_LIMIT) as i64,
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut entities = Vec::with_capacity(rows.len());
+    for (id, name, kind, mention_count) in rows {
+        let Some(kind) = EntityKind::parse(&kind) else { continue };
+        let documents = load_entity_documents(&db, &id).map_err(|e| e.to_string())?;
+        entities.push(EntityInfo { id, name, kind, mention_count: mention_count as usize, documents });
+    }
+    Ok(entities)
+}
+
+// Graph neighbours: entities mentioned in the same chunks, strongest links first
+#[tauri::command]
+fn related_entities(
+    entity_id: String,
+    limit: Option<usize>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<RelatedEntity>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT e.id, e.name, e.kind, COUNT(DISTINCT other.chunk_id) FROM entity_mentions own
+             JOIN entity_mentions other ON other.chunk_id = own.chunk_id AND other.entity_id != own.entity_id
+             JOIN entities e ON e.id = other.entity_id
+             JOIN documents d ON d.id = other.document_id AND d.deleted_at IS NULL
+             WHERE own.entity_id = ?1
+             GROUP BY e.id ORDER BY COUNT(DISTINCT other.chunk_id) DESC, e.name LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let related = stmt
+        .query_map(params![entity_id, limit.unwrap_or(DEFAULT_ENTITY_LIMIT) as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, i64>(3)?))
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .filter_map(|(id, name, kind, shared)| {
+            Some(RelatedEntity { id, name, kind: EntityKind::parse(&kind)?, shared_chunks: shared as usize })
+        })
+        .collect();
+    Ok(related)
+}
+
+// ---------- Language Detection ----------------------------------------------------
+
+const LANGUAGE_SAMPLE_CHARS: usize = 4000;
+const LANGUAGE_MIN_CONFIDENCE: f64 = 0.5;
+// Model name fragments of embedding models trained on English text only
+const ENGLISH_ONLY_EMBEDDING_MODELS: &[&str] = &[
+    "all-minilm", "all-mpnet", "all-distilroberta", "multi-qa-", "msmarco", "bge-small-en", "bge-base-en",
+    "bge-large-en", "gte-small", "gte-base", "gte-large", "e5-small-v2", "e5-base-v2", "e5-large-v2",
+    "nomic-embed-text", "mxbai-embed-large", "text-embedding-ada",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedLanguage {
+    pub code: String, // ISO 639-3, e.g. "eng", "pol"
+    pub name: String,
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageCount {
+    pub code: Option<String>, // None for documents too short or mixed to call
+    pub name: Option<String>,
+    pub document_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageOverview {
+    pub languages: Vec<LanguageCount>,
+    pub embedding_model: String, // after multilingual routing
+    pub english_only: bool,
+    pub warnings: Vec<String>,
+}
+
+fn detect_language(text: &str) -> Option<DetectedLanguage> {
+    let sample: String = text.chars().take(LANGUAGE_SAMPLE_CHARS).collect();
+    let info = whatlang::detect(&sample)?;
+    (info.confidence() >= LANGUAGE_MIN_CONFIDENCE).then(|| DetectedLanguage {
+        code: info.lang().code().to_string(),
+        name: info.lang().eng_name().to_string(),
+        confidence: info.confidence(),
+    })
+}
+
+fn language_name(code: &str) -> String {
+    whatlang::Lang::from_code(code).map_or_else(|| code.to_string(), |lang| lang.eng_name().to_string())
+}
+
+fn embedding_model_is_english_only(model: &EmbeddingModel) -> bool {
+    let key = embedding_model_key(model).to_lowercase();
+    !key.contains("multilingual") && ENGLISH_ONLY_EMBEDDING_MODELS.iter().any(|name| key.contains(name))
+}
+
+// The multilingual model the knowledge base should use: set, needed (English-only main model) and
+// justified (a non-English document is stored)
+fn multilingual_route(conn: &Connection, config: &RAGConfig) -> Result<Option<EmbeddingModel>> {
+    let Some(multilingual) = &config.multilingual_embedding_model else { return Ok(None) };
+    if !embedding_model_is_english_only(&config.embedding_model) {
+        return Ok(None);
+    }
+    let non_english: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM documents WHERE language IS NOT NULL AND language != 'eng' AND deleted_at IS NULL)",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(non_english.then(|| multilingual.clone()))
+}
+
+// One index can't mix vector spaces, so the switch only happens once no chunk is left in the English-only
+// model's space; until reembed_all_documents moves them, the configured model stays in use
+fn routed_embedding_config(conn: &Connection, config: &RAGConfig) -> Result<RAGConfig> {
+    let mut routed = config.clone();
+    let Some(multilingual) = multilingual_route(conn, config)? else { return Ok(routed) };
+    let remaining: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM document_chunks dc JOIN documents d ON d.id = dc.document_id
+         WHERE dc.embedding_model = ?1 AND d.deleted_at IS NULL",
+        params![embedding_model_key(&config.embedding_model)],
+        |row| row.get(0),
+    )?;
+    if remaining > 0 {
+        warn!(
+            "{} chunk(s) still use the English-only model {}; re-embed the knowledge base to switch to {}",
+            remaining,
+            embedding_model_key(&config.embedding_model),
+            embedding_model_key(&multilingual)
+        );
+        return Ok(routed);
+    }
+    routed.embedding_model = multilingual;
+    Ok(routed)
+}
+
+fn language_warning(language: &DetectedLanguage, config: &RAGConfig) -> Option<String> {
+    (language.code != "eng" && embedding_model_is_english_only(&config.embedding_model)).then(|| {
+        format!(
+            "{} text is embedded with the English-only model {}; set a multilingual embedding model for better retrieval",
+            language.name,
+            embedding_model_key(&config.embedding_model)
+        )
+    })
+}
+
+// Stores the document's language and returns the config its chunks should be embedded with
+fn apply_document_language(conn: &Connection, document_id: &str, text: &str, config: &RAGConfig) -> Result<RAGConfig> {
+    let language = detect_language(text);
+    conn.execute(
+        "UPDATE documents SET language = ?1 WHERE id = ?2",
+        params![language.as_ref().map(|l| l.code.as_str()), document_id],
+    )?;
+    let routed = routed_embedding_config(conn, config)?;
+    if let Some(warning) = language.as_ref().and_then(|l| language_warning(l, &routed)) {
+        warn!("{}: {}", document_id, warning);
+    }
+    Ok(routed)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Translation {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "context")]
+    Context, // passages in another language are translated into the query's before generation
+    #[serde(rename = "query")]
+    Query, // the query is translated into the knowledge base's main language before retrieval
+}
+
+// Most common language among searchable documents
+fn dominant_language(conn: &Connection) -> Option<String> {
+    conn.query_row(
+        "SELECT language FROM documents
+         WHERE language IS NOT NULL AND is_searchable = 1 AND deleted_at IS NULL
+         GROUP BY language ORDER BY COUNT(*) DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+async fn translate_text(target: &GenerationTarget, text: &str, language: &str) -> Result<String> {
+    let prompt = format!(
+        "Translate the following text into {}. Keep names, numbers and bracketed markers unchanged. \
+         Reply with the translation only.\n\nText:\n{}",
+        language, text
+    );
+    let translation = generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.0 })).await?;
+    Ok(translation.trim().to_string())
+}
+
+// Copies of the passages for the prompt, with foreign-language ones translated into `language`.
+// The originals stay in the response so citations point at the source text.
+async fn translate_context(
+    target: &GenerationTarget,
+    results: &[RetrievalResult],
+    language: &DetectedLanguage,
+) -> Vec<RetrievalResult> {
+    let mut translated = Vec::with_capacity(results.len());
+    for result in results {
+        let mut copy = result.clone();
+        if detect_language(&result.content).is_some_and(|detected| detected.code != language.code) {
+            match translate_text(target, &result.content, &language.name).await {
+                Ok(text) if !text.is_empty() => copy.content = text,
+                Ok(_) => {}
+                Err(e) => warn!("Failed to translate passage {}: {}", result.chunk_id, e),
+            }
+        }
+        translated.push(copy);
+    }
+    translated
+}
+
+#[tauri::command]
+fn detect_text_language(text: String) -> Result<Option<DetectedLanguage>, String> {
+    Ok(detect_language(&text))
+}
+
+#[tauri::command]
+fn get_language_overview(
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<LanguageOverview, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT language, COUNT(*) FROM documents WHERE deleted_at IS NULL
+             GROUP BY language ORDER BY COUNT(*) DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let languages: Vec<LanguageCount> = stmt
+        .query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)))
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .map(|(code, count)| LanguageCount {
+            name: code.as_deref().map(language_name),
+            code,
+            document_count: count as usize,
+        })
+        .collect();
+
+    let routed = routed_embedding_config(&db, &config).map_err(|e| e.to_string())?;
+    let english_only = embedding_model_is_english_only(&routed.embedding_model);
+    let mut warnings = Vec::new();
+    for language in &languages {
+        if let (Some(code), Some(name)) = (&language.code, &language.name) {
+            let detected = DetectedLanguage { code: code.clone(), name: name.clone(), confidence: 1.0 };
+            if let Some(warning) = language_warning(&detected, &routed) {
+                warnings.push(format!("{} document(s): {}", language.document_count, warning));
+            }
+        }
+    }
+    if let Some(multilingual) = multilingual_route(&db, &config).map_err(|e| e.to_string())? {
+        if embedding_model_key(&multilingual) != embedding_model_key(&routed.embedding_model) {
+            warnings.push(format!(
+                "Re-embed the knowledge base to move it to the multilingual model {}",
+                embedding_model_key(&multilingual)
+            ));
+        }
+    }
+
+    Ok(LanguageOverview { languages, embedding_model: embedding_model_key(&routed.embedding_model), english_only, warnings })
+}
+
+// ---------- Folder Filters --------------------------------------------------------
+
+const DEFAULT_MAX_FILE_SIZE_BYTES: u64 = 200 * 1024 * 1024;
+
+// Stop-list for watched folders and folder imports. Patterns without a slash match
+// any path component, so "node_modules" skips that folder at every depth.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderFilter {
+    #[serde(default)]
+    pub include: Vec<String>, // empty means every file
+    #[serde(default = "default_folder_excludes")]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_max_file_size_bytes")]
+    pub max_file_size_bytes: u64,
+}
+
+fn default_folder_excludes() -> Vec<String> {
+    vec!["node_modules".to_string(), ".git".to_string()]
+}
+
+fn default_max_file_size_bytes() -> u64 {
+    DEFAULT_MAX_FILE_SIZE_BYTES
+}
+
+impl Default for FolderFilter {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: default_folder_excludes(),
+            max_file_size_bytes: DEFAULT_MAX_FILE_SIZE_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    Excluded,
+    NotIncluded,
+    TooLarge,
+    Unsupported, // no built-in extractor or plugin handles the extension
+}
+
+// Per-reason counts reported in import and watcher job summaries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SkippedFiles {
+    pub excluded: usize,
+    pub not_included: usize,
+    pub too_large: usize,
+    pub unsupported: usize,
+}
+
+impl SkippedFiles {
+    fn record(&mut self, reason: SkipReason) {
+        match reason {
+            SkipReason::Excluded => self.excluded += 1,
+            SkipReason::NotIncluded => self.not_included += 1,
+            SkipReason::TooLarge => self.too_large += 1,
+            SkipReason::Unsupported => self.unsupported += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.excluded + self.not_included + self.too_large + self.unsupported
+    }
+}
+
+struct CompiledFolderFilter {
+    include: Option<GlobSet>,
+    glob: Option<GlobSet>, // ingest_directory's glob; a file must match it as well as include
+    exclude: GlobSet,
+    max_file_size_bytes: u64,
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.iter().map(|p| p.trim().trim_end_matches('/')).filter(|p| !p.is_empty()) {
+        let anchored = if pattern.contains('/') { pattern.to_string() } else { format!("**/{}", pattern) };
+        builder.add(Glob::new(&anchored).map_err(|e| anyhow::anyhow!("Invalid pattern '{}': {}", pattern, e))?);
+        builder.add(Glob::new(&format!("{}/**", anchored)).map_err(|e| anyhow::anyhow!("Invalid pattern '{}': {}", pattern, e))?);
+    }
+    Ok(builder.build()?)
+}
+
+impl CompiledFolderFilter {
+    fn compile(filter: &FolderFilter) -> Result<Self> {
+        Ok(Self {
+            include: if filter.include.is_empty() { None } else { Some(build_glob_set(&filter.include)?) },
+            glob: None,
+            exclude: build_glob_set(&filter.exclude)?,
+            max_file_size_bytes: filter.max_file_size_bytes,
+        })
+    }
+
+    // relative is the path below the watched root, so patterns never match the root's own parents
+    fn excludes(&self, relative: &Path) -> bool {
+        self.exclude.is_match(relative)
+    }
+
+    fn check_file(&self, relative: &Path, size: u64) -> std::result::Result<(), SkipReason> {
+        if self.excludes(relative) {
+            return Err(SkipReason::Excluded);
+        }
+        if [&self.include, &self.glob].into_iter().flatten().any(|set| !set.is_match(relative)) {
+            return Err(SkipReason::NotIncluded);
+        }
+        if self.max_file_size_bytes > 0 && size > self.max_file_size_bytes {
+            return Err(SkipReason::TooLarge);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderScan {
+    pub files: Vec<PathBuf>,
+    pub skipped: SkippedFiles,
+}
+
+// Walks root, pruning excluded directories instead of descending into them
+fn scan_folder(root: &Path, filter: &CompiledFolderFilter, recursive: bool) -> Result<FolderScan> {
+    let mut scan = FolderScan::default();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(long_path(&dir)) {
+            Ok(entries) => entries,
+            Err(e) if dir != root => {
+                warn!("Skipping unreadable folder {}: {}", dir.display(), e);
+                continue;
+            }
+            Err(e) => return Err(IngestError::from_io(root, &e).into()),
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = dir.join(entry.file_name());
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            let Ok(metadata) = entry.metadata() else { continue };
+
+            if metadata.is_dir() {
+                if filter.excludes(&relative) {
+                    scan.skipped.record(SkipReason::Excluded);
+                } else if recursive {
+                    pending.push(path);
+                }
+            } else if metadata.is_file() {
+                match filter.check_file(&relative, metadata.len()) {
+                    Ok(()) => scan.files.push(path),
+                    Err(reason) => scan.skipped.record(reason),
+                }
+            }
+        }
+    }
+    scan.files.sort();
+    Ok(scan)
+}
+
+// Lets the settings screen show what a filter would pick up before a folder is imported or watched
+#[tauri::command]
+async fn preview_folder_filter(path: String, filter: FolderFilter, recursive: bool) -> Result<FolderScan, String> {
+    let compiled = CompiledFolderFilter::compile(&filter).map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(move || scan_folder(Path::new(&path), &compiled, recursive))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+// ---------- Directory Ingestion ---------------------------------------------------
+
+const DEFAULT_INGEST_PARALLELISM: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestProgress {
+    pub job_id: String,
+    pub file_path: String,
+    pub status: ReindexStatus,
+    pub processed: usize,
+    pub total: usize,
+    pub error: Option<IngestError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryIngestResult {
+    pub job_id: String,
+    pub results: Vec<ReindexResult>,
+    pub skipped: SkippedFiles,
+    pub processing_time_ms: u64,
+}
+
+// Imports every supported file under a folder; files already in the library are re-indexed
+// incrementally, so running it again over the same folder only picks up changes
+#[tauri::command]
+async fn ingest_directory(
+    path: String,
+    recursive: bool,
+    glob: Option<String>,
+    filter: Option<FolderFilter>,
+    parallelism: Option<usize>,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<DirectoryIngestResult, String> {
+    let start_time = std::time::Instant::now();
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let plugins = Arc::new(plugin_state.lock().map_err(|e| e.to_string())?.clone());
+
+    let filter = filter.unwrap_or_default();
+    let mut compiled = CompiledFolderFilter::compile(&filter).map_err(|e| e.to_string())?;
+    if let Some(glob) = glob.filter(|g| !g.trim().is_empty()) {
+        compiled.glob = Some(build_glob_set(&[glob]).map_err(|e| e.to_string())?);
+    }
+    let root = PathBuf::from(&path);
+    let mut scan = tokio::task::spawn_blocking(move || scan_folder(&root, &compiled, recursive))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let (files, unsupported): (Vec<PathBuf>, Vec<PathBuf>) =
+        std::mem::take(&mut scan.files).into_iter().partition(|file| is_supported_file(file, &plugins));
+    for _ in &unsupported {
+        scan.skipped.record(SkipReason::Unsupported);
+    }
+
+    let job_id = Uuid::new_v4().to_string();
+    let total = files.len();
+    let permits = Arc::new(tokio::sync::Semaphore::new(parallelism.unwrap_or(DEFAULT_INGEST_PARALLELISM).max(1)));
+    let config = Arc::new(config);
+    let mut tasks = tokio::task::JoinSet::new();
+    for file_path in files {
+        let permits = permits.clone();
+        let config = config.clone();
+        let providers = providers_state.inner().clone();
+        let db = db_state.inner().clone();
+        let plugins = plugins.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            match reimport_file(&file_path, &config, &providers, &db, &plugins).await {
+                Ok(result) => result,
+                Err(e) => ReindexResult {
+                    file_path: file_path.display().to_string(),
+                    document_id: None,
+                    status: ReindexStatus::Failed,
+                    chunks_reused: 0,
+                    chunks_embedded: 0,
+                    message: Some(e.to_string()),
+                    error: Some(IngestError::classify(&file_path, &e)),
+                },
+            }
+        });
+    }
+
+    let mut results = Vec::with_capacity(total);
+    while let Some(joined) = tasks.join_next().await {
+        let result = joined.map_err(|e| e.to_string())?;
+        if matches!(result.status, ReindexStatus::Added | ReindexStatus::Updated) {
+            if let Some(document_id) = &result.document_id {
+                let _ = app.emit("document_processed", document_id);
+            }
+        }
+        let _ = app.emit(
+            "ingest_progress",
+            &IngestProgress {
+                job_id: job_id.clone(),
+                file_path: result.file_path.clone(),
+                status: result.status.clone(),
+                processed: results.len() + 1,
+                total,
+                error: result.error.clone(),
+            },
+        );
+        results.push(result);
+    }
+    results.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+    info!(
+        "Ingested {} file(s) from {} ({} skipped)",
+        results.len(),
+        path,
+        scan.skipped.total()
+    );
+    Ok(DirectoryIngestResult {
+        job_id,
+        results,
+        skipped: scan.skipped,
+        processing_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+// ---------- Chunk Preview ---------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkPreview {
+    pub index: usize,
+    pub content: String,
+    pub metadata: serde_json::Value,
+    pub characters: usize,
+    pub tokens: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkPreviewResult {
+    pub file_path: String,
+    pub chunks: Vec<ChunkPreview>,
+    pub total_tokens: usize,
+    pub embedding_model: String,
+    pub estimated_cost_usd: f64, // 0.0 for local and self-hosted models
+}
+
+// Published per-million-token prices for hosted embedding models; unknown and local models count as free
+fn embedding_price_per_million_tokens(model: &EmbeddingModel) -> f64 {
+    match model {
+        EmbeddingModel::OpenAI { model, base_url: None, .. } => match model.as_str() {
+            "text-embedding-3-small" => 0.02,
+            "text-embedding-3-large" => 0.13,
+            "text-embedding-ada-002" => 0.10,
+            _ => 0.0,
+        },
+        _ => 0.0,
+    }
+}
+
+// Extracts and chunks a file exactly as ingestion would, without touching the database,
+// so chunk settings can be tuned before anything is embedded
+#[tauri::command]
+async fn preview_chunks(
+    file_path: String,
+    config: RAGConfig,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+) -> Result<ChunkPreviewResult, String> {
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+    let extracted = extract_document(Path::new(&file_path), &plugins, &config.extraction)
+        .await
+        .map_err(|e| e.to_string())?;
+    let chunks = chunk_document_with_parents(&extracted.text, &extracted.sections, &config, providers_state.inner()).await.chunks;
+
+    // Token counts use the model's tokenizer when there is one, else roughly four characters per token
+    let tokenizer = load_tokenizer_async(&config.embedding_model).await.ok();
+    let count_tokens = |text: &str| match &tokenizer {
+        Some(tokenizer) => tokenizer.encode(text, false).map(|encoding| encoding.len()).unwrap_or(text.len() / 4),
+        None => text.chars().count().div_ceil(4),
+    };
+
+    let chunks: Vec<ChunkPreview> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, (content, metadata))| ChunkPreview {
+            index,
+            characters: content.chars().count(),
+            tokens: count_tokens(&content),
+            content,
+            metadata,
+        })
+        .collect();
+    let total_tokens = chunks.iter().map(|chunk| chunk.tokens).sum();
+
+    Ok(ChunkPreviewResult {
+        file_path,
+        chunks,
+        total_tokens,
+        embedding_model: embedding_model_key(&config.embedding_model),
+        estimated_cost_usd: total_tokens as f64 / 1_000_000.0 * embedding_price_per_million_tokens(&config.embedding_model),
+    })
+}
+
+// ---------- Watched Folders --------------------------------------------------------
+
+// Editors save in several writes; a file is processed once events for it stop for this long
+const WATCH_DEBOUNCE_MS: u64 = 2000;
+// A file under continuous writes (a growing log, a download) is still processed this long after its first event
+const WATCH_MAX_WAIT_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchedFolder {
+    pub id: String,
+    pub path: String,
+    pub recursive: bool,
+    pub filter: FolderFilter,
+    pub created_at: DateTime<Utc>,
+}
+
+// Dropping a watcher closes its event channel, which ends the task processing that folder
+#[derive(Default)]
+pub struct FolderWatchers {
+    watchers: Mutex<HashMap<String, RecommendedWatcher>>,
+}
+
+fn load_watched_folders(conn: &Connection) -> Result<Vec<WatchedFolder>> {
+    let mut stmt = conn.prepare("SELECT id, path, recursive, filter, created_at FROM watched_folders ORDER BY created_at ASC")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, bool>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+
+    Ok(rows
+        .filter_map(Result::ok)
+        .filter_map(|(id, path, recursive, filter, created_at)| {
+            Some(WatchedFolder {
+                id,
+                path,
+                recursive,
+                filter: serde_json::from_str(&filter).ok()?,
+                created_at: DateTime::parse_from_rfc3339(&created_at).ok()?.with_timezone(&Utc),
+            })
+        })
+        .collect())
+}
+
+// Re-imports one changed file with the current configuration if the folder's filter accepts it
+async fn process_watched_file(app: &AppHandle, root: &Path, filter: &CompiledFolderFilter, file_path: &Path) {
+    let Ok(metadata) = std::fs::metadata(long_path(file_path)) else { return };
+    let relative = file_path.strip_prefix(root).unwrap_or(file_path);
+    if !metadata.is_file() || filter.check_file(relative, metadata.len()).is_err() {
+        return;
+    }
+
+    let plugins = app
+        .state::<Arc<Mutex<Vec<ExtractorPlugin>>>>()
+        .lock()
+        .map(|plugins| plugins.clone())
+        .unwrap_or_default();
+    if !is_supported_file(file_path, &plugins) {
+        return;
+    }
+    let config = app
+        .try_state::<Arc<Mutex<RAGConfig>>>()
+        .and_then(|config| config.lock().ok().map(|config| config.clone()))
+        .unwrap_or_default();
+    let db_state = app.state::<Arc<DbPool>>().inner().clone();
+    let providers = app.state::<Arc<EmbeddingProviders>>().inner().clone();
+
+    match reimport_file(file_path, &config, &providers, &db_state, &plugins).await {
+        Ok(result) if matches!(result.status, ReindexStatus::Added | ReindexStatus::Updated) => {
+            info!("Auto-ingested {} ({:?})", result.file_path, result.status);
+            if let Some(document_id) = &result.document_id {
+                let _ = app.emit("document_processed", document_id);
+            }
+            let _ = app.emit("document_auto_ingested", &result);
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to auto-ingest {}: {}", file_path.display(), e),
+    }
+}
+
+// Starts watching a folder; with catch_up, files changed while the app was closed are queued too
+fn start_folder_watcher(app: &AppHandle, folder: &WatchedFolder, catch_up: bool) -> Result<RecommendedWatcher> {
+    let root = PathBuf::from(&folder.path);
+    let filter = CompiledFolderFilter::compile(&folder.filter)?;
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+
+    let events = tx.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+            for path in event.paths {
+                let _ = events.send(path);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Folder watcher error: {}", e),
+    })?;
+    let mode = if folder.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(&long_path(&root), mode)?;
+
+    if catch_up {
+        let scan_root = root.clone();
+        let scan_filter = CompiledFolderFilter::compile(&folder.filter)?;
+        let recursive = folder.recursive;
+        thread::spawn(move || match scan_folder(&scan_root, &scan_filter, recursive) {
+            Ok(scan) => {
+                for path in scan.files {
+                    let _ = tx.send(path);
+                }
+            }
+            Err(e) => warn!("Failed to scan watched folder {}: {}", scan_root.display(), e),
+        });
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut pending = std::collections::BTreeSet::new();
+        while let Some(path) = rx.recv().await {
+            pending.insert(path);
+            let deadline = tokio::time::Instant::now() + Duration::from_millis(WATCH_MAX_WAIT_MS);
+            loop {
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                let quiet = Duration::from_millis(WATCH_DEBOUNCE_MS).min(remaining);
+                match tokio::time::timeout(quiet, rx.recv()).await {
+                    Ok(Some(path)) => {
+                        pending.insert(path);
+                    }
+                    _ => break,
+                }
+            }
+            for path in std::mem::take(&mut pending) {
+                process_watched_file(&app, &root, &filter, &path).await;
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+fn start_watched_folders(app: &AppHandle, db_state: &Arc<DbPool>, watchers: &FolderWatchers) -> Result<()> {
+    let folders = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        load_watched_folders(&db)?
+    };
+    let mut active = watchers.watchers.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    for folder in folders {
+        match start_folder_watcher(app, &folder, true) {
+            Ok(watcher) => {
+                active.insert(folder.id, watcher);
+            }
+            Err(e) => warn!("Failed to watch {}: {}", folder.path, e),
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn add_watched_folder(
+    path: String,
+    recursive: bool,
+    filter: Option<FolderFilter>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    watchers_state: tauri::State<'_, Arc<FolderWatchers>>,
+    app: AppHandle,
+) -> Result<WatchedFolder, String> {
+    if !long_path(Path::new(&path)).is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+
+    let folder = WatchedFolder {
+        id: Uuid::new_v4().to_string(),
+        path,
+        recursive,
+        filter: filter.unwrap_or_default(),
+        created_at: Utc::now(),
+    };
+    // New folders are not imported wholesale here; ingest_directory covers the initial load
+    let watcher = start_folder_watcher(&app, &folder, false).map_err(|e| e.to_string())?;
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute(
+        "INSERT INTO watched_folders (id, path, recursive, filter, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            folder.id,
+            folder.path,
+            folder.recursive,
+            serde_json::to_string(&folder.filter).map_err(|e| e.to_string())?,
+            folder.created_at.to_rfc3339(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    record_audit(&db, "settings_changed", Some(&folder.id), serde_json::json!({
+        "watched_folder": "added",
+        "path": folder.path,
+        "recursive": folder.recursive,
+    }));
+
+    watchers_state
+        .watchers
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(folder.id.clone(), watcher);
+    Ok(folder)
+}
+
+#[tauri::command]
+fn remove_watched_folder(
+    folder_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    watchers_state: tauri::State<'_, Arc<FolderWatchers>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM watched_folders WHERE id = ?1", params![folder_id])
+        .map_err(|e| e.to_string())?;
+    if removed == 0 {
+        return Err(format!("Watched folder not found: {}", folder_id));
+    }
+
+    watchers_state.watchers.lock().map_err(|e| e.to_string())?.remove(&folder_id);
+    record_audit(&db, "settings_changed", Some(&folder_id), serde_json::json!({ "watched_folder": "removed" }));
+    Ok(())
+}
+
+#[tauri::command]
+fn list_watched_folders(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<WatchedFolder>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    load_watched_folders(&db).map_err(|e| e.to_string())
+}
+
+// ---------- Web Crawler -----------------------------------------------------------
+
+const CRAWL_USER_AGENT: &str = concat!("RAG_app/", env!("CARGO_PKG_VERSION"));
+const CRAWL_TIMEOUT_SECS: u64 = 30;
+// Bodies past this are abandoned mid-download rather than buffered
+const CRAWL_MAX_PAGE_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlOptions {
+    #[serde(default = "default_crawl_max_depth")]
+    pub max_depth: usize, // 0 fetches only the start page
+    #[serde(default = "default_crawl_max_pages")]
+    pub max_pages: usize,
+    #[serde(default = "default_true")]
+    pub same_domain: bool,
+    #[serde(default = "default_true")]
+    pub respect_robots: bool,
+    #[serde(default)]
+    pub delay_ms: u64, // pause between requests to go easy on the site
+}
+
+fn default_crawl_max_depth() -> usize {
+    2
+}
+
+fn default_crawl_max_pages() -> usize {
+    50
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: default_crawl_max_depth(),
+            max_pages: default_crawl_max_pages(),
+            same_domain: true,
+            respect_robots: true,
+            delay_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawledPage {
+    pub url: String,
+    pub depth: usize,
+    pub document_id: Option<String>,
+    pub status: ReindexStatus,
+    pub chunks_embedded: usize,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlProgress {
+    pub job_id: String,
+    pub page: CrawledPage,
+    pub pages_fetched: usize,
+    pub max_pages: usize,
+    pub queued: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlResult {
+    pub job_id: String,
+    pub pages: Vec<CrawledPage>,
+    pub processing_time_ms: u64,
+}
+
+// Allow/Disallow patterns from the "*" group of a robots.txt; the longest matching pattern wins,
+// Allow on a tie. Patterns may use `*` anywhere and a trailing `$` anchor
+#[derive(Debug, Default)]
+struct RobotsRules {
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    fn parse(body: &str) -> Self {
+        let mut rules = Vec::new();
+        let mut applies = false;
+        let mut reading_agents = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive User-agent lines share one group
+                    if !reading_agents {
+                        applies = false;
+                    }
+                    reading_agents = true;
+                    applies |= value == "*";
+                }
+                field @ ("allow" | "disallow") => {
+                    reading_agents = false;
+                    if applies && !value.is_empty() {
+                        rules.push((field == "allow", value.to_string()));
+                    }
+                }
+                _ => reading_agents = false,
+            }
+        }
+        Self { rules }
+    }
+
+    // Rules are matched against the path and query, as the robots.txt spec asks
+    fn allows_url(&self, url: &reqwest::Url) -> bool {
+        match url.query() {
+            Some(query) => self.allows(&format!("{}?{}", url.path(), query)),
+            None => self.allows(url.path()),
+        }
+    }
+
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| robots_pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+// Prefix match where `*` stands for any run of characters and a trailing `$` pins the end
+fn robots_pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // The last literal of an anchored pattern has to sit at the very end
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+// A missing or unreadable robots.txt allows everything
+async fn fetch_robots(client: &reqwest::Client, url: &reqwest::Url) -> RobotsRules {
+    let Ok(robots_url) = url.join("/robots.txt") else { return RobotsRules::default() };
+    match client.get(robots_url).send().await {
+        Ok(response) if response.status().is_success() => {
+            RobotsRules::parse(&response.text().await.unwrap_or_default())
+        }
+        _ => RobotsRules::default(),
+    }
+}
+
+async fn fetch_web_page(client: &reqwest::Client, url: &reqwest::Url) -> Result<(reqwest::Url, HtmlPage)> {
+    let response = client.get(url.clone()).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("HTTP {}", status));
+    }
+    let final_url = response.url().clone();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("text/html")
+        .to_ascii_lowercase();
+    // Decided from the headers, so a PDF or video link is never downloaded just to be rejected
+    let is_html = content_type.contains("html");
+    if !is_html && !content_type.starts_with("text/plain") {
+        return Err(anyhow::anyhow!("Unsupported content type {}", content_type));
+    }
+    if response.content_length().is_some_and(|length| length as usize > CRAWL_MAX_PAGE_BYTES) {
+        return Err(anyhow::anyhow!("Page is larger than {} bytes", CRAWL_MAX_PAGE_BYTES));
+    }
+
+    // Content-Length can be missing or wrong, so the cap is enforced while streaming too
+    let mut response = response;
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > CRAWL_MAX_PAGE_BYTES {
+            return Err(anyhow::anyhow!("Page is larger than {} bytes", CRAWL_MAX_PAGE_BYTES));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let body = String::from_utf8_lossy(&bytes).into_owned();
+
+    if is_html {
+        Ok((final_url, html_to_text(&body)))
+    } else {
+        Ok((final_url, HtmlPage { title: None, text: body, links: Vec::new() }))
+    }
+}
+
+// Pages are keyed by URL, so crawling a site again only re-embeds pages whose text changed
+async fn ingest_web_page(
+    url: &str,
+    page: &HtmlPage,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    db_state: &Arc<DbPool>,
+) -> Result<(String, ReindexStatus, usize)> {
+    let content_hash = calculate_content_hash(&page.text);
+    let title = page.title.clone().unwrap_or_else(|| url.to_string());
+    let existing: Option<(String, String)> = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        ensure_writable(&db)?;
+        db.query_row(
+            "SELECT id, content_hash FROM documents WHERE file_path = ?1 AND deleted_at IS NULL
+             ORDER BY created_at DESC LIMIT 1",
+            params![url],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok()
+    };
+
+    match existing {
+        Some((document_id, stored_hash)) if stored_hash == content_hash => Ok((document_id, ReindexStatus::Unchanged, 0)),
+        Some((document_id, _)) => {
+            let config = &{
+                let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                apply_document_language(&db, &document_id, &page.text, config)?
+            };
+            let (_, embedded) = rechunk_document_incremental(&document_id, &page.text, &[], db_state, config, providers, true).await?;
+            let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            db.execute(
+                "UPDATE documents SET title = ?1, content = ?2, content_hash = ?3, updated_at = ?4 WHERE id = ?5",
+                params![title, page.text, content_hash, Utc::now().to_rfc3339(), document_id],
+            )?;
+            Ok((document_id, ReindexStatus::Updated, embedded))
+        }
+        None => {
+            let now = Utc::now();
+            let document = Document {
+                id: Uuid::new_v4().to_string(),
+                title,
+                content: page.text.clone(),
+                file_path: Some(url.to_string()),
+                file_type: "html".to_string(),
+                content_hash,
+                is_searchable: true,
+                is_sensitive: false,
+                metadata: serde_json::json!({ "source_url": url }),
+                tags: Vec::new(),
+                created_at: now,
+                updated_at: now,
+            };
+            let config = &{
+                let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                insert_document(&db, &document)?;
+                record_audit(&db, "document_added", Some(&document.id), serde_json::json!({
+                    "title": document.title,
+                    "source_url": url,
+                }));
+                apply_document_language(&db, &document.id, &page.text, config)?
+            };
+            let chunks_created = process_document_chunks_enhanced(&document.id, &page.text, &[], db_state, config, providers).await?;
+            Ok((document.id, ReindexStatus::Added, chunks_created))
+        }
+    }
+}
+
+// robots.txt is fetched once per origin and cached for the rest of the crawl
+async fn robots_allow(client: &reqwest::Client, robots: &mut HashMap<String, RobotsRules>, url: &reqwest::Url) -> bool {
+    let origin = url.origin().ascii_serialization();
+    if !robots.contains_key(&origin) {
+        robots.insert(origin.clone(), fetch_robots(client, url).await);
+    }
+    robots[&origin].allows_url(url)
+}
+
+// Breadth-first crawl from start, so max_pages keeps the pages closest to the start page
+async fn crawl_site(
+    start: reqwest::Url,
+    options: &CrawlOptions,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+    db_state: &Arc<DbPool>,
+    app: &AppHandle,
+    job_id: &str,
+) -> Result<Vec<CrawledPage>> {
+    let client = reqwest::Client::builder()
+        .user_agent(CRAWL_USER_AGENT)
+        .timeout(Duration::from_secs(CRAWL_TIMEOUT_SECS))
+        .build()?;
+    let mut robots: HashMap<String, RobotsRules> = HashMap::new();
+    let mut queue = std::collections::VecDeque::from([(start.clone(), 0usize)]);
+    let mut seen = std::collections::HashSet::from([start.to_string()]);
+    let mut pages = Vec::new();
+    let mut fetched = 0;
+
+    while let Some((url, depth)) = queue.pop_front() {
+        if fetched >= options.max_pages {
+            break;
+        }
+        let mut page = CrawledPage {
+            url: url.to_string(),
+            depth,
+            document_id: None,
+            status: ReindexStatus::Skipped,
+            chunks_embedded: 0,
+            message: None,
+        };
+
+        if options.respect_robots && !robots_allow(&client, &mut robots, &url).await {
+            page.message = Some("Disallowed by robots.txt".to_string());
+        } else {
+            fetched += 1;
+            match fetch_web_page(&client, &url).await {
+                Err(e) => {
+                    page.status = ReindexStatus::Failed;
+                    page.message = Some(e.to_string());
+                }
+                // A redirect can leave the site or land on a disallowed path; the final URL is checked like a link
+                Ok((final_url, _)) if options.same_domain && final_url.host_str() != start.host_str() => {
+                    page.message = Some(format!("Redirected off-site to {}", final_url));
+                }
+                Ok((final_url, _))
+                    if options.respect_robots && !robots_allow(&client, &mut robots, &final_url).await =>
+                {
+                    page.message = Some(format!("Redirect target {} is disallowed by robots.txt", final_url));
+                }
+                Ok((final_url, html)) => {
+                    seen.insert(final_url.to_string());
+                    if depth < options.max_depth {
+                        for link in &html.links {
+                            let Ok(mut next) = final_url.join(link) else { continue };
+                            next.set_fragment(None);
+                            let in_scope = matches!(next.scheme(), "http" | "https")
+                                && (!options.same_domain || next.host_str() == start.host_str());
+                            if in_scope && seen.insert(next.to_string()) {
+                                queue.push_back((next, depth + 1));
+                            }
+                        }
+                    }
+
+                    if html.text.trim().is_empty() {
+                        page.message = Some("No text content".to_string());
+                    } else {
+                        match ingest_web_page(url.as_str(), &html, config, providers, db_state).await {
+                            Ok((document_id, status, chunks_embedded)) => {
+                                if matches!(status, ReindexStatus::Added | ReindexStatus::Updated) {
+                                    let _ = app.emit("document_processed", &document_id);
+                                }
+                                page.document_id = Some(document_id);
+                                page.status = status;
+                                page.chunks_embedded = chunks_embedded;
+                            }
+                            Err(e) => {
+                                page.status = ReindexStatus::Failed;
+                                page.message = Some(e.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            if options.delay_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(options.delay_ms)).await;
+            }
+        }
+
+        let _ = app.emit(
+            "crawl_progress",
+            &CrawlProgress {
+                job_id: job_id.to_string(),
+                page: page.clone(),
+                pages_fetched: fetched,
+                max_pages: options.max_pages,
+                queued: queue.len(),
+            },
+        );
+        pages.push(page);
+    }
+
+    Ok(pages)
+}
+
+fn parse_crawl_url(url: &str) -> Result<reqwest::Url> {
+    let url = reqwest::Url::parse(url.trim()).map_err(|e| anyhow::anyhow!("Invalid URL '{}': {}", url, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!("Only http and https URLs can be ingested"));
+    }
+    Ok(url)
+}
+
+#[tauri::command]
+async fn ingest_url(
+    url: String,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<CrawledPage, String> {
+    let start = parse_crawl_url(&url).map_err(|e| e.to_string())?;
+    let options = CrawlOptions { max_depth: 0, max_pages: 1, ..Default::default() };
+    let pages = crawl_site(start, &options, &config, providers_state.inner(), db_state.inner(), &app, &Uuid::new_v4().to_string())
+        .await
+        .map_err(|e| e.to_string())?;
+    pages.into_iter().next().ok_or_else(|| format!("Nothing was fetched from {}", url))
+}
+
+#[tauri::command]
+async fn crawl_website(
+    url: String,
+    options: Option<CrawlOptions>,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<CrawlResult, String> {
+    let start_time = std::time::Instant::now();
+    let start = parse_crawl_url(&url).map_err(|e| e.to_string())?;
+    let options = options.unwrap_or_default();
+    let job_id = Uuid::new_v4().to_string();
+
+    let pages = crawl_site(start, &options, &config, providers_state.inner(), db_state.inner(), &app, &job_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Crawled {} page(s) from {}", pages.len(), url);
+
+    Ok(CrawlResult {
+        job_id,
+        pages,
+        processing_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+// ---------- Shared Knowledge Base -------------------------------------------------
+
+const LOCAL_DATABASE_FILE: &str = "rag_documents.db";
+const KNOWLEDGE_BASE_SETTINGS_FILE: &str = "knowledge_base.json";
+
+// Kept next to the local database rather than inside it, since it decides which database to open
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeBaseSettings {
+    #[serde(default)]
+    pub shared_path: Option<String>, // database on a network share, opened read-only
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBaseInfo {
+    pub shared_path: Option<String>,
+    pub read_only: bool,
+    pub document_count: usize,
+}
+
+fn load_knowledge_base_settings(data_dir: &Path) -> KnowledgeBaseSettings {
+    std::fs::read_to_string(data_dir.join(KNOWLEDGE_BASE_SETTINGS_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_knowledge_base_settings(data_dir: &Path, settings: &KnowledgeBaseSettings) -> Result<()> {
+    std::fs::write(data_dir.join(KNOWLEDGE_BASE_SETTINGS_FILE), serde_json::to_string_pretty(settings)?)?;
+    Ok(())
+}
+
+fn open_shared_knowledge_base(path: &Path) -> Result<DbPool> {
+    let pool = DbPool::open_read_only(&long_path(path))?;
+    let conn = pool.lock()?;
+    // A WAL file can't be read without writing its shared-memory index next to it
+    let journal_mode: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .map_err(|e| anyhow::anyhow!("{} can't be opened read-only: {}", path.display(), e))?;
+    if journal_mode.eq_ignore_ascii_case("wal") {
+        return Err(anyhow::anyhow!(
+            "{} is in WAL mode; share a backup of the knowledge base instead of its live database file",
+            path.display()
+        ));
+    }
+    conn.query_row("SELECT COUNT(*) FROM document_chunks", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| anyhow::anyhow!("{} is not a knowledge base: {}", path.display(), e))?;
+    drop(conn);
+    Ok(pool)
+}
+
+fn open_local_knowledge_base(data_dir: &Path) -> Result<DbPool> {
+    let pool = DbPool::open(&data_dir.join(LOCAL_DATABASE_FILE), DB_POOL_SIZE)?;
+    init_db(&pool.lock()?)?;
+    Ok(pool)
+}
+
+fn is_read_only(conn: &Connection) -> bool {
+    conn.is_readonly(rusqlite::DatabaseName::Main).unwrap_or(false)
+}
+
+fn ensure_writable(conn: &Connection) -> Result<()> {
+    if is_read_only(conn) {
+        return Err(anyhow::anyhow!(
+            "The knowledge base is a read-only shared copy; ingestion and edits are disabled"
+        ));
+    }
+    Ok(())
+}
+
+fn knowledge_base_info(conn: &Connection, shared_path: Option<String>) -> Result<KnowledgeBaseInfo> {
+    let document_count: i64 =
+        conn.query_row("SELECT COUNT(*) FROM documents WHERE deleted_at IS NULL", [], |row| row.get(0))?;
+    Ok(KnowledgeBaseInfo {
+        shared_path,
+        read_only: is_read_only(conn),
+        document_count: document_count as usize,
+    })
+}
+
+#[tauri::command]
+fn get_knowledge_base_info(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<KnowledgeBaseInfo, String> {
+    let data_dir = app_data_dir(&app).map_err(|e| e.to_string())?;
+    let settings = load_knowledge_base_settings(&data_dir);
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    // A shared path that failed to open at startup leaves the local database in use
+    let shared_path = settings.shared_path.filter(|_| is_read_only(&db));
+    knowledge_base_info(&db, shared_path).map_err(|e| e.to_string())
+}
+
+// Switches the open knowledge base in place; None returns to the local database.
+// The embedding cache always stays in the local database.
+#[tauri::command]
+fn set_shared_knowledge_base(
+    path: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    app: AppHandle,
+) -> Result<KnowledgeBaseInfo, String> {
+    let data_dir = app_data_dir(&app).map_err(|e| e.to_string())?;
+    let path = path.filter(|p| !p.trim().is_empty());
+    let pool = match &path {
+        Some(shared) => open_shared_knowledge_base(Path::new(shared)),
+        None => open_local_knowledge_base(&data_dir),
+    }
+    .map_err(|e| e.to_string())?;
+
+    save_knowledge_base_settings(&data_dir, &KnowledgeBaseSettings { shared_path: path.clone() })
+        .map_err(|e| e.to_string())?;
+
+    db_state.replace(pool);
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    // The graph describes the previous database's chunks
+    *hnsw_state.lock().map_err(|e| e.to_string())? = None;
+    let info = knowledge_base_info(&db, path).map_err(|e| e.to_string())?;
+    info!(
+        "Switched to {} knowledge base with {} document(s)",
+        if info.read_only { "shared read-only" } else { "local" },
+        info.document_count
+    );
+    let _ = app.emit("knowledge_base_changed", &info);
+    Ok(info)
+}
+
+// ---------- Backup and Restore ----------------------------------------------------
+
+const BACKUP_PAGES_PER_STEP: std::os::raw::c_int = 256;
+const BACKUP_STEP_PAUSE_MS: u64 = 10; // lets other connections write between steps of a large copy
+const PRE_RESTORE_BACKUP_FILE: &str = "rag_documents.pre-restore.db";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub size_bytes: u64,
+    pub document_count: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+// Copies page by page with the online backup API, so the knowledge base stays usable during the copy
+fn copy_database(source: &Connection, destination: &mut Connection) -> Result<()> {
+    let backup = rusqlite::backup::Backup::new(source, destination)?;
+    backup.run_to_completion(BACKUP_PAGES_PER_STEP, Duration::from_millis(BACKUP_STEP_PAUSE_MS), None)?;
+    Ok(())
+}
+
+fn verify_integrity(conn: &Connection) -> Result<()> {
+    let result: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if result != "ok" {
+        return Err(anyhow::anyhow!("Integrity check failed: {}", result));
+    }
+    conn.query_row("SELECT COUNT(*) FROM document_chunks", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| anyhow::anyhow!("Not a knowledge base: {}", e))?;
+    Ok(())
+}
+
+fn backup_to_file(conn: &Connection, path: &Path) -> Result<BackupInfo> {
+    let mut destination = Connection::open(long_path(path))?;
+    copy_database(conn, &mut destination)?;
+    // The copied header carries the local database's WAL mode; backups get shared as read-only knowledge bases
+    destination.pragma_update_and_check(None, "journal_mode", "DELETE", |row| row.get::<_, String>(0))?;
+    verify_integrity(&destination)?;
+    let document_count: i64 =
+        destination.query_row("SELECT COUNT(*) FROM documents WHERE deleted_at IS NULL", [], |row| row.get(0))?;
+    drop(destination);
+    Ok(BackupInfo {
+        path: path.display().to_string(),
+        size_bytes: std::fs::metadata(long_path(path))?.len(),
+        document_count: document_count as usize,
+        created_at: Utc::now(),
+    })
+}
+
+#[tauri::command]
+fn backup_database(path: String, db_state: tauri::State<'_, Arc<DbPool>>) -> Result<BackupInfo, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let info = backup_to_file(&db, Path::new(&path)).map_err(|e| e.to_string())?;
+    if !is_read_only(&db) {
+        record_audit(&db, "database_backup", None, serde_json::json!({ "path": info.path, "size_bytes": info.size_bytes }));
+    }
+    Ok(info)
+}
+
+// Replaces the local knowledge base with a verified backup. The current contents are saved next to the
+// database first, so a restore from the wrong file can itself be undone.
+#[tauri::command]
+fn restore_database(
+    path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    app: AppHandle,
+) -> Result<KnowledgeBaseInfo, String> {
+    let source = Connection::open_with_flags(
+        long_path(Path::new(&path)),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| e.to_string())?;
+    verify_integrity(&source).map_err(|e| format!("{} can't be restored: {}", path, e))?;
+
+    let data_dir = app_data_dir(&app).map_err(|e| e.to_string())?;
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    backup_to_file(&db, &data_dir.join(PRE_RESTORE_BACKUP_FILE)).map_err(|e| e.to_string())?;
+    copy_database(&source, &mut db).map_err(|e| e.to_string())?;
+
+    // Backups from older versions are upgraded like any other database
+    init_db(&db).map_err(|e| e.to_string())?;
+    // Vector indexes and the embedding sidecar describe the replaced chunks
+    reset_vec_index(&db).map_err(|e| e.to_string())?;
+    let mut store = store_state.lock().map_err(|e| e.to_string())?;
+    reset_embedding_store(&db, &mut store).map_err(|e| e.to_string())?;
+    *hnsw_state.lock().map_err(|e| e.to_string())? = None;
+
+    record_audit(&db, "database_restored", None, serde_json::json!({ "path": path }));
+    let info = knowledge_base_info(&db, None).map_err(|e| e.to_string())?;
+    info!("Restored knowledge base from {} with {} document(s)", path, info.document_count);
+    let _ = app.emit("knowledge_base_changed", &info);
+    Ok(info)
+}
+
+// ---------- Knowledge Base Archive ------------------------------------------------
+
+// Version 2 archives carry every chunk column and the parent passages; version 1 ones still import
+const ARCHIVE_FORMAT_VERSION: u32 = 2;
+const ARCHIVE_MANIFEST_ENTRY: &str = "manifest.json";
+const ARCHIVE_CONFIG_ENTRY: &str = "config.json";
+const ARCHIVE_SESSIONS_ENTRY: &str = "chat_sessions.json";
+const ARCHIVE_DOCUMENTS_DIR: &str = "documents/";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub embedding_model: String, // the exporting config's model; each chunk records its own
+    pub document_count: usize,
+    pub chat_session_count: usize,
+}
+
+// One document_chunks row with every column, so citations, parent expansion and per-chunk
+// embedding models survive the trip. Fields missing from version 1 archives default to None.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedChunk {
+    pub id: String,
+    pub chunk_index: i32,
+    pub content: String,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    pub embedding: String, // hex-encoded BLOB, float32 or int8 as stored
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    pub parent_chunk_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedParentChunk {
+    pub id: String,
+    pub chunk_index: i32,
+    pub content: String,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedDocument {
+    pub document: Document,
+    #[serde(default)]
+    pub sections: Vec<TextSection>,
+    #[serde(default)]
+    pub language: Option<String>,
+    pub chunks: Vec<ArchivedChunk>,
+    #[serde(default)]
+    pub parent_chunks: Vec<ArchivedParentChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedChatSession {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ChatMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveExportReport {
+    pub path: String,
+    pub documents: usize,
+    pub chunks: usize,
+    pub chat_sessions: usize,
+    pub skipped_sensitive: usize, // encrypted with this device's key, so useless elsewhere
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveImportReport {
+    pub documents_added: usize,
+    pub documents_replaced: usize,
+    pub documents_copied: usize,
+    pub documents_skipped: usize, // unchanged, or kept local under keep_mine
+    pub chat_sessions_added: usize,
+    pub config_applied: bool,
+}
+
+fn export_archived_document(conn: &Connection, document_id: &str) -> Result<ArchivedDocument> {
+    let document = conn.query_row(
+        &format!("SELECT {} FROM documents d WHERE d.id = ?1", DOCUMENT_COLUMNS),
+        params![document_id],
+        |row| document_from_row(row, 0),
+    )?;
+    let language: Option<String> =
+        conn.query_row("SELECT language FROM documents WHERE id = ?1", params![document_id], |row| row.get(0))?;
+    let parse_time = |value: String| {
+        DateTime::parse_from_rfc3339(&value)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+    };
+    let parse_metadata = |value: Option<String>| value.and_then(|m| serde_json::from_str(&m).ok());
+
+    let mut stmt = conn.prepare(
+        "SELECT dc.id, dc.chunk_index, dc.content, dc.content_hash, COALESCE(se.embedding, dc.embedding),
+                dc.embedding_model, dc.metadata, dc.parent_chunk_id, dc.created_at
+         FROM document_chunks dc
+         LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+         WHERE dc.document_id = ?1 ORDER BY dc.chunk_index",
+    )?;
+    let chunks = stmt
+        .query_map(params![document_id], |row| {
+            Ok(ArchivedChunk {
+                id: row.get(0)?,
+                chunk_index: row.get(1)?,
+                content: row.get(2)?,
+                content_hash: row.get(3)?,
+                embedding: hex::encode(row.get::<_, Vec<u8>>(4)?),
+                embedding_model: row.get(5)?,
+                metadata: parse_metadata(row.get(6)?),
+                parent_chunk_id: row.get(7)?,
+                created_at: parse_time(row.get(8)?)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, chunk_index, content, metadata, created_at FROM parent_chunks
+         WHERE document_id = ?1 ORDER BY chunk_index",
+    )?;
+    let parent_chunks = stmt
+        .query_map(params![document_id], |row| {
+            Ok(ArchivedParentChunk {
+                id: row.get(0)?,
+                chunk_index: row.get(1)?,
+                content: row.get(2)?,
+                metadata: parse_metadata(row.get(3)?),
+                created_at: parse_time(row.get(4)?)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(ArchivedDocument {
+        document,
+        sections: load_document_sections(conn, document_id),
+        language,
+        chunks,
+        parent_chunks,
+    })
+}
+
+// Replaces the document, its chunks and parent passages in one transaction
+fn apply_archived_document(conn: &mut Connection, archived: &ArchivedDocument) -> Result<()> {
+    let doc = &archived.document;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    tx.execute("DELETE FROM document_chunks WHERE document_id = ?1", params![doc.id])?;
+    tx.execute("DELETE FROM parent_chunks WHERE document_id = ?1", params![doc.id])?;
+    upsert_document_row(&tx, doc)?;
+    store_document_sections(&tx, &doc.id, &archived.sections)?;
+    tx.execute("UPDATE documents SET language = ?1 WHERE id = ?2", params![archived.language, doc.id])?;
+    tx.execute("DELETE FROM document_tags WHERE document_id = ?1", params![doc.id])?;
+    for tag in &doc.tags {
+        attach_tag(&tx, &doc.id, tag)?;
+    }
+
+    for parent in &archived.parent_chunks {
+        tx.execute(
+            "INSERT INTO parent_chunks (id, document_id, chunk_index, content, metadata, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                parent.id,
+                doc.id,
+                parent.chunk_index,
+                parent.content,
+                parent.metadata.as_ref().map(|m| m.to_string()),
+                parent.created_at.to_rfc3339(),
+            ],
+        )?;
+    }
+    for chunk in &archived.chunks {
+        let embedding_bytes = hex::decode(&chunk.embedding)?;
+        tx.execute(
+            "INSERT INTO document_chunks (id, document_id, chunk_index, content, content_hash, embedding,
+                                          embedding_model, embedding_dim, metadata, parent_chunk_id, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                chunk.id,
+                doc.id,
+                chunk.chunk_index,
+                chunk.content,
+                chunk.content_hash.clone().unwrap_or_else(|| calculate_content_hash(&chunk.content)),
+                embedding_bytes,
+                chunk.embedding_model,
+                embedding_dimension(&embedding_bytes) as i64,
+                chunk.metadata.as_ref().map(|m| m.to_string()),
+                chunk.parent_chunk_id,
+                chunk.created_at.to_rfc3339(),
+            ],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+// A copy kept next to the local document needs fresh ids, with chunks still pointing at their parents
+fn reassign_archived_ids(archived: &mut ArchivedDocument) {
+    archived.document.id = Uuid::new_v4().to_string();
+    let mut parent_ids = HashMap::new();
+    for parent in &mut archived.parent_chunks {
+        let new_id = Uuid::new_v4().to_string();
+        parent_ids.insert(std::mem::replace(&mut parent.id, new_id.clone()), new_id);
+    }
+    for chunk in &mut archived.chunks {
+        chunk.id = Uuid::new_v4().to_string();
+        chunk.parent_chunk_id = chunk.parent_chunk_id.take().and_then(|id| parent_ids.get(&id).cloned());
+    }
+}
+
+fn load_archived_sessions(conn: &Connection) -> Result<Vec<ArchivedChatSession>> {
+    let sessions: Vec<(String, String, String, String)> = {
+        let mut stmt = conn.prepare("SELECT id, title, created_at, updated_at FROM chat_sessions ORDER BY created_at")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    let parse = |value: &str| DateTime::parse_from_rfc3339(value).map(|t| t.with_timezone(&Utc));
+    sessions
+        .into_iter()
+        .map(|(id, title, created_at, updated_at)| {
+            Ok(ArchivedChatSession {
+                messages: load_chat_messages(conn, &id)?,
+                id,
+                title,
+                created_at: parse(&created_at)?,
+                updated_at: parse(&updated_at)?,
+            })
+        })
+        .collect()
+}
+
+fn write_archive_entry<W: std::io::Write + std::io::Seek>(
+    archive: &mut zip::ZipWriter<W>,
+    name: &str,
+    value: &impl Serialize,
+) -> Result<()> {
+    use std::io::Write;
+
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    archive.start_file(name, options)?;
+    archive.write_all(&serde_json::to_vec(value)?)?;
+    Ok(())
+}
+
+// Documents with their chunks and vectors, the current settings without API keys, and chat history
+#[tauri::command]
+fn export_knowledge_base(
+    path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<ArchiveExportReport, String> {
+    let config = strip_config_secrets(&config_state.lock().map_err(|e| e.to_string())?);
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let (document_ids, skipped_sensitive): (Vec<String>, usize) = {
+        let mut stmt = db
+            .prepare("SELECT id, is_sensitive FROM documents WHERE deleted_at IS NULL ORDER BY created_at")
+            .map_err(|e| e.to_string())?;
+        let rows: Vec<(String, bool)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .filter_map(Result::ok)
+            .collect();
+        let skipped = rows.iter().filter(|(_, sensitive)| *sensitive).count();
+        (rows.into_iter().filter(|(_, sensitive)| !sensitive).map(|(id, _)| id).collect(), skipped)
+    };
+    let sessions = load_archived_sessions(&db).map_err(|e| e.to_string())?;
+
+    let file = std::fs::File::create(long_path(Path::new(&path))).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipWriter::new(file);
+    let mut chunks = 0;
+    for document_id in &document_ids {
+        let document = export_archived_document(&db, document_id).map_err(|e| e.to_string())?;
+        chunks += document.chunks.len();
+        write_archive_entry(&mut archive, &format!("{}{}.json", ARCHIVE_DOCUMENTS_DIR, document_id), &document)
+            .map_err(|e| e.to_string())?;
+    }
+    write_archive_entry(&mut archive, ARCHIVE_SESSIONS_ENTRY, &sessions).map_err(|e| e.to_string())?;
+    write_archive_entry(&mut archive, ARCHIVE_CONFIG_ENTRY, &config).map_err(|e| e.to_string())?;
+    // Written last, so an archive cut short by a failure has no manifest and is refused on import
+    let manifest = ArchiveManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        exported_at: Utc::now(),
+        embedding_model: embedding_model_key(&config.embedding_model),
+        document_count: document_ids.len(),
+        chat_session_count: sessions.len(),
+    };
+    write_archive_entry(&mut archive, ARCHIVE_MANIFEST_ENTRY, &manifest).map_err(|e| e.to_string())?;
+    archive.finish().map_err(|e| e.to_string())?;
+
+    let report = ArchiveExportReport {
+        size_bytes: std::fs::metadata(long_path(Path::new(&path))).map_err(|e| e.to_string())?.len(),
+        path,
+        documents: document_ids.len(),
+        chunks,
+        chat_sessions: sessions.len(),
+        skipped_sensitive,
+    };
+    if !is_read_only(&db) {
+        record_audit(&db, "knowledge_base_exported", None, serde_json::json!({
+            "path": report.path,
+            "documents": report.documents,
+            "chat_sessions": report.chat_sessions,
+        }));
+    }
+    Ok(report)
+}
+
+// Documents whose id already exists are resolved with on_conflict (keep_mine by default); identical
+// copies are skipped. Chat sessions are only added, never merged into an existing one.
+#[tauri::command]
+fn import_knowledge_base(
+    path: String,
+    on_conflict: Option<ConflictStrategy>,
+    apply_config: bool,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    hnsw_state: tauri::State<'_, Arc<Mutex<Option<HnswIndex>>>>,
+    app: AppHandle,
+) -> Result<ArchiveImportReport, String> {
+    let on_conflict = on_conflict.unwrap_or(ConflictStrategy::KeepMine);
+    let mut db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+
+    let file = std::fs::File::open(long_path(Path::new(&path))).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let manifest: ArchiveManifest = read_zip_entry(&mut archive, ARCHIVE_MANIFEST_ENTRY)
+        .ok_or_else(|| format!("{} is not a knowledge base archive", path))
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))?;
+    if manifest.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(format!("{} was written by a newer version of the app", path));
+    }
+
+    let current = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let incoming_config = match read_zip_entry(&mut archive, ARCHIVE_CONFIG_ENTRY).filter(|_| apply_config) {
+        Some(json) => {
+            let mut incoming: RAGConfig = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+            restore_api_keys(&mut incoming, &current);
+            Some(incoming)
+        }
+        None => None,
+    };
+    let target = incoming_config.as_ref().unwrap_or(&current);
+
+    let document_entries: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with(ARCHIVE_DOCUMENTS_DIR) && name.ends_with(".json"))
+        .map(str::to_string)
+        .collect();
+    let mut documents = Vec::with_capacity(document_entries.len());
+    for entry in document_entries {
+        let json = read_zip_entry(&mut archive, &entry).ok_or_else(|| format!("Unreadable archive entry {}", entry))?;
+        let mut document: ArchivedDocument = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        // Version 1 archives only recorded the exporting config's model
+        for chunk in &mut document.chunks {
+            chunk.embedding_model.get_or_insert_with(|| manifest.embedding_model.clone());
+        }
+        documents.push(document);
+    }
+
+    // Searching vectors from another model fails outright, so such an archive is refused before anything
+    // is written. The multilingual model counts too, since routed documents are embedded with it.
+    let accepted: Vec<String> = std::iter::once(&target.embedding_model)
+        .chain(target.multilingual_embedding_model.as_ref())
+        .map(embedding_model_key)
+        .collect();
+    let archived_models = documents.iter().flat_map(|d| &d.chunks).filter_map(|c| c.embedding_model.clone());
+    let local_models: Vec<String> = if incoming_config.is_some() {
+        let mut stmt = db
+            .prepare("SELECT DISTINCT embedding_model FROM document_chunks WHERE embedding_model IS NOT NULL")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
+        rows.filter_map(Result::ok).collect()
+    } else {
+        Vec::new()
+    };
+    if let Some(foreign) = archived_models.chain(local_models).find(|model| !accepted.contains(model)) {
+        return Err(format!(
+            "{} cannot be imported: chunks embedded with {} would share an index with {}; \
+             switch the embedding model to match, or re-embed one side first",
+            path,
+            foreign,
+            accepted.join(" / ")
+        ));
+    }
+
+    let mut report = ArchiveImportReport::default();
+    if let Some(incoming) = incoming_config {
+        *config_state.lock().map_err(|e| e.to_string())? = incoming;
+        report.config_applied = true;
+    }
+
+    for mut incoming in documents {
+        match local_document_fingerprint(&db, &incoming.document.id) {
+            None => report.documents_added += 1,
+            Some(fingerprint) if fingerprint == document_fingerprint(&incoming.document) => {
+                report.documents_skipped += 1;
+                continue;
+            }
+            Some(_) => match on_conflict {
+                ConflictStrategy::KeepMine => {
+                    report.documents_skipped += 1;
+                    continue;
+                }
+                ConflictStrategy::KeepTheirs => report.documents_replaced += 1,
+                ConflictStrategy::KeepBoth => {
+                    reassign_archived_ids(&mut incoming);
+                    incoming.document.title = format!("{} (conflicted copy)", incoming.document.title);
+                    report.documents_copied += 1;
+                }
+            },
+        }
+        apply_archived_document(&mut db, &incoming).map_err(|e| e.to_string())?;
+    }
+
+    let sessions: Vec<ArchivedChatSession> = match read_zip_entry(&mut archive, ARCHIVE_SESSIONS_ENTRY) {
+        Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    for session in &sessions {
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|e| e.to_string())?;
+        let added = tx
+            .execute(
+                "INSERT OR IGNORE INTO chat_sessions (id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![session.id, session.title, session.created_at.to_rfc3339(), session.updated_at.to_rfc3339()],
+            )
+            .map_err(|e| e.to_string())?;
+        if added == 0 {
+            continue;
+        }
+        for message in &session.messages {
+            tx.execute(
+                "INSERT OR IGNORE INTO chat_messages (id, content, role, document_references, session_id, reply_to, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    message.id,
+                    message.content,
+                    message.role,
+                    serde_json::to_string(&message.document_references).map_err(|e| e.to_string())?,
+                    session.id,
+                    message.reply_to,
+                    message.created_at.to_rfc3339(),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        report.chat_sessions_added += 1;
+    }
+
+    // The graph describes the chunks from before the import
+    *hnsw_state.lock().map_err(|e| e.to_string())? = None;
+    record_audit(&db, "knowledge_base_imported", None, serde_json::json!({
+        "path": path,
+        "documents_added": report.documents_added,
+        "documents_replaced": report.documents_replaced,
+        "documents_copied": report.documents_copied,
+        "chat_sessions_added": report.chat_sessions_added,
+    }));
+    let _ = app.emit("knowledge_base_imported", &report);
+    Ok(report)
+}
+
+// ---------- Re-embedding ----------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReembedProgress {
+    pub document_id: String,
+    pub processed_documents: usize,
+    pub total_documents: usize,
+    pub chunks_embedded: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReembedReport {
+    pub embedding_model: String,
+    pub documents_reembedded: usize,
+    pub chunks_embedded: usize,
+    pub skipped_locked: Vec<String>, // sensitive documents that need an unlocked session
+    pub failed: Vec<DocumentFailure>,
+    pub processing_time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentFailure {
+    pub document_id: String,
+    pub error: String,
+}
+
+// Replaces every vector of one document, and its Q/A keys, with ones from config.embedding_model
+async fn reembed_document(
+    document_id: &str,
+    is_sensitive: bool,
+    content_key: Option<&ContentKey>,
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<usize> {
+    let (chunks, questions): (Vec<(String, String)>, Vec<(String, String)>) = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut stmt = db.prepare("SELECT id, content FROM document_chunks WHERE document_id = ?1 ORDER BY chunk_index")?;
+        let chunks = stmt
+            .query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(Result::ok)
+            .collect();
+        let mut stmt = db.prepare("SELECT id, question FROM qa_keys WHERE document_id = ?1")?;
+        let questions = stmt
+            .query_map(params![document_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(Result::ok)
+            .collect();
+        (chunks, questions)
+    };
+
+    // Vectors are always computed from plaintext, even when the stored chunk is encrypted
+    let plaintexts: Vec<String> = chunks
+        .iter()
+        .map(|(_, content)| match (is_sensitive, content_key) {
+            (false, _) => Ok(content.clone()),
+            (true, Some(key)) => decrypt_text(key, content),
+            (true, None) => Err(anyhow::anyhow!("Document {} is locked", document_id)),
+        })
+        .collect::<Result<_>>()?;
+    let embeddings = providers.embed_batch(&plaintexts, config).await?;
+    let question_texts: Vec<String> = questions.iter().map(|(_, q)| q.clone()).collect();
+    let question_embeddings = providers.embed_batch(&question_texts, config).await?;
+
+    let model_key = embedding_model_key(&config.embedding_model);
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    for (((chunk_id, _), plaintext), embedding) in chunks.iter().zip(&plaintexts).zip(&embeddings) {
+        let embedding_key = shared_embedding_key(&config.embedding_model, plaintext);
+        let embedding_bytes = encode_embedding(embedding, config.embedding_storage);
+        tx.execute(
+            "INSERT OR IGNORE INTO shared_embeddings (key, embedding, created_at) VALUES (?1, ?2, ?3)",
+            params![embedding_key, embedding_bytes, Utc::now().to_rfc3339()],
+        )?;
+        tx.execute(
+            "UPDATE document_chunks SET embedding = ?1, embedding_key = ?2, embedding_model = ?3, embedding_dim = ?4
+             WHERE id = ?5",
+            params![&[][..] as &[u8], embedding_key, model_key, embedding.len() as i64, chunk_id],
+        )?;
+    }
+    for ((question_id, _), embedding) in questions.iter().zip(&question_embeddings) {
+        let embedding_bytes = encode_embedding(embedding, config.embedding_storage);
+        tx.execute("UPDATE qa_keys SET embedding = ?1 WHERE id = ?2", params![embedding_bytes, question_id])?;
+    }
+    tx.commit()?;
+
+    Ok(chunks.len())
+}
+
+// Switching models leaves old vectors in a different space; this moves the whole knowledge base over
+#[tauri::command]
+async fn reembed_all_documents(
+    mut config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    key_state: tauri::State<'_, Arc<Mutex<Option<ContentKey>>>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<ReembedReport, String> {
+    let start_time = std::time::Instant::now();
+    let content_key = key_state.lock().map_err(|e| e.to_string())?.clone();
+    let documents: Vec<(String, bool)> = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        if let Some(multilingual) = multilingual_route(&db, &config).map_err(|e| e.to_string())? {
+            config.embedding_model = multilingual;
+        }
+        let mut stmt = db
+            .prepare("SELECT id, is_sensitive FROM documents ORDER BY created_at")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut report = ReembedReport {
+        embedding_model: embedding_model_key(&config.embedding_model),
+        ..Default::default()
+    };
+    for (index, (document_id, is_sensitive)) in documents.iter().enumerate() {
+        if *is_sensitive && content_key.is_none() {
+            report.skipped_locked.push(document_id.clone());
+            continue;
+        }
+
+        match reembed_document(document_id, *is_sensitive, content_key.as_ref(), db_state.inner(), &config, providers_state.inner()).await {
+            Ok(chunks_embedded) => {
+                report.documents_reembedded += 1;
+                report.chunks_embedded += chunks_embedded;
+                let _ = app.emit(
+                    "reembed_progress",
+                    &ReembedProgress {
+                        document_id: document_id.clone(),
+                        processed_documents: index + 1,
+                        total_documents: documents.len(),
+                        chunks_embedded,
+                    },
+                );
+            }
+            Err(e) => {
+                warn!("Re-embedding {} failed: {}", document_id, e);
+                report.failed.push(DocumentFailure { document_id: document_id.clone(), error: e.to_string() });
+            }
+        }
+    }
+
+    {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        // Vectors from the previous model are no longer referenced by any chunk
+        db.execute(
+            "DELETE FROM shared_embeddings WHERE key NOT IN
+             (SELECT embedding_key FROM document_chunks WHERE embedding_key IS NOT NULL)",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        let mut store = store_state.lock().map_err(|e| e.to_string())?;
+        reset_embedding_store(&db, &mut store).map_err(|e| e.to_string())?;
+        reset_vec_index(&db).map_err(|e| e.to_string())?;
+        record_audit(
+            &db,
+            "documents_reembedded",
+            None,
+            serde_json::json!({
+                "embedding_model": report.embedding_model,
+                "documents": report.documents_reembedded,
+                "chunks": report.chunks_embedded,
+            }),
+        );
+    }
+
+    report.processing_time_ms = start_time.elapsed().as_millis() as u64;
+    info!(
+        "Re-embedded {} chunk(s) across {} document(s) with {}",
+        report.chunks_embedded, report.documents_reembedded, report.embedding_model
+    );
+    Ok(report)
+}
+
+// ---------- Personalization -------------------------------------------------------
+
+const PERSONALIZATION_HALF_LIFE_DAYS: f64 = 30.0;
+const PERSONALIZATION_MAX_BOOST: f32 = 0.15; // share of the result set's score spread
+const AFFINITY_OPEN_WEIGHT: f64 = 1.0;
+const AFFINITY_CITATION_WEIGHT: f64 = 2.0;
+
+fn decayed_affinity(score: f64, updated_at: &str, now: DateTime<Utc>) -> f64 {
+    let age_days = DateTime::parse_from_rfc3339(updated_at)
+        .map(|t| (now - t.with_timezone(&Utc)).num_seconds().max(0) as f64 / 86_400.0)
+        .unwrap_or(0.0);
+    score * 0.5f64.powf(age_days / PERSONALIZATION_HALF_LIFE_DAYS)
+}
+
+// Saturates, so a long history nudges ranking but never outweighs similarity
+fn affinity_boost(score: f64) -> f32 {
+    PERSONALIZATION_MAX_BOOST * (1.0 - (-score / 3.0).exp()) as f32
+}
+
+fn bump_document_affinity(conn: &Connection, profile: &str, document_id: &str, weight: f64) -> Result<()> {
+    let now = Utc::now();
+    let current: Option<(f64, String)> = conn
+        .query_row(
+            "SELECT score, updated_at FROM document_affinity WHERE profile = ?1 AND document_id = ?2",
+            params![profile, document_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok();
+    let score = current.map_or(0.0, |(score, updated_at)| decayed_affinity(score, &updated_at, now)) + weight;
+
+    conn.execute(
+        "INSERT INTO document_affinity (profile, document_id, score, updated_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(profile, document_id) DO UPDATE SET score = excluded.score, updated_at = excluded.updated_at",
+        params![profile, document_id, score, now.to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+fn record_citation_affinity(conn: &Connection, profile: &str, chunk_ids: &[String]) -> Result<()> {
+    if is_read_only(conn) {
+        return Ok(());
+    }
+    let mut document_ids = std::collections::BTreeSet::new();
+    for chunk_id in chunk_ids {
+        if let Ok(document_id) = conn.query_row(
+            "SELECT document_id FROM document_chunks WHERE id = ?1",
+            params![chunk_id],
+            |row| row.get::<_, String>(0),
+        ) {
+            document_ids.insert(document_id);
+        }
+    }
+    // One answer citing several chunks of a document counts once for it
+    for document_id in document_ids {
+        bump_document_affinity(conn, profile, &document_id, AFFINITY_CITATION_WEIGHT)?;
+    }
+    Ok(())
+}
+
+// The boost is added as a share of the score spread, so it lifts a document the same way whatever the metric's
+// scale or sign; multiplying would push negative dot-product or euclidean scores further down instead
+fn apply_personalization(conn: &Connection, results: &mut [RetrievalResult], profile: &str) -> Result<()> {
+    let now = Utc::now();
+    let (min, max) = results.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), r| {
+        (min.min(r.similarity_score), max.max(r.similarity_score))
+    });
+    let spread = if max > min { max - min } else { 1.0 };
+    let mut stmt = conn.prepare(
+        "SELECT a.score, a.updated_at FROM document_chunks dc
+         JOIN document_affinity a ON a.document_id = dc.document_id
+         WHERE dc.id = ?1 AND a.profile = ?2",
+    )?;
+    for result in results.iter_mut() {
+        let affinity: Option<(f64, String)> = stmt
+            .query_row(params![result.chunk_id, profile], |row| Ok((row.get(0)?, row.get(1)?)))
+            .ok();
+        if let Some((score, updated_at)) = affinity {
+            result.similarity_score += spread * affinity_boost(decayed_affinity(score, &updated_at, now));
+        }
+    }
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    Ok(())
+}
+
+#[tauri::command]
+fn record_document_open(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<(), String> {
+    let Some(profile) = config_state.lock().map_err(|e| e.to_string())?.personalization_profile.clone() else {
+        return Ok(());
+    };
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    if is_read_only(&db) {
+        return Ok(());
+    }
+    bump_document_affinity(&db, &profile, &document_id, AFFINITY_OPEN_WEIGHT).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reset_personalization(
+    profile: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<usize, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    db.execute("DELETE FROM document_affinity WHERE profile = ?1", params![profile])
+        .map_err(|e| e.to_string())
+}
+
+// ---------- Structured Output -----------------------------------------------------
+
+const STRUCTURED_OUTPUT_MAX_ATTEMPTS: usize = 3;
+
+// Compiling doubles as schema validation; an Err means the schema itself is unusable
+fn schema_violations(schema: &serde_json::Value, instance: &serde_json::Value) -> Result<Vec<String>> {
+    let compiled = jsonschema::JSONSchema::compile(schema)
+        .map_err(|e| anyhow::anyhow!("Invalid response schema: {}", e))?;
+    let violations = match compiled.validate(instance) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() { e.to_string() } else { format!("{}: {}", path, e) }
+            })
+            .collect(),
+    };
+    Ok(violations)
+}
+
+// Models like to wrap JSON in prose or code fences; take the outermost object or array
+fn extract_json_payload(reply: &str) -> Option<serde_json::Value> {
+    let trimmed = reply.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+    let start = trimmed.find(['{', '['])?;
+    let end = trimmed.rfind(['}', ']'])?;
+    (end > start).then(|| serde_json::from_str(&trimmed[start..=end]).ok()).flatten()
+}
+
+// Asks for JSON matching the schema and feeds validation errors back until it conforms
+async fn generate_structured_answer(
+    target: &GenerationTarget,
+    query: &str,
+    context: &[RetrievalResult],
+    schema: &serde_json::Value,
+    answer_style: AnswerStyle,
+) -> Result<serde_json::Value> {
+    let context_text = context
+        .iter()
+        .enumerate()
+        .map(|(i, r)| context_entry(i, r))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let base_prompt = format!(
+        "Answer the question using the context below. {}\n\
+         Respond with a single JSON value that conforms to this JSON schema, and nothing else:\n{}\n\n\
+         Context:\n{}\n\nQuestion: {}",
+        answer_style.instructions(),
+        serde_json::to_string_pretty(schema)?,
+        context_text,
+        query
+    );
+
+    // Providers that support constrained decoding can use the schema directly
+    let mut options = answer_style.generation_params();
+    options["temperature"] = serde_json::json!(0.0);
+    options["response_format"] = serde_json::json!({ "type": "json_schema", "schema": schema });
+
+    let mut prompt = base_prompt.clone();
+    let mut last_problem = String::new();
+    for attempt in 1..=STRUCTURED_OUTPUT_MAX_ATTEMPTS {
+        let reply = generate_text(target, &prompt, &options).await?;
+        last_problem = match extract_json_payload(&reply) {
+            Some(value) => {
+                let violations = schema_violations(schema, &value)?;
+                if violations.is_empty() {
+                    return Ok(value);
+                }
+                violations.join("; ")
+            }
+            None => "the reply was not valid JSON".to_string(),
+        };
+        debug!("Structured output attempt {} rejected: {}", attempt, last_problem);
+        prompt = format!(
+            "{}\n\nYour previous reply was rejected because {}. Previous reply:\n{}\n\nReply again with corrected JSON only.",
+            base_prompt, last_problem, reply
+        );
+    }
+
+    Err(anyhow::anyhow!(
+        "Model output did not match the response schema after {} attempts: {}",
+        STRUCTURED_OUTPUT_MAX_ATTEMPTS,
+        last_problem
+    ))
+}
+
+// ---------- Prompt Templates ------------------------------------------------------
+
+// Placeholders a template may use; anything else in braces is left as written
+const PROMPT_VARIABLES: &[&str] = &["context", "question", "history", "instructions", "sources"];
+
+const DEFAULT_PROMPT_TEMPLATE: &str =
+    "Answer the question using the context below. Cite each passage you use by its number in square brackets, like [1]. \
+     {instructions}\n\nContext:\n{context}\n\n{history}Question: {question}";
+const FINE_TUNED_MOCK_TEMPLATE: &str =
+    "Fine-tuned model response to: {question}\n\n[This would be the output from your fine-tuned model]";
+const FINE_TUNED_NO_CONTEXT_MOCK_TEMPLATE: &str = "Fine-tuned model response (no relevant context found): {question}";
+const FINE_TUNED_RAG_MOCK_TEMPLATE: &str = "Fine-tuned model response based on context:\n\nQuery: {question}\n\nRelevant context:\n{context}\n\n[This would be the enhanced fine-tuned model response using the retrieved context]";
+const BASE_NO_CONTEXT_MOCK_TEMPLATE: &str = "I don't have relevant information to answer: {question}\n\nPlease upload relevant documents to help me provide a better response.";
+const BASE_RAG_MOCK_TEMPLATE: &str = "Based on the documents in your knowledge base:\n\nQuery: {question}\n\nAnswer: Based on the retrieved information, here's what I found:\n\n{context}\n\nSources: {sources}";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub system_prompt: Option<String>, // sent as the system message ahead of the rendered template
+    pub template: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Single pass, so braces inside substituted context are never expanded
+fn render_prompt_template(template: &str, variables: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            variables.iter().find(|(name, _)| *name == &after[..close]).map(|(_, value)| (close, *value))
+        });
+        match value {
+            Some((close, value)) => {
+                rendered.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// Brace-wrapped identifiers only, so JSON examples in a template are not mistaken for variables
+fn template_variables(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .collect()
+}
+
+fn prompt_messages(template: Option<&PromptTemplate>, variables: &[(&str, &str)]) -> Vec<LLMMessage> {
+    let mut messages = Vec::with_capacity(2);
+    if let Some(system) = template.and_then(|t| t.system_prompt.as_deref()).filter(|s| !s.trim().is_empty()) {
+        messages.push(LLMMessage::new("system", render_prompt_template(system, variables)));
+    }
+    let user = template.map_or(DEFAULT_PROMPT_TEMPLATE, |t| t.template.as_str());
+    messages.push(LLMMessage::new("user", render_prompt_template(user, variables)));
+    messages
+}
+
+fn default_history_turns() -> usize {
+    6
+}
+
+// The most recent messages only, so long conversations do not crowd out the context
+fn history_window(history: &[LLMMessage], turns: usize) -> &[LLMMessage] {
+    &history[history.len().saturating_sub(turns)..]
+}
+
+// Rendered into {history}; empty when there is no conversation yet
+fn format_history(history: &[LLMMessage]) -> String {
+    if history.is_empty() {
+        return String::new();
+    }
+    let turns: Vec<String> = history
+        .iter()
+        .filter(|m| m.role != "system")
+        .map(|m| format!("{}: {}", if m.role == "assistant" { "Assistant" } else { "User" }, m.content.trim()))
+        .collect();
+    format!("Conversation so far:\n{}\n\n", turns.join("\n"))
+}
+
+// Follow-ups like "what about the second one?" retrieve nothing on their own.
+// Without a model the previous question is prepended so retrieval still sees the topic.
+async fn condense_query(query: &str, history: &[LLMMessage], target: Option<&GenerationTarget>) -> String {
+    let Some(target) = target else {
+        return match history.iter().rev().find(|m| m.role == "user") {
+            Some(previous) => format!("{} {}", previous.content.trim(), query),
+            None => query.to_string(),
+        };
+    };
+    let prompt = format!(
+        "{}Rewrite the follow-up question below as a single standalone question that can be understood without the conversation. \
+         Reply with the question only.\n\nFollow-up question: {}",
+        format_history(history),
+        query
+    );
+    match generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.0, "max_tokens": 200 })).await {
+        Ok(rewritten) if !rewritten.trim().is_empty() => rewritten.trim().trim_matches('"').to_string(),
+        Ok(_) => query.to_string(),
+        Err(e) => {
+            warn!("Failed to condense follow-up question, retrieving with it as asked: {}", e);
+            query.to_string()
+        }
+    }
+}
+
+// Spelling fixes, resolved references and a few synonyms help both the embedding and keyword matches
+async fn rewrite_query(query: &str, history: &[LLMMessage], target: &GenerationTarget) -> String {
+    let prompt = format!(
+        "{}Rewrite the search query below so it finds the right passages in a document collection: \
+         fix spelling mistakes, replace pronouns and references to the conversation with what they refer to, \
+         and append a few closely related keywords or synonyms. Reply with the rewritten query only, on one line.\n\n\
+         Query: {}",
+        format_history(history),
+        query
+    );
+    match generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.0, "max_tokens": 100 })).await {
+        Ok(rewritten) => match rewritten.lines().map(str::trim).find(|line| !line.is_empty()) {
+            Some(line) => line.trim_matches('"').to_string(),
+            None => query.to_string(),
+        },
+        Err(e) => {
+            warn!("Failed to rewrite query, retrieving with it as asked: {}", e);
+            query.to_string()
+        }
+    }
+}
+
+const MAX_QUERY_VARIATIONS: usize = 5;
+
+// Rephrasings reach passages worded differently from the question; none at all if the model fails
+async fn generate_query_variations(query: &str, count: usize, target: &GenerationTarget) -> Vec<String> {
+    let count = count.min(MAX_QUERY_VARIATIONS);
+    let prompt = format!(
+        "Write {} different rephrasings of the search query below that ask for the same information in other words. \
+         Reply with one rephrasing per line and nothing else.\n\nQuery: {}",
+        count, query
+    );
+    let reply = match generate_text(target, &prompt, &serde_json::json!({ "temperature": 0.7, "max_tokens": 300 })).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            warn!("Failed to generate query variations, retrieving with the query alone: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut variations: Vec<String> = Vec::with_capacity(count);
+    for line in reply.lines() {
+        // Models number or bullet their lists despite being asked not to
+        let variation = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*' | ' '))
+            .trim_matches('"')
+            .trim();
+        if !variation.is_empty() && !variation.eq_ignore_ascii_case(query) && !variations.iter().any(|v| v == variation) {
+            variations.push(variation.to_string());
+        }
+    }
+    variations.truncate(count);
+    variations
+}
+
+fn prompt_template_from_row(row: &rusqlite::Row) -> rusqlite::Result<PromptTemplate> {
+    let timestamp = |value: String| DateTime::parse_from_rfc3339(&value).map(|t| t.with_timezone(&Utc)).unwrap_or_default();
+    Ok(PromptTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        system_prompt: row.get(2)?,
+        template: row.get(3)?,
+        created_at: timestamp(row.get(4)?),
+        updated_at: timestamp(row.get(5)?),
+    })
+}
+
+fn load_prompt_template(conn: &Connection, template_id: &str) -> Result<PromptTemplate> {
+    conn.query_row(
+        "SELECT id, name, system_prompt, template, created_at, updated_at FROM prompt_templates WHERE id = ?1",
+        params![template_id],
+        prompt_template_from_row,
+    )
+    .map_err(|_| anyhow::anyhow!("Prompt template not found: {}", template_id))
+}
+
+// Creates a template, or replaces the one with the given id
+#[tauri::command]
+fn save_prompt_template(
+    id: Option<String>,
+    name: String,
+    template: String,
+    system_prompt: Option<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<PromptTemplate, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("A prompt template needs a name".to_string());
+    }
+    if !template_variables(&template).contains(&"question") {
+        return Err("A prompt template must include {question}".to_string());
+    }
+    let unknown: Vec<&str> = template_variables(&template)
+        .into_iter()
+        .chain(system_prompt.as_deref().map(template_variables).unwrap_or_default())
+        .filter(|name| !PROMPT_VARIABLES.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "Unknown template variables: {} (available: {})",
+            unknown.join(", "),
+            PROMPT_VARIABLES.join(", ")
+        ));
+    }
+
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let now = Utc::now();
+    let created_at = match &id {
+        Some(id) => load_prompt_template(&db, id).map_err(|e| e.to_string())?.created_at,
+        None => now,
+    };
+    let prompt_template = PromptTemplate {
+        id: id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+        name,
+        system_prompt: system_prompt.filter(|s| !s.trim().is_empty()),
+        template,
+        created_at,
+        updated_at: now,
+    };
+    db.execute(
+        "INSERT OR REPLACE INTO prompt_templates (id, name, system_prompt, template, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            prompt_template.id,
+            prompt_template.name,
+            prompt_template.system_prompt,
+            prompt_template.template,
+            prompt_template.created_at.to_rfc3339(),
+            prompt_template.updated_at.to_rfc3339(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(prompt_template)
+}
+
+#[tauri::command]
+fn delete_prompt_template(
+    template_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute("DELETE FROM prompt_templates WHERE id = ?1", params![template_id])
+        .map_err(|e| e.to_string())?;
+
+    if removed == 0 {
+        return Err(format!("Prompt template not found: {}", template_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_prompt_templates(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<PromptTemplate>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare("SELECT id, name, system_prompt, template, created_at, updated_at FROM prompt_templates ORDER BY name ASC")
+        .map_err(|e| e.to_string())?;
+    let templates = stmt
+        .query_map([], prompt_template_from_row)
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(templates)
+}
+
+// ---------- Context Budget --------------------------------------------------------
+
+const DEFAULT_CONTEXT_WINDOW: usize = 4096;
+// A truncated passage shorter than this is more noise than evidence
+const MIN_TRUNCATED_PASSAGE_TOKENS: usize = 32;
+
+static BPE_CACHE: OnceLock<Mutex<HashMap<String, Arc<CoreBPE>>>> = OnceLock::new();
+
+// Counts tokens the way the generation model will, or estimates when its tokenizer is unknown
+enum TokenCounter {
+    Bpe(Arc<CoreBPE>),
+    Estimate,
+}
+
+impl TokenCounter {
+    fn for_target(target: &GenerationTarget) -> Self {
+        match target {
+            GenerationTarget::Llm(LLMProvider::OpenAI { model, .. }) => {
+                load_bpe(model).map(TokenCounter::Bpe).unwrap_or(TokenCounter::Estimate)
+            }
+            _ => TokenCounter::Estimate,
+        }
+    }
+
+    fn count(&self, text: &str) -> usize {
+        match self {
+            TokenCounter::Bpe(bpe) => bpe.encode_with_special_tokens(text).len(),
+            TokenCounter::Estimate => text.chars().count().div_ceil(4),
+        }
+    }
+}
+
+// Unknown model names (e.g. on OpenAI-compatible servers) fall back to the GPT-4 encoding
+fn load_bpe(model: &str) -> Result<Arc<CoreBPE>> {
+    let cache = BPE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(bpe) = cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.get(model) {
+        return Ok(bpe.clone());
+    }
+    let bpe = Arc::new(tiktoken_rs::get_bpe_from_model(model).or_else(|_| tiktoken_rs::cl100k_base())?);
+    cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.insert(model.to_string(), bpe.clone());
+    Ok(bpe)
+}
+
+fn default_context_window(target: &GenerationTarget) -> usize {
+    match target {
+        GenerationTarget::Llm(LLMProvider::OpenAI { model, base_url: None, .. }) => tiktoken_rs::model::get_context_size(model),
+        GenerationTarget::Llm(LLMProvider::Anthropic { .. }) => 200_000,
+        GenerationTarget::Llm(LLMProvider::Gemini { .. }) => 1_000_000,
+        GenerationTarget::Llm(LLMProvider::Mistral { .. }) => 32_000,
+        // Ollama's default num_ctx, whatever the model itself supports
+        GenerationTarget::Llm(LLMProvider::Ollama { .. }) => 2048,
+        _ => DEFAULT_CONTEXT_WINDOW,
+    }
+}
+
+fn context_entry(index: usize, result: &RetrievalResult) -> String {
+    format!("[{}] From {}: {}", index + 1, result.document_title, result.content)
+}
+
+// Cuts at a sentence boundary, or a word boundary when even the first sentence is too long
+fn truncate_to_tokens(text: &str, max_tokens: usize, counter: &TokenCounter) -> String {
+    let mut kept = String::new();
+    for sentence in text.split_sentence_bounds() {
+        if counter.count(&format!("{}{}", kept, sentence)) > max_tokens {
+            break;
+        }
+        kept.push_str(sentence);
+    }
+    if kept.trim().is_empty() {
+        for word in text.split_word_bounds() {
+            if counter.count(&format!("{}{}", kept, word)) > max_tokens {
+                break;
+            }
+            kept.push_str(word);
+        }
+    }
+    format!("{}…", kept.trim_end())
+}
+
+// Best passages first until the window is full; the first passage that does not fit is truncated
+fn pack_context(
+    results: Vec<RetrievalResult>,
+    target: &GenerationTarget,
+    config: &RAGConfig,
+    template: Option<&PromptTemplate>,
+    query: &str,
+    history: &str,
+    answer_style: AnswerStyle,
+) -> Vec<RetrievalResult> {
+    let counter = TokenCounter::for_target(target);
+    let window = config.context_window.unwrap_or_else(|| default_context_window(target));
+    let reserved = generation_options(config, answer_style)["max_tokens"].as_u64().unwrap_or(0) as usize;
+    let prompt_overhead: usize = prompt_messages(
+        template,
+        &[("context", ""), ("question", query), ("history", history), ("instructions", answer_style.instructions())],
+    )
+    .iter()
+    .map(|message| counter.count(&message.content))
+    .sum();
+    let mut remaining = window.saturating_sub(reserved + prompt_overhead);
+
+    let mut results = results;
+    results.sort_by(|a, b| b.similarity_score.total_cmp(&a.similarity_score));
+    let mut packed = Vec::with_capacity(results.len());
+    for mut result in results {
+        // Entries are joined by a blank line, about two tokens
+        let cost = counter.count(&context_entry(packed.len(), &result)) + 2;
+        if cost <= remaining {
+            remaining -= cost;
+            packed.push(result);
+            continue;
+        }
+        let header = counter.count(&context_entry(packed.len(), &RetrievalResult { content: String::new(), ..result.clone() })) + 2;
+        let available = remaining.saturating_sub(header);
+        if available >= MIN_TRUNCATED_PASSAGE_TOKENS {
+            result.content = truncate_to_tokens(&result.content, available, &counter);
+            packed.push(result);
+        }
+        break;
+    }
+    if packed.is_empty() {
+        warn!("Context window of {} tokens leaves no room for retrieved passages", window);
+    }
+    packed
+}
+
+// ---------- Answer Grounding ------------------------------------------------------
+
+// Fragments shorter than this ("See [2].") say nothing that could be unsupported
+const MIN_GROUNDED_SENTENCE_WORDS: usize = 4;
+
+fn default_grounding_threshold() -> f32 {
+    0.6
+}
+
+// Each sentence is embedded and compared with every passage; a sentence is supported when its
+// best match reaches the threshold. Returns the supported share and the sentences that were not.
+async fn check_grounding(
+    answer: &str,
+    context: &[RetrievalResult],
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<Option<(f32, Vec<String>)>> {
+    let sentences: Vec<&str> = answer
+        .unicode_sentences()
+        .map(str::trim)
+        .filter(|sentence| sentence.unicode_words().count() >= MIN_GROUNDED_SENTENCE_WORDS)
+        .collect();
+    if sentences.is_empty() {
+        return Ok(None);
+    }
+
+    // Citation markers carry no meaning for the embedding
+    let mut texts: Vec<String> = sentences
+        .iter()
+        .map(|sentence| {
+            let mut text = (*sentence).to_string();
+            for citation in extract_citations(sentence, context) {
+                text = text.replace(&format!("[{}]", citation.marker), "");
+            }
+            text
+        })
+        .collect();
+    texts.extend(context.iter().map(|r| r.content.clone()));
+    let embeddings = providers.embed_batch(&texts, config).await?;
+    let (sentence_vectors, passage_vectors) = embeddings.split_at(sentences.len());
+
+    let unsupported: Vec<String> = sentences
+        .iter()
+        .zip(sentence_vectors)
+        .filter(|(_, sentence)| {
+            let best = passage_vectors
+                .iter()
+                .map(|passage| cosine_similarity(sentence, passage))
+                .fold(f32::MIN, f32::max);
+            best < config.grounding_threshold
+        })
+        .map(|(sentence, _)| sentence.to_string())
+        .collect();
+    let score = 1.0 - unsupported.len() as f32 / sentences.len() as f32;
+    Ok(Some((score, unsupported)))
+}
+
+// ---------- Document Tags ---------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagInfo {
+    pub name: String,
+    pub document_count: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+// SQL condition on `d` limiting rows to documents carrying any of the tags bound at ?{param};
+// the queries it joins number their own parameters, so the caller says which slot it takes
+fn tag_filter_clause(param: usize) -> String {
+    format!(
+        " AND (?{0} IS NULL OR d.id IN (SELECT dt.document_id FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+                                       WHERE t.name IN (SELECT value FROM json_each(?{0}))))",
+        param
+    )
+}
+
+// Value bound for tag_filter_clause: a JSON array of tag names, NULL when unfiltered
+fn tag_filter_value(tags: &[String]) -> Option<String> {
+    (!tags.is_empty()).then(|| serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string()))
+}
+
+// SQL condition on `dc` for a ChunkFilter; chunks without the metadata never match an active filter.
+// Values are inlined so the clause can follow any query's own numbered parameters.
+fn chunk_filter_clause(filter: &ChunkFilter) -> String {
+    let mut clause = String::new();
+    if let Some(sender) = filter.sender.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        clause.push_str(&format!(
+            " AND instr(lower(json_extract(dc.metadata, '$.from')), lower('{}')) > 0",
+            sender.replace('\'', "''")
+        ));
+    }
+    // Extractors store dates as UTC RFC 3339, so text comparison orders them correctly
+    if let Some(date_from) = filter.date_from {
+        clause.push_str(&format!(" AND json_extract(dc.metadata, '$.date') >= '{}'", date_from.to_rfc3339()));
+    }
+    if let Some(date_to) = filter.date_to {
+        clause.push_str(&format!(" AND json_extract(dc.metadata, '$.date') <= '{}'", date_to.to_rfc3339()));
+    }
+    clause
+}
+
+fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(anyhow::anyhow!("Tag must not be empty"));
+    }
+    Ok(tag.to_string())
+}
+
+// Creates the tag on first use; returns 1 when the document newly carries it
+fn attach_tag(conn: &Connection, document_id: &str, tag: &str) -> rusqlite::Result<usize> {
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (id, name, created_at) VALUES (?1, ?2, ?3)",
+        params![Uuid::new_v4().to_string(), tag, now],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO document_tags (document_id, tag_id, created_at)
+         SELECT ?1, id, ?3 FROM tags WHERE name = ?2",
+        params![document_id, tag, now],
+    )
+}
+
+#[tauri::command]
+fn tag_document(
+    document_id: String,
+    tag: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let tag = normalize_tag(&tag).map_err(|e| e.to_string())?;
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let exists: i64 = db
+        .query_row(
+            "SELECT COUNT(*) FROM documents WHERE id = ?1 AND deleted_at IS NULL",
+            params![document_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if exists == 0 {
+        return Err(format!("Document not found: {}", document_id));
+    }
+
+    if attach_tag(&db, &document_id, &tag).map_err(|e| e.to_string())? > 0 {
+        record_audit(&db, "settings_changed", Some(&document_id), serde_json::json!({ "tag_added": tag }));
+    }
+    // Tagging by hand keeps the tag when auto-tagging runs again
+    db.execute(
+        "UPDATE document_tags SET auto = 0 WHERE document_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        params![document_id, tag],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+fn untag_document(
+    document_id: String,
+    tag: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<(), String> {
+    let tag = normalize_tag(&tag).map_err(|e| e.to_string())?;
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let removed = db
+        .execute(
+            "DELETE FROM document_tags WHERE document_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            params![document_id, tag],
+        )
+        .map_err(|e| e.to_string())?;
+    if removed > 0 {
+        record_audit(&db, "settings_changed", Some(&document_id), serde_json::json!({ "tag_removed": tag }));
+    }
+    Ok(())
+}
+
+// Tags with no documents left are listed until removed, so a filter using them keeps working
+#[tauri::command]
+fn list_tags(db_state: tauri::State<'_, Arc<DbPool>>) -> Result<Vec<TagInfo>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT t.name, COUNT(d.id), t.created_at FROM tags t
+             LEFT JOIN document_tags dt ON dt.tag_id = t.id
+             LEFT JOIN documents d ON d.id = dt.document_id AND d.deleted_at IS NULL
+             GROUP BY t.id ORDER BY t.name COLLATE NOCASE",
+        )
+        .map_err(|e| e.to_string())?;
+    let tags = stmt
+        .query_map([], |row| {
+            Ok(TagInfo {
+                name: row.get(0)?,
+                document_count: row.get::<_, i64>(1)? as usize,
+                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(2)?)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(tags)
+}
+
+// ---------- Auto Tagging ----------------------------------------------------------
+
+const MAX_KEYWORD_WORDS: usize = 3; // longer runs are usually lists or headings, not key phrases
+const AUTO_TAG_SAMPLE_CHARS: usize = 8000; // text shown to the model when naming topics
+const MAX_AUTO_TAG_CHARS: usize = 40;
+
+const KEYWORD_STOPWORDS: &[&str] = &[
+    "about", "above", "after", "again", "against", "all", "also", "and", "any", "are", "because", "been", "before",
+    "being", "below", "between", "both", "but", "can", "could", "did", "does", "doing", "down", "during", "each",
+    "etc", "few", "for", "from", "further", "had", "has", "have", "having", "her", "here", "hers", "herself", "him",
+    "himself", "his", "how", "however", "into", "its", "itself", "just", "may", "might", "more", "most", "must",
+    "myself", "nor", "not", "now", "off", "once", "one", "only", "other", "our", "ours", "ourselves", "out", "over",
+    "own", "same", "she", "should", "some", "such", "than", "that", "the", "their", "theirs", "them", "themselves",
+    "then", "there", "these", "they", "this", "those", "through", "too", "two", "under", "until", "upon", "use",
+    "used", "using", "very", "was", "were", "what", "when", "where", "which", "while", "who", "whom", "why", "will",
+    "with", "would", "you", "your", "yours", "yourself", "yourselves",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicInfo {
+    pub name: String,
+    pub document_count: usize,
+    pub auto_count: usize, // documents that got the tag from auto-tagging rather than by hand
+    pub example_titles: Vec<String>,
+}
+
+// RAKE: candidate phrases are runs of content words, and each word scores its co-occurrence degree over its frequency.
+// Phrases are weighted by how often they recur so one-off wording in long documents doesn't crowd out real topics.
+fn extract_keywords(text: &str, count: usize) -> Vec<String> {
+    let mut phrases: Vec<Vec<String>> = Vec::new();
+    for fragment in text.split(|c: char| (c.is_ascii_punctuation() && c != '\'' && c != '-') || c == '\n') {
+        let mut current = Vec::new();
+        for word in fragment.unicode_words() {
+            let word = word.to_lowercase();
+            let is_content = word.chars().count() >= 3
+                && !word.chars().all(char::is_numeric)
+                && !KEYWORD_STOPWORDS.contains(&word.as_str());
+            if is_content {
+                current.push(word);
+            } else if !current.is_empty() {
+                phrases.push(std::mem::take(&mut current));
+            }
+        }
+        if !current.is_empty() {
+            phrases.push(current);
+        }
+    }
+    phrases.retain(|phrase| phrase.len() <= MAX_KEYWORD_WORDS);
+
+    let mut frequency: HashMap<&str, f32> = HashMap::new();
+    let mut degree: HashMap<&str, f32> = HashMap::new();
+    for phrase in &phrases {
+        for word in phrase {
+            *frequency.entry(word.as_str()).or_default() += 1.0;
+            *degree.entry(word.as_str()).or_default() += phrase.len() as f32;
+        }
+    }
+    let mut weights: HashMap<String, f32> = HashMap::new();
+    for phrase in &phrases {
+        let score: f32 = phrase.iter().map(|word| degree[word.as_str()] / frequency[word.as_str()]).sum();
+        *weights.entry(phrase.join(" ")).or_default() += score;
+    }
+
+    let mut ranked: Vec<(String, f32)> = weights.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+    ranked.into_iter().take(count).map(|(phrase, _)| phrase).collect()
+}
+
+// One tag per line; list markers, quotes and overlong lines are dropped
+fn parse_topic_tags(reply: &str, count: usize) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for line in reply.lines() {
+        let tag = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')' | '#'))
+            .trim()
+            .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | ','))
+            .trim()
+            .to_lowercase();
+        if tag.is_empty() || tag.chars().count() > MAX_AUTO_TAG_CHARS || tags.contains(&tag) {
+            continue;
+        }
+        tags.push(tag);
+        if tags.len() == count {
+            break;
+        }
+    }
+    tags
+}
+
+async fn extract_topic_tags(target: &GenerationTarget, text: &str, count: usize) -> Result<Vec<String>> {
+    let sample: String = text.chars().take(AUTO_TAG_SAMPLE_CHARS).collect();
+    let prompt = format!(
+        "List up to {} short topic tags (one to three words each) that describe the following text. \
+         Reply with one lowercase tag per line and nothing else.\n\nText:\n{}",
+        count, sample
+    );
+    let options = serde_json::json!({ "temperature": 0.0, "max_tokens": 120 });
+    Ok(parse_topic_tags(&generate_text(target, &prompt, &options).await?, count))
+}
+
+// Replaces the document's previous auto tags; tags the user attached by hand are left alone
+fn replace_auto_tags(conn: &Connection, document_id: &str, tags: &[String]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM document_tags WHERE document_id = ?1 AND auto = 1", params![document_id])?;
+    for tag in tags {
+        if attach_tag(conn, document_id, tag)? > 0 {
+            conn.execute(
+                "UPDATE document_tags SET auto = 1 WHERE document_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+                params![document_id, tag],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Tag names are stored in plain text, so sensitive documents are never auto-tagged
+async fn auto_tag_document_text(
+    document_id: &str,
+    content: &str,
+    db_state: &Arc<DbPool>,
+    config: &RAGConfig,
+) -> Result<Vec<String>> {
+    let is_sensitive: bool = {
+        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        db.query_row("SELECT is_sensitive FROM documents WHERE id = ?1", params![document_id], |row| row.get(0))?
+    };
+    if is_sensitive {
+        return Ok(Vec::new());
+    }
+
+    let count = config.auto_tag_count.max(1);
+    let tags = match (config.auto_tagging, generation_target(config)) {
+        (AutoTagging::Llm, Some(target)) => extract_topic_tags(&target, content, count).await?,
+        _ => extract_keywords(content, count),
+    };
+    let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    replace_auto_tags(&db, document_id, &tags)?;
+    Ok(tags)
+}
+
+// Runs auto-tagging now, using the configured method or keywords when auto-tagging is off
+#[tauri::command]
+async fn auto_tag_document(
+    document_id: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<Vec<String>, String> {
+    let mut config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    if config.auto_tagging == AutoTagging::Off {
+        config.auto_tagging = AutoTagging::Keywords;
+    }
+    let (content, is_sensitive): (String, bool) = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        ensure_writable(&db).map_err(|e| e.to_string())?;
+        db.query_row(
+            "SELECT content, is_sensitive FROM documents WHERE id = ?1 AND deleted_at IS NULL",
+            params![document_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|_| format!("Document not found: {}", document_id))?
+    };
+    if is_sensitive {
+        return Err("Sensitive documents are not auto-tagged".to_string());
+    }
+
+    let tags = auto_tag_document_text(&document_id, &content, db_state.inner(), &config)
+        .await
+        .map_err(|e| e.to_string())?;
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    record_audit(&db, "settings_changed", Some(&document_id), serde_json::json!({ "auto_tags": tags }));
+    Ok(tags)
+}
+
+// Tags in use, most common first, as an overview of what the knowledge base covers
+#[tauri::command]
+fn get_topic_overview(
+    limit: Option<usize>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<TopicInfo>, String> {
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    let mut stmt = db
+        .prepare(
+            "SELECT t.name, COUNT(d.id), COALESCE(SUM(dt.auto), 0) FROM tags t
+             JOIN document_tags dt ON dt.tag_id = t.id
+             JOIN documents d ON d.id = dt.document_id AND d.deleted_at IS NULL
+             GROUP BY t.id ORDER BY COUNT(d.id) DESC, t.name COLLATE NOCASE LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(String, i64, i64)> = stmt
+        .query_map(params![limit.unwrap_or(50) as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut titles_stmt = db
+        .prepare(
+            "SELECT d.title FROM documents d
+             JOIN document_tags dt ON dt.document_id = d.id
+             JOIN tags t ON t.id = dt.tag_id
+             WHERE t.name = ?1 AND d.deleted_at IS NULL
+             ORDER BY d.updated_at DESC LIMIT 3",
+        )
+        .map_err(|e| e.to_string())?;
+    let mut topics = Vec::with_capacity(rows.len());
+    for (name, document_count, auto_count) in rows {
+        let example_titles = titles_stmt
+            .query_map(params![name], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .filter_map(Result::ok)
+            .collect();
+        topics.push(TopicInfo {
+            name,
+            document_count: document_count as usize,
+            auto_count: auto_count as usize,
+            example_titles,
+        });
+    }
+    Ok(topics)
+}
+
+// ---------- Bulk Operations -------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkProgress {
+    pub job_id: String,
+    pub operation: String,
+    pub processed: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkResult {
+    pub job_id: String,
+    pub operation: String,
+    pub affected: usize,
+    pub failed: Vec<DocumentFailure>,
+    pub processing_time_ms: u64,
+}
+
+struct BulkJob<'a> {
+    app: &'a AppHandle,
+    result: BulkResult,
+    total: usize,
+    processed: usize,
+    start_time: std::time::Instant,
+}
+
+impl<'a> BulkJob<'a> {
+    fn new(app: &'a AppHandle, operation: &str, total: usize) -> Self {
+        Self {
+            app,
+            result: BulkResult {
+                job_id: Uuid::new_v4().to_string(),
+                operation: operation.to_string(),
+                affected: 0,
+                failed: Vec::new(),
+                processing_time_ms: 0,
+            },
+            total,
+            processed: 0,
+            start_time: std::time::Instant::now(),
+        }
+    }
+
+    fn record(&mut self, document_id: &str, outcome: Result<bool>) {
+        match outcome {
+            Ok(true) => self.result.affected += 1,
+            Ok(false) => self.result.failed.push(DocumentFailure {
+                document_id: document_id.to_string(),
+                error: "Document not found".to_string(),
+            }),
+            Err(e) => self.result.failed.push(DocumentFailure { document_id: document_id.to_string(), error: e.to_string() }),
+        }
+        self.processed += 1;
+        let _ = self.app.emit(
+            "bulk_progress",
+            &BulkProgress {
+                job_id: self.result.job_id.clone(),
+                operation: self.result.operation.clone(),
+                processed: self.processed,
+                total: self.total,
+            },
+        );
+    }
+
+    fn finish(mut self) -> BulkResult {
+        self.result.processing_time_ms = self.start_time.elapsed().as_millis() as u64;
+        info!(
+            "Bulk {} affected {} of {} document(s)",
+            self.result.operation, self.result.affected, self.total
+        );
+        self.result
+    }
+}
+
+// Applies one statement per document in a single transaction; any SQL error rolls the whole batch back.
+// Progress is only reported once the batch has committed, and the ids that changed are returned.
+fn run_bulk_update(
+    db_state: &Arc<DbPool>,
+    job: &mut BulkJob<'_>,
+    document_ids: &[String],
+    audit_action: &str,
+    details: serde_json::Value,
+    update: impl Fn(&rusqlite::Transaction, &str) -> rusqlite::Result<usize>,
+) -> Result<Vec<String>> {
+    let mut db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    ensure_writable(&db)?;
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut outcomes = Vec::with_capacity(document_ids.len());
+    for document_id in document_ids {
+        let changed = update(&tx, document_id)?;
+        if changed > 0 {
+            record_audit(&tx, audit_action, Some(document_id), details.clone());
+        }
+        outcomes.push((document_id, changed > 0));
+    }
+    tx.commit()?;
+
+    let mut changed = Vec::new();
+    for (document_id, affected) in outcomes {
+        job.record(document_id, Ok(affected));
+        if affected {
+            changed.push(document_id.clone());
+        }
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn delete_documents(
+    document_ids: Vec<String>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<BulkResult, String> {
+    let mut job = BulkJob::new(&app, "delete", document_ids.len());
+    let details = serde_json::json!({ "job_id": job.result.job_id });
+    let deleted = run_bulk_update(db_state.inner(), &mut job, &document_ids, "document_deleted", details, |tx, id| {
+        delete_document_rows(tx, id)
+    })
+    .map_err(|e| e.to_string())?;
+    for document_id in deleted {
+        dispatch_webhook_event(db_state.inner(), "document.deleted", serde_json::json!({ "document_id": document_id }));
+    }
+    Ok(job.finish())
+}
+
+#[tauri::command]
+fn tag_documents(
+    document_ids: Vec<String>,
+    tag: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<BulkResult, String> {
+    let tag = tag.trim().to_string();
+    if tag.is_empty() {
+        return Err("Tag must not be empty".to_string());
+    }
+
+    let mut job = BulkJob::new(&app, "tag", document_ids.len());
+    let details = serde_json::json!({ "job_id": job.result.job_id, "tag": tag });
+    run_bulk_update(db_state.inner(), &mut job, &document_ids, "settings_changed", details, |tx, id| {
+        let exists: usize = tx.query_row("SELECT COUNT(*) FROM documents WHERE id = ?1", params![id], |row| row.get(0))?;
+        if exists > 0 {
+            attach_tag(tx, id, &tag)?;
+        }
+        Ok(exists)
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(job.finish())
+}
+
+#[tauri::command]
+fn move_documents(
+    document_ids: Vec<String>,
+    collection: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    app: AppHandle,
+) -> Result<BulkResult, String> {
+    let mut job = BulkJob::new(&app, "move", document_ids.len());
+    let details = serde_json::json!({ "job_id": job.result.job_id, "collection": collection });
+    run_bulk_update(db_state.inner(), &mut job, &document_ids, "settings_changed", details, |tx, id| {
+        tx.execute(
+            "UPDATE documents SET metadata = json_set(COALESCE(metadata, '{}'), '$.collection', ?1), updated_at = ?2
+             WHERE id = ?3",
+            params![collection, Utc::now().to_rfc3339(), id],
+        )
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(job.finish())
+}
+
+// Re-chunks and re-embeds each document from its stored text; every document is swapped atomically on its own
+#[tauri::command]
+async fn reprocess_documents(
+    document_ids: Vec<String>,
+    config: RAGConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    app: AppHandle,
+) -> Result<BulkResult, String> {
+    ensure_writable(&*db_state.lock().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let mut job = BulkJob::new(&app, "reprocess", document_ids.len());
+
+    for document_id in &document_ids {
+        let stored: Option<(String, bool, Vec<TextSection>)> = {
+            let db = db_state.lock().map_err(|e| e.to_string())?;
+            db.query_row(
+                "SELECT content, is_sensitive FROM documents WHERE id = ?1",
+                params![document_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .ok()
+            .map(|(content, is_sensitive)| (content, is_sensitive, load_document_sections(&db, document_id)))
+        };
+
+        let outcome = match stored {
+            None => Ok(false),
+            // Re-chunking would write plaintext chunk text next to encrypted content
+            Some((_, true, _)) => Err(anyhow::anyhow!("Sensitive documents cannot be reprocessed")),
+            Some((content, false, sections)) => {
+                rechunk_document_incremental(
+                    document_id,
+                    &content,
+                    &sections,
+                    db_state.inner(),
+                    &config,
+                    providers_state.inner(),
+                    true,
+                )
+                .await
+                    .map(|_| true)
+            }
+        };
+        if matches!(outcome, Ok(true)) {
+            let _ = app.emit("document_processed", document_id);
+        }
+        job.record(document_id, outcome);
+    }
+
+    Ok(job.finish())
+}
+
+// ---------- Session Attachments ---------------------------------------------------
+
+// Files attached to one chat session. They live only in memory and are never written to the
+// database or the embedding cache, so closing the session (or the app) discards them completely.
+#[derive(Default)]
+pub struct SessionAttachments {
+    sessions: HashMap<String, Vec<SessionAttachment>>,
+}
+
+struct SessionAttachment {
+    id: String,
+    title: String,
+    file_path: String,
+    embedding_model: String,
+    chunks: Vec<(String, Vec<f32>)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAttachmentInfo {
+    pub id: String,
+    pub title: String,
+    pub file_path: String,
+    pub chunks: usize,
+}
+
+impl SessionAttachment {
+    fn info(&self) -> SessionAttachmentInfo {
+        SessionAttachmentInfo {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            file_path: self.file_path.clone(),
+            chunks: self.chunks.len(),
+        }
+    }
+}
+
+async fn search_session_attachments(
+    session_state: &Arc<Mutex<SessionAttachments>>,
+    session_id: &str,
+    query: &str,
+    config: &RAGConfig,
+    providers: &EmbeddingProviders,
+) -> Result<Vec<RetrievalResult>> {
+    let has_attachments = session_state
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?
+        .sessions
+        .get(session_id)
+        .map_or(false, |attachments| !attachments.is_empty());
+    if !has_attachments {
+        return Ok(Vec::new());
+    }
+
+    // Already embedded (and cached) by the knowledge base search for the same query
+    let query_embedding = providers.embed(query, config).await?;
+    let model_key = embedding_model_key(&config.embedding_model);
+    let sessions = session_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    let mut results = Vec::new();
+    for attachment in sessions.sessions.get(session_id).into_iter().flatten() {
+        if attachment.embedding_model != model_key {
+            warn!("Skipping session attachment {} embedded with {}", attachment.title, attachment.embedding_model);
+            continue;
+        }
+        for (index, (content, embedding)) in attachment.chunks.iter().enumerate() {
+            let similarity = compute_similarity(config.similarity_metric, &query_embedding, embedding);
+            if similarity > config.similarity_threshold {
+                results.push(RetrievalResult {
+                    chunk_id: format!("session:{}:{}", attachment.id, index),
+                    content: content.clone(),
+                    document_title: attachment.title.clone(),
+                    similarity_score: similarity,
+                    source_info: format!("Attached to this session: {}", attachment.file_path),
+                    merged_chunk_ids: Vec::new(),
+                    merged_sources: Vec::new(),
+                    vector_score: None,
+                    keyword_score: None,
+                    rerank_score: None,
+                });
+            }
+        }
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+async fn attach_session_file(
+    session_id: String,
+    file_path: String,
+    config: RAGConfig,
+    plugin_state: tauri::State<'_, Arc<Mutex<Vec<ExtractorPlugin>>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+) -> Result<SessionAttachmentInfo, String> {
+    let plugins = plugin_state.lock().map_err(|e| e.to_string())?.clone();
+    let path = PathBuf::from(&file_path);
+    let extracted = extract_document(&path, &plugins, &config.extraction).await.map_err(|e| e.to_string())?;
+
+    warm_tokenizer(&config).await;
+    let chunks = chunk_text_with_config(&extracted.text, &config);
+    // Bypasses the embedding cache so nothing derived from the file is persisted
+    let embeddings = providers_state
+        .embed_uncached(&chunks, &config)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let attachment = SessionAttachment {
+        id: Uuid::new_v4().to_string(),
+        title: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Attachment".to_string()),
+        file_path,
+        embedding_model: embedding_model_key(&config.embedding_model),
+        chunks: chunks.into_iter().zip(embeddings).collect(),
+    };
+    let info = attachment.info();
+    session_state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .sessions
+        .entry(session_id)
+        .or_default()
+        .push(attachment);
+    Ok(info)
+}
+
+#[tauri::command]
+fn list_session_files(
+    session_id: String,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+) -> Result<Vec<SessionAttachmentInfo>, String> {
+    let sessions = session_state.lock().map_err(|e| e.to_string())?;
+    Ok(sessions
+        .sessions
+        .get(&session_id)
+        .map(|attachments| attachments.iter().map(SessionAttachment::info).collect())
+        .unwrap_or_default())
+}
+
+#[tauri::command]
+fn close_session(
+    session_id: String,
+    session_state: tauri::State<'_, Arc<Mutex<SessionAttachments>>>,
+) -> Result<usize, String> {
+    let mut sessions = session_state.lock().map_err(|e| e.to_string())?;
+    Ok(sessions.sessions.remove(&session_id).map_or(0, |attachments| attachments.len()))
+}
+
+// ---------- Pipeline Manifest -----------------------------------------------------
+
+// Bumped whenever a manifest field changes meaning; older manifests still apply
+const PIPELINE_MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEmbedding {
+    pub model_key: String,
+    pub revision: Option<String>, // Hugging Face commit of the cached weights, None for hosted APIs
+    pub dimension: Option<usize>, // most common dimension among indexed chunks for this model
+    pub storage: EmbeddingStorage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestChunking {
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+    #[serde(default)]
+    pub parent_chunk_size: Option<usize>,
+    pub chunk_unit: ChunkUnit,
+    pub qa_extraction: QaExtraction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestPromptTemplate {
+    pub answer_style: AnswerStyle,
+    pub instructions: String,
+    pub generation_params: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRetrieval {
+    pub mode: RAGMode,
+    pub top_k: usize,
+    pub similarity_threshold: f32,
+    pub similarity_metric: SimilarityMetric,
+    pub vector_backend: VectorBackend,
+    pub hybrid_fusion: HybridFusion,
+    pub diversity_lambda: Option<f32>,
+    pub tags: Vec<String>,
+    pub personalization_profile: Option<String>,
+    pub generation_provider: Option<ProviderModel>,
+    #[serde(default)]
+    pub llm_provider: Option<LLMProvider>, // API key redacted
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineManifest {
+    pub manifest_version: u32,
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    pub embedding: ManifestEmbedding,
+    pub chunking: ManifestChunking,
+    pub prompt_templates: Vec<ManifestPromptTemplate>,
+    pub retrieval: ManifestRetrieval,
+    pub reranker: Option<serde_json::Value>, // reranker model and candidate pool, without keys
+    pub config: RAGConfig, // complete config with API keys removed; this is what apply uses
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestApplyReport {
+    pub config: RAGConfig,
+    pub reindex_required: bool,
+    pub warnings: Vec<String>,
+}
+
+// Commit recorded in the local hf-hub cache for a model repo
+fn cached_model_revision(repo_id: &str) -> Option<String> {
+    let repo = hf_hub::Repo::model(repo_id.to_string());
+    let refs = hf_hub::Cache::default().path().join(repo.folder_name()).join("refs").join("main");
+    std::fs::read_to_string(refs).ok().map(|r| r.trim().to_string()).filter(|r| !r.is_empty())
+}
+
+fn embedding_model_revision(model: &EmbeddingModel) -> Option<String> {
+    match model {
+        EmbeddingModel::HuggingFace { model_name, .. } => cached_model_revision(model_name),
+        EmbeddingModel::Local { model_path } if !Path::new(model_path).is_dir() => cached_model_revision(model_path),
+        _ => None,
+    }
+}
+
+fn indexed_embedding_dimension(conn: &Connection, model_key: &str) -> Option<usize> {
+    conn.query_row(
+        "SELECT embedding_dim FROM document_chunks
+         WHERE embedding_model = ?1 AND embedding_dim IS NOT NULL
+         GROUP BY embedding_dim ORDER BY COUNT(*) DESC LIMIT 1",
+        params![model_key],
+        |row| row.get::<_, i64>(0),
+    )
+    .ok()
+    .map(|d| d as usize)
+}
+
+// Keeps the current key when the incoming config uses the same kind of model without one
+fn restore_api_keys(config: &mut RAGConfig, current: &RAGConfig) {
+    let current_models = [Some(&current.embedding_model), current.multilingual_embedding_model.as_ref()];
+    let models = std::iter::once(&mut config.embedding_model).chain(config.multilingual_embedding_model.as_mut());
+    for (model, current_model) in models.zip(current_models) {
+        match (model, current_model) {
+            (EmbeddingModel::OpenAI { api_key, .. }, Some(EmbeddingModel::OpenAI { api_key: current_key, .. }))
+                if api_key.is_empty() =>
+            {
+                *api_key = current_key.clone();
+            }
+            (EmbeddingModel::HuggingFace { api_key, .. }, Some(EmbeddingModel::HuggingFace { api_key: current_key, .. }))
+                if api_key.is_none() =>
+            {
+                *api_key = current_key.clone();
+            }
+            _ => {}
+        }
+    }
+    if let (
+        VectorBackend::Qdrant { url, api_key, .. },
+        VectorBackend::Qdrant { url: current_url, api_key: current_key, .. },
+    ) = (&mut config.vector_backend, &current.vector_backend)
+    {
+        if api_key.is_none() && url == current_url {
+            *api_key = current_key.clone();
+        }
+    }
+    if let (
+        Some(RerankerConfig { model: RerankerModel::Cohere { api_key, .. }, .. }),
+        Some(RerankerConfig { model: RerankerModel::Cohere { api_key: current_key, .. }, .. }),
+    ) = (&mut config.reranker, &current.reranker)
+    {
+        if api_key.is_empty() {
+            *api_key = current_key.clone();
+        }
+    }
+    restore_llm_api_key(config, current);
+}
+
+fn build_pipeline_manifest(conn: &Connection, config: &RAGConfig) -> PipelineManifest {
+    let model_key = embedding_model_key(&config.embedding_model);
+    // Manifests are shared in bug reports, so keys never leave the machine
+    let redacted = strip_config_secrets(config);
+
+    PipelineManifest {
+        manifest_version: PIPELINE_MANIFEST_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        embedding: ManifestEmbedding {
+            dimension: indexed_embedding_dimension(conn, &model_key),
+            revision: embedding_model_revision(&config.embedding_model),
+            model_key,
+            storage: config.embedding_storage,
+        },
+        chunking: ManifestChunking {
+            chunk_size: config.chunk_size,
+            chunk_overlap: config.chunk_overlap,
+            parent_chunk_size: active_parent_chunk_size(config),
+            chunk_unit: config.chunk_unit.clone(),
+            qa_extraction: config.qa_extraction,
+        },
+        prompt_templates: [AnswerStyle::Concise, AnswerStyle::Detailed, AnswerStyle::QuotesOnly]
+            .into_iter()
+            .map(|style| ManifestPromptTemplate {
+                answer_style: style,
+                instructions: style.instructions().to_string(),
+                generation_params: style.generation_params(),
+            })
+            .collect(),
+        retrieval: ManifestRetrieval {
+            mode: config.mode.clone(),
+            top_k: config.top_k,
+            similarity_threshold: config.similarity_threshold,
+            similarity_metric: config.similarity_metric,
+            vector_backend: config.vector_backend.clone(),
+            hybrid_fusion: config.hybrid_fusion,
+            diversity_lambda: config.diversity_lambda,
+            tags: config.tags.clone(),
+            personalization_profile: config.personalization_profile.clone(),
+            generation_provider: config.generation_provider.clone(),
+            llm_provider: redacted.llm_provider.clone(),
+        },
+        reranker: config.reranker.as_ref().map(|r| {
+            serde_json::json!({
+                "model": match &r.model {
+                    RerankerModel::Local { model_path } => format!("local:{}", model_path),
+                    RerankerModel::Cohere { model, .. } => format!("cohere:{}", model),
+                },
+                "candidates": r.candidates,
+            })
+        }),
+        config: redacted,
+    }
+}
+
+// Differences between the manifest's environment and this machine that can change results
+fn manifest_warnings(manifest: &PipelineManifest, conn: &Connection) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let app_version = env!("CARGO_PKG_VERSION");
+    if manifest.app_version != app_version {
+        warnings.push(format!(
+            "Manifest was exported by version {}, this is version {}",
+            manifest.app_version, app_version
+        ));
+    }
+
+    let local_revision = embedding_model_revision(&manifest.config.embedding_model);
+    if let Some(revision) = &manifest.embedding.revision {
+        if local_revision.as_ref() != Some(revision) {
+            warnings.push(format!(
+                "Embedding model {} is pinned to revision {} but the local cache has {}",
+                manifest.embedding.model_key,
+                revision,
+                local_revision.as_deref().unwrap_or("nothing")
+            ));
+        }
+    }
+
+    if let (Some(expected), Some(indexed)) = (
+        manifest.embedding.dimension,
+        indexed_embedding_dimension(conn, &manifest.embedding.model_key),
+    ) {
+        if expected != indexed {
+            warnings.push(format!(
+                "Manifest expects {}-dimensional embeddings but the local index holds {}",
+                expected, indexed
+            ));
+        }
+    }
+
+    let defaults = [AnswerStyle::Concise, AnswerStyle::Detailed, AnswerStyle::QuotesOnly];
+    for template in &manifest.prompt_templates {
+        if defaults.contains(&template.answer_style) && template.instructions != template.answer_style.instructions() {
+            warnings.push(format!(
+                "Prompt template for {:?} differs from the one built into this version",
+                template.answer_style
+            ));
+        }
+    }
+    warnings
+}
+
+#[tauri::command]
+fn export_pipeline_manifest(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+) -> Result<PipelineManifest, String> {
+    let config = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let db = db_state.lock().map_err(|e| e.to_string())?;
+    Ok(build_pipeline_manifest(&db, &config))
+}
+
+#[tauri::command]
+async fn apply_pipeline_manifest(
+    path: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    app: AppHandle,
+) -> Result<ManifestApplyReport, String> {
+    let manifest: PipelineManifest = serde_json::from_str(&std::fs::read_to_string(&path).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("Not a pipeline manifest: {}", e))?;
+    if manifest.manifest_version > PIPELINE_MANIFEST_VERSION {
+        return Err(format!(
+            "Manifest version {} is newer than the supported version {}; update the app",
+            manifest.manifest_version, PIPELINE_MANIFEST_VERSION
+        ));
+    }
+
+    let current = config_state.lock().map_err(|e| e.to_string())?.clone();
+    let mut config = manifest.config.clone();
+    restore_api_keys(&mut config, &current);
+
+    let warnings = {
+        let db = db_state.lock().map_err(|e| e.to_string())?;
+        let warnings = manifest_warnings(&manifest, &db);
+        record_audit(&db, "pipeline_manifest_applied", Some(&path), serde_json::json!({
+            "manifest_version": manifest.manifest_version,
+            "app_version": manifest.app_version,
+            "embedding_model": manifest.embedding.model_key,
+        }));
+        warnings
+    };
+    for warning in &warnings {
+        warn!("Pipeline manifest {}: {}", path, warning);
+    }
+
+    let reindex_required = config_changes_index(&current, &config);
+    set_rag_config(config.clone(), None, db_state, app).await?;
+
+    Ok(ManifestApplyReport { config, reindex_required, warnings })
+}
+
+// ---------- Reranking -------------------------------------------------------------
+
+// Longest query+passage pair fed to a local cross-encoder
+const CROSS_ENCODER_MAX_TOKENS: usize = 512;
+
+fn default_rerank_candidates() -> usize {
+    20
+}
+
+// Scores each passage against the query and re-sorts; similarity_score becomes the rerank score
+async fn rerank_results(reranker: &RerankerConfig, query: &str, results: &mut Vec<RetrievalResult>) -> Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    let passages: Vec<String> = results.iter().map(|r| r.content.clone()).collect();
+    let scores = match &reranker.model {
+        RerankerModel::Local { model_path } => cross_encoder_scores(model_path, query, &passages).await?,
+        RerankerModel::Cohere { api_key, model } => cohere_rerank_scores(api_key, model, query, &passages).await?,
+    };
+
+    for (result, score) in results.iter_mut().zip(scores) {
+        result.rerank_score = Some(score);
+        result.similarity_score = score;
+    }
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    Ok(())
+}
+
+async fn cohere_rerank_scores(api_key: &str, model: &str, query: &str, passages: &[String]) -> Result<Vec<f32>> {
+    let response = reqwest::Client::new()
+        .post("https://api.cohere.ai/v1/rerank")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "model": model,
+            "query": query,
+            "documents": passages,
+            "top_n": passages.len(),
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Cohere rerank request failed ({})", response.status()));
+    }
+
+    // Results come back sorted by relevance and point at their input by index
+    let body: serde_json::Value = response.json().await?;
+    let mut scores = vec![0.0f32; passages.len()];
+    for entry in body["results"].as_array().into_iter().flatten() {
+        if let (Some(index), Some(score)) = (entry["index"].as_u64(), entry["relevance_score"].as_f64()) {
+            if let Some(slot) = scores.get_mut(index as usize) {
+                *slot = score as f32;
+            }
+        }
+    }
+    Ok(scores)
+}
+
+// BERT with a pooler and a single-logit classifier head, as in ms-marco cross-encoders
+#[cfg(feature = "local-embeddings")]
+struct CrossEncoder {
+    model: candle_transformers::models::bert::BertModel,
+    pooler: candle_nn::Linear,
+    classifier: candle_nn::Linear,
+    tokenizer: Tokenizer,
+}
+
+#[cfg(feature = "local-embeddings")]
+static CROSS_ENCODER_CACHE: OnceLock<Mutex<HashMap<String, Arc<CrossEncoder>>>> = OnceLock::new();
+
+#[cfg(feature = "local-embeddings")]
+impl CrossEncoder {
+    // Same layout rules as LocalEmbedder::load: a local folder or a HuggingFace repo id
+    fn load(model_path: &str) -> Result<Self> {
+        use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+
+        let local_dir = Path::new(model_path);
+        let (config_path, tokenizer_path, weights_path) = if local_dir.is_dir() {
+            (
+                local_dir.join("config.json"),
+                local_dir.join("tokenizer.json"),
+                local_dir.join("model.safetensors"),
+            )
+        } else {
+            let repo = hf_hub::api::sync::Api::new()?.model(model_path.to_string());
+            (repo.get("config.json")?, repo.get("tokenizer.json")?, repo.get("model.safetensors")?)
+        };
+
+        let config_json = std::fs::read_to_string(config_path)?;
+        let config: Config = serde_json::from_str(&config_json)?;
+        let hidden_size = serde_json::from_str::<serde_json::Value>(&config_json)?["hidden_size"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("{} has no hidden_size in config.json", model_path))? as usize;
+        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let weights = unsafe {
+            candle_nn::VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, compute_device())?
+        };
+
+        Ok(Self {
+            model: BertModel::load(weights.clone(), &config)?,
+            pooler: candle_nn::linear(hidden_size, hidden_size, weights.pp("bert.pooler.dense"))?,
+            classifier: candle_nn::linear(hidden_size, 1, weights.pp("classifier"))?,
+            tokenizer,
+        })
+    }
+
+    // Relevance in [0, 1] for each (query, passage) pair
+    fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f32>> {
+        use candle_nn::Module;
+
+        let device = compute_device();
+        let pairs: Vec<(String, String)> = passages.iter().map(|p| (query.to_string(), p.clone())).collect();
+        let encodings = self.tokenizer.encode_batch(pairs, true).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let width = encodings
+            .iter()
+            .map(|e| e.get_ids().len().min(CROSS_ENCODER_MAX_TOKENS))
+            .max()
+            .unwrap_or(0);
+        if width == 0 {
+            return Ok(vec![0.0; passages.len()]);
+        }
+
+        let mut ids = Vec::with_capacity(passages.len() * width);
+        let mut type_ids = Vec::with_capacity(passages.len() * width);
+        let mut mask = Vec::with_capacity(passages.len() * width);
+        for encoding in &encodings {
+            let len = encoding.get_ids().len().min(width);
+            ids.extend_from_slice(&encoding.get_ids()[..len]);
+            type_ids.extend_from_slice(&encoding.get_type_ids()[..len]);
+            mask.extend(std::iter::repeat(1u32).take(len));
+            for padded in [&mut ids, &mut type_ids, &mut mask] {
+                padded.extend(std::iter::repeat(0u32).take(width - len));
+            }
+        }
+
+        let shape = (passages.len(), width);
+        let token_ids = candle_core::Tensor::from_vec(ids, shape, device)?;
+        let token_type_ids = candle_core::Tensor::from_vec(type_ids, shape, device)?;
+        let attention_mask = candle_core::Tensor::from_vec(mask, shape, device)?;
+        let hidden = self.model.forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+
+        let cls = hidden.narrow(1, 0, 1)?.squeeze(1)?;
+        let pooled = self.pooler.forward(&cls)?.tanh()?;
+        let logits = self.classifier.forward(&pooled)?.squeeze(1)?.to_vec1::<f32>()?;
+        Ok(logits.into_iter().map(|logit| 1.0 / (1.0 + (-logit).exp())).collect())
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn cross_encoder_scores(model_path: &str, query: &str, passages: &[String]) -> Result<Vec<f32>> {
+    let (model_path, query, passages) = (model_path.to_string(), query.to_string(), passages.to_vec());
+
+    tokio::task::spawn_blocking(move || {
+        let cache = CROSS_ENCODER_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+        let cached = cache.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.get(&model_path).cloned();
+        let encoder = match cached {
+            Some(encoder) => encoder,
+            None => {
+                info!("Loading cross-encoder {} on {}", model_path, compute_device_name());
+                let encoder = Arc::new(CrossEncoder::load(&model_path)?);
+                cache
+                    .lock()
+                    .map_err(|e| anyhow::anyhow!(e.to_string()))?
+                    .insert(model_path.clone(), encoder.clone());
+                encoder
+            }
+        };
+        encoder.score(&query, &passages)
     })
+    .await?
 }
 
-async fn retrieve_context_enhanced(
+#[cfg(not(feature = "local-embeddings"))]
+async fn cross_encoder_scores(model_path: &str, _query: &str, _passages: &[String]) -> Result<Vec<f32>> {
+    Err(anyhow::anyhow!(
+        "Local cross-encoder {} needs a build with the local-embeddings feature",
+        model_path
+    ))
+}
+
+// ---------- Keyword Search --------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordMatch {
+    pub chunk_id: String,
+    pub document_id: String,
+    pub document_title: String,
+    pub content: String,
+    pub snippet: String,
+    pub bm25_score: f32, // higher is better, unlike SQLite's bm25()
+    pub source_info: String,
+}
+
+// Quotes every term so identifiers like E-1042 or foo::bar are not parsed as FTS5 syntax
+fn fts_match_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| term.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+fn search_chunk_keywords(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    tags: &[String],
+    chunk_filter: &ChunkFilter,
+) -> Result<Vec<KeywordMatch>> {
+    let Some(match_query) = fts_match_query(query) else { return Ok(Vec::new()) };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT dc.id, d.id, d.title, dc.content, snippet(chunk_fts, 0, '[', ']', '…', 12), -bm25(chunk_fts), d.file_path,
+                dc.metadata
+         FROM chunk_fts
+         JOIN document_chunks dc ON dc.id = chunk_fts.chunk_id
+         JOIN documents d ON dc.document_id = d.id
+         WHERE chunk_fts MATCH ?1 AND d.is_searchable = 1 AND d.deleted_at IS NULL AND d.is_sensitive = 0{}{}
+         ORDER BY bm25(chunk_fts)
+         LIMIT ?2",
+        tag_filter_clause(3),
+        chunk_filter_clause(chunk_filter)
+    ))?;
+    let rows = stmt.query_map(params![match_query, limit as i64, tag_filter_value(tags)], |row| {
+        Ok(KeywordMatch {
+            chunk_id: row.get(0)?,
+            document_id: row.get(1)?,
+            document_title: row.get(2)?,
+            content: row.get(3)?,
+            snippet: row.get(4)?,
+            bm25_score: row.get::<_, f64>(5)? as f32,
+            source_info: chunk_source_info(row.get(6)?, row.get(7)?),
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// Rank offset from the original RRF paper; dampens the gap between the first few ranks
+const RRF_K: f32 = 60.0;
+
+// Merges the rankings of several query phrasings; similarity_score becomes the fused score in [0, 1]
+fn fuse_query_rankings(rankings: Vec<Vec<RetrievalResult>>, limit: usize) -> Vec<RetrievalResult> {
+    let best = rankings.len() as f32 / (RRF_K + 1.0);
+    let mut fused: Vec<(f32, RetrievalResult)> = Vec::new();
+    for mut ranking in rankings {
+        ranking.sort_by(|a, b| b.similarity_score.total_cmp(&a.similarity_score));
+        for (rank, result) in ranking.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match fused.iter_mut().find(|(_, existing)| existing.chunk_id == result.chunk_id) {
+                Some((total, _)) => *total += score,
+                None => fused.push((score, result)),
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0));
+    fused.truncate(limit);
+    fused
+        .into_iter()
+        .map(|(score, mut result)| {
+            result.similarity_score = score / best;
+            result
+        })
+        .collect()
+}
+
+// Merges BM25 matches into ranked vector results; similarity_score becomes the fused score in [0, 1]
+fn fuse_keyword_matches(
+    conn: &Connection,
+    results: &mut Vec<RetrievalResult>,
     query: &str,
     config: &RAGConfig,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<Vec<RetrievalResult>, String> {
-    let query_embedding = generate_embedding_with_config(query, config)
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    let mut results = Vec::new();
+) -> Result<()> {
+    if config.hybrid_fusion == HybridFusion::Off {
+        return Ok(());
+    }
+    let matches = search_chunk_keywords(conn, query, config.top_k, &config.tags, &config.chunk_filter)?;
+
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    for result in results.iter_mut() {
+        result.vector_score = Some(result.similarity_score);
+    }
+    for keyword in &matches {
+        match results.iter_mut().find(|r| r.chunk_id == keyword.chunk_id) {
+            Some(existing) => existing.keyword_score = Some(keyword.bm25_score),
+            None => results.push(RetrievalResult {
+                chunk_id: keyword.chunk_id.clone(),
+                content: keyword.content.clone(),
+                document_title: keyword.document_title.clone(),
+                similarity_score: 0.0,
+                source_info: keyword.source_info.clone(),
+                merged_chunk_ids: Vec::new(),
+                merged_sources: Vec::new(),
+                vector_score: None,
+                keyword_score: Some(keyword.bm25_score),
+                rerank_score: None,
+            }),
+        }
+    }
+
+    match config.hybrid_fusion {
+        HybridFusion::Off => {}
+        HybridFusion::Rrf => {
+            let rank_score = |rank: Option<usize>| rank.map_or(0.0, |r| 1.0 / (RRF_K + r as f32 + 1.0));
+            let best = 2.0 * rank_score(Some(0));
+            // Vector results were sorted first and keyword-only ones appended after them
+            for (index, result) in results.iter_mut().enumerate() {
+                let vector_rank = result.vector_score.is_some().then_some(index);
+                let keyword_rank = matches.iter().position(|m| m.chunk_id == result.chunk_id);
+                result.similarity_score = (rank_score(vector_rank) + rank_score(keyword_rank)) / best;
+            }
+        }
+        HybridFusion::Weighted { vector_weight } => {
+            let weight = vector_weight.clamp(0.0, 1.0);
+            let max_bm25 = matches.iter().map(|m| m.bm25_score).fold(0.0f32, f32::max);
+            for result in results.iter_mut() {
+                let keyword = match (result.keyword_score, max_bm25 > 0.0) {
+                    (Some(score), true) => score / max_bm25,
+                    _ => 0.0,
+                };
+                result.similarity_score = weight * result.vector_score.unwrap_or(0.0) + (1.0 - weight) * keyword;
+            }
+        }
+    }
+
+    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    results.truncate(config.top_k);
+    Ok(())
+}
 
+// Exact-term lookup for identifiers, error codes and names that embeddings blur together
+#[tauri::command]
+fn keyword_search(
+    query: String,
+    limit: Option<usize>,
+    tags: Option<Vec<String>>,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<Vec<KeywordMatch>, String> {
     let db = db_state.lock().map_err(|e| e.to_string())?;
-    
-    let mut stmt = db
-        .prepare("SELECT dc.id, dc.content, dc.embedding, d.title, d.file_path
-                  FROM document_chunks dc
-                  JOIN documents d ON dc.document_id = d.id")
-        .map_err(|e| e.to_string())?;
+    search_chunk_keywords(&db, &query, limit.unwrap_or(20), &tags.unwrap_or_default(), &ChunkFilter::default()).map_err(|e| e.to_string())
+}
 
-    let chunk_iter = stmt
-        .query_map([], |row| {
-            let chunk_id: String = row.get(0)?;
-            let content: String = row.get(1)?;
-            let embedding_bytes: Vec<u8> = row.get(2)?;
-            let doc_title: String = row.get(3)?;
-            let file_path: Option<String> = row.get(4)?;
-            
-            let embedding: Vec<f32> = embedding_bytes
-                .chunks_exact(4)
-                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                .collect();
+// ---------- Vector Stores ---------------------------------------------------------
+
+// Chunk vectors sent per upsert while mirroring the database into a store
+const VECTOR_STORE_SYNC_BATCH: usize = 512;
+
+// What a shared store needs to show a chunk to installs that do not have its document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkPayload {
+    pub document_id: String,
+    pub document_title: String,
+    pub content: String,
+    pub source_info: String,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+pub struct ChunkVector {
+    pub chunk_id: String,
+    pub vector: Vec<f32>,
+    pub payload: ChunkPayload,
+}
+
+pub struct VectorHit {
+    pub chunk_id: String,
+    pub score: Option<f32>, // on compute_similarity's scale; only stores that return payloads report it
+    pub payload: Option<ChunkPayload>,
+}
+
+// A chunk only the shared store holds, e.g. from a teammate's documents
+pub struct RemoteChunk {
+    pub chunk_id: String,
+    pub score: f32,
+    pub payload: ChunkPayload,
+}
+
+impl RemoteChunk {
+    fn into_result(self) -> RetrievalResult {
+        RetrievalResult {
+            chunk_id: self.chunk_id,
+            content: self.payload.content,
+            document_title: self.payload.document_title,
+            similarity_score: self.score,
+            source_info: self.payload.source_info,
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        }
+    }
+}
+
+// Candidates from an external store: ids of chunks this database holds, which are rescored
+// locally, and chunks that exist only in the store, which keep the store's score
+pub struct ExternalCandidates {
+    pub local_ids: String, // JSON array, bound to json_each in the retrieval queries
+    pub remote: Vec<RemoteChunk>,
+}
+
+// An external home for chunk vectors. Local retrieval still reads chunk text from SQLite;
+// the payload only serves chunks that other installs wrote to a shared store.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    // Stable key for the store's position in chunk_changes
+    fn key(&self) -> String;
+
+    // Replaces any vectors already stored for these chunk ids
+    async fn upsert(&self, vectors: &[ChunkVector]) -> Result<()>;
+
+    async fn delete(&self, chunk_ids: &[String]) -> Result<()>;
+
+    // Nearest chunks by the store's configured metric, closest first
+    async fn search(&self, query: &[f32], k: usize) -> Result<Vec<VectorHit>>;
+}
+
+// Latest seq handed out by chunk_changes, which survives the rows being pruned
+fn chunk_change_head(conn: &Connection) -> i64 {
+    conn.query_row(
+        "SELECT seq FROM sqlite_sequence WHERE name = 'chunk_changes'",
+        [],
+        |row| row.get(0),
+    )
+    .unwrap_or(0)
+}
+
+fn record_change_cursor(conn: &Connection, store_key: &str, last_change: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO vector_store_sync (store_key, last_change, synced_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(store_key) DO UPDATE SET last_change = excluded.last_change, synced_at = excluded.synced_at",
+        params![store_key, last_change, Utc::now().to_rfc3339()],
+    )?;
+    prune_chunk_changes(conn)
+}
+
+// A store not synced for this long has most likely been switched away from; its cursor is
+// dropped so it stops pinning the change log, and it gets a full copy if it is ever used again
+const VECTOR_STORE_CURSOR_EXPIRY_DAYS: i64 = 30;
+
+// Drops changes every store has applied; with no stores registered the log is emptied
+fn prune_chunk_changes(conn: &Connection) -> Result<()> {
+    let expired = conn.execute(
+        "DELETE FROM vector_store_sync WHERE synced_at < ?1",
+        params![(Utc::now() - chrono::Duration::days(VECTOR_STORE_CURSOR_EXPIRY_DAYS)).to_rfc3339()],
+    )?;
+    if expired > 0 {
+        info!("Expired {} stale vector store cursor(s)", expired);
+    }
+    conn.execute(
+        "DELETE FROM chunk_changes WHERE seq <= COALESCE(
+             (SELECT MIN(last_change) FROM vector_store_sync),
+             (SELECT MAX(seq) FROM chunk_changes))",
+        [],
+    )?;
+    Ok(())
+}
+
+// Chunk ids a store has to re-send and drop, or None when it needs a full copy
+fn pending_chunk_changes(conn: &Connection, store_key: &str) -> Result<(i64, Option<(Vec<String>, Vec<String>)>)> {
+    let head = chunk_change_head(conn);
+    let cursor: Option<i64> = conn
+        .query_row(
+            "SELECT last_change FROM vector_store_sync WHERE store_key = ?1",
+            params![store_key],
+            |row| row.get(0),
+        )
+        .ok();
+    let Some(cursor) = cursor else { return Ok((head, None)) };
+
+    // Changes after the cursor were pruned, e.g. the row was deleted by hand
+    let oldest: Option<i64> = conn.query_row("SELECT MIN(seq) FROM chunk_changes", [], |row| row.get(0))?;
+    if cursor < head && oldest.map_or(true, |oldest| oldest > cursor + 1) {
+        return Ok((head, None));
+    }
+
+    let mut latest: HashMap<String, String> = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT chunk_id, op FROM chunk_changes WHERE seq > ?1 AND seq <= ?2 ORDER BY seq ASC")?;
+        let rows = stmt.query_map(params![cursor, head], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for (chunk_id, op) in rows.filter_map(Result::ok) {
+            latest.insert(chunk_id, op);
+        }
+    }
+    let (deleted, changed): (Vec<_>, Vec<_>) = latest.into_iter().partition(|(_, op)| op == "delete");
+    Ok((
+        head,
+        Some((
+            changed.into_iter().map(|(id, _)| id).collect(),
+            deleted.into_iter().map(|(id, _)| id).collect(),
+        )),
+    ))
+}
+
+// Sensitive documents never leave the device, so their chunks are not loaded at all
+fn load_chunk_vectors(conn: &Connection, chunk_ids: &[String]) -> Result<Vec<ChunkVector>> {
+    let mut stmt = conn.prepare(
+        "SELECT dc.id, COALESCE(se.embedding, dc.embedding), d.id, d.title, dc.content, d.file_path, dc.metadata,
+                (SELECT json_group_array(t.name) FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+                 WHERE dt.document_id = d.id)
+         FROM document_chunks dc
+         JOIN documents d ON d.id = dc.document_id
+         LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+         WHERE dc.id IN (SELECT value FROM json_each(?1)) AND d.is_sensitive = 0",
+    )?;
+    let rows = stmt.query_map(params![serde_json::to_string(chunk_ids)?], |row| {
+        let bytes: Vec<u8> = row.get(1)?;
+        let metadata: Option<String> = row.get(6)?;
+        let tags: Option<String> = row.get(7)?;
+        Ok(ChunkVector {
+            chunk_id: row.get(0)?,
+            vector: decode_embedding(&bytes),
+            payload: ChunkPayload {
+                document_id: row.get(2)?,
+                document_title: row.get(3)?,
+                content: row.get(4)?,
+                source_info: chunk_source_info(row.get(5)?, metadata.clone()),
+                metadata: metadata
+                    .and_then(|m| serde_json::from_str(&m).ok())
+                    .unwrap_or(serde_json::Value::Null),
+                tags: tags.and_then(|t| serde_json::from_str(&t).ok()).unwrap_or_default(),
+            },
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// Mirrors chunk writes logged since the store's last sync; a store seen for the first time gets every chunk
+async fn sync_vector_store(db_state: &Arc<DbPool>, store: &dyn VectorStore) -> Result<usize> {
+    let store_key = store.key();
+    let (head, upserts, deletes) = {
+        let conn = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        // A shared copy cannot record the cursor; the team's writer keeps the store current
+        if is_read_only(&conn) {
+            return Ok(0);
+        }
+        match pending_chunk_changes(&conn, &store_key)? {
+            (head, Some((changed, deleted))) => (head, changed, deleted),
+            (head, None) => {
+                // Sensitive chunks never go to a store, not even as ids to withdraw
+                let mut stmt = conn.prepare(
+                    "SELECT dc.id FROM document_chunks dc JOIN documents d ON d.id = dc.document_id
+                     WHERE d.is_sensitive = 0",
+                )?;
+                let ids: Vec<String> = stmt.query_map([], |row| row.get(0))?.filter_map(Result::ok).collect();
+                info!("Copying {} chunk vector(s) to {}", ids.len(), store_key);
+                (head, ids, Vec::new())
+            }
+        }
+    };
+
+    if !deletes.is_empty() {
+        store.delete(&deletes).await?;
+    }
+    for batch in upserts.chunks(VECTOR_STORE_SYNC_BATCH) {
+        let vectors = {
+            let conn = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            load_chunk_vectors(&conn, batch)?
+        };
+        // A chunk that changed but did not load was deleted since or belongs to a document marked sensitive
+        let loaded: std::collections::HashSet<&str> = vectors.iter().map(|v| v.chunk_id.as_str()).collect();
+        let withdrawn: Vec<String> = batch.iter().filter(|id| !loaded.contains(id.as_str())).cloned().collect();
+        if !withdrawn.is_empty() {
+            store.delete(&withdrawn).await?;
+        }
+        store.upsert(&vectors).await?;
+    }
+
+    let conn = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    record_change_cursor(&conn, &store_key, head)?;
+    Ok(upserts.len() + deletes.len())
+}
+
+// Connected stores for the session, keyed by their configuration
+pub struct VectorStores {
+    data_dir: PathBuf,
+    stores: Mutex<HashMap<String, Arc<dyn VectorStore>>>,
+    sync_lock: tokio::sync::Mutex<()>, // one sync at a time, so delete-then-add never interleaves
+}
+
+impl VectorStores {
+    fn new(data_dir: PathBuf) -> Self {
+        Self { data_dir, stores: Mutex::new(HashMap::new()), sync_lock: tokio::sync::Mutex::new(()) }
+    }
+
+    // None for the backends that search the SQLite database itself
+    async fn resolve(&self, backend: &VectorBackend, metric: SimilarityMetric) -> Result<Option<Arc<dyn VectorStore>>> {
+        let key = match backend {
+            VectorBackend::LanceDb { uri } => format!(
+                "lancedb:{}",
+                uri.clone().unwrap_or_else(|| self.data_dir.join("lancedb").to_string_lossy().to_string())
+            ),
+            VectorBackend::Qdrant { url, collection, .. } => {
+                format!("qdrant:{}/{}", url.trim_end_matches('/'), collection)
+            }
+            VectorBackend::Linear | VectorBackend::SqliteVec | VectorBackend::Hnsw => return Ok(None),
+        };
+        // The metric is fixed when a store is opened, so switching it opens the store again
+        let key = format!("{}#{:?}", key, metric);
+        if let Some(store) = self.stores.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?.get(&key) {
+            return Ok(Some(store.clone()));
+        }
+
+        let store: Arc<dyn VectorStore> = match backend {
+            VectorBackend::LanceDb { uri } => Arc::new(
+                LanceDbStore::connect(
+                    &uri.clone().unwrap_or_else(|| self.data_dir.join("lancedb").to_string_lossy().to_string()),
+                    metric,
+                )
+                .await?,
+            ),
+            VectorBackend::Qdrant { url, collection, api_key } => {
+                Arc::new(QdrantStore::connect(url, collection, api_key.clone(), metric).await?)
+            }
+            VectorBackend::Linear | VectorBackend::SqliteVec | VectorBackend::Hnsw => return Ok(None),
+        };
+        self.stores
+            .lock()
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?
+            .insert(key, store.clone());
+        Ok(Some(store))
+    }
+
+    // None for the built-in backends
+    async fn candidates(
+        &self,
+        db_state: &Arc<DbPool>,
+        config: &RAGConfig,
+        query_embedding: &[f32],
+    ) -> Result<Option<ExternalCandidates>> {
+        let Some(store) = self.resolve(&config.vector_backend, config.similarity_metric).await? else {
+            return Ok(None);
+        };
+        {
+            let _guard = self.sync_lock.lock().await;
+            sync_vector_store(db_state, store.as_ref()).await?;
+        }
+        let hits = store.search(query_embedding, config.top_k * VECTOR_INDEX_OVERFETCH).await?;
+
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.chunk_id.as_str()).collect();
+        let local: std::collections::HashSet<String> = {
+            let conn = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            let mut stmt = conn.prepare("SELECT id FROM document_chunks WHERE id IN (SELECT value FROM json_each(?1))")?;
+            let rows = stmt.query_map(params![serde_json::to_string(&ids)?], |row| row.get(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
+
+        let (local_hits, remote_hits): (Vec<VectorHit>, Vec<VectorHit>) =
+            hits.into_iter().partition(|hit| local.contains(&hit.chunk_id));
+        let remote = remote_hits
+            .into_iter()
+            .filter_map(|hit| Some(RemoteChunk { score: hit.score?, payload: hit.payload?, chunk_id: hit.chunk_id }))
+            .filter(|chunk| {
+                chunk.score > config.similarity_threshold
+                    && (config.tags.is_empty() || chunk.payload.tags.iter().any(|t| config.tags.contains(t)))
+                    && config.chunk_filter.matches(&chunk.payload.metadata)
+            })
+            .collect();
+        let local_ids: Vec<String> = local_hits.into_iter().map(|hit| hit.chunk_id).collect();
+        Ok(Some(ExternalCandidates { local_ids: serde_json::to_string(&local_ids)?, remote }))
+    }
+}
+
+// LanceDB keeps one table per embedding dimension, each holding (chunk_id, vector)
+#[cfg(feature = "lancedb")]
+pub struct LanceDbStore {
+    uri: String,
+    db: lancedb::Connection,
+    distance: lancedb::DistanceType, // applied per query, so the stored vectors do not depend on it
+}
+
+#[cfg(feature = "lancedb")]
+impl LanceDbStore {
+    async fn connect(uri: &str, metric: SimilarityMetric) -> Result<Self> {
+        let db = lancedb::connect(uri).execute().await?;
+        let distance = match metric {
+            SimilarityMetric::Cosine => lancedb::DistanceType::Cosine,
+            SimilarityMetric::Dot => lancedb::DistanceType::Dot,
+            SimilarityMetric::Euclidean => lancedb::DistanceType::L2,
+        };
+        Ok(Self { uri: uri.to_string(), db, distance })
+    }
+
+    fn table_name(dimension: usize) -> String {
+        format!("chunks_{}", dimension)
+    }
+
+    fn schema(dimension: usize) -> Arc<arrow_schema::Schema> {
+        use arrow_schema::{DataType, Field, Schema};
+        Arc::new(Schema::new(vec![
+            Field::new("chunk_id", DataType::Utf8, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), dimension as i32),
+                false,
+            ),
+        ]))
+    }
+
+    async fn chunk_tables(&self) -> Result<Vec<String>> {
+        let names = self.db.table_names().execute().await?;
+        Ok(names.into_iter().filter(|name| name.starts_with("chunks_")).collect())
+    }
+
+    fn id_filter(chunk_ids: &[String]) -> String {
+        let ids: Vec<String> = chunk_ids.iter().map(|id| format!("'{}'", id.replace('\'', "''"))).collect();
+        format!("chunk_id IN ({})", ids.join(", "))
+    }
+}
+
+#[cfg(feature = "lancedb")]
+#[async_trait]
+impl VectorStore for LanceDbStore {
+    fn key(&self) -> String {
+        format!("lancedb:{}", self.uri)
+    }
+
+    async fn upsert(&self, vectors: &[ChunkVector]) -> Result<()> {
+        use arrow_array::{types::Float32Type, FixedSizeListArray, RecordBatch, RecordBatchIterator, StringArray};
+
+        let ids: Vec<String> = vectors.iter().map(|v| v.chunk_id.clone()).collect();
+        self.delete(&ids).await?;
+
+        let mut by_dimension: BTreeMap<usize, Vec<&ChunkVector>> = BTreeMap::new();
+        for entry in vectors.iter().filter(|v| !v.vector.is_empty()) {
+            by_dimension.entry(entry.vector.len()).or_default().push(entry);
+        }
+
+        let existing = self.chunk_tables().await?;
+        for (dimension, entries) in by_dimension {
+            let schema = Self::schema(dimension);
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from_iter_values(entries.iter().map(|e| e.chunk_id.as_str()))),
+                    Arc::new(FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        entries.iter().map(|e| Some(e.vector.iter().copied().map(Some).collect::<Vec<_>>())),
+                        dimension as i32,
+                    )),
+                ],
+            )?;
+            let reader = Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema));
+
+            let name = Self::table_name(dimension);
+            if existing.contains(&name) {
+                self.db.open_table(&name).execute().await?.add(reader).execute().await?;
+            } else {
+                self.db.create_table(&name, reader).execute().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, chunk_ids: &[String]) -> Result<()> {
+        if chunk_ids.is_empty() {
+            return Ok(());
+        }
+        for name in self.chunk_tables().await? {
+            let table = self.db.open_table(&name).execute().await?;
+            for ids in chunk_ids.chunks(VECTOR_STORE_SYNC_BATCH) {
+                table.delete(&Self::id_filter(ids)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Every chunk in a local dataset is also in SQLite, so hits carry neither score nor payload
+    async fn search(&self, query: &[f32], k: usize) -> Result<Vec<VectorHit>> {
+        use arrow_array::{RecordBatch, StringArray};
+        use futures::TryStreamExt;
+        use lancedb::query::{ExecutableQuery, QueryBase};
+
+        let name = Self::table_name(query.len());
+        if !self.chunk_tables().await?.contains(&name) {
+            return Ok(Vec::new());
+        }
+        let batches: Vec<RecordBatch> = self
+            .db
+            .open_table(&name)
+            .execute()
+            .await?
+            .query()
+            .nearest_to(query)?
+            .distance_type(self.distance)
+            .limit(k)
+            .execute()
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut hits = Vec::new();
+        for batch in &batches {
+            if let Some(column) = batch.column_by_name("chunk_id").and_then(|c| c.as_any().downcast_ref::<StringArray>()) {
+                hits.extend(column.iter().flatten().map(|id| VectorHit { chunk_id: id.to_string(), score: None, payload: None }));
+            }
+        }
+        Ok(hits)
+    }
+}
+
+#[cfg(not(feature = "lancedb"))]
+pub struct LanceDbStore;
+
+#[cfg(not(feature = "lancedb"))]
+impl LanceDbStore {
+    async fn connect(uri: &str, _metric: SimilarityMetric) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "The LanceDB store at {} needs a build with the lancedb feature",
+            uri
+        ))
+    }
+}
+
+#[cfg(not(feature = "lancedb"))]
+#[async_trait]
+impl VectorStore for LanceDbStore {
+    fn key(&self) -> String {
+        "lancedb".to_string()
+    }
+
+    async fn upsert(&self, _vectors: &[ChunkVector]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn delete(&self, _chunk_ids: &[String]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn search(&self, _query: &[f32], _k: usize) -> Result<Vec<VectorHit>> {
+        Ok(Vec::new())
+    }
+}
+
+// Qdrant over its REST API; point ids are derived from chunk ids. The chunk id, text and source
+// ride along as payload so every member of a team collection can cite the others' chunks.
+pub struct QdrantStore {
+    client: reqwest::Client,
+    url: String,
+    collection: String,
+    api_key: Option<String>,
+    metric: SimilarityMetric,
+    dimension: Mutex<Option<usize>>, // vector size of the collection once known
+}
+
+fn qdrant_distance(metric: SimilarityMetric) -> &'static str {
+    match metric {
+        SimilarityMetric::Cosine => "Cosine",
+        SimilarityMetric::Dot => "Dot",
+        SimilarityMetric::Euclidean => "Euclid",
+    }
+}
+
+impl QdrantStore {
+    async fn connect(url: &str, collection: &str, api_key: Option<String>, metric: SimilarityMetric) -> Result<Self> {
+        let store = Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            collection: collection.to_string(),
+            api_key: api_key.filter(|k| !k.is_empty()),
+            metric,
+            dimension: Mutex::new(None),
+        };
+        let response = store.request(reqwest::Method::GET, "").send().await?;
+        if response.status().is_success() {
+            let info: serde_json::Value = response.json().await?;
+            let vectors = &info["result"]["config"]["params"]["vectors"];
+            // Scores from another distance would not be comparable with the local ones
+            if let Some(distance) = vectors["distance"].as_str().filter(|d| *d != qdrant_distance(metric)) {
+                return Err(anyhow::anyhow!(
+                    "Qdrant collection {} uses {} distance but similarity_metric is {:?}",
+                    collection,
+                    distance,
+                    metric
+                ));
+            }
+            let size = vectors["size"].as_u64();
+            *store.dimension.lock().map_err(|e| anyhow::anyhow!(e.to_string()))? = size.map(|s| s as usize);
+        } else if response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("Qdrant at {} answered {}", store.url, response.status()));
+        }
+        Ok(store)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, format!("{}/collections/{}{}", self.url, self.collection, path));
+        match &self.api_key {
+            Some(key) => request.header("api-key", key),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, action: &str) -> Result<serde_json::Value> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Qdrant {} on {} failed ({}): {}", action, self.collection, status, body));
+        }
+        Ok(response.json().await?)
+    }
+
+    fn dimension(&self) -> Result<Option<usize>> {
+        Ok(*self.dimension.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?)
+    }
+
+    // Creates the collection on first write; a team collection with another size is never replaced
+    async fn ensure_collection(&self, dimension: usize) -> Result<()> {
+        match self.dimension()? {
+            Some(existing) if existing == dimension => Ok(()),
+            Some(existing) => Err(anyhow::anyhow!(
+                "Qdrant collection {} holds {}-dimensional vectors but the chunks have {}",
+                self.collection,
+                existing,
+                dimension
+            )),
+            None => {
+                let body = serde_json::json!({ "vectors": { "size": dimension, "distance": qdrant_distance(self.metric) } });
+                self.send(self.request(reqwest::Method::PUT, "").json(&body), "create").await?;
+                *self.dimension.lock().map_err(|e| anyhow::anyhow!(e.to_string()))? = Some(dimension);
+                info!("Created Qdrant collection {} ({} dimensions)", self.collection, dimension);
+                Ok(())
+            }
+        }
+    }
+
+    fn point_id(chunk_id: &str) -> String {
+        let digest = Sha256::digest(chunk_id.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        Uuid::from_bytes(bytes).to_string()
+    }
+}
+
+#[async_trait]
+impl VectorStore for QdrantStore {
+    fn key(&self) -> String {
+        format!("qdrant:{}/{}", self.url, self.collection)
+    }
+
+    async fn upsert(&self, vectors: &[ChunkVector]) -> Result<()> {
+        let Some(dimension) = vectors.iter().map(|v| v.vector.len()).find(|len| *len > 0) else { return Ok(()) };
+        self.ensure_collection(dimension).await?;
+
+        let mut points = Vec::with_capacity(vectors.len());
+        for entry in vectors.iter().filter(|v| v.vector.len() == dimension) {
+            let mut payload = serde_json::to_value(&entry.payload)?;
+            payload["chunk_id"] = serde_json::json!(entry.chunk_id);
+            points.push(serde_json::json!({
+                "id": Self::point_id(&entry.chunk_id),
+                "vector": entry.vector,
+                "payload": payload,
+            }));
+        }
+        if points.len() < vectors.len() {
+            warn!("Skipped {} chunk vector(s) that do not match the Qdrant collection size", vectors.len() - points.len());
+        }
+        self.send(
+            self.request(reqwest::Method::PUT, "/points?wait=true").json(&serde_json::json!({ "points": points })),
+            "upsert",
+        )
+        .await?;
+        Ok(())
+    }
 
-            Ok((chunk_id, content, embedding, doc_title, file_path))
-        })
-        .map_err(|e| e.to_string())?;
+    async fn delete(&self, chunk_ids: &[String]) -> Result<()> {
+        if chunk_ids.is_empty() || self.dimension()?.is_none() {
+            return Ok(());
+        }
+        let points: Vec<String> = chunk_ids.iter().map(|id| Self::point_id(id)).collect();
+        self.send(
+            self.request(reqwest::Method::POST, "/points/delete?wait=true")
+                .json(&serde_json::json!({ "points": points })),
+            "delete",
+        )
+        .await?;
+        Ok(())
+    }
 
-    for chunk_result in chunk_iter {
-        if let Ok((chunk_id, content, chunk_embedding, doc_title, file_path)) = chunk_result {
-            let similarity = cosine_similarity(&query_embedding, &chunk_embedding);
-            
-            if similarity > config.similarity_threshold {
-                results.push(RetrievalResult {
-                    chunk_id,
-                    content,
-                    document_title: doc_title,
-                    similarity_score: similarity,
-                    source_info: file_path.unwrap_or_else(|| "Unknown source".to_string()),
-                });
+    async fn search(&self, query: &[f32], k: usize) -> Result<Vec<VectorHit>> {
+        match self.dimension()? {
+            None => return Ok(Vec::new()),
+            Some(dimension) if dimension != query.len() => {
+                return Err(anyhow::anyhow!(
+                    "Qdrant collection {} holds {}-dimensional vectors but the query has {}",
+                    self.collection,
+                    dimension,
+                    query.len()
+                ));
             }
+            Some(_) => {}
         }
+        let body = serde_json::json!({ "vector": query, "limit": k, "with_payload": true });
+        let response = self.send(self.request(reqwest::Method::POST, "/points/search").json(&body), "search").await?;
+        let metric = self.metric;
+        Ok(response["result"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .filter_map(|hit| {
+                        let chunk_id = hit["payload"]["chunk_id"].as_str()?.to_string();
+                        // Euclid scores are distances; the rest are already similarities
+                        let score = hit["score"].as_f64().map(|score| match metric {
+                            SimilarityMetric::Euclidean => 1.0 / (1.0 + score as f32),
+                            SimilarityMetric::Cosine | SimilarityMetric::Dot => score as f32,
+                        });
+                        // Points written before payloads were mirrored only carry the chunk id
+                        let payload = serde_json::from_value::<ChunkPayload>(hit["payload"].clone()).ok();
+                        Some(VectorHit { chunk_id, score, payload })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
     }
+}
 
-    // Sort by similarity and take top-k
-    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
-    results.truncate(config.top_k);
+// ---------- HNSW Index ----------------------------------------------------------------
 
-    Ok(results)
+// Graph parameters sized for up to a few hundred thousand chunks
+const HNSW_MAX_CONNECTIONS: usize = 16;
+const HNSW_MAX_LAYERS: usize = 16;
+const HNSW_EF_CONSTRUCTION: usize = 200;
+const HNSW_MIN_EF_SEARCH: usize = 64;
+// Deleted or re-embedded entries cannot be removed from the graph, so rebuild past this share
+const HNSW_MAX_STALE_FRACTION: f32 = 0.25;
+// Cap on the over-fetch factor, should a graph be searched while mostly stale
+const HNSW_MAX_OVERSAMPLE: f32 = 8.0;
+// vector_store_sync key for the in-memory graph
+const HNSW_CHANGE_CURSOR: &str = "hnsw";
+
+pub struct HnswIndex {
+    dimension: usize,
+    graph: Hnsw<'static, f32, DistCosine>,
+    chunk_ids: Vec<String>,        // graph data id -> chunk id, including stale entries
+    live: HashMap<String, usize>,  // chunk id -> its current data id
+    last_change: i64,              // highest chunk_changes seq applied
 }
 
-async fn generate_answer_with_mode(
-    query: &str,
-    context: &[RetrievalResult],
-    mode: &RAGMode,
-) -> String {
-    match mode {
-        RAGMode::FineTunedOnly => {
-            format!("Fine-tuned model response to: {}\n\n[This would be the output from your fine-tuned model]", query)
+impl HnswIndex {
+    fn build(conn: &Connection, dimension: usize) -> Result<Self> {
+        let start_time = std::time::Instant::now();
+        let last_change = chunk_change_head(conn);
+        let vectors: Vec<(String, Vec<f32>)> = {
+            let mut stmt = conn.prepare(
+                "SELECT dc.id, COALESCE(se.embedding, dc.embedding) FROM document_chunks dc
+                 LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+                 WHERE dc.embedding_dim = ?1",
+            )?;
+            let rows = stmt.query_map(params![dimension as i64], |row| {
+                let bytes: Vec<u8> = row.get(1)?;
+                Ok((row.get(0)?, decode_embedding(&bytes)))
+            })?;
+            rows.filter_map(Result::ok).filter(|(_, v)| is_indexable(v, dimension)).collect()
+        };
+
+        let graph = Hnsw::new(
+            HNSW_MAX_CONNECTIONS,
+            vectors.len().max(1024),
+            HNSW_MAX_LAYERS,
+            HNSW_EF_CONSTRUCTION,
+            DistCosine {},
+        );
+        let batch: Vec<(&Vec<f32>, usize)> = vectors.iter().enumerate().map(|(id, (_, v))| (v, id)).collect();
+        graph.parallel_insert(&batch);
+
+        let chunk_ids: Vec<String> = vectors.into_iter().map(|(chunk_id, _)| chunk_id).collect();
+        let live = chunk_ids.iter().enumerate().map(|(id, chunk_id)| (chunk_id.clone(), id)).collect();
+
+        // The log only has to cover changes since the newest in-memory build
+        if !is_read_only(conn) {
+            record_change_cursor(conn, HNSW_CHANGE_CURSOR, last_change)?;
         }
-        RAGMode::FineTunedWithRAG => {
-            if context.is_empty() {
-                format!("Fine-tuned model response (no relevant context found): {}", query)
-            } else {
-                let context_text = context.iter()
-                    .map(|r| format!("From {}: {}", r.document_title, r.content))
-                    .collect::<Vec<_>>()
-                    .join("\n\n");
-                
-                format!("Fine-tuned model response based on context:\n\nQuery: {}\n\nRelevant context:\n{}\n\n[This would be the enhanced fine-tuned model response using the retrieved context]", query, context_text)
-            }
+        info!(
+            "Built HNSW index over {} {}-dimensional chunk(s) in {}ms",
+            chunk_ids.len(),
+            dimension,
+            start_time.elapsed().as_millis()
+        );
+        Ok(Self { dimension, graph, chunk_ids, live, last_change })
+    }
+
+    // Applies chunk inserts, deletes and re-embeddings logged since the last call
+    fn catch_up(&mut self, conn: &Connection) -> Result<usize> {
+        // A shared copy is a snapshot; switching to it rebuilds the index
+        if is_read_only(conn) {
+            return Ok(0);
         }
-        RAGMode::BaseWithRAG => {
-            if context.is_empty() {
-                format!("I don't have relevant information to answer: {}\n\nPlease upload relevant documents to help me provide a better response.", query)
-            } else {
-                let context_text = context.iter()
-                    .map(|r| r.content.clone())
-                    .collect::<Vec<_>>()
-                    .join("\n\n");
-                
-                format!("Based on the documents in your knowledge base:\n\nQuery: {}\n\nAnswer: Based on the retrieved information, here's what I found:\n\n{}\n\nSources: {}", 
-                    query, 
-                    context_text,
-                    context.iter().map(|r| r.document_title.clone()).collect::<Vec<_>>().join(", ")
-                )
+
+        let changes: Vec<(i64, String, String)> = {
+            let mut stmt =
+                conn.prepare("SELECT seq, chunk_id, op FROM chunk_changes WHERE seq > ?1 ORDER BY seq ASC")?;
+            let rows = stmt.query_map(params![self.last_change], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.filter_map(Result::ok).collect()
+        };
+
+        for (seq, chunk_id, op) in &changes {
+            if op == "payload" {
+                self.last_change = *seq;
+                continue;
+            }
+            self.live.remove(chunk_id);
+            if op != "delete" {
+                let vector: Option<Vec<f32>> = conn
+                    .query_row(
+                        "SELECT COALESCE(se.embedding, dc.embedding) FROM document_chunks dc
+                         LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+                         WHERE dc.id = ?1",
+                        params![chunk_id],
+                        |row| row.get::<_, Vec<u8>>(0),
+                    )
+                    .ok()
+                    .map(|bytes| decode_embedding(&bytes));
+                if let Some(vector) = vector.filter(|v| is_indexable(v, self.dimension)) {
+                    let id = self.chunk_ids.len();
+                    self.graph.insert((&vector, id));
+                    self.chunk_ids.push(chunk_id.clone());
+                    self.live.insert(chunk_id.clone(), id);
+                }
             }
+            self.last_change = *seq;
+        }
+
+        if !changes.is_empty() {
+            record_change_cursor(conn, HNSW_CHANGE_CURSOR, self.last_change)?;
         }
+        Ok(changes.len())
     }
-}
 
-#[tauri::command]
-async fn test_rag_query(
-    query: String,
-    config: RAGConfig,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<RAGResponse, String> {
-    // This is specifically for testing - always use BaseWithRAG mode
-    query_rag_enhanced(query, RAGMode::BaseWithRAG, config, db_state).await
-}
+    // An empty graph has nothing stale in it
+    fn stale_fraction(&self) -> f32 {
+        let total = self.chunk_ids.len();
+        if total == 0 {
+            return 0.0;
+        }
+        (total - self.live.len().min(total)) as f32 / total as f32
+    }
 
-// ---------- Original Tauri Commands --------------------------------------------
+    // Nearest live chunk ids; stale graph entries are skipped, so the search over-fetches by their share
+    fn search(&self, query: &[f32], k: usize) -> Vec<String> {
+        let live_share = (1.0 - self.stale_fraction()).max(1.0 / HNSW_MAX_OVERSAMPLE);
+        let fetch = ((k as f32) / live_share).ceil() as usize + 1;
+        self.graph
+            .search(query, fetch, fetch.max(HNSW_MIN_EF_SEARCH))
+            .into_iter()
+            .filter_map(|neighbour| {
+                let chunk_id = self.chunk_ids.get(neighbour.d_id)?;
+                (self.live.get(chunk_id) == Some(&neighbour.d_id)).then(|| chunk_id.clone())
+            })
+            .take(k)
+            .collect()
+    }
+}
 
-#[tauri::command]
-async fn upload_document(
-    file_path: String,
-    title: Option<String>,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-    app: AppHandle,
-) -> Result<Document, String> {
-    let content = extract_text_from_file(&file_path)
-        .await
-        .map_err(|e| e.to_string())?;
-    
-    let file_name = std::path::Path::new(&file_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("Unknown")
-        .to_string();
-    
-    let doc_title = title.unwrap_or(file_name);
-    let content_hash = calculate_content_hash(&content);
-    let now = Utc::now();
+// Zero vectors have no cosine distance and would poison the graph
+fn is_indexable(vector: &[f32], dimension: usize) -> bool {
+    vector.len() == dimension && vector.iter().any(|v| *v != 0.0)
+}
 
-    // Extract file type before moving file_path
-    let file_type = std::path::Path::new(&file_path)
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+// The graph is built with DistCosine, so its neighbours are only right for cosine similarity
+fn check_hnsw_metric(metric: SimilarityMetric) -> Result<()> {
+    if metric != SimilarityMetric::Cosine {
+        return Err(anyhow::anyhow!(
+            "The HNSW backend only supports cosine similarity, but similarity_metric is {:?}",
+            metric
+        ));
+    }
+    Ok(())
+}
 
-    let document = Document {
-        id: Uuid::new_v4().to_string(),
-        title: doc_title,
-        content: content.clone(),
-        file_path: Some(file_path),
-        file_type,
-        content_hash,
-        created_at: now,
-        updated_at: now,
+fn hnsw_candidates(
+    conn: &Connection,
+    hnsw: &Mutex<Option<HnswIndex>>,
+    metric: SimilarityMetric,
+    query_embedding: &[f32],
+    k: usize,
+) -> Result<Vec<String>> {
+    check_hnsw_metric(metric)?;
+    let mut index = hnsw.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let rebuild = match index.as_mut() {
+        Some(existing) if existing.dimension == query_embedding.len() => {
+            existing.catch_up(conn)?;
+            existing.stale_fraction() > HNSW_MAX_STALE_FRACTION
+        }
+        _ => true,
     };
+    if rebuild {
+        *index = Some(HnswIndex::build(conn, query_embedding.len())?);
+    }
+    Ok(index.as_ref().map(|i| i.search(query_embedding, k)).unwrap_or_default())
+}
 
-    // Save to database
-    {
-        let db = db_state.lock().map_err(|e| e.to_string())?;
-        db.execute(
-            "INSERT INTO documents (id, title, content, file_path, file_type, content_hash, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                document.id,
-                document.title,
-                document.content,
-                document.file_path,
-                document.file_type,
-                document.content_hash,
-                document.created_at.to_rfc3339(),
-                document.updated_at.to_rfc3339(),
-            ],
-        ).map_err(|e| e.to_string())?;
+// Builds the graph in the background so the first query after switching backends stays fast
+fn warm_hnsw_index(app: &AppHandle, config: &RAGConfig) {
+    let (Some(db_state), Some(hnsw_state)) =
+        (app.try_state::<Arc<DbPool>>(), app.try_state::<Arc<Mutex<Option<HnswIndex>>>>())
+    else {
+        return;
+    };
+    if check_hnsw_metric(config.similarity_metric).is_err() {
+        return;
     }
+    let (db, hnsw) = (db_state.inner().clone(), hnsw_state.inner().clone());
+    let model_key = embedding_model_key(&config.embedding_model);
 
-    // Process chunks in background
-    let doc_id = document.id.clone();
-    let db_clone = db_state.inner().clone();
-    let app_clone = app.clone();
-    
-    tokio::spawn(async move {
-        if let Err(e) = process_document_chunks(&doc_id, &content, &db_clone).await {
-            eprintln!("Error processing chunks: {}", e);
+    thread::spawn(move || {
+        let result = (|| -> Result<()> {
+            let conn = db.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            let Some(dimension) = indexed_embedding_dimension(&conn, &model_key) else { return Ok(()) };
+            let mut index = hnsw.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            if index.as_ref().map_or(true, |i| i.dimension != dimension) {
+                *index = Some(HnswIndex::build(&conn, dimension)?);
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            warn!("Failed to build HNSW index: {}", e);
         }
-        let _ = app_clone.emit("document_processed", &doc_id);
     });
+}
+
+// ---------- Vector Index (sqlite-vec) ---------------------------------------------
 
-    Ok(document)
+// Candidates fetched per requested result, since visibility filters run after the index lookup
+const VECTOR_INDEX_OVERFETCH: usize = 4;
+// Upper bound on k accepted by vec0 queries
+const VECTOR_INDEX_MAX_K: usize = 4096;
+
+// Registers vec0 for every connection opened afterwards, so it must run before init_db
+#[cfg(feature = "sqlite-vec")]
+fn register_sqlite_vec() {
+    unsafe {
+        rusqlite::ffi::sqlite3_auto_extension(Some(std::mem::transmute(
+            sqlite_vec::sqlite3_vec_init as *const (),
+        )));
+    }
 }
 
-async fn process_document_chunks(
-    document_id: &str,
-    content: &str,
-    db_state: &Arc<Mutex<Connection>>,
-) -> Result<()> {
-    let chunks = chunk_text(content, 200, 50); // 200 words per chunk, 50 word overlap
-    
-    for (index, chunk_content) in chunks.iter().enumerate() {
-        let embedding = generate_embedding(chunk_content);
-        let embedding_bytes: Vec<u8> = embedding.iter()
-            .flat_map(|f| f.to_le_bytes().to_vec())
-            .collect();
+// vec0 has cosine and L2 distances; both order neighbours like the matching similarity, dot has no equivalent
+fn vec_index_metric(metric: SimilarityMetric) -> Result<&'static str> {
+    match metric {
+        SimilarityMetric::Cosine => Ok("cosine"),
+        SimilarityMetric::Euclidean => Ok("l2"),
+        SimilarityMetric::Dot => Err(anyhow::anyhow!(
+            "The sqlite_vec backend supports cosine and euclidean similarity, but similarity_metric is Dot"
+        )),
+    }
+}
 
-        let chunk = DocumentChunk {
-            id: Uuid::new_v4().to_string(),
-            document_id: document_id.to_string(),
-            chunk_index: index as i32,
-            content: chunk_content.clone(),
-            embedding,
-            created_at: Utc::now(),
-        };
+// One vec0 table per dimension, so switching embedding models never mixes vector sizes
+#[cfg(feature = "sqlite-vec")]
+fn vec_index_table(dimension: usize) -> String {
+    format!("vec_chunks_{}", dimension)
+}
 
-        let db = db_state.lock().map_err(|e| anyhow::anyhow!(e.to_string()))?;
-        db.execute(
-            "INSERT INTO document_chunks (id, document_id, chunk_index, content, embedding, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![
-                chunk.id,
-                chunk.document_id,
-                chunk.chunk_index,
-                chunk.content,
-                embedding_bytes,
-                chunk.created_at.to_rfc3339(),
-            ],
+// The distance the table was created with, read back from its schema
+#[cfg(feature = "sqlite-vec")]
+fn vec_index_distance(conn: &Connection, dimension: usize) -> Result<Option<String>> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![vec_index_table(dimension)],
+            |row| row.get(0),
+        )
+        .ok();
+    Ok(sql.map(|sql| {
+        sql.split("distance_metric=")
+            .nth(1)
+            .map(|rest| rest.trim_end_matches(')').trim().to_string())
+            .unwrap_or_else(|| "l2".to_string()) // vec0's default
+    }))
+}
+
+// Indexes chunks ingested since the last query and drops rows whose chunk is gone. A table built
+// for another metric is dropped and rebuilt, since its neighbours would be wrong for this one
+#[cfg(feature = "sqlite-vec")]
+fn catch_up_vec_index(conn: &Connection, dimension: usize, distance: &str) -> Result<usize> {
+    let table = vec_index_table(dimension);
+    if vec_index_distance(conn, dimension)?.is_some_and(|existing| existing != distance) {
+        info!("Rebuilding {} for {} distance", table, distance);
+        conn.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+        conn.execute(
+            "DELETE FROM vec_chunk_rows WHERE chunk_id IN (SELECT id FROM document_chunks WHERE embedding_dim = ?1)",
+            params![dimension as i64],
+        )?;
+    }
+    conn.execute(
+        &format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING vec0(embedding float[{}] distance_metric={})",
+            table, dimension, distance
+        ),
+        [],
+    )?;
+    conn.execute(
+        &format!("DELETE FROM {} WHERE rowid NOT IN (SELECT id FROM vec_chunk_rows)", table),
+        [],
+    )?;
+
+    let pending: Vec<(String, Vec<u8>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT dc.id, COALESCE(se.embedding, dc.embedding) FROM document_chunks dc
+             LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+             LEFT JOIN vec_chunk_rows r ON r.chunk_id = dc.id
+             WHERE r.id IS NULL AND dc.embedding_dim = ?1",
         )?;
+        let rows = stmt.query_map(params![dimension as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    if pending.is_empty() {
+        return Ok(0);
     }
 
-    Ok(())
+    let tx = rusqlite::Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    for (chunk_id, bytes) in &pending {
+        tx.execute("INSERT INTO vec_chunk_rows (chunk_id) VALUES (?1)", params![chunk_id])?;
+        let row_id = tx.last_insert_rowid();
+        // vec0 takes raw little-endian f32, so int8 vectors are widened on the way in
+        let vector = encode_embedding(&decode_embedding(bytes), EmbeddingStorage::Float32);
+        tx.execute(
+            &format!("INSERT INTO {} (rowid, embedding) VALUES (?1, ?2)", table),
+            params![row_id, vector],
+        )?;
+    }
+    tx.commit()?;
+    info!("Indexed {} chunk(s) in {}", pending.len(), table);
+    Ok(pending.len())
 }
 
-#[tauri::command]
-fn get_documents(
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<Vec<Document>, String> {
-    let db = db_state.lock().map_err(|e| e.to_string())?;
-    let mut stmt = db
-        .prepare("SELECT id, title, content, file_path, file_type, content_hash, created_at, updated_at FROM documents ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let document_iter = stmt
-        .query_map([], |row| {
-            Ok(Document {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                content: row.get(2)?,
-                file_path: row.get(3)?,
-                file_type: row.get(4)?,
-                content_hash: row.get(5)?,
-                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(6)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
-                updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
-            })
-        })
-        .map_err(|e| e.to_string())?;
+// JSON array of candidate chunk ids for the scoring query, None when every chunk should be scanned
+fn vector_index_candidates(
+    conn: &Connection,
+    backend: &VectorBackend,
+    metric: SimilarityMetric,
+    hnsw: &Mutex<Option<HnswIndex>>,
+    query_embedding: &[f32],
+    limit: usize,
+) -> Result<Option<String>> {
+    match backend {
+        VectorBackend::Linear => Ok(None),
+        VectorBackend::LanceDb { .. } | VectorBackend::Qdrant { .. } => Err(anyhow::anyhow!(
+            "External vector stores are queried through VectorStores before the database lock"
+        )),
+        VectorBackend::Hnsw => {
+            let k = limit * VECTOR_INDEX_OVERFETCH;
+            let ids = hnsw_candidates(conn, hnsw, metric, query_embedding, k)?;
+            Ok(Some(serde_json::to_string(&ids)?))
+        }
+        #[cfg(feature = "sqlite-vec")]
+        VectorBackend::SqliteVec => {
+            let dimension = query_embedding.len();
+            let distance = vec_index_metric(metric)?;
+            if !is_read_only(conn) {
+                catch_up_vec_index(conn, dimension, distance)?;
+            } else if vec_index_distance(conn, dimension)?.as_deref() != Some(distance) {
+                // A shared copy without a matching index is still searchable, just slower
+                return Ok(None);
+            }
 
-    let documents: Vec<Document> = document_iter
-        .filter_map(Result::ok)
-        .collect();
+            let k = (limit * VECTOR_INDEX_OVERFETCH).clamp(1, VECTOR_INDEX_MAX_K);
+            let mut stmt = conn.prepare(&format!(
+                "SELECT r.chunk_id FROM
+                     (SELECT rowid FROM {} WHERE embedding MATCH ?1 AND k = ?2) v
+                 JOIN vec_chunk_rows r ON r.id = v.rowid",
+                vec_index_table(dimension)
+            ))?;
+            let query = encode_embedding(query_embedding, EmbeddingStorage::Float32);
+            let ids: Vec<String> = stmt
+                .query_map(params![query, k as i64], |row| row.get(0))?
+                .filter_map(Result::ok)
+                .collect();
+            Ok(Some(serde_json::to_string(&ids)?))
+        }
+        #[cfg(not(feature = "sqlite-vec"))]
+        VectorBackend::SqliteVec => {
+            let _ = (conn, metric, query_embedding, limit);
+            Err(anyhow::anyhow!(
+                "The sqlite_vec vector backend needs a build with the sqlite-vec feature"
+            ))
+        }
+    }
+}
 
-    Ok(documents)
+// Drops every index table; the next query rebuilds the one for its dimension
+fn reset_vec_index(conn: &Connection) -> Result<()> {
+    #[cfg(feature = "sqlite-vec")]
+    {
+        let tables: Vec<String> = {
+            let mut stmt = conn.prepare(
+                "SELECT name FROM sqlite_master
+                 WHERE name LIKE 'vec_chunks_%' AND sql LIKE 'CREATE VIRTUAL TABLE%'",
+            )?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
+        for table in tables {
+            conn.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+        }
+    }
+    conn.execute("DELETE FROM vec_chunk_rows", [])?;
+    Ok(())
 }
 
-#[tauri::command]
-async fn search_documents(
-    query: String,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<Vec<SearchResult>, String> {
-    let query_embedding = generate_embedding(&query);
-    let mut results = Vec::new();
+// ---------- Memory-Mapped Embedding Store ----------------------------------------
 
-    let db = db_state.lock().map_err(|e| e.to_string())?;
-    
-    // Get all chunks with their embeddings
-    let mut stmt = db
-        .prepare("SELECT dc.document_id, dc.content, dc.embedding, d.id, d.title, d.content, d.file_path, d.file_type, d.content_hash, d.created_at, d.updated_at
-                  FROM document_chunks dc
-                  JOIN documents d ON dc.document_id = d.id")
-        .map_err(|e| e.to_string())?;
+const EMBEDDING_STORE_MAGIC: &[u8; 8] = b"RAGEMB01";
+const EMBEDDING_STORE_HEADER_LEN: usize = 16;
 
-    let chunk_iter = stmt
-        .query_map([], |row| {
-            let embedding_bytes: Vec<u8> = row.get(2)?;
-            let embedding: Vec<f32> = embedding_bytes
-                .chunks_exact(4)
-                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                .collect();
+/// Optional sidecar file holding every chunk embedding at a fixed stride, so similarity
+/// scans read vectors straight out of an mmap instead of deserializing BLOBs row by row.
+/// Slot numbers are mapped to chunk ids in the `embedding_slots` table.
+pub struct EmbeddingStore {
+    path: PathBuf,
+    dimension: Option<usize>,
+    mmap: Option<memmap2::Mmap>,
+    slot_count: u64,
+}
 
-            Ok((
-                row.get::<_, String>(1)?, // chunk content
-                embedding,
-                Document {
-                    id: row.get(3)?,
-                    title: row.get(4)?,
-                    content: row.get(5)?,
-                    file_path: row.get(6)?,
-                    file_type: row.get(7)?,
-                    content_hash: row.get(8)?,
-                    created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(9)?)
-                        .unwrap()
-                        .with_timezone(&Utc),
-                    updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(10)?)
-                        .unwrap()
-                        .with_timezone(&Utc),
-                },
-            ))
-        })
-        .map_err(|e| e.to_string())?;
+impl EmbeddingStore {
+    fn new(path: PathBuf) -> Self {
+        Self { path, dimension: None, mmap: None, slot_count: 0 }
+    }
 
-    let mut doc_results: HashMap<String, (Document, Vec<String>, f32)> = HashMap::new();
+    fn stride(&self) -> usize {
+        self.dimension.unwrap_or(0) * 4
+    }
 
-    for chunk_result in chunk_iter {
-        if let Ok((chunk_content, chunk_embedding, document)) = chunk_result {
-            let similarity = cosine_similarity(&query_embedding, &chunk_embedding);
-            
-            if similarity > 0.3 { // Threshold for relevance
-                doc_results
-                    .entry(document.id.clone())
-                    .and_modify(|(_, chunks, max_sim)| {
-                        chunks.push(chunk_content.clone());
-                        *max_sim = max_sim.max(similarity);
-                    })
-                    .or_insert((document, vec![chunk_content], similarity));
-            }
+    // Opens an existing sidecar file; a missing file simply means an empty store
+    fn open(&mut self) -> Result<()> {
+        if !self.path.exists() {
+            self.dimension = None;
+            self.mmap = None;
+            self.slot_count = 0;
+            return Ok(());
+        }
+
+        let file = std::fs::File::open(&self.path)?;
+        // Safety: the file is only ever written by this process, which drops the map before
+        // writing and recreates it afterwards
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        if mmap.len() < EMBEDDING_STORE_HEADER_LEN || &mmap[..8] != EMBEDDING_STORE_MAGIC {
+            return Err(anyhow::anyhow!("{} is not an embedding store", self.path.display()));
         }
+
+        let dimension = u32::from_le_bytes([mmap[8], mmap[9], mmap[10], mmap[11]]) as usize;
+        self.dimension = Some(dimension);
+        self.slot_count = ((mmap.len() - EMBEDDING_STORE_HEADER_LEN) / (dimension * 4).max(1)) as u64;
+        self.mmap = Some(mmap);
+        Ok(())
     }
 
-    // Convert to SearchResult vector and sort by similarity
-    for (_, (document, chunks, similarity)) in doc_results {
-        results.push(SearchResult {
-            document,
-            relevant_chunks: chunks,
-            similarity_score: similarity,
-        });
+    fn vector_bytes(&self, slot: u64) -> Option<&[u8]> {
+        let mmap = self.mmap.as_ref()?;
+        let start = EMBEDDING_STORE_HEADER_LEN + slot as usize * self.stride();
+        mmap.get(start..start + self.stride())
     }
 
-    results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
-    results.truncate(10); // Return top 10 results
+    // Slots below slot_count that no chunk maps to any more, lowest first
+    fn free_slots(&self, conn: &Connection) -> Result<Vec<u64>> {
+        let used: std::collections::HashSet<u64> = {
+            let mut stmt = conn.prepare("SELECT slot FROM embedding_slots")?;
+            let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+            rows.filter_map(Result::ok).map(|slot| slot as u64).collect()
+        };
+        Ok((0..self.slot_count).filter(|slot| !used.contains(slot)).collect())
+    }
 
-    Ok(results)
-}
+    // Fills released slots first and appends the rest, so the file only grows with the live chunk count
+    fn append(&mut self, conn: &Connection, vectors: &[(String, Vec<u8>)]) -> Result<usize> {
+        use std::io::{Seek, SeekFrom, Write};
 
-#[tauri::command]
-async fn chat_with_documents(
-    message: String,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<ChatResponse, String> {
-    // First, search for relevant documents
-    let search_results = search_documents(message.clone(), db_state.clone()).await?;
-    
-    // Save user message
-    let user_msg = ChatMessage {
-        id: Uuid::new_v4().to_string(),
-        content: message.clone(),
-        role: "user".to_string(),
-        document_references: search_results.iter().map(|r| r.document.id.clone()).collect(),
-        created_at: Utc::now(),
-    };
+        if vectors.is_empty() {
+            return Ok(0);
+        }
+
+        let dimension = match self.dimension {
+            Some(dimension) => dimension,
+            None => {
+                let dimension = embedding_dimension(&vectors[0].1);
+                let mut header = Vec::with_capacity(EMBEDDING_STORE_HEADER_LEN);
+                header.extend_from_slice(EMBEDDING_STORE_MAGIC);
+                header.extend_from_slice(&(dimension as u32).to_le_bytes());
+                header.extend_from_slice(&[0u8; 4]);
+                std::fs::write(&self.path, header)?;
+                self.dimension = Some(dimension);
+                dimension
+            }
+        };
 
-    // Generate AI response (mock implementation)
-    let context = search_results
-        .iter()
-        .flat_map(|r| r.relevant_chunks.iter())
-        .cloned()
-        .collect::<Vec<_>>()
-        .join("\n\n");
+        let mut free = self.free_slots(conn)?.into_iter();
+        self.mmap = None;
+        let mut file = std::fs::OpenOptions::new().write(true).open(&self.path)?;
+        let mut appended = 0;
+        for (chunk_id, bytes) in vectors {
+            // The store is always f32 so slots stay fixed-width whatever the database format
+            let vector = decode_embedding(bytes);
+            if vector.len() != dimension {
+                warn!("Skipping chunk {} in embedding store: dimension {} != {}", chunk_id, vector.len(), dimension);
+                continue;
+            }
+            let slot = match free.next() {
+                Some(slot) => slot,
+                None => {
+                    self.slot_count += 1;
+                    self.slot_count - 1
+                }
+            };
+            file.seek(SeekFrom::Start((EMBEDDING_STORE_HEADER_LEN + slot as usize * dimension * 4) as u64))?;
+            file.write_all(&encode_embedding(&vector, EmbeddingStorage::Float32))?;
+            conn.execute(
+                "INSERT OR REPLACE INTO embedding_slots (chunk_id, slot) VALUES (?1, ?2)",
+                params![chunk_id, slot as i64],
+            )?;
+            appended += 1;
+        }
+        file.sync_data()?;
 
-    let ai_response = if context.is_empty() {
-        "I don't have any relevant documents to answer your question. Please upload some documents first.".to_string()
-    } else {
-        format!(
-            "Based on the uploaded documents, here's what I found:\n\n{}\n\nThis information comes from {} document(s) in your knowledge base.",
-            context,
-            search_results.len()
-        )
-    };
+        self.open()?;
+        Ok(appended)
+    }
+}
 
-    let assistant_msg = ChatMessage {
-        id: Uuid::new_v4().to_string(),
-        content: ai_response,
-        role: "assistant".to_string(),
-        document_references: search_results.iter().map(|r| r.document.id.clone()).collect(),
-        created_at: Utc::now(),
+// Appends any chunks ingested since the last scan, so ingestion never has to know about the store
+fn catch_up_embedding_store(conn: &Connection, store: &mut EmbeddingStore) -> Result<usize> {
+    if store.mmap.is_none() {
+        store.open()?;
+    }
+
+    let pending: Vec<(String, Vec<u8>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT dc.id, COALESCE(se.embedding, dc.embedding) FROM document_chunks dc
+             LEFT JOIN shared_embeddings se ON se.key = dc.embedding_key
+             LEFT JOIN embedding_slots es ON es.chunk_id = dc.id
+             WHERE es.chunk_id IS NULL",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
     };
 
-    // Save both messages to database
+    store.append(conn, &pending)
+}
+
+fn scan_embedding_store(
+    conn: &Connection,
+    store: &EmbeddingStore,
+    query_embedding: &[f32],
+    config: &RAGConfig,
+    content_key: Option<&ContentKey>,
+) -> Result<Vec<RetrievalResult>> {
+    if store.dimension.is_some_and(|d| d != query_embedding.len()) {
+        return Err(anyhow::anyhow!(
+            "Embedding store holds {}-dimensional vectors but the query has {}; rebuild the store",
+            store.dimension.unwrap_or(0),
+            query_embedding.len()
+        ));
+    }
+
+    // First pass scores slots only, keeping the best top_k in a bounded heap; chunk text is loaded for the winners afterwards
+    let mut top = TopK::new(config.top_k);
+    let mut buffer: Vec<f32> = Vec::with_capacity(query_embedding.len());
     {
-        let db = db_state.lock().map_err(|e| e.to_string())?;
-        
-        for msg in [&user_msg, &assistant_msg] {
-            db.execute(
-                "INSERT INTO chat_messages (id, content, role, document_references, created_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![
-                    msg.id,
-                    msg.content,
-                    msg.role,
-                    serde_json::to_string(&msg.document_references).unwrap_or_default(),
-                    msg.created_at.to_rfc3339(),
-                ],
-            ).map_err(|e| e.to_string())?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT dc.rowid, es.slot FROM embedding_slots es
+             JOIN document_chunks dc ON dc.id = es.chunk_id
+             JOIN documents d ON dc.document_id = d.id
+             WHERE d.is_searchable = 1 AND d.deleted_at IS NULL AND (d.is_sensitive = 0 OR ?1){}{}",
+            tag_filter_clause(2),
+            chunk_filter_clause(&config.chunk_filter)
+        ))?;
+        let rows = stmt.query_map(params![content_key.is_some(), tag_filter_value(&config.tags)], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        for (row_id, slot) in rows.filter_map(Result::ok) {
+            let Some(bytes) = store.vector_bytes(slot as u64) else { continue };
+            buffer.clear();
+            buffer.extend(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+
+            let similarity = compute_similarity(config.similarity_metric, query_embedding, &buffer);
+            if similarity > config.similarity_threshold {
+                top.offer(similarity, row_id);
+            }
         }
     }
 
-    Ok(ChatResponse {
-        message: assistant_msg,
-        sources: search_results,
-    })
+    let winners = top.into_sorted();
+    let mut results = Vec::with_capacity(winners.len());
+    for (similarity, row_id) in winners {
+        let (chunk_id, content, doc_title, file_path, is_sensitive, chunk_metadata): (
+            String,
+            String,
+            String,
+            Option<String>,
+            bool,
+            Option<String>,
+        ) = conn.query_row(
+            "SELECT dc.id, dc.content, d.title, d.file_path, d.is_sensitive, dc.metadata
+             FROM document_chunks dc JOIN documents d ON dc.document_id = d.id
+             WHERE dc.rowid = ?1",
+            params![row_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )?;
+
+        let content = match (is_sensitive, content_key) {
+            (false, _) => content,
+            (true, Some(key)) => decrypt_text(key, &content)?,
+            (true, None) => continue,
+        };
+
+        results.push(RetrievalResult {
+            chunk_id,
+            content,
+            document_title: doc_title,
+            similarity_score: similarity,
+            source_info: chunk_source_info(file_path, chunk_metadata),
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        });
+    }
+
+    Ok(results)
 }
 
 #[tauri::command]
-fn get_chat_history(
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<Vec<ChatMessage>, String> {
+fn rebuild_embedding_store(
+    db_state: tauri::State<'_, Arc<DbPool>>,
+    store_state: tauri::State<'_, Arc<Mutex<EmbeddingStore>>>,
+) -> Result<usize, String> {
     let db = db_state.lock().map_err(|e| e.to_string())?;
-    let mut stmt = db
-        .prepare("SELECT id, content, role, document_references, created_at FROM chat_messages ORDER BY created_at ASC")
-        .map_err(|e| e.to_string())?;
+    ensure_writable(&db).map_err(|e| e.to_string())?;
+    let mut store = store_state.lock().map_err(|e| e.to_string())?;
+    let written = reset_embedding_store(&db, &mut store).map_err(|e| e.to_string())?;
+    info!("Rebuilt embedding store with {} vectors", written);
+    Ok(written)
+}
 
-    let message_iter = stmt
-        .query_map([], |row| {
-            let doc_refs_str: String = row.get(3)?;
-            let document_references: Vec<String> = serde_json::from_str(&doc_refs_str)
-                .unwrap_or_default();
+// Rebuilding also truncates the file to the live chunks; catch-up only reuses slots and never shrinks it
+fn reset_embedding_store(conn: &Connection, store: &mut EmbeddingStore) -> Result<usize> {
+    store.mmap = None;
+    if store.path.exists() {
+        std::fs::remove_file(&store.path)?;
+    }
+    conn.execute("DELETE FROM embedding_slots", [])?;
+    store.open()?;
+    catch_up_embedding_store(conn, store)
+}
 
-            Ok(ChatMessage {
-                id: row.get(0)?,
-                content: row.get(1)?,
-                role: row.get(2)?,
-                document_references,
-                created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
-            })
-        })
-        .map_err(|e| e.to_string())?;
+// ---------- Hardware Acceleration ----------------------------------------------
 
-    let messages: Vec<ChatMessage> = message_iter
-        .filter_map(Result::ok)
-        .collect();
+// Acceleration only applies to local models, which all run on candle; there is no ort or llama.cpp backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccelerationInfo {
+    pub inference_backend: String,
+    pub compiled_backends: Vec<String>, // devices candle was built for
+    pub device: String,
+    pub cuda_available: bool,
+    pub metal_available: bool,
+    pub embedding_model: String,
+    pub local_embedding_tokens_per_sec: Option<f64>, // None when the configured embedding model is not local
+    pub benchmark_ms: u64,
+}
+
+const LOCAL_INFERENCE_BACKEND: &str = "candle";
 
-    Ok(messages)
+const ACCELERATION_BENCHMARK_TEXT: &str = "Retrieval-augmented generation combines a search step over your own documents with a language model, so answers can cite the passages they were drawn from.";
+const ACCELERATION_BENCHMARK_RUNS: usize = 8;
+
+fn compiled_acceleration_backends() -> Vec<String> {
+    let mut backends = vec!["cpu".to_string()];
+    if cfg!(feature = "cuda") {
+        backends.push("cuda".to_string());
+    }
+    if cfg!(feature = "metal") {
+        backends.push("metal".to_string());
+    }
+    backends
 }
 
-#[tauri::command]
-fn delete_document(
-    document_id: String,
-    db_state: tauri::State<'_, Arc<Mutex<Connection>>>,
-) -> Result<(), String> {
-    let db = db_state.lock().map_err(|e| e.to_string())?;
-    
-    // Delete document (chunks will be deleted automatically due to CASCADE)
-    db.execute("DELETE FROM documents WHERE id = ?", params![document_id])
-        .map_err(|e| e.to_string())?;
+#[cfg(feature = "local-embeddings")]
+static COMPUTE_DEVICE: OnceLock<candle_core::Device> = OnceLock::new();
 
-    Ok(())
+// Picks the fastest device compiled in and actually present, falling back to CPU
+#[cfg(feature = "local-embeddings")]
+fn compute_device() -> &'static candle_core::Device {
+    COMPUTE_DEVICE.get_or_init(|| {
+        #[cfg(feature = "cuda")]
+        if candle_core::utils::cuda_is_available() {
+            match candle_core::Device::new_cuda(0) {
+                Ok(device) => return device,
+                Err(e) => warn!("CUDA reported available but failed to initialise: {}", e),
+            }
+        }
+        #[cfg(feature = "metal")]
+        if candle_core::utils::metal_is_available() {
+            match candle_core::Device::new_metal(0) {
+                Ok(device) => return device,
+                Err(e) => warn!("Metal reported available but failed to initialise: {}", e),
+            }
+        }
+        candle_core::Device::Cpu
+    })
+}
+
+fn compute_device_name() -> String {
+    #[cfg(feature = "local-embeddings")]
+    {
+        match compute_device() {
+            candle_core::Device::Cpu => "cpu".to_string(),
+            candle_core::Device::Cuda(_) => "cuda:0".to_string(),
+            candle_core::Device::Metal(_) => "metal:0".to_string(),
+        }
+    }
+    #[cfg(not(feature = "local-embeddings"))]
+    {
+        "cpu".to_string()
+    }
 }
 
 #[tauri::command]
-async fn run_fine_tune(config: String, app: AppHandle) -> Result<(), String> {
-    let script_path = app
-        .path_resolver()
-        .resolve_resource("../backend/fine_tune.py")
-        .ok_or("Script not found")?;
+async fn get_acceleration_info(
+    config: Option<RAGConfig>,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
+    providers_state: tauri::State<'_, Arc<EmbeddingProviders>>,
+) -> Result<AccelerationInfo, String> {
+    let config = match config {
+        Some(config) => config,
+        None => config_state.lock().map_err(|e| e.to_string())?.clone(),
+    };
 
-        let mut child = Command::new("python3")
-        .arg(script_path)
-        .arg(config)
-            .stdout(Stdio::piped())
-            .spawn()
-        .map_err(|e| e.to_string())?;
+    let embedding_model = match &config.embedding_model {
+        EmbeddingModel::HuggingFace { model_name, .. } => model_name.clone(),
+        EmbeddingModel::OpenAI { model, .. } => model.clone(),
+        EmbeddingModel::Local { model_path } => model_path.clone(),
+        EmbeddingModel::Plugin { provider, model } => format!("{}/{}", provider, model),
+    };
 
-        if let Some(stdout) = child.stdout.take() {
-        let app_handle = app.clone();
-        tauri::async_runtime::spawn(async move {
-            let mut reader = BufReader::new(stdout).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                let _ = app_handle.emit("fine_tune_log", line);
+    // Only a local model runs on `device`; timing a remote provider would measure the network instead
+    let (local_embedding_tokens_per_sec, elapsed) = if matches!(config.embedding_model, EmbeddingModel::Local { .. }) {
+        let tokens_per_run = load_tokenizer_async(&config.embedding_model)
+            .await
+            .ok()
+            .and_then(|t| t.encode(ACCELERATION_BENCHMARK_TEXT, false).ok().map(|e| e.len()))
+            .unwrap_or_else(|| ACCELERATION_BENCHMARK_TEXT.split_whitespace().count());
+
+        // embed() would answer repeats from embedding_cache, so every run goes to the model; the untimed
+        // warm-up keeps model loading out of the measurement
+        let texts = [ACCELERATION_BENCHMARK_TEXT.to_string()];
+        let _ = providers_state.embed_uncached(&texts, &config).await;
+        let start_time = std::time::Instant::now();
+        let mut succeeded = 0;
+        for _ in 0..ACCELERATION_BENCHMARK_RUNS {
+            if providers_state.embed_uncached(&texts, &config).await.is_ok() {
+                succeeded += 1;
             }
-        });
-    }
+        }
+        let elapsed = start_time.elapsed();
 
-    child.wait().await.map_err(|e| e.to_string())?;
-    Ok(())
+        let tokens_per_sec = if succeeded > 0 && elapsed.as_secs_f64() > 0.0 {
+            Some((tokens_per_run * succeeded) as f64 / elapsed.as_secs_f64())
+        } else {
+            None
+        };
+        (tokens_per_sec, elapsed)
+    } else {
+        (None, Duration::ZERO)
+    };
+
+    #[cfg(feature = "cuda")]
+    let cuda_available = candle_core::utils::cuda_is_available();
+    #[cfg(not(feature = "cuda"))]
+    let cuda_available = false;
+    #[cfg(feature = "metal")]
+    let metal_available = candle_core::utils::metal_is_available();
+    #[cfg(not(feature = "metal"))]
+    let metal_available = false;
+
+    Ok(AccelerationInfo {
+        inference_backend: LOCAL_INFERENCE_BACKEND.to_string(),
+        compiled_backends: compiled_acceleration_backends(),
+        device: compute_device_name(),
+        cuda_available,
+        metal_available,
+        embedding_model,
+        local_embedding_tokens_per_sec,
+        benchmark_ms: elapsed.as_millis() as u64,
+    })
 }
 
 // ---------- Main Application ---------------------------------------------------
 
 fn main() {
+    #[cfg(feature = "sqlite-vec")]
+    register_sqlite_vec();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
@@ -1156,18 +17961,75 @@ fn main() {
         .setup(|app| {
             // Initialize database
             let data_dir = app_data_dir(&app.app_handle())?;
-            let db_path = data_dir.join("rag_documents.db");
-            let conn = Connection::open(db_path)?;
-            
-            init_db(&conn).expect("Failed to initialize database");
+            let db_path = data_dir.join(LOCAL_DATABASE_FILE);
+            app.manage(Arc::new(Mutex::new(EmbeddingStore::new(data_dir.join("embeddings.f32")))));
+            let local = DbPool::open(&db_path, DB_POOL_SIZE)?;
+            {
+                let conn = local.lock()?;
+                init_db(&conn).expect("Failed to initialize database");
+
+                let extractor_plugins = load_extractor_plugins(&conn).unwrap_or_else(|e| {
+                    warn!("Failed to load extractor plugins: {}", e);
+                    Vec::new()
+                });
+                app.manage(Arc::new(Mutex::new(extractor_plugins)));
+
+                if let Err(e) = load_provider_plugins(&conn) {
+                    warn!("Failed to load provider plugins: {}", e);
+                }
+                if let Err(e) = load_llm_api_keys(&conn) {
+                    warn!("Failed to load LLM API keys: {}", e);
+                }
+            }
             
-            let db = Arc::new(Mutex::new(conn));
+            // The embedding cache stays local even when documents come from a shared copy
+            let cache_db = Arc::new(DbPool::open(&db_path, 2)?);
+            let pool = match load_knowledge_base_settings(&data_dir).shared_path {
+                Some(shared_path) => match open_shared_knowledge_base(Path::new(&shared_path)) {
+                    Ok(shared) => {
+                        info!("Using shared read-only knowledge base at {}", shared_path);
+                        shared
+                    }
+                    Err(e) => {
+                        warn!("Shared knowledge base {} unavailable, using the local one: {}", shared_path, e);
+                        local
+                    }
+                },
+                None => local,
+            };
+
+            let db = Arc::new(pool);
+            spawn_retention_job(db.clone(), app.app_handle().clone());
+
+            // Embedding backends keep their clients and loaded models for the whole session
+            app.manage(Arc::new(EmbeddingProviders::with_builtin().with_cache(cache_db)));
             app.manage(db);
+
+            // Sensitive documents stay locked until unlocked in this session
+            app.manage(Arc::new(Mutex::new(None::<ContentKey>)));
+
+            // Files attached to a single chat session, discarded when it closes
+            app.manage(Arc::new(Mutex::new(SessionAttachments::default())));
+
+            // Built on first use of the hnsw backend
+            app.manage(Arc::new(Mutex::new(None::<HnswIndex>)));
+            // External vector stores are connected on first use
+            app.manage(Arc::new(VectorStores::new(data_dir.clone())));
+            app.manage(Arc::new(ActiveGenerations::default()));
+
+            // Watched folders need every other piece of state, so they start last
+            let watchers = Arc::new(FolderWatchers::default());
+            app.manage(watchers.clone());
             
             // Initialize default RAG configuration
             let default_config = RAGConfig::default();
             app.manage(Arc::new(Mutex::new(default_config)));
 
+            let db_state = app.state::<Arc<DbPool>>().inner().clone();
+            if let Err(e) = start_watched_folders(app.app_handle(), &db_state, &watchers) {
+                warn!("Failed to start folder watchers: {}", e);
+            }
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -1177,13 +18039,153 @@ fn main() {
             search_documents,
             chat_with_documents,
             get_chat_history,
+            // Chat session commands
+            create_chat_session,
+            list_chat_sessions,
+            rename_chat_session,
+            delete_chat_session,
+            export_chat_session,
+            regenerate_answer,
+            // Message feedback commands
+            rate_message,
+            list_message_feedback,
             delete_document,
+            set_document_searchable,
+            update_document_metadata,
+            get_query_history,
+            unlock_sensitive_documents,
+            lock_sensitive_documents,
+            set_document_sensitive,
+            get_audit_log,
+            // Sync commands
+            set_sync_target,
+            get_sync_status,
+            sync_now,
+            list_conflicts,
+            resolve_conflict,
+            // Webhook commands
+            add_webhook,
+            remove_webhook,
+            list_webhooks,
+            get_webhook_deliveries,
+            // Extractor plugin commands
+            register_extractor_plugin,
+            unregister_extractor_plugin,
+            list_extractor_plugins,
+            // Provider plugin commands
+            register_provider_plugin,
+            unregister_provider_plugin,
+            list_providers,
+            get_provider_telemetry,
+            // LLM API key commands
+            set_llm_api_key,
+            remove_llm_api_key,
+            list_llm_api_keys,
+            get_acceleration_info,
+            rebuild_embedding_store,
+            reimport_files,
+            reprocess_document,
+            // Retention commands
+            add_retention_rule,
+            remove_retention_rule,
+            list_retention_rules,
+            run_retention_now,
+            restore_document,
+            load_demo_content,
+            // Retrieval regression commands
+            add_golden_question,
+            remove_golden_question,
+            list_golden_questions,
+            check_config_regression,
+            // Evaluation commands
+            delete_eval_set,
+            list_eval_sets,
+            add_eval_case,
+            list_eval_cases,
+            run_evaluation,
+            list_eval_runs,
+            delete_eval_run,
+            // Benchmark commands
+            benchmark_retrieval,
+            // Chunk usage commands
+            accept_answer,
+            get_chunk_stats,
+            // Folder filter commands
+            preview_folder_filter,
+            ingest_directory,
+            list_spreadsheet_sheets,
+            preview_chunks,
+            // Web ingestion commands
+            ingest_url,
+            crawl_website,
+            // Watched folder commands
+            add_watched_folder,
+            remove_watched_folder,
+            list_watched_folders,
+            // Prompt template commands
+            save_prompt_template,
+            delete_prompt_template,
+            list_prompt_templates,
+            // Chat tool commands
+            register_chat_tool,
+            remove_chat_tool,
+            list_chat_tools,
+            // Document summary commands
+            summarize_document,
+            get_document_summary,
+            list_document_summaries,
+            // Entity commands
+            extract_document_entities,
+            query_entities,
+            related_entities,
+            // Language commands
+            detect_text_language,
+            get_language_overview,
+            // Tag commands
+            tag_document,
+            untag_document,
+            list_tags,
+            // Auto-tagging commands
+            auto_tag_document,
+            get_topic_overview,
+            // Bulk document commands
+            delete_documents,
+            tag_documents,
+            move_documents,
+            reprocess_documents,
+            // Session attachment commands
+            attach_session_file,
+            list_session_files,
+            close_session,
+            // Keyword search commands
+            keyword_search,
+            // Pipeline manifest commands
+            export_pipeline_manifest,
+            apply_pipeline_manifest,
+            // Personalization commands
+            record_document_open,
+            reset_personalization,
+            // Embedding maintenance commands
+            clear_embedding_cache,
+            reembed_all_documents,
+            // Shared knowledge base commands
+            get_knowledge_base_info,
+            set_shared_knowledge_base,
+            // Backup commands
+            backup_database,
+            restore_database,
+            // Knowledge base archive commands
+            export_knowledge_base,
+            import_knowledge_base,
             // Enhanced RAG commands
             set_rag_config,
             get_rag_config,
             process_document_enhanced,
             query_rag_enhanced,
             test_rag_query,
+            compare_configs,
+            cancel_generation,
+            query_rag_agentic,
             // Fine-tune command from remote
             run_fine_tune,
             // System monitoring commands
@@ -1305,8 +18307,16 @@ pub struct DockerConfig {
 }
 
 #[tauri::command]
-async fn export_model_to_huggingface(config: ExportConfig) -> Result<String, String> {
-    info!("Exporting model to HuggingFace format: {:?}", config);
+async fn export_model_to_huggingface(
+    config: ExportConfig,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<String, String> {
+    info!("Exporting model to HuggingFace format: {:?}", config.model_name);
+    record_audit_state(&db_state, "model_exported", Some(&config.model_name), serde_json::json!({
+        "output_dir": config.output_dir,
+        "push_to_hub": config.push_to_hub,
+        "hub_repo_name": config.hub_repo_name,
+    }));
     
     // Mock implementation - in real app, this would call the Python export script
     let output = format!(
@@ -1323,8 +18333,17 @@ async fn export_model_to_huggingface(config: ExportConfig) -> Result<String, Str
 }
 
 #[tauri::command]
-async fn generate_fastapi_endpoint(config: APIConfig, output_dir: String) -> Result<String, String> {
-    info!("Generating FastAPI endpoint: {:?}", config);
+async fn generate_fastapi_endpoint(
+    config: APIConfig,
+    output_dir: String,
+    db_state: tauri::State<'_, Arc<DbPool>>,
+) -> Result<String, String> {
+    info!("Generating FastAPI endpoint: {}", config.api_name);
+    record_audit_state(&db_state, "api_server_configured", Some(&config.api_name), serde_json::json!({
+        "host": config.host,
+        "port": config.port,
+        "auth_enabled": config.auth_token.is_some(),
+    }));
     
     // Mock implementation - in real app, this would generate the API files
     Ok(format!(
@@ -1350,12 +18369,23 @@ async fn generate_docker_deployment(config: DockerConfig, output_dir: String) ->
 async fn chat_base_model(
     query: String, 
     temperature: f32, 
-    max_tokens: u32
+    max_tokens: u32,
+    config_state: tauri::State<'_, Arc<Mutex<RAGConfig>>>,
 ) -> Result<ChatResponse, String> {
     info!("Chat with base model: {}", query);
     
-    // Mock implementation for base model chat
-    let response = format!("Base model response to: {}", query);
+    let config = RAGConfig {
+        temperature: Some(temperature),
+        max_tokens: Some(max_tokens),
+        ..config_state.lock().map_err(|e| e.to_string())?.clone()
+    };
+    let response = match generation_target(&config) {
+        Some(target) => generate_text(&target, &query, &generation_options(&config, AnswerStyle::default()))
+            .await
+            .map_err(|e| e.to_string())?,
+        // Mock implementation when no generation backend is configured
+        None => format!("Base model response to: {}", query),
+    };
     
     Ok(ChatResponse {
         message: ChatMessage {
@@ -1363,6 +18393,7 @@ async fn chat_base_model(
             role: "assistant".to_string(),
         },
         sources: vec![],
+        tool_calls: Vec::new(),
     })
 }
 
@@ -1383,6 +18414,7 @@ async fn chat_fine_tuned(
             role: "assistant".to_string(),
         },
         sources: vec![],
+        tool_calls: Vec::new(),
     })
 }
 
@@ -1424,5 +18456,457 @@ async fn chat_hybrid_mode(
             role: "assistant".to_string(),
         },
         sources,
+        tool_calls: Vec::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passage(chunk_id: &str, score: f32) -> RetrievalResult {
+        sourced_passage(chunk_id, score, "Doc", "doc.txt", "")
+    }
+
+    fn sourced_passage(chunk_id: &str, score: f32, title: &str, source: &str, content: &str) -> RetrievalResult {
+        RetrievalResult {
+            chunk_id: chunk_id.to_string(),
+            content: content.to_string(),
+            document_title: title.to_string(),
+            similarity_score: score,
+            source_info: source.to_string(),
+            merged_chunk_ids: Vec::new(),
+            merged_sources: Vec::new(),
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+        }
+    }
+
+    fn chunk_ids(results: &[RetrievalResult]) -> Vec<&str> {
+        results.iter().map(|r| r.chunk_id.as_str()).collect()
+    }
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    fn test_document(id: &str, title: &str) -> Document {
+        let now = Utc::now();
+        Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: String::new(),
+            file_path: None,
+            file_type: "txt".to_string(),
+            content_hash: calculate_content_hash(id),
+            is_searchable: true,
+            is_sensitive: false,
+            metadata: serde_json::json!({}),
+            tags: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn insert_test_chunk(conn: &Connection, document_id: &str, chunk_id: &str, content: &str, embedding: &[f32]) {
+        conn.execute(
+            "INSERT INTO document_chunks (id, document_id, chunk_index, content, embedding, embedding_model, embedding_dim, created_at)
+             VALUES (?1, ?2, 0, ?3, ?4, 'test', ?5, ?6)",
+            params![
+                chunk_id,
+                document_id,
+                content,
+                encode_embedding(embedding, EmbeddingStorage::Float32),
+                embedding.len() as i64,
+                Utc::now().to_rfc3339(),
+            ],
+        )
+        .unwrap();
+    }
+
+    fn clock(entries: &[(&str, u64)]) -> VectorClock {
+        entries.iter().map(|(device, counter)| (device.to_string(), *counter)).collect()
+    }
+
+    // ---------- Fusion
+
+    #[test]
+    fn fuse_query_rankings_handles_no_rankings() {
+        assert!(fuse_query_rankings(Vec::new(), 5).is_empty());
+        assert!(fuse_query_rankings(vec![Vec::new(), Vec::new()], 5).is_empty());
+    }
+
+    #[test]
+    fn fuse_query_rankings_rewards_chunks_found_by_several_phrasings() {
+        let fused = fuse_query_rankings(
+            vec![vec![passage("a", 0.9), passage("b", 0.8)], vec![passage("b", 0.7), passage("c", 0.6)]],
+            5,
+        );
+        assert_eq!(chunk_ids(&fused), ["b", "a", "c"]);
+        assert!(fused.iter().all(|r| (0.0..=1.0).contains(&r.similarity_score)));
+    }
+
+    #[test]
+    fn fuse_query_rankings_keeps_input_order_on_ties_and_truncates() {
+        let fused = fuse_query_rankings(vec![vec![passage("a", 0.5)], vec![passage("b", 0.5)]], 5);
+        assert_eq!(chunk_ids(&fused), ["a", "b"]);
+        assert_eq!(fused[0].similarity_score, fused[1].similarity_score);
+
+        let fused = fuse_query_rankings(vec![vec![passage("a", 0.9), passage("b", 0.8), passage("c", 0.7)]], 2);
+        assert_eq!(chunk_ids(&fused), ["a", "b"]);
+        assert_eq!(fused[0].similarity_score, 1.0);
+    }
+
+    #[test]
+    fn fuse_keyword_matches_is_a_no_op_when_off() {
+        let conn = test_db();
+        let mut results = vec![passage("a", 0.9)];
+        fuse_keyword_matches(&conn, &mut results, "anything", &RAGConfig::default()).unwrap();
+        assert_eq!(chunk_ids(&results), ["a"]);
+        assert_eq!(results[0].similarity_score, 0.9);
+        assert_eq!(results[0].vector_score, None);
+    }
+
+    #[test]
+    fn fuse_keyword_matches_adds_keyword_only_chunks() {
+        let conn = test_db();
+        insert_document(&conn, &test_document("d1", "Notes")).unwrap();
+        insert_test_chunk(&conn, "d1", "c1", "invoice total for march", &[1.0, 0.0]);
+        insert_test_chunk(&conn, "d1", "c2", "error code E1234 appears on startup", &[0.0, 1.0]);
+        let config = RAGConfig { hybrid_fusion: HybridFusion::Rrf, ..RAGConfig::default() };
+
+        let mut results = vec![passage("c1", 0.9)];
+        fuse_keyword_matches(&conn, &mut results, "E1234", &config).unwrap();
+        // Each chunk tops one ranking, so they tie and the vector result keeps its place
+        assert_eq!(chunk_ids(&results), ["c1", "c2"]);
+        assert_eq!(results[0].similarity_score, results[1].similarity_score);
+        assert_eq!(results[1].vector_score, None);
+        assert!(results[1].keyword_score.is_some());
+
+        let mut results = vec![passage("c2", 0.2), passage("c1", 0.9)];
+        fuse_keyword_matches(&conn, &mut results, "invoice", &config).unwrap();
+        assert_eq!(chunk_ids(&results), ["c1", "c2"]);
+        assert_eq!(results[0].similarity_score, 1.0);
+    }
+
+    #[test]
+    fn fuse_keyword_matches_handles_empty_inputs() {
+        let conn = test_db();
+        let config = RAGConfig { hybrid_fusion: HybridFusion::Rrf, ..RAGConfig::default() };
+        let mut results = Vec::new();
+        fuse_keyword_matches(&conn, &mut results, "", &config).unwrap();
+        assert!(results.is_empty());
+        fuse_keyword_matches(&conn, &mut results, "nothing indexed", &config).unwrap();
+        assert!(results.is_empty());
+    }
+
+    // ---------- Deduplication
+
+    #[test]
+    fn deduplicate_context_handles_empty_and_distinct_passages() {
+        assert!(deduplicate_context(Vec::new()).is_empty());
+
+        let results = vec![
+            sourced_passage("a", 0.9, "Doc", "doc.txt", "Revenue grew in every region this year."),
+            sourced_passage("b", 0.8, "Doc", "doc.txt", "Completely unrelated passage about gardening tools."),
+        ];
+        assert_eq!(chunk_ids(&deduplicate_context(results)), ["a", "b"]);
+    }
+
+    #[test]
+    fn deduplicate_context_folds_contained_passages() {
+        let results = vec![
+            sourced_passage("a", 0.7, "Doc", "doc.txt", "The warranty covers parts and labour for two years."),
+            sourced_passage("b", 0.9, "Doc", "doc.txt", "parts and labour"),
+        ];
+        let deduplicated = deduplicate_context(results);
+        assert_eq!(chunk_ids(&deduplicated), ["a"]);
+        assert_eq!(deduplicated[0].merged_chunk_ids, ["b"]);
+        assert_eq!(deduplicated[0].similarity_score, 0.9);
+    }
+
+    #[test]
+    fn deduplicate_context_keeps_the_anchor_source_of_cross_document_duplicates() {
+        let results = vec![
+            sourced_passage("a", 0.9, "Report", "report.pdf", "The quarterly report shows revenue grew in every region this year."),
+            sourced_passage("b", 0.8, "Copy", "copy.pdf", "The quarterly report shows revenue grew in every region this year!"),
+            sourced_passage("c", 0.7, "Report", "report.pdf", "in every region this year. Costs fell slightly over the same period."),
+        ];
+        let deduplicated = deduplicate_context(results);
+        assert_eq!(deduplicated.len(), 1);
+        let kept = &deduplicated[0];
+        assert_eq!(kept.document_title, "Report");
+        assert_eq!(kept.source_info, "report.pdf");
+        assert_eq!(
+            kept.merged_sources,
+            [PassageSource { document_title: "Copy".to_string(), source_info: "copy.pdf".to_string() }]
+        );
+        // The same-source continuation still joins the anchor after the cross-document merge
+        assert_eq!(kept.merged_chunk_ids, ["b", "c"]);
+        assert!(kept.content.ends_with("Costs fell slightly over the same period."));
+    }
+
+    // ---------- HNSW staleness
+
+    #[test]
+    fn hnsw_index_skips_deleted_and_reembedded_chunks() {
+        let conn = test_db();
+        insert_document(&conn, &test_document("d1", "Vectors")).unwrap();
+        insert_test_chunk(&conn, "d1", "c1", "one", &[1.0, 0.0, 0.0]);
+        insert_test_chunk(&conn, "d1", "c2", "two", &[0.0, 1.0, 0.0]);
+        insert_test_chunk(&conn, "d1", "c3", "three", &[0.0, 0.0, 1.0]);
+        insert_test_chunk(&conn, "d1", "c4", "four", &[1.0, 1.0, 0.0]);
+
+        let mut index = HnswIndex::build(&conn, 3).unwrap();
+        assert_eq!(index.stale_fraction(), 0.0);
+        assert_eq!(index.search(&[1.0, 0.0, 0.0], 1), ["c1"]);
+
+        conn.execute("DELETE FROM document_chunks WHERE id = 'c1'", []).unwrap();
+        assert_eq!(index.catch_up(&conn).unwrap(), 1);
+        assert_eq!(index.stale_fraction(), 0.25);
+        let found = index.search(&[1.0, 0.0, 0.0], 4);
+        assert!(!found.contains(&"c1".to_string()));
+        assert_eq!(found.len(), 3);
+
+        conn.execute(
+            "UPDATE document_chunks SET embedding = ?1 WHERE id = 'c2'",
+            params![encode_embedding(&[0.0, 1.0, 1.0], EmbeddingStorage::Float32)],
+        )
+        .unwrap();
+        index.catch_up(&conn).unwrap();
+        assert_eq!(index.chunk_ids.len(), 5);
+        assert_eq!(index.live.len(), 3);
+        assert_eq!(index.live.get("c2"), Some(&4));
+    }
+
+    #[test]
+    fn hnsw_candidates_rebuild_a_mostly_stale_index() {
+        let conn = test_db();
+        insert_document(&conn, &test_document("d1", "Vectors")).unwrap();
+        insert_test_chunk(&conn, "d1", "c1", "one", &[1.0, 0.0]);
+        insert_test_chunk(&conn, "d1", "c2", "two", &[0.0, 1.0]);
+        let hnsw = Mutex::new(Some(HnswIndex::build(&conn, 2).unwrap()));
+
+        conn.execute("DELETE FROM document_chunks WHERE id = 'c1'", []).unwrap();
+        let found = hnsw_candidates(&conn, &hnsw, SimilarityMetric::Cosine, &[1.0, 0.0], 2).unwrap();
+        assert_eq!(found, ["c2"]);
+        let index = hnsw.lock().unwrap();
+        assert_eq!(index.as_ref().map(|i| i.chunk_ids.len()), Some(1));
+        assert_eq!(index.as_ref().map(HnswIndex::stale_fraction), Some(0.0));
+    }
+
+    #[test]
+    fn hnsw_index_over_an_empty_corpus_has_nothing_stale() {
+        let conn = test_db();
+        let index = HnswIndex::build(&conn, 3).unwrap();
+        assert_eq!(index.stale_fraction(), 0.0);
+        assert!(hnsw_candidates(&conn, &Mutex::new(None), SimilarityMetric::Dot, &[1.0], 1).is_err());
+    }
+
+    // ---------- robots.txt
+
+    #[test]
+    fn robots_rules_only_read_the_wildcard_group() {
+        let rules = RobotsRules::parse(
+            "User-agent: Googlebot\nDisallow: /\n\nUser-agent: *\nDisallow: /private # staff only\nDisallow:\n",
+        );
+        assert!(rules.allows("/"));
+        assert!(rules.allows("/public/page"));
+        assert!(!rules.allows("/private/page"));
+
+        let rules = RobotsRules::parse("User-agent: Googlebot\nUser-agent: *\nDisallow: /tmp\n\nUser-agent: Bingbot\nDisallow: /\n");
+        assert!(!rules.allows("/tmp/file"));
+        assert!(rules.allows("/index.html"));
+    }
+
+    #[test]
+    fn robots_rules_allow_everything_when_empty() {
+        assert!(RobotsRules::parse("").allows("/anything"));
+        assert!(RobotsRules::default().allows("/"));
+    }
+
+    #[test]
+    fn robots_rules_prefer_the_longest_match_and_allow_on_ties() {
+        let rules = RobotsRules::parse("User-agent: *\nDisallow: /docs\nAllow: /docs/public\nAllow: /page\nDisallow: /page\n");
+        assert!(!rules.allows("/docs/internal"));
+        assert!(rules.allows("/docs/public/index.html"));
+        assert!(rules.allows("/page"));
+    }
+
+    #[test]
+    fn robots_rules_support_wildcards_and_end_anchors() {
+        let rules = RobotsRules::parse("User-agent: *\nDisallow: /*.pdf$\nDisallow: /*?session=\nAllow: /files/*.pdf$\n");
+        assert!(!rules.allows("/reports/q1.pdf"));
+        assert!(rules.allows("/reports/q1.pdf?download=1"));
+        assert!(rules.allows("/files/q1.pdf"));
+        assert!(!rules.allows_url(&reqwest::Url::parse("https://example.com/cart?session=42").unwrap()));
+        assert!(rules.allows_url(&reqwest::Url::parse("https://example.com/cart").unwrap()));
+    }
+
+    #[test]
+    fn robots_pattern_matching_edge_cases() {
+        assert!(robots_pattern_matches("/", "/anything"));
+        assert!(robots_pattern_matches("*", ""));
+        assert!(robots_pattern_matches("/a*b*c", "/a-x-b-y-c-z"));
+        assert!(!robots_pattern_matches("/a*c*b", "/a-b-c"));
+        assert!(!robots_pattern_matches("/a$", "/ab"));
+        assert!(robots_pattern_matches("/a$", "/a"));
+        assert!(robots_pattern_matches("/*$", "/"));
+    }
+
+    // ---------- Sync conflicts
+
+    #[test]
+    fn compare_clocks_orders_vector_clocks() {
+        assert_eq!(compare_clocks(&clock(&[]), &clock(&[])), ClockOrdering::Equal);
+        assert_eq!(compare_clocks(&clock(&[("a", 1)]), &clock(&[("a", 1), ("b", 0)])), ClockOrdering::Equal);
+        assert_eq!(compare_clocks(&clock(&[("a", 2)]), &clock(&[("a", 1)])), ClockOrdering::After);
+        assert_eq!(compare_clocks(&clock(&[("a", 1)]), &clock(&[("a", 1), ("b", 1)])), ClockOrdering::Before);
+        assert_eq!(compare_clocks(&clock(&[("a", 2)]), &clock(&[("a", 1), ("b", 1)])), ClockOrdering::Concurrent);
+        assert_eq!(merge_clocks(&clock(&[("a", 2)]), &clock(&[("a", 1), ("b", 1)])), clock(&[("a", 2), ("b", 1)]));
+    }
+
+    #[test]
+    fn merge_tag_sets_keeps_changes_from_both_sides() {
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(merge_tag_sets(None, &tags(&["a"]), &tags(&["b"])), tags(&["a", "b"]));
+        assert_eq!(merge_tag_sets(None, &[], &[]), Vec::<String>::new());
+        // b was removed locally and c added remotely since the last sync
+        assert_eq!(
+            merge_tag_sets(Some(&tags(&["a", "b"])), &tags(&["a"]), &tags(&["a", "b", "c"])),
+            tags(&["a", "c"])
+        );
+    }
+
+    fn conflicting_edit_and_delete(conn: &Connection, local_deleted: bool) -> (SyncManifestEntry, String) {
+        let local = SyncManifestEntry {
+            clock: clock(&[("local", 2)]),
+            fingerprint: "local".to_string(),
+            deleted: local_deleted,
+            updated_at: None,
+        };
+        let remote = SyncManifestEntry {
+            clock: clock(&[("local", 1), ("remote", 1)]),
+            fingerprint: "remote".to_string(),
+            deleted: !local_deleted,
+            updated_at: None,
+        };
+        assert_eq!(compare_clocks(&local.clock, &remote.clock), ClockOrdering::Concurrent);
+
+        let payload = (!remote.deleted).then(|| SyncedDocument {
+            document: test_document("doc", "Remote title"),
+            chunks: vec![SyncedChunk {
+                id: "remote-chunk".to_string(),
+                chunk_index: 0,
+                content: "edited remotely".to_string(),
+                embedding: hex::encode(encode_embedding(&[1.0, 0.0], EmbeddingStorage::Float32)),
+                embedding_model: Some("test".to_string()),
+                created_at: Utc::now(),
+            }],
+        });
+        record_sync_conflict(conn, "doc", "sync", Some(&local), &remote, payload.as_ref()).unwrap();
+        let conflict_id = conn.query_row("SELECT id FROM sync_conflicts", [], |row| row.get(0)).unwrap();
+        (remote, conflict_id)
+    }
+
+    fn stored_entry(conn: &Connection) -> (VectorClock, bool) {
+        conn.query_row("SELECT clock, deleted FROM sync_documents WHERE document_id = 'doc'", [], |row| {
+            Ok((serde_json::from_str(&row.get::<_, String>(0)?).unwrap(), row.get(1)?))
+        })
+        .unwrap()
+    }
+
+    fn unresolved_conflicts(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM sync_conflicts WHERE resolved_at IS NULL", [], |row| row.get(0)).unwrap()
+    }
+
+    fn document_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM documents WHERE id = 'doc'", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn local_edit_against_remote_delete_keep_theirs_deletes() {
+        let mut conn = test_db();
+        insert_document(&conn, &test_document("doc", "Local title")).unwrap();
+        let (remote, conflict_id) = conflicting_edit_and_delete(&conn, false);
+
+        resolve_sync_conflict(&mut conn, &conflict_id, &ConflictStrategy::KeepTheirs).unwrap();
+        assert_eq!(document_count(&conn), 0);
+        let (stored_clock, deleted) = stored_entry(&conn);
+        assert!(deleted);
+        assert_eq!(compare_clocks(&stored_clock, &remote.clock), ClockOrdering::After);
+        assert_eq!(unresolved_conflicts(&conn), 0);
+    }
+
+    #[test]
+    fn local_edit_against_remote_delete_keep_mine_wins_the_next_sync() {
+        let mut conn = test_db();
+        insert_document(&conn, &test_document("doc", "Local title")).unwrap();
+        let (remote, conflict_id) = conflicting_edit_and_delete(&conn, false);
+
+        resolve_sync_conflict(&mut conn, &conflict_id, &ConflictStrategy::KeepMine).unwrap();
+        assert_eq!(document_count(&conn), 1);
+        let (stored_clock, deleted) = stored_entry(&conn);
+        assert!(!deleted);
+        // Dominates the remote tombstone, so the next sync pushes the local version
+        assert_eq!(compare_clocks(&stored_clock, &remote.clock), ClockOrdering::After);
+        assert_eq!(compare_clocks(&stored_clock, &clock(&[("local", 2), ("remote", 1)])), ClockOrdering::After);
+    }
+
+    #[test]
+    fn local_delete_against_remote_edit_keep_theirs_restores_the_document() {
+        let mut conn = test_db();
+        let (_, conflict_id) = conflicting_edit_and_delete(&conn, true);
+
+        resolve_sync_conflict(&mut conn, &conflict_id, &ConflictStrategy::KeepTheirs).unwrap();
+        let title: String = conn.query_row("SELECT title FROM documents WHERE id = 'doc'", [], |row| row.get(0)).unwrap();
+        assert_eq!(title, "Remote title");
+        let chunks: i64 = conn
+            .query_row("SELECT COUNT(*) FROM document_chunks WHERE document_id = 'doc'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(chunks, 1);
+        assert!(!stored_entry(&conn).1);
+    }
+
+    #[test]
+    fn resolving_an_unknown_or_resolved_conflict_fails() {
+        let mut conn = test_db();
+        assert!(resolve_sync_conflict(&mut conn, "missing", &ConflictStrategy::KeepMine).is_err());
+
+        insert_document(&conn, &test_document("doc", "Local title")).unwrap();
+        let (_, conflict_id) = conflicting_edit_and_delete(&conn, false);
+        resolve_sync_conflict(&mut conn, &conflict_id, &ConflictStrategy::KeepMine).unwrap();
+        assert!(resolve_sync_conflict(&mut conn, &conflict_id, &ConflictStrategy::KeepTheirs).is_err());
+    }
+
+    // ---------- Ranking helpers
+
+    #[test]
+    fn top_k_keeps_the_best_rows_and_the_first_of_a_tie() {
+        let mut top = TopK::new(0);
+        top.offer(1.0, 1);
+        assert!(top.into_sorted().is_empty());
+
+        let mut top = TopK::new(2);
+        for (score, row_id) in [(0.1, 1), (0.9, 2), (0.5, 3)] {
+            top.offer(score, row_id);
+        }
+        assert_eq!(top.into_sorted(), [(0.9, 2), (0.5, 3)]);
+
+        let mut top = TopK::new(1);
+        top.offer(0.5, 1);
+        top.offer(0.5, 2);
+        assert_eq!(top.into_sorted(), [(0.5, 1)]);
+    }
+
+    #[test]
+    fn retrieval_metrics_handle_empty_expectations() {
+        assert_eq!(retrieval_metrics(&[], &[]), (0.0, 0.0));
+        assert_eq!(retrieval_metrics(&[], &["a".to_string()]), (0.0, 0.0));
+        let ranked = vec![vec!["x".to_string()], vec!["a".to_string()]];
+        assert_eq!(retrieval_metrics(&ranked, &["a".to_string(), "b".to_string()]), (0.5, 0.5));
+    }
+}